@@ -0,0 +1,474 @@
+// This module watches operational signals that should stop trading even while PnL looks fine.
+// Each signal keeps its own samples and is evaluated against its own threshold and window.
+
+use std::collections::VecDeque;
+use std::fmt;
+
+use ethers::types::{I256, U256};
+use thiserror::Error;
+use tokio::time::{Duration, Instant};
+
+/// Basis points in one.
+const BPS: u64 = 10_000;
+/// RPC calls needed within the window before the error rate is trusted.
+pub const DEFAULT_MIN_RPC_CALLS: usize = 20;
+
+/// A limit on a signal measured over a rolling window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Threshold<T> {
+    /// Value at which the signal trips the breaker.
+    pub limit: T,
+    /// Samples older than this are forgotten.
+    pub window: Duration,
+}
+
+impl<T> Threshold<T> {
+    /// Creates a threshold over a rolling window.
+    pub fn new(limit: T, window: Duration) -> Self {
+        Self { limit, window }
+    }
+}
+
+/// Limits of the operational signals. Every signal is off unless set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignalLimits {
+    /// Bundle reverts in a row, counting only the ones within the window.
+    pub bundle_reverts: Option<Threshold<u32>>,
+    /// Mean divergence of realized from simulated PnL within the window, in basis points.
+    pub divergence_bps: Option<Threshold<u32>>,
+    /// Share of failed RPC calls within the window, in basis points.
+    pub rpc_error_bps: Option<Threshold<u32>>,
+    /// RPC calls needed within the window before the error rate can trip the breaker.
+    pub min_rpc_calls: usize,
+    /// Native balance of the wallet below which it cannot keep paying for gas.
+    pub min_gas_balance: Option<U256>,
+    /// Time without a new head block after which the node is considered stale.
+    pub max_head_age: Option<Duration>,
+}
+
+impl Default for SignalLimits {
+    fn default() -> Self {
+        Self {
+            bundle_reverts: None,
+            divergence_bps: None,
+            rpc_error_bps: None,
+            min_rpc_calls: DEFAULT_MIN_RPC_CALLS,
+            min_gas_balance: None,
+            max_head_age: None,
+        }
+    }
+}
+
+impl SignalLimits {
+    /// Trips after `limit` bundle reverts in a row within `window`.
+    pub fn with_bundle_reverts(mut self, limit: u32, window: Duration) -> Self {
+        self.bundle_reverts = Some(Threshold::new(limit, window));
+        self
+    }
+
+    /// Trips once realized PnL diverges from simulated PnL by `limit_bps` on average within `window`.
+    pub fn with_divergence(mut self, limit_bps: u32, window: Duration) -> Self {
+        self.divergence_bps = Some(Threshold::new(limit_bps, window));
+        self
+    }
+
+    /// Trips once `limit_bps` of the RPC calls within `window` failed, out of at least `min_calls`.
+    pub fn with_rpc_errors(mut self, limit_bps: u32, window: Duration, min_calls: usize) -> Self {
+        self.rpc_error_bps = Some(Threshold::new(limit_bps, window));
+        self.min_rpc_calls = min_calls;
+        self
+    }
+
+    /// Trips once the wallet's native balance falls below `floor`.
+    pub fn with_min_gas_balance(mut self, floor: U256) -> Self {
+        self.min_gas_balance = Some(floor);
+        self
+    }
+
+    /// Trips once no new head block arrived for `max_age`.
+    pub fn with_max_head_age(mut self, max_age: Duration) -> Self {
+        self.max_head_age = Some(max_age);
+        self
+    }
+}
+
+/// A signal that reached its limit.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum SignalBreach {
+    /// Too many bundles reverted in a row.
+    #[error("{count} bundle reverts in a row reached the limit of {limit}")]
+    BundleReverts {
+        /// Reverts in a row within the window.
+        count: u32,
+        /// Configured limit.
+        limit: u32,
+    },
+    /// Realized outcomes drifted away from the simulations.
+    #[error("Simulation divergence of {divergence_bps} bps reached the limit of {limit_bps} bps")]
+    Divergence {
+        /// Mean divergence within the window.
+        divergence_bps: u32,
+        /// Configured limit.
+        limit_bps: u32,
+    },
+    /// Too many RPC calls failed.
+    #[error("{errors} of {calls} RPC calls failed, reaching the limit of {limit_bps} bps")]
+    RpcErrors {
+        /// Failed calls within the window.
+        errors: usize,
+        /// Calls within the window.
+        calls: usize,
+        /// Configured limit.
+        limit_bps: u32,
+    },
+    /// The wallet is running out of gas money.
+    #[error("Gas balance {balance} is below the floor of {floor}")]
+    LowGasBalance {
+        /// Latest balance.
+        balance: U256,
+        /// Configured floor.
+        floor: U256,
+    },
+    /// The node stopped delivering head blocks.
+    #[error("No new head after block {block:?} for {age:?}, limit is {limit:?}")]
+    StaleHead {
+        /// Last head block seen, if any.
+        block: Option<u64>,
+        /// Time since that block arrived, or since monitoring started.
+        age: Duration,
+        /// Configured limit.
+        limit: Duration,
+    },
+}
+
+/// Signal breaches that tripped the breaker together.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignalBreaches(pub Vec<SignalBreach>);
+
+impl fmt::Display for SignalBreaches {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, breach) in self.0.iter().enumerate() {
+            if index > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{breach}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Samples of the operational signals.
+///
+/// Windowed samples are only kept for the signals that are on, and no longer than their window.
+#[derive(Debug)]
+pub struct Signals {
+    started: Instant,
+    /// Times of the bundle reverts since the last bundle that landed.
+    reverts: VecDeque<Instant>,
+    divergences: VecDeque<(Instant, u32)>,
+    rpc_calls: VecDeque<(Instant, bool)>,
+    gas_balance: Option<U256>,
+    head: Option<(u64, Instant)>,
+}
+
+impl Signals {
+    /// Creates empty signals; a missing head block counts as stale from `started` on.
+    pub fn new(started: Instant) -> Self {
+        Self {
+            started,
+            reverts: VecDeque::new(),
+            divergences: VecDeque::new(),
+            rpc_calls: VecDeque::new(),
+            gas_balance: None,
+            head: None,
+        }
+    }
+
+    /// Records whether a bundle landed or reverted, unless `limits` leave the signal off.
+    pub fn record_bundle(&mut self, limits: &SignalLimits, landed: bool, at: Instant) {
+        let Some(threshold) = limits.bundle_reverts else {
+            return;
+        };
+        if landed {
+            self.reverts.clear();
+        } else {
+            expire(&mut self.reverts, threshold.window, at, |at| *at);
+            self.reverts.push_back(at);
+        }
+    }
+
+    /// Records the simulated and realized PnL of a trade, unless `limits` leave the signal off.
+    pub fn record_outcome(
+        &mut self,
+        limits: &SignalLimits,
+        simulated: I256,
+        realized: I256,
+        at: Instant,
+    ) {
+        let Some(threshold) = limits.divergence_bps else {
+            return;
+        };
+        expire(&mut self.divergences, threshold.window, at, |(at, _)| *at);
+        self.divergences
+            .push_back((at, divergence_bps(simulated, realized)));
+    }
+
+    /// Records whether an RPC call succeeded, unless `limits` leave the signal off.
+    pub fn record_rpc_call(&mut self, limits: &SignalLimits, ok: bool, at: Instant) {
+        let Some(threshold) = limits.rpc_error_bps else {
+            return;
+        };
+        expire(&mut self.rpc_calls, threshold.window, at, |(at, _)| *at);
+        self.rpc_calls.push_back((at, ok));
+    }
+
+    /// Records the wallet's native balance.
+    pub fn update_gas_balance(&mut self, balance: U256) {
+        self.gas_balance = Some(balance);
+    }
+
+    /// Records a head block; only a higher block counts as progress.
+    pub fn update_head(&mut self, block: u64, at: Instant) {
+        if self.head.is_none_or(|(head, _)| block > head) {
+            self.head = Some((block, at));
+        }
+    }
+
+    /// Forgets the windowed samples, so the breaches they caused do not trip a breaker that was closed again.
+    pub fn clear_windows(&mut self) {
+        self.reverts.clear();
+        self.divergences.clear();
+        self.rpc_calls.clear();
+    }
+
+    /// Evaluates every signal at `now`, dropping samples that left their window.
+    ///
+    /// Returns every breached signal, empty if none.
+    pub fn evaluate(&mut self, limits: &SignalLimits, now: Instant) -> Vec<SignalBreach> {
+        let mut breaches = Vec::new();
+
+        if let Some(threshold) = limits.bundle_reverts {
+            expire(&mut self.reverts, threshold.window, now, |at| *at);
+            let count = self.reverts.len() as u32;
+            if count >= threshold.limit {
+                breaches.push(SignalBreach::BundleReverts {
+                    count,
+                    limit: threshold.limit,
+                });
+            }
+        }
+
+        if let Some(threshold) = limits.divergence_bps {
+            expire(&mut self.divergences, threshold.window, now, |(at, _)| *at);
+            if !self.divergences.is_empty() {
+                let total: u64 = self.divergences.iter().map(|(_, bps)| *bps as u64).sum();
+                let mean = (total / self.divergences.len() as u64).min(u32::MAX as u64) as u32;
+                if mean >= threshold.limit {
+                    breaches.push(SignalBreach::Divergence {
+                        divergence_bps: mean,
+                        limit_bps: threshold.limit,
+                    });
+                }
+            }
+        }
+
+        if let Some(threshold) = limits.rpc_error_bps {
+            expire(&mut self.rpc_calls, threshold.window, now, |(at, _)| *at);
+            let calls = self.rpc_calls.len();
+            let errors = self.rpc_calls.iter().filter(|(_, ok)| !ok).count();
+            if calls > 0
+                && calls >= limits.min_rpc_calls
+                && errors as u64 * BPS >= threshold.limit as u64 * calls as u64
+            {
+                breaches.push(SignalBreach::RpcErrors {
+                    errors,
+                    calls,
+                    limit_bps: threshold.limit,
+                });
+            }
+        }
+
+        if let (Some(floor), Some(balance)) = (limits.min_gas_balance, self.gas_balance) {
+            if balance < floor {
+                breaches.push(SignalBreach::LowGasBalance { balance, floor });
+            }
+        }
+
+        if let Some(limit) = limits.max_head_age {
+            let (block, since) = match self.head {
+                Some((block, at)) => (Some(block), at),
+                None => (None, self.started),
+            };
+            let age = now.saturating_duration_since(since);
+            if age >= limit {
+                breaches.push(SignalBreach::StaleHead { block, age, limit });
+            }
+        }
+
+        breaches
+    }
+}
+
+/// Drops the samples at the front of `samples` taken a `window` or more before `now`.
+fn expire<T>(
+    samples: &mut VecDeque<T>,
+    window: Duration,
+    now: Instant,
+    time: impl Fn(&T) -> Instant,
+) {
+    while samples
+        .front()
+        .is_some_and(|sample| now.saturating_duration_since(time(sample)) >= window)
+    {
+        samples.pop_front();
+    }
+}
+
+/// Distance of the realized PnL from the simulated one, relative to the simulated one, in basis points.
+fn divergence_bps(simulated: I256, realized: I256) -> u32 {
+    let difference = simulated.saturating_sub(realized).unsigned_abs();
+    if difference.is_zero() {
+        return 0;
+    }
+    let expected = simulated.unsigned_abs();
+    if expected.is_zero() {
+        return BPS as u32;
+    }
+    let bps = difference.saturating_mul(U256::from(BPS)) / expected;
+    bps.min(U256::from(u32::MAX)).as_u32()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seconds(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_windowed_signals() {
+        let start = Instant::now();
+        let limits = SignalLimits::default()
+            .with_bundle_reverts(3, seconds(60))
+            .with_divergence(2_000, seconds(60))
+            .with_rpc_errors(5_000, seconds(20), 4);
+        let mut signals = Signals::new(start);
+
+        // Reverts older than the window or before a landed bundle do not count.
+        signals.record_bundle(&limits, false, start);
+        signals.record_bundle(&limits, false, start + seconds(50));
+        signals.record_bundle(&limits, true, start + seconds(55));
+        for offset in [70, 80] {
+            signals.record_bundle(&limits, false, start + seconds(offset));
+        }
+        signals.record_outcome(
+            &limits,
+            I256::from(100),
+            I256::from(-100),
+            start + seconds(10),
+        );
+        signals.record_outcome(
+            &limits,
+            I256::from(100),
+            I256::from(90),
+            start + seconds(80),
+        );
+        for (offset, ok) in [(75, true), (76, false), (77, false)] {
+            signals.record_rpc_call(&limits, ok, start + seconds(offset));
+        }
+        assert_eq!(signals.evaluate(&limits, start + seconds(80)), vec![]);
+
+        signals.record_bundle(&limits, false, start + seconds(90));
+        signals.record_outcome(
+            &limits,
+            I256::from(100),
+            I256::from(50),
+            start + seconds(90),
+        );
+        signals.record_rpc_call(&limits, true, start + seconds(85));
+        signals.record_rpc_call(&limits, false, start + seconds(86));
+        assert_eq!(
+            signals.evaluate(&limits, start + seconds(90)),
+            vec![
+                SignalBreach::BundleReverts { count: 3, limit: 3 },
+                SignalBreach::Divergence {
+                    divergence_bps: 3_000,
+                    limit_bps: 2_000,
+                },
+                SignalBreach::RpcErrors {
+                    errors: 3,
+                    calls: 5,
+                    limit_bps: 5_000,
+                },
+            ]
+        );
+
+        signals.clear_windows();
+        assert_eq!(signals.evaluate(&limits, start + seconds(90)), vec![]);
+    }
+
+    #[test]
+    fn test_samples_are_bounded_by_the_enabled_windows() {
+        let start = Instant::now();
+        let limits = SignalLimits::default().with_rpc_errors(5_000, seconds(20), 4);
+        let mut signals = Signals::new(start);
+
+        for offset in 0..100 {
+            let at = start + seconds(offset);
+            signals.record_bundle(&limits, false, at);
+            signals.record_outcome(&limits, I256::from(100), I256::zero(), at);
+            signals.record_rpc_call(&limits, false, at);
+        }
+        assert!(signals.reverts.is_empty());
+        assert!(signals.divergences.is_empty());
+        assert_eq!(signals.rpc_calls.len(), 20);
+    }
+
+    #[test]
+    fn test_gas_balance_and_stale_head() {
+        let start = Instant::now();
+        let limits = SignalLimits::default()
+            .with_min_gas_balance(U256::exp10(17))
+            .with_max_head_age(seconds(30));
+        let mut signals = Signals::new(start);
+
+        assert_eq!(
+            signals.evaluate(&limits, start + seconds(30)),
+            vec![SignalBreach::StaleHead {
+                block: None,
+                age: seconds(30),
+                limit: seconds(30),
+            }]
+        );
+
+        signals.update_head(100, start + seconds(20));
+        signals.update_head(99, start + seconds(40));
+        signals.update_gas_balance(U256::exp10(17));
+        assert_eq!(signals.evaluate(&limits, start + seconds(45)), vec![]);
+
+        signals.update_gas_balance(U256::exp10(17) - 1);
+        assert_eq!(
+            signals.evaluate(&limits, start + seconds(50)),
+            vec![
+                SignalBreach::LowGasBalance {
+                    balance: U256::exp10(17) - 1,
+                    floor: U256::exp10(17),
+                },
+                SignalBreach::StaleHead {
+                    block: Some(100),
+                    age: seconds(30),
+                    limit: seconds(30),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_divergence_bps() {
+        assert_eq!(divergence_bps(I256::from(100), I256::from(100)), 0);
+        assert_eq!(divergence_bps(I256::from(100), I256::from(120)), 2_000);
+        assert_eq!(divergence_bps(I256::from(-50), I256::from(-100)), 10_000);
+        assert_eq!(divergence_bps(I256::zero(), I256::from(1)), 10_000);
+    }
+}
@@ -0,0 +1,391 @@
+use ethers::types::{Address, Bytes, U256, U64};
+use revm::{
+    interpreter::{CallInputs, CallScheme, CreateInputs, Gas, InstructionResult},
+    primitives::{Address as rAddress, Bytes as rBytes, CreateScheme},
+    Database, EVMData, Inspector,
+};
+use serde::Serialize;
+
+use crate::simulation::{decode_revert_reason, from_revm_address};
+
+/// Kind of a call frame, named as in geth's `callTracer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum CallKind {
+    /// `CALL`, or the top-level call of a transaction.
+    Call,
+    /// `STATICCALL`.
+    StaticCall,
+    /// `DELEGATECALL`.
+    DelegateCall,
+    /// `CALLCODE`.
+    CallCode,
+    /// `CREATE`, or a contract creation transaction.
+    Create,
+    /// `CREATE2`.
+    Create2,
+}
+
+/// A call and its subcalls, serializing to the shape of geth's `callTracer` output.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallFrame {
+    /// Opcode that made the call.
+    #[serde(rename = "type")]
+    pub kind: CallKind,
+    /// Caller, or the context the call runs in for delegate calls and call code.
+    pub from: Address,
+    /// Callee, or the created contract. None if a creation failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<Address>,
+    /// Value transferred, None for static and delegate calls.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<U256>,
+    /// Gas made available to the call.
+    pub gas: U64,
+    /// Gas the call used, including its subcalls.
+    pub gas_used: U64,
+    /// Calldata, or the init code of a creation.
+    pub input: Bytes,
+    /// Return or revert data.
+    #[serde(skip_serializing_if = "<[u8]>::is_empty")]
+    pub output: Bytes,
+    /// Why the call failed, None if it succeeded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Reason decoded from the revert data.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revert_reason: Option<String>,
+    /// Calls made by this call, in order.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub calls: Vec<CallFrame>,
+}
+
+impl CallFrame {
+    /// Returns the function selector of the input, None for creations and calls without one.
+    pub fn selector(&self) -> Option<[u8; 4]> {
+        match self.kind {
+            CallKind::Create | CallKind::Create2 => None,
+            _ => self
+                .input
+                .get(..4)
+                .map(|selector| selector.try_into().unwrap()),
+        }
+    }
+
+    /// Returns true if the call did not fail.
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// Returns the innermost failed call, the one whose failure the calls above it propagated.
+    ///
+    /// Returns None if the call succeeded.
+    pub fn failed_call(&self) -> Option<&CallFrame> {
+        if self.is_success() {
+            return None;
+        }
+        // A reverting call reverts after its last failed subcall, if the failure is propagated.
+        match self.calls.iter().rev().find(|call| !call.is_success()) {
+            Some(call) => call.failed_call(),
+            None => Some(self),
+        }
+    }
+
+    /// Returns the trace as `callTracer` JSON.
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).expect("call frames serialize")
+    }
+}
+
+/// Inspector recording the call tree of a transaction.
+///
+/// Pass it to `EVM::inspect` as `&mut tracer`, then take the trace with `into_trace`.
+#[derive(Debug, Default)]
+pub struct CallTracer {
+    stack: Vec<CallFrame>,
+    root: Option<CallFrame>,
+}
+
+impl CallTracer {
+    /// Returns the call tree of the traced transaction, None if nothing was traced.
+    pub fn into_trace(self) -> Option<CallFrame> {
+        self.root
+    }
+
+    fn start(&mut self, frame: CallFrame) {
+        self.stack.push(frame);
+    }
+
+    fn finish(
+        &mut self,
+        gas_used: u64,
+        ret: InstructionResult,
+        output: &rBytes,
+        created: Option<rAddress>,
+    ) {
+        let Some(mut frame) = self.stack.pop() else {
+            return;
+        };
+        frame.gas_used = U64::from(gas_used);
+        frame.output = Bytes::from(output.to_vec());
+        frame.error = call_error(ret);
+        if ret == InstructionResult::Revert {
+            frame.revert_reason = decode_revert_reason(output);
+        }
+        if matches!(frame.kind, CallKind::Create | CallKind::Create2) {
+            frame.to = created.map(from_revm_address);
+        }
+
+        match self.stack.last_mut() {
+            Some(parent) => parent.calls.push(frame),
+            None => self.root = Some(frame),
+        }
+    }
+}
+
+impl<DB: Database> Inspector<DB> for CallTracer {
+    fn call(
+        &mut self,
+        _data: &mut EVMData<'_, DB>,
+        inputs: &mut CallInputs,
+    ) -> (InstructionResult, Gas, rBytes) {
+        // Delegate calls and call code run in the caller's context, so the calling contract is the context address.
+        let (kind, from, value) = match inputs.context.scheme {
+            CallScheme::Call => (
+                CallKind::Call,
+                inputs.context.caller,
+                Some(inputs.transfer.value),
+            ),
+            CallScheme::StaticCall => (CallKind::StaticCall, inputs.context.caller, None),
+            CallScheme::DelegateCall => (CallKind::DelegateCall, inputs.context.address, None),
+            CallScheme::CallCode => (
+                CallKind::CallCode,
+                inputs.context.address,
+                Some(inputs.transfer.value),
+            ),
+        };
+        self.start(CallFrame {
+            kind,
+            from: from_revm_address(from),
+            to: Some(from_revm_address(inputs.contract)),
+            value: value.map(|value| U256(value.into_limbs())),
+            gas: U64::from(inputs.gas_limit),
+            gas_used: U64::zero(),
+            input: Bytes::from(inputs.input.to_vec()),
+            output: Bytes::default(),
+            error: None,
+            revert_reason: None,
+            calls: Vec::new(),
+        });
+        (InstructionResult::Continue, Gas::new(0), rBytes::new())
+    }
+
+    fn call_end(
+        &mut self,
+        _data: &mut EVMData<'_, DB>,
+        inputs: &CallInputs,
+        remaining_gas: Gas,
+        ret: InstructionResult,
+        out: rBytes,
+    ) -> (InstructionResult, Gas, rBytes) {
+        self.finish(
+            inputs.gas_limit.saturating_sub(remaining_gas.remaining()),
+            ret,
+            &out,
+            None,
+        );
+        (ret, remaining_gas, out)
+    }
+
+    fn create(
+        &mut self,
+        _data: &mut EVMData<'_, DB>,
+        inputs: &mut CreateInputs,
+    ) -> (InstructionResult, Option<rAddress>, Gas, rBytes) {
+        self.start(CallFrame {
+            kind: match inputs.scheme {
+                CreateScheme::Create => CallKind::Create,
+                CreateScheme::Create2 { .. } => CallKind::Create2,
+            },
+            from: from_revm_address(inputs.caller),
+            to: None,
+            value: Some(U256(inputs.value.into_limbs())),
+            gas: U64::from(inputs.gas_limit),
+            gas_used: U64::zero(),
+            input: Bytes::from(inputs.init_code.to_vec()),
+            output: Bytes::default(),
+            error: None,
+            revert_reason: None,
+            calls: Vec::new(),
+        });
+        (
+            InstructionResult::Continue,
+            None,
+            Gas::new(0),
+            rBytes::new(),
+        )
+    }
+
+    fn create_end(
+        &mut self,
+        _data: &mut EVMData<'_, DB>,
+        inputs: &CreateInputs,
+        ret: InstructionResult,
+        address: Option<rAddress>,
+        remaining_gas: Gas,
+        out: rBytes,
+    ) -> (InstructionResult, Option<rAddress>, Gas, rBytes) {
+        self.finish(
+            inputs.gas_limit.saturating_sub(remaining_gas.remaining()),
+            ret,
+            &out,
+            address,
+        );
+        (ret, address, remaining_gas, out)
+    }
+}
+
+/// Returns the `callTracer` error of a call that ended with `ret`, None if it succeeded.
+fn call_error(ret: InstructionResult) -> Option<String> {
+    match ret {
+        InstructionResult::Continue
+        | InstructionResult::Stop
+        | InstructionResult::Return
+        | InstructionResult::SelfDestruct => None,
+        InstructionResult::Revert => Some("execution reverted".to_string()),
+        InstructionResult::OutOfGas => Some("out of gas".to_string()),
+        ret => Some(format!("{ret:?}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::ERROR_SELECTOR;
+    use ethers::abi::{encode, Token};
+    use revm::{
+        db::{CacheDB, EmptyDB},
+        primitives::{AccountInfo, Bytecode, TransactTo},
+        EVM,
+    };
+    use serde_json::json;
+
+    fn frame(kind: CallKind, to: Address) -> CallFrame {
+        CallFrame {
+            kind,
+            from: Address::repeat_byte(0xca),
+            to: Some(to),
+            value: None,
+            gas: U64::from(100_000),
+            gas_used: U64::from(21_000),
+            input: Bytes::from(vec![0x02, 0x2c, 0x0d, 0x9f]),
+            output: Bytes::default(),
+            error: None,
+            revert_reason: None,
+            calls: Vec::new(),
+        }
+    }
+
+    // Bytecode storing `data` in memory and reverting with it.
+    fn revert_with(data: &[u8]) -> Vec<u8> {
+        let mut code = Vec::new();
+        for (index, chunk) in data.chunks(32).enumerate() {
+            let mut word = [0u8; 32];
+            word[..chunk.len()].copy_from_slice(chunk);
+            code.push(0x7f);
+            code.extend(word);
+            code.extend([0x60, (index * 32) as u8, 0x52]);
+        }
+        code.extend([0x60, data.len() as u8, 0x60, 0x00, 0xfd]);
+        code
+    }
+
+    // Bytecode calling `target` with no data and stopping whatever the outcome.
+    fn call_and_stop(target: Address) -> Vec<u8> {
+        let mut code = vec![
+            0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x73,
+        ];
+        code.extend(target.as_bytes());
+        code.extend([0x5a, 0xf1, 0x50, 0x00]);
+        code
+    }
+
+    #[test]
+    fn test_traces_inner_revert() {
+        let caller = Address::repeat_byte(0xca);
+        let router = Address::repeat_byte(0x0a);
+        let pair = Address::repeat_byte(0x0b);
+        let mut reason = ERROR_SELECTOR.to_vec();
+        reason.extend(encode(&[Token::String("UniswapV2: K".into())]));
+
+        let mut db = CacheDB::new(EmptyDB::default());
+        for (address, code) in [(router, call_and_stop(pair)), (pair, revert_with(&reason))] {
+            db.insert_account_info(
+                rAddress::from(address.0),
+                AccountInfo {
+                    code: Some(Bytecode::new_raw(code.into())),
+                    ..Default::default()
+                },
+            );
+        }
+
+        let mut evm = EVM::new();
+        evm.database(db);
+        evm.env.tx.caller = rAddress::from(caller.0);
+        evm.env.tx.transact_to = TransactTo::Call(rAddress::from(router.0));
+        evm.env.tx.gas_limit = 200_000;
+        let mut tracer = CallTracer::default();
+        evm.inspect(&mut tracer).unwrap();
+        let trace = tracer.into_trace().unwrap();
+
+        assert!(trace.is_success());
+        assert_eq!(trace.to, Some(router));
+        assert_eq!(trace.calls.len(), 1);
+        let inner = &trace.calls[0];
+        assert_eq!((inner.from, inner.to), (router, Some(pair)));
+        assert_eq!(inner.error.as_deref(), Some("execution reverted"));
+        assert_eq!(inner.revert_reason.as_deref(), Some("UniswapV2: K"));
+        assert!(inner.gas_used > U64::zero());
+    }
+
+    #[test]
+    fn test_call_tracer_json() {
+        let router = Address::repeat_byte(0x0a);
+        let pair = Address::repeat_byte(0x0b);
+        let mut inner = frame(CallKind::StaticCall, pair);
+        inner.from = router;
+        inner.error = Some("execution reverted".to_string());
+        inner.revert_reason = Some("UniswapV2: K".to_string());
+        let mut root = frame(CallKind::Call, router);
+        root.value = Some(U256::zero());
+        root.error = Some("execution reverted".to_string());
+        root.calls.push(inner);
+
+        assert_eq!(root.selector(), Some([0x02, 0x2c, 0x0d, 0x9f]));
+        assert_eq!(root.failed_call().unwrap().to, Some(pair));
+        assert_eq!(
+            root.to_json(),
+            json!({
+                "type": "CALL",
+                "from": Address::repeat_byte(0xca),
+                "to": router,
+                "value": "0x0",
+                "gas": "0x186a0",
+                "gasUsed": "0x5208",
+                "input": "0x022c0d9f",
+                "error": "execution reverted",
+                "calls": [{
+                    "type": "STATICCALL",
+                    "from": router,
+                    "to": pair,
+                    "gas": "0x186a0",
+                    "gasUsed": "0x5208",
+                    "input": "0x022c0d9f",
+                    "error": "execution reverted",
+                    "revertReason": "UniswapV2: K"
+                }]
+            })
+        );
+    }
+}
@@ -1,95 +1,540 @@
-use chrono::{DateTime, Utc};
-use ethers::types::U256;
-use std::sync::Arc;
-use tokio::sync::RwLock;
-use tokio::time::{interval, Duration};
-use log;
-
-#[derive(Debug, Clone)]
-pub struct Trade {
-    pub timestamp: DateTime<Utc>,
-    pub profit_loss: U256,
-}
+// This module stops trading when realized losses exceed the configured limits.
+// The breaker trips open instead of exiting, and lets a few probe trades through after a cool-down.
+// Besides PnL it trips on the operational signals of `breaker_signals`.
 
-#[derive(Debug)]
-pub struct RiskEngine {
-    daily_loss_limit: U256,
-    trades: Arc<RwLock<Vec<Trade>>>,
-}
+use chrono::Utc;
+use ethers::types::{Address, I256, U256};
+use mev_risk::{LimitBreach, RiskEngine, RiskLimits, TradeProposal};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex, MutexGuard,
+};
+use thiserror::Error;
+use tokio::sync::broadcast;
+use tokio::time::{interval, Duration, Instant};
 
-impl RiskEngine {
-    pub fn new(daily_loss_limit: U256) -> Self {
-        Self {
-            daily_loss_limit,
-            trades: Arc::new(RwLock::new(Vec::new())),
-        }
-    }
+use crate::breaker_signals::{SignalBreaches, SignalLimits, Signals};
 
-    pub async fn validate_trade(&self, profit_loss: U256) -> bool {
-        let now = Utc::now();
-        let trades = self.trades.read().await;
-        
-        // Calculate total losses for today
-        let today_losses = trades
-            .iter()
-            .filter(|trade| {
-                trade.timestamp.date_naive() == now.date_naive() && 
-                trade.profit_loss < U256::zero()
-            })
-            .fold(U256::zero(), |acc, trade| acc + trade.profit_loss);
-
-        // Check if new trade would exceed daily loss limit
-        if today_losses + profit_loss > self.daily_loss_limit {
-            return false;
-        }
+/// Breaker events kept for a slow subscriber before the oldest are dropped.
+const EVENT_CAPACITY: usize = 16;
+/// Time the breaker stays open before letting probe trades through.
+pub const DEFAULT_COOLDOWN: Duration = Duration::from_secs(300);
+/// Profitable probe trades needed to close a half-open breaker.
+pub const DEFAULT_PROBE_TRADES: u32 = 1;
 
-        true
-    }
+/// Why the breaker opened.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum TripReason {
+    /// A risk limit was reached.
+    #[error(transparent)]
+    Limit(#[from] LimitBreach),
+    /// A probe trade of the half-open breaker lost money.
+    #[error("Probe trade lost {}", .0.unsigned_abs())]
+    ProbeLoss(I256),
+    /// Operational signals reached their limits.
+    #[error("{0}")]
+    Signals(SignalBreaches),
+}
 
-    pub async fn record_trade(&self, profit_loss: U256) {
-        let trade = Trade {
-            timestamp: Utc::now(),
-            profit_loss,
-        };
+/// State of a circuit breaker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BreakerState {
+    /// Trades are checked against the limits.
+    Closed,
+    /// No trades are allowed until the cool-down ends.
+    Open {
+        /// Why the breaker opened.
+        reason: TripReason,
+        /// End of the cool-down.
+        until: Instant,
+    },
+    /// A limited number of probe trades are allowed to test whether trading can resume.
+    HalfOpen {
+        /// Probe trades allowed so far.
+        probes: u32,
+        /// Probe trades recorded with a profit, or breaking even.
+        successes: u32,
+    },
+}
 
-        let mut trades = self.trades.write().await;
-        trades.push(trade);
+/// A state change of a circuit breaker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BreakerEvent {
+    /// The breaker opened; strategies should stop sending trades.
+    Tripped(TripReason),
+    /// The cool-down ended and probe trades are allowed.
+    HalfOpen,
+    /// Enough probe trades succeeded and trading resumed.
+    Closed,
+    /// The breaker was closed by hand.
+    Reset,
+}
+
+/// Permission to send a trade, returned by `CircuitBreaker::check_trade`.
+///
+/// The permit of a half-open breaker identifies its probe, so only that trade's outcome settles the probe.
+/// Pass it to `record_trade` once the trade is realized, or to `release_trade` if it was not sent or did not
+/// land; a probe whose permit is dropped stays pending until the breaker opens or is reset.
+#[derive(Debug, PartialEq, Eq)]
+#[must_use]
+pub struct TradePermit {
+    probe: Option<u64>,
+}
+
+impl TradePermit {
+    /// Returns true if the trade is a probe of a half-open breaker.
+    pub fn is_probe(&self) -> bool {
+        self.probe.is_some()
     }
 }
 
+/// Stops trading once a risk limit is reached and resumes it after a cool-down and successful probe trades.
+///
+/// Closing the breaker, after probes or by hand, leaves the trades that tripped it out of the limits.
 #[derive(Debug)]
 pub struct CircuitBreaker {
     risk_engine: Arc<RiskEngine>,
     check_interval: Duration,
+    cooldown: Duration,
+    probe_trades: u32,
+    signal_limits: SignalLimits,
+    signals: Mutex<Signals>,
+    state: Mutex<BreakerState>,
+    /// Probes allowed in the current half-open state whose outcome is not recorded yet.
+    pending_probes: Mutex<Vec<u64>>,
+    next_probe: AtomicU64,
+    events: broadcast::Sender<BreakerEvent>,
 }
 
 impl CircuitBreaker {
-    pub fn new(daily_loss_limit: U256, check_interval: Duration) -> Self {
+    /// Creates a closed breaker over a new risk engine with PnL in `quote_token`.
+    pub fn new(quote_token: Address, limits: RiskLimits, check_interval: Duration) -> Self {
+        let risk_engine = RiskEngine::default()
+            .with_quote_token(quote_token)
+            .with_limits(limits);
+        Self::from_engine(Arc::new(risk_engine), check_interval)
+    }
+
+    /// Creates a closed breaker over a risk engine shared with the strategies, such as one with a store.
+    pub fn from_engine(risk_engine: Arc<RiskEngine>, check_interval: Duration) -> Self {
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
         Self {
-            risk_engine: Arc::new(RiskEngine::new(daily_loss_limit)),
+            risk_engine,
             check_interval,
+            cooldown: DEFAULT_COOLDOWN,
+            probe_trades: DEFAULT_PROBE_TRADES,
+            signal_limits: SignalLimits::default(),
+            signals: Mutex::new(Signals::new(Instant::now())),
+            state: Mutex::new(BreakerState::Closed),
+            pending_probes: Mutex::new(Vec::new()),
+            next_probe: AtomicU64::new(0),
+            events,
+        }
+    }
+
+    /// Sets how long the breaker stays open before probe trades are allowed.
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Sets how many probe trades are allowed when half-open, all of which must succeed to close.
+    pub fn with_probe_trades(mut self, probe_trades: u32) -> Self {
+        self.probe_trades = probe_trades.max(1);
+        self
+    }
+
+    /// Sets the limits of the operational signals.
+    pub fn with_signal_limits(mut self, signal_limits: SignalLimits) -> Self {
+        self.signal_limits = signal_limits;
+        self
+    }
+
+    /// Risk engine the breaker checks.
+    pub fn risk_engine(&self) -> &Arc<RiskEngine> {
+        &self.risk_engine
+    }
+
+    /// Subscribes to the breaker's state changes.
+    pub fn subscribe(&self) -> broadcast::Receiver<BreakerEvent> {
+        self.events.subscribe()
+    }
+
+    /// Current state, moving to half-open if the cool-down has ended.
+    pub fn state(&self) -> BreakerState {
+        let (state, event) = {
+            let mut state = self.lock_state();
+            let event = self.end_cooldown(&mut state);
+            (state.clone(), event)
+        };
+        self.emit(event);
+        state
+    }
+
+    /// Returns a permit if `proposal` may be sent.
+    ///
+    /// When closed the proposal must pass every check of the risk engine. When half-open, each allowed trade is
+    /// a probe that only needs to pass the pre-trade checks, and its permit must be passed to `record_trade`,
+    /// or to `release_trade` if it was not sent or did not land.
+    pub fn check_trade(&self, proposal: &TradeProposal) -> Option<TradePermit> {
+        // Ends an elapsed cool-down before the signals are checked, so probes are never sent past a breach.
+        self.check_limits();
+
+        let mut state = self.lock_state();
+        match &mut *state {
+            BreakerState::Closed => {
+                drop(state);
+                self.risk_engine
+                    .check_trade(proposal)
+                    .ok()
+                    .map(|()| TradePermit { probe: None })
+            }
+            BreakerState::Open { .. } => None,
+            BreakerState::HalfOpen { probes, .. } => {
+                if *probes >= self.probe_trades
+                    || self.risk_engine.check_position(proposal).is_err()
+                {
+                    return None;
+                }
+                *probes += 1;
+                let probe = self.next_probe.fetch_add(1, Ordering::Relaxed);
+                lock(&self.pending_probes).push(probe);
+                Some(TradePermit { probe: Some(probe) })
+            }
         }
     }
 
-    pub async fn check_trade(&self, profit_loss: U256) -> bool {
-        self.risk_engine.validate_trade(profit_loss).await
+    /// Records the realized trade of a permit for `strategy`, tripping the breaker if it reaches a limit or the
+    /// permit's probe lost.
+    pub fn record_trade(
+        &self,
+        permit: TradePermit,
+        strategy: impl Into<String>,
+        profit_loss: I256,
+    ) {
+        self.risk_engine.record_trade(strategy, profit_loss);
+        self.settle_probe(permit, profit_loss);
     }
 
-    pub async fn record_trade(&self, profit_loss: U256) {
-        self.risk_engine.record_trade(profit_loss).await;
+    /// Records that the trade of a permit was not sent or did not land.
+    ///
+    /// A probe counts as breaking even, without recording a trade.
+    pub fn release_trade(&self, permit: TradePermit) {
+        self.settle_probe(permit, I256::zero());
     }
 
-    pub async fn run(&mut self) {
+    /// Settles the probe of a permit with the PnL of its trade, if it is pending in the current half-open state.
+    fn settle_probe(&self, permit: TradePermit, profit_loss: I256) {
+        let event = {
+            let mut state = self.lock_state();
+            match &mut *state {
+                // Permits of a closed breaker, or of probes from an earlier half-open state, settle nothing.
+                BreakerState::HalfOpen { .. }
+                    if !permit.probe.is_some_and(|probe| self.take_probe(probe)) =>
+                {
+                    None
+                }
+                BreakerState::HalfOpen { .. } if profit_loss.is_negative() => {
+                    Some(self.open(&mut state, TripReason::ProbeLoss(profit_loss)))
+                }
+                BreakerState::HalfOpen { successes, .. } => {
+                    *successes += 1;
+                    (*successes >= self.probe_trades).then(|| {
+                        *state = BreakerState::Closed;
+                        BreakerEvent::Closed
+                    })
+                }
+                _ => None,
+            }
+        };
+
+        match event {
+            Some(BreakerEvent::Closed) => {
+                self.risk_engine.reset_limits(Utc::now());
+                lock(&self.signals).clear_windows();
+                self.emit(Some(BreakerEvent::Closed));
+            }
+            Some(event) => self.emit(Some(event)),
+            None => self.check_limits(),
+        }
+    }
+
+    /// Removes a probe from the pending ones, returning true if it was pending.
+    fn take_probe(&self, probe: u64) -> bool {
+        let mut pending = lock(&self.pending_probes);
+        let found = pending.iter().position(|pending| *pending == probe);
+        found.map(|index| pending.swap_remove(index)).is_some()
+    }
+
+    /// Closes the breaker by hand, whatever its state.
+    pub fn reset(&self) {
+        self.risk_engine.reset_limits(Utc::now());
+        lock(&self.signals).clear_windows();
+        *self.lock_state() = BreakerState::Closed;
+        log::info!("Circuit breaker reset");
+        self.emit(Some(BreakerEvent::Reset));
+    }
+
+    /// Records whether a bundle landed or reverted.
+    pub fn record_bundle(&self, landed: bool) {
+        lock(&self.signals).record_bundle(&self.signal_limits, landed, Instant::now());
+        self.check_limits();
+    }
+
+    /// Records the simulated and realized PnL of a trade.
+    pub fn record_outcome(&self, simulated: I256, realized: I256) {
+        lock(&self.signals).record_outcome(
+            &self.signal_limits,
+            simulated,
+            realized,
+            Instant::now(),
+        );
+        self.check_limits();
+    }
+
+    /// Records whether an RPC call succeeded.
+    pub fn record_rpc_call(&self, ok: bool) {
+        lock(&self.signals).record_rpc_call(&self.signal_limits, ok, Instant::now());
+        self.check_limits();
+    }
+
+    /// Records the wallet's native balance.
+    pub fn update_gas_balance(&self, balance: U256) {
+        lock(&self.signals).update_gas_balance(balance);
+        self.check_limits();
+    }
+
+    /// Records a new head block.
+    pub fn update_head(&self, block: u64) {
+        lock(&self.signals).update_head(block, Instant::now());
+        self.check_limits();
+    }
+
+    /// Checks the limits every `check_interval` and ends cool-downs, until the task is dropped.
+    pub async fn run(&self) {
         let mut interval = interval(self.check_interval);
-        
+
         loop {
             interval.tick().await;
-            
-            // Check daily losses against threshold using validate_trade
-            if let false = self.risk_engine.validate_trade(U256::zero()).await {
-                log::error!("Daily loss limit exceeded! Shutting down...");
-                std::process::exit(1);
+            self.check_limits();
+        }
+    }
+
+    /// Trips the breaker if a signal or, when closed, a PnL limit is reached, and ends an elapsed cool-down.
+    fn check_limits(&self) {
+        let state = self.state();
+        if matches!(state, BreakerState::Open { .. }) {
+            return;
+        }
+
+        let breaches = lock(&self.signals).evaluate(&self.signal_limits, Instant::now());
+        let reason = if !breaches.is_empty() {
+            TripReason::Signals(SignalBreaches(breaches))
+        } else if state == BreakerState::Closed {
+            match self.risk_engine.check_limits() {
+                Ok(()) => return,
+                Err(breach) => breach.into(),
             }
+        } else {
+            return;
+        };
+
+        let event = {
+            let mut state = self.lock_state();
+            (!matches!(*state, BreakerState::Open { .. })).then(|| self.open(&mut state, reason))
+        };
+        self.emit(event);
+    }
+
+    fn open(&self, state: &mut BreakerState, reason: TripReason) -> BreakerEvent {
+        log::error!("Circuit breaker tripped: {reason}");
+        *state = BreakerState::Open {
+            reason: reason.clone(),
+            until: Instant::now() + self.cooldown,
+        };
+        BreakerEvent::Tripped(reason)
+    }
+
+    fn end_cooldown(&self, state: &mut BreakerState) -> Option<BreakerEvent> {
+        match state {
+            BreakerState::Open { until, .. } if *until <= Instant::now() => {
+                log::info!("Circuit breaker half-open, allowing probe trades");
+                // Probes measure the windowed signals afresh; live ones like the gas balance still apply.
+                lock(&self.signals).clear_windows();
+                lock(&self.pending_probes).clear();
+                *state = BreakerState::HalfOpen {
+                    probes: 0,
+                    successes: 0,
+                };
+                Some(BreakerEvent::HalfOpen)
+            }
+            _ => None,
         }
     }
+
+    fn lock_state(&self) -> MutexGuard<'_, BreakerState> {
+        lock(&self.state)
+    }
+
+    fn emit(&self, event: Option<BreakerEvent>) {
+        if let Some(event) = event {
+            // Nobody listening is fine, the state is still queryable.
+            let _ = self.events.send(event);
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::breaker_signals::SignalBreach;
+
+    fn trade(max_loss: u64) -> TradeProposal {
+        TradeProposal::new(Address::repeat_byte(2), U256::from(10_000))
+            .with_value(U256::from(10_000))
+            .with_strategy("sandwich")
+            .with_max_loss(U256::from(max_loss))
+    }
+
+    fn breaker_with(cooldown: Duration) -> CircuitBreaker {
+        CircuitBreaker::new(
+            Address::repeat_byte(1),
+            RiskLimits::new(U256::from(100)),
+            Duration::from_secs(1),
+        )
+        .with_cooldown(cooldown)
+        .with_probe_trades(2)
+    }
+
+    fn daily_loss(loss: u64) -> BreakerEvent {
+        BreakerEvent::Tripped(TripReason::Limit(LimitBreach::DailyLoss {
+            loss: U256::from(loss),
+            limit: U256::from(100),
+        }))
+    }
+
+    #[tokio::test]
+    async fn test_breaker_recovers_through_probe_trades() {
+        let breaker = breaker_with(Duration::ZERO);
+        let mut events = breaker.subscribe();
+
+        let permit = breaker.check_trade(&trade(60)).unwrap();
+        assert!(!permit.is_probe());
+        assert!(breaker.check_trade(&trade(100)).is_none());
+        breaker.record_trade(permit, "sandwich", I256::from(-100));
+        assert_eq!(events.recv().await.unwrap(), daily_loss(100));
+
+        // With no cool-down the breaker is half-open right away, and allows two probes whatever their PnL.
+        let first = breaker.check_trade(&trade(10)).unwrap();
+        assert!(first.is_probe());
+        assert_eq!(events.recv().await.unwrap(), BreakerEvent::HalfOpen);
+        let second = breaker.check_trade(&trade(10)).unwrap();
+        assert!(breaker.check_trade(&trade(0)).is_none());
+
+        // The probes settle in any order, each with its own outcome.
+        breaker.record_trade(second, "sandwich", I256::from(5));
+        assert!(matches!(breaker.state(), BreakerState::HalfOpen { .. }));
+        breaker.release_trade(first);
+        assert_eq!(events.recv().await.unwrap(), BreakerEvent::Closed);
+        assert_eq!(breaker.state(), BreakerState::Closed);
+
+        // The loss that tripped the breaker no longer counts against the limit.
+        assert!(breaker.check_trade(&trade(60)).is_some());
+        assert_eq!(breaker.risk_engine().daily_pnl(Utc::now()), I256::from(-95));
+    }
+
+    #[tokio::test]
+    async fn test_breaker_reopens_on_probe_loss_and_resets_by_hand() {
+        let breaker = breaker_with(Duration::ZERO);
+        let mut events = breaker.subscribe();
+        let permit = breaker.check_trade(&trade(0)).unwrap();
+        breaker.record_trade(permit, "sandwich", I256::from(-150));
+        assert_eq!(events.recv().await.unwrap(), daily_loss(150));
+
+        let probe = breaker.check_trade(&trade(0)).unwrap();
+        breaker.record_trade(probe, "sandwich", I256::from(-1));
+        assert_eq!(events.recv().await.unwrap(), BreakerEvent::HalfOpen);
+        assert_eq!(
+            events.recv().await.unwrap(),
+            BreakerEvent::Tripped(TripReason::ProbeLoss(I256::from(-1)))
+        );
+
+        // A long cool-down keeps the breaker open until it is reset by hand.
+        let breaker = breaker_with(Duration::from_secs(3600));
+        let mut events = breaker.subscribe();
+        let permit = breaker.check_trade(&trade(0)).unwrap();
+        breaker.record_trade(permit, "sandwich", I256::from(-150));
+        assert_eq!(events.recv().await.unwrap(), daily_loss(150));
+        assert!(breaker.check_trade(&trade(0)).is_none());
+        assert!(matches!(
+            breaker.state(),
+            BreakerState::Open {
+                reason: TripReason::Limit(LimitBreach::DailyLoss { .. }),
+                ..
+            }
+        ));
+
+        breaker.reset();
+        assert_eq!(events.recv().await.unwrap(), BreakerEvent::Reset);
+        assert!(breaker.check_trade(&trade(99)).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_breaker_reports_signal_breaches() {
+        let breaker = breaker_with(Duration::ZERO).with_signal_limits(
+            SignalLimits::default()
+                .with_bundle_reverts(2, Duration::from_secs(60))
+                .with_min_gas_balance(U256::exp10(17)),
+        );
+        let mut events = breaker.subscribe();
+
+        // A trade allowed while closed settles after the breaker tripped.
+        let closed = breaker.check_trade(&trade(0)).unwrap();
+        breaker.update_gas_balance(U256::exp10(18));
+        breaker.record_bundle(false);
+        assert_eq!(breaker.state(), BreakerState::Closed);
+        breaker.record_bundle(false);
+
+        let Ok(BreakerEvent::Tripped(reason)) = events.recv().await else {
+            panic!("expected a trip");
+        };
+        assert_eq!(
+            reason,
+            TripReason::Signals(SignalBreaches(vec![SignalBreach::BundleReverts {
+                count: 2,
+                limit: 2,
+            }]))
+        );
+        assert_eq!(
+            reason.to_string(),
+            "2 bundle reverts in a row reached the limit of 2"
+        );
+
+        // The reverts that tripped the breaker are forgotten once it is half-open.
+        let probe = breaker.check_trade(&trade(0)).unwrap();
+        breaker.record_trade(probe, "sandwich", I256::zero());
+        // A trade that was not allowed as a probe does not count towards closing.
+        breaker.record_trade(closed, "sandwich", I256::zero());
+        assert_eq!(
+            breaker.state(),
+            BreakerState::HalfOpen {
+                probes: 1,
+                successes: 1
+            }
+        );
+        let probe = breaker.check_trade(&trade(0)).unwrap();
+        breaker.record_trade(probe, "sandwich", I256::zero());
+        assert_eq!(breaker.state(), BreakerState::Closed);
+
+        // A signal that is still breached blocks trades right away.
+        breaker.update_gas_balance(U256::exp10(16));
+        assert!(breaker.check_trade(&trade(0)).is_none());
+        assert_ne!(breaker.state(), BreakerState::Closed);
+    }
 }
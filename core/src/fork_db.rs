@@ -0,0 +1,350 @@
+use ethers::{
+    providers::Middleware,
+    types::{Address, BlockId, Bytes, H256, U256},
+    utils::keccak256,
+};
+use revm::{
+    db::DatabaseRef,
+    primitives::{AccountInfo, Address as rAddress, Bytecode, B256, KECCAK_EMPTY, U256 as rU256},
+};
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, RwLock},
+};
+use tokio::runtime::{Handle, RuntimeFlavor};
+
+use crate::simulation::from_revm_address;
+
+/// Errors of reading state through a `ForkDB`.
+#[derive(Debug, thiserror::Error)]
+pub enum ForkDbError {
+    /// The provider failed to answer a request.
+    #[error("Provider error: {0}")]
+    ProviderError(String),
+    /// The provider does not know the requested block.
+    #[error("Block not found: {0}")]
+    BlockNotFound(u64),
+    /// Code was requested by a hash no fetched account has.
+    #[error("Code not found for hash {0:?}")]
+    MissingCode(H256),
+    /// The provider cannot be called from the current runtime.
+    #[error("State must be fetched from a multi-threaded tokio runtime or a blocking task")]
+    UnsupportedRuntime,
+}
+
+/// Account state at the pinned block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForkAccount {
+    /// Balance in wei.
+    pub balance: U256,
+    /// Number of transactions sent, or contracts created by a contract.
+    pub nonce: u64,
+    /// Runtime code, empty for externally owned accounts.
+    pub code: Bytes,
+    /// Keccak hash of the code.
+    pub code_hash: H256,
+}
+
+#[derive(Debug, Default)]
+struct ForkCache {
+    accounts: RwLock<HashMap<Address, ForkAccount>>,
+    storage: RwLock<HashMap<(Address, H256), H256>>,
+    block_hashes: RwLock<HashMap<u64, H256>>,
+}
+
+/// Read-only revm database that fetches state from a provider at a pinned block on demand.
+///
+/// Accounts, storage slots and block hashes are cached after the first read. Clones share the cache, so
+/// every simulation of a block reads each slot from the provider once; wrap a clone in a `CacheDB` to
+/// keep a simulation's writes local. `on_new_block` moves to a fresh cache, leaving existing clones
+/// pinned to the block they were made for.
+///
+/// The provider is called from synchronous revm callbacks, so the database must be used on a
+/// multi-threaded tokio runtime; elsewhere reads fail with `ForkDbError::UnsupportedRuntime`.
+#[derive(Debug)]
+pub struct ForkDB<M> {
+    provider: Arc<M>,
+    block_number: u64,
+    cache: Arc<ForkCache>,
+}
+
+impl<M> Clone for ForkDB<M> {
+    fn clone(&self) -> Self {
+        Self {
+            provider: self.provider.clone(),
+            block_number: self.block_number,
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+impl<M: Middleware> ForkDB<M> {
+    /// Creates a database reading the state after `block_number`.
+    pub fn new(provider: Arc<M>, block_number: u64) -> Self {
+        Self {
+            provider,
+            block_number,
+            cache: Arc::new(ForkCache::default()),
+        }
+    }
+
+    /// Returns the block whose state is read.
+    pub fn block_number(&self) -> u64 {
+        self.block_number
+    }
+
+    /// Pins the database to a new block, dropping the cached state if the block changed.
+    ///
+    /// Returns true if the cache was invalidated.
+    pub fn on_new_block(&mut self, block_number: u64) -> bool {
+        if block_number == self.block_number {
+            return false;
+        }
+        self.block_number = block_number;
+        self.cache = Arc::new(ForkCache::default());
+        true
+    }
+
+    /// Returns the account at the pinned block, fetching its balance, nonce and code on a cache miss.
+    pub async fn account(&self, address: Address) -> Result<ForkAccount, ForkDbError> {
+        if let Some(account) = self.cache.accounts.read().unwrap().get(&address) {
+            return Ok(account.clone());
+        }
+
+        let block = Some(self.block_id());
+        let (balance, nonce, code) = tokio::try_join!(
+            self.provider.get_balance(address, block),
+            self.provider.get_transaction_count(address, block),
+            self.provider.get_code(address, block),
+        )
+        .map_err(provider_error)?;
+        let account = ForkAccount {
+            balance,
+            nonce: nonce.as_u64(),
+            code_hash: if code.is_empty() {
+                H256::from(KECCAK_EMPTY.0)
+            } else {
+                H256::from(keccak256(&code))
+            },
+            code,
+        };
+
+        self.cache
+            .accounts
+            .write()
+            .unwrap()
+            .insert(address, account.clone());
+        Ok(account)
+    }
+
+    /// Returns a storage slot at the pinned block, fetching it on a cache miss.
+    pub async fn storage_at(&self, address: Address, slot: H256) -> Result<H256, ForkDbError> {
+        if let Some(value) = self.cache.storage.read().unwrap().get(&(address, slot)) {
+            return Ok(*value);
+        }
+
+        let value = self
+            .provider
+            .get_storage_at(address, slot, Some(self.block_id()))
+            .await
+            .map_err(provider_error)?;
+        self.cache
+            .storage
+            .write()
+            .unwrap()
+            .insert((address, slot), value);
+        Ok(value)
+    }
+
+    /// Returns the hash of a block, fetching it on a cache miss.
+    pub async fn block_hash_at(&self, number: u64) -> Result<H256, ForkDbError> {
+        if let Some(hash) = self.cache.block_hashes.read().unwrap().get(&number) {
+            return Ok(*hash);
+        }
+
+        let hash = self
+            .provider
+            .get_block(number)
+            .await
+            .map_err(provider_error)?
+            .and_then(|block| block.hash)
+            .ok_or(ForkDbError::BlockNotFound(number))?;
+        self.cache
+            .block_hashes
+            .write()
+            .unwrap()
+            .insert(number, hash);
+        Ok(hash)
+    }
+
+    fn block_id(&self) -> BlockId {
+        BlockId::from(self.block_number)
+    }
+
+    /// Runs a provider call from a synchronous revm callback.
+    ///
+    /// Returns Err(ForkDbError::UnsupportedRuntime) outside a tokio runtime and on a current-thread runtime,
+    /// where blocking the only worker would deadlock or panic.
+    fn block_on<F: Future>(&self, future: F) -> Result<F::Output, ForkDbError> {
+        let handle = Handle::try_current().map_err(|_| ForkDbError::UnsupportedRuntime)?;
+        if handle.runtime_flavor() == RuntimeFlavor::CurrentThread {
+            return Err(ForkDbError::UnsupportedRuntime);
+        }
+        Ok(tokio::task::block_in_place(|| handle.block_on(future)))
+    }
+}
+
+impl<M: Middleware> DatabaseRef for ForkDB<M> {
+    type Error = ForkDbError;
+
+    fn basic(&self, address: rAddress) -> Result<Option<AccountInfo>, Self::Error> {
+        let account = self.block_on(self.account(from_revm_address(address)))??;
+        Ok(Some(AccountInfo {
+            balance: rU256::from_limbs(account.balance.0),
+            nonce: account.nonce,
+            code_hash: B256::from(account.code_hash.0),
+            code: Some(Bytecode::new_raw(account.code.to_vec().into())),
+        }))
+    }
+
+    fn code_by_hash(&self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        // revm loads code with the account, so this is only reached for code the cache already holds.
+        let code_hash = H256::from(code_hash.0);
+        self.cache
+            .accounts
+            .read()
+            .unwrap()
+            .values()
+            .find(|account| account.code_hash == code_hash)
+            .map(|account| Bytecode::new_raw(account.code.to_vec().into()))
+            .ok_or(ForkDbError::MissingCode(code_hash))
+    }
+
+    fn storage(&self, address: rAddress, index: rU256) -> Result<rU256, Self::Error> {
+        let slot = H256::from(index.to_be_bytes::<32>());
+        let value = self.block_on(self.storage_at(from_revm_address(address), slot))??;
+        Ok(rU256::from_be_bytes(value.0))
+    }
+
+    fn block_hash(&self, number: rU256) -> Result<B256, Self::Error> {
+        let number = u64::try_from(number).unwrap_or(u64::MAX);
+        let hash = self.block_on(self.block_hash_at(number))??;
+        Ok(B256::from(hash.0))
+    }
+}
+
+fn provider_error<E: std::fmt::Display>(err: E) -> ForkDbError {
+    ForkDbError::ProviderError(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{
+        providers::{MockProvider, Provider},
+        types::{Block, U64},
+    };
+
+    fn mocked(block_number: u64) -> (ForkDB<Provider<MockProvider>>, MockProvider) {
+        let (provider, mock) = Provider::mocked();
+        (ForkDB::new(Arc::new(provider), block_number), mock)
+    }
+
+    // MockProvider answers from the back of its queue, so responses are pushed in reverse request order.
+    fn push_account(mock: &MockProvider, balance: u64, nonce: u64, code: &[u8]) {
+        mock.push::<Bytes, _>(Bytes::from(code.to_vec())).unwrap();
+        mock.push::<U256, _>(U256::from(nonce)).unwrap();
+        mock.push::<U256, _>(U256::from(balance)).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_account_is_fetched_at_pinned_block_once() {
+        let (db, mock) = mocked(100);
+        let address = Address::repeat_byte(1);
+        push_account(&mock, 5, 2, &[0x60, 0x00]);
+
+        let account = db.account(address).await.unwrap();
+        assert_eq!(account.balance, U256::from(5));
+        assert_eq!(account.nonce, 2);
+        assert_eq!(account.code_hash, H256::from(keccak256([0x60, 0x00])));
+        mock.assert_request("eth_getBalance", (address, "0x64"))
+            .unwrap();
+
+        // The second read and reads through clones are served from the cache.
+        let info = db
+            .clone()
+            .basic(rAddress::from(address.0))
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.nonce, 2);
+        assert!(db.code_by_hash(info.code_hash).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_current_thread_runtime_is_an_error() {
+        let (db, _mock) = mocked(100);
+
+        assert!(matches!(
+            db.basic(rAddress::from(Address::repeat_byte(1).0)),
+            Err(ForkDbError::UnsupportedRuntime)
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_storage_and_block_hash_are_cached() {
+        let (db, mock) = mocked(100);
+        let address = Address::repeat_byte(1);
+        mock.push::<H256, _>(H256::from_low_u64_be(7)).unwrap();
+
+        let value = db
+            .storage(rAddress::from(address.0), rU256::from(3))
+            .unwrap();
+        assert_eq!(value, rU256::from(7));
+        assert_eq!(
+            db.storage(rAddress::from(address.0), rU256::from(3))
+                .unwrap(),
+            value
+        );
+        mock.assert_request("eth_getStorageAt", (address, "0x3", "0x64"))
+            .unwrap();
+
+        let block = Block::<H256> {
+            hash: Some(H256::repeat_byte(9)),
+            number: Some(U64::from(99)),
+            ..Default::default()
+        };
+        mock.push::<Block<H256>, _>(block).unwrap();
+        assert_eq!(db.block_hash(rU256::from(99)).unwrap(), B256::from([9; 32]));
+        assert_eq!(db.block_hash(rU256::from(99)).unwrap(), B256::from([9; 32]));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_new_block_invalidates_cache() {
+        let (mut db, mock) = mocked(100);
+        let address = Address::repeat_byte(1);
+        push_account(&mock, 5, 2, &[]);
+        db.account(address).await.unwrap();
+        let pinned = db.clone();
+
+        assert!(!db.on_new_block(100));
+        assert!(db.on_new_block(101));
+
+        push_account(&mock, 8, 3, &[]);
+        assert_eq!(db.account(address).await.unwrap().balance, U256::from(8));
+        assert_eq!(
+            pinned.account(address).await.unwrap().balance,
+            U256::from(5)
+        );
+        assert_eq!(pinned.block_number(), 100);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_provider_error() {
+        let (db, _mock) = mocked(100);
+        assert!(matches!(
+            db.account(Address::repeat_byte(1)).await,
+            Err(ForkDbError::ProviderError(_))
+        ));
+    }
+}
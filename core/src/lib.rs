@@ -10,8 +10,12 @@
 #![forbid(unsafe_code)]
 
 pub mod blockchain;
+pub mod breaker_signals;
+pub mod call_tracer;
 pub mod circuit_breaker;
+pub mod fork_db;
 pub mod mempool;
+pub mod mempool_capture;
 pub mod middleware;
 pub mod risk;
 pub mod security;
@@ -1,54 +1,1005 @@
 // This module monitors the mempool for pending transactions.
 // It provides functionality to receive and process transactions from the mempool.
 
+use std::{
+    collections::{hash_map::Entry, HashMap, VecDeque},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
 use ethers::{
-    providers::{Provider, Ws, Middleware}, // Import Middleware trait for mempool operations.
-    types::Transaction,
+    providers::{Middleware, Provider, SubscriptionStream, Ws}, // Import Middleware trait for mempool operations.
+    types::{Transaction, H256},
+};
+use futures_util::{
+    future::{self, join_all},
+    stream::StreamExt,
 };
+use rand::Rng;
+use serde::Deserialize;
 use thiserror::Error;
-use tokio::sync::mpsc;
-use futures_util::stream::StreamExt; 
+use tokio::sync::{broadcast, mpsc};
 
-// Error types for mempool operations.
+use crate::mempool_capture::{self, CaptureRecord, CaptureWriter, ReplaySpeed};
+
+/// Status changes kept for a slow status receiver before the oldest are dropped.
+const STATUS_CAPACITY: usize = 16;
+/// Time without a pending transaction after which the connection is assumed dead.
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+/// Time a transaction hash is remembered, long enough to outlast a transaction's stay in the mempool.
+pub const DEFAULT_DEDUP_TTL: Duration = Duration::from_secs(300);
+
+/// Error types for mempool operations.
 #[derive(Error, Debug)]
 pub enum MempoolError {
+    /// No WebSocket connection could be made to an endpoint.
     #[error("WebSocket connection failed: {0}")]
     ConnectionFailure(String),
+    /// A pending transaction could not be processed.
     #[error("Transaction processing error")]
     ProcessingError,
+    /// Reading or writing the capture file failed.
+    #[error("Capture file error: {0}")]
+    CaptureError(#[from] std::io::Error),
+    /// A line of the capture file is not a capture record.
+    #[error("Invalid capture record on line {line}: {reason}")]
+    InvalidCapture {
+        /// Line number, counted from 1.
+        line: usize,
+        /// Why the line could not be parsed.
+        reason: String,
+    },
+}
+
+/// Connection state of the pending transaction subscription.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionStatus {
+    /// Subscribed to pending transactions.
+    Connected,
+    /// The connection or the subscription was lost.
+    Disconnected {
+        /// Why the connection or the subscription ended.
+        reason: String,
+    },
+    /// Waiting `delay` before reconnection attempt `attempt`, counted from 1 since the last connection.
+    Reconnecting {
+        /// Reconnection attempt about to be made.
+        attempt: u32,
+        /// Time waited before the attempt.
+        delay: Duration,
+    },
+}
+
+/// Exponential backoff between reconnection attempts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Backoff {
+    /// Delay before the first attempt.
+    pub initial: Duration,
+    /// Upper bound of the delay.
+    pub max: Duration,
+    /// Factor the delay grows by with each failed attempt.
+    pub multiplier: f64,
+    /// Share of the delay, between 0 and 1, that is removed at random.
+    pub jitter: f64,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.2,
+        }
+    }
+}
+
+impl Backoff {
+    /// Returns the delay before reconnection attempt `attempt`, counted from 1.
+    ///
+    /// Jitter keeps watchers that lost the same node from reconnecting at the same time.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let delay = (self.initial.as_secs_f64() * self.multiplier.powi(exponent))
+            .min(self.max.as_secs_f64());
+        let jitter = rand::thread_rng().gen_range(0.0..=self.jitter.clamp(0.0, 1.0));
+        Duration::from_secs_f64(delay * (1.0 - jitter))
+    }
+}
+
+/// Where and when a transaction was first announced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sighting {
+    /// Index of the endpoint that announced it.
+    pub source: usize,
+    /// Time it was announced at.
+    pub at: Instant,
+}
+
+/// Set of recently seen transaction hashes, each forgotten `ttl` after it was first seen.
+#[derive(Debug)]
+pub struct SeenSet {
+    ttl: Duration,
+    hashes: HashMap<H256, Sighting>,
+    expiries: VecDeque<(Instant, H256)>,
+}
+
+impl SeenSet {
+    /// Creates an empty set whose hashes are forgotten `ttl` after they were first seen.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            hashes: HashMap::new(),
+            expiries: VecDeque::new(),
+        }
+    }
+
+    /// Records a hash, returning true if it was not seen within the TTL.
+    pub fn insert(&mut self, hash: H256) -> bool {
+        self.observe(hash, 0, Instant::now()).is_none()
+    }
+
+    /// Records that `source` announced a hash at `now`, returning the first sighting if it was seen within the TTL.
+    pub fn observe(&mut self, hash: H256, source: usize, now: Instant) -> Option<Sighting> {
+        while let Some((expiry, expired)) = self.expiries.front() {
+            if *expiry > now {
+                break;
+            }
+            self.hashes.remove(expired);
+            self.expiries.pop_front();
+        }
+        if let Some(first) = self.hashes.get(&hash) {
+            return Some(*first);
+        }
+        self.hashes.insert(hash, Sighting { source, at: now });
+        self.expiries.push_back((now + self.ttl, hash));
+        None
+    }
+
+    /// Returns the first sighting of the hash, None if it was not seen within the TTL.
+    pub fn first_seen(&self, hash: &H256) -> Option<Sighting> {
+        self.hashes.get(hash).copied()
+    }
+
+    /// Returns true if the hash was seen within the TTL.
+    pub fn contains(&self, hash: &H256) -> bool {
+        self.hashes.contains_key(hash)
+    }
+
+    /// Returns the number of hashes seen within the TTL.
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    /// Returns true if no hash was seen within the TTL.
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+}
+
+/// Announcement counts of an endpoint, to tell slow nodes from fast ones.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceStats {
+    /// WebSocket URL of the endpoint.
+    pub endpoint: String,
+    /// Transactions the endpoint announced, including ones another endpoint announced first.
+    pub announced: u64,
+    /// Transactions the endpoint announced before every other endpoint.
+    pub first: u64,
+    /// Sum of the delays behind the first endpoint of the transactions announced later.
+    pub total_delay: Duration,
+    /// Largest delay behind the first endpoint.
+    pub max_delay: Duration,
+}
+
+impl SourceStats {
+    /// Returns the share of the endpoint's transactions it announced first, None if it announced none.
+    pub fn first_ratio(&self) -> Option<f64> {
+        (self.announced > 0).then(|| self.first as f64 / self.announced as f64)
+    }
+
+    /// Returns the mean delay of the transactions announced after another endpoint, None if there were none.
+    pub fn mean_delay(&self) -> Option<Duration> {
+        let late = u32::try_from(self.announced - self.first).unwrap_or(u32::MAX);
+        (late > 0).then(|| self.total_delay / late)
+    }
+}
+
+/// Deduplicates the announcements of every endpoint and records which endpoint announced each first.
+///
+/// A hash is fetched by one endpoint at a time and only counts as forwarded once fetched, so the next endpoint
+/// announcing a hash whose fetch failed tries again.
+#[derive(Debug)]
+struct Aggregator {
+    seen: SeenSet,
+    forwarded: SeenSet,
+    /// Hashes being fetched, with the endpoint fetching each.
+    hydrating: HashMap<H256, usize>,
+    stats: Vec<SourceStats>,
 }
 
-// MempoolWatcher struct watches for pending transactions in the mempool.
+impl Aggregator {
+    fn new(endpoints: &[String], dedup_ttl: Duration) -> Self {
+        Self {
+            seen: SeenSet::new(dedup_ttl),
+            forwarded: SeenSet::new(dedup_ttl),
+            hydrating: HashMap::new(),
+            stats: endpoints
+                .iter()
+                .map(|endpoint| SourceStats {
+                    endpoint: endpoint.clone(),
+                    ..Default::default()
+                })
+                .collect(),
+        }
+    }
+
+    /// Records that `source` announced a hash, returning true if no endpoint announced it before.
+    fn observe(&mut self, hash: H256, source: usize, now: Instant) -> bool {
+        match self.seen.observe(hash, source, now) {
+            None => {
+                let stats = &mut self.stats[source];
+                stats.announced += 1;
+                stats.first += 1;
+                true
+            }
+            // Announced again by the same endpoint, after a reconnect.
+            Some(first) if first.source == source => false,
+            Some(first) => {
+                let delay = now.saturating_duration_since(first.at);
+                let stats = &mut self.stats[source];
+                stats.announced += 1;
+                stats.total_delay += delay;
+                stats.max_delay = stats.max_delay.max(delay);
+                false
+            }
+        }
+    }
+
+    /// Returns true if `source` is to fetch a hash, which is neither forwarded nor being fetched by an endpoint.
+    fn claim(&mut self, hash: H256, source: usize) -> bool {
+        if self.forwarded.contains(&hash) {
+            return false;
+        }
+        match self.hydrating.entry(hash) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(source);
+                true
+            }
+        }
+    }
+
+    /// Ends the fetch of a claimed hash, which any endpoint may claim again unless it was `fetched`.
+    fn release(&mut self, hash: H256, fetched: bool, now: Instant) {
+        self.hydrating.remove(&hash);
+        if fetched {
+            self.forwarded.observe(hash, 0, now);
+        }
+    }
+
+    /// Releases the hashes `source` was still fetching when its subscription ended.
+    fn abandon(&mut self, source: usize) {
+        self.hydrating.retain(|_, fetching| *fetching != source);
+    }
+}
+
+/// Item of a `newPendingTransactions` subscription, a full transaction if the node sends them.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum PendingItem {
+    Hash(H256),
+    Full(Box<Transaction>),
+}
+
+impl PendingItem {
+    fn hash(&self) -> H256 {
+        match self {
+            PendingItem::Hash(hash) => *hash,
+            PendingItem::Full(tx) => tx.hash,
+        }
+    }
+}
+
+/// Settings of a `MempoolWatcher`.
+#[derive(Debug, Clone)]
+pub struct MempoolConfig {
+    /// WebSocket endpoints whose pending transactions are merged.
+    pub endpoints: Vec<String>,
+    /// Delays between reconnection attempts of an endpoint.
+    pub backoff: Backoff,
+    /// Pending transactions buffered for the receiver.
+    pub channel_capacity: usize,
+    /// Time without an announcement after which an endpoint is reconnected, None to wait forever.
+    pub idle_timeout: Option<Duration>,
+    /// Subscribes to full pending transactions, falling back to hashes if the node does not support it.
+    pub full_transactions: bool,
+    /// Hashes fetched from a node at the same time when it only sends hashes.
+    pub hydration_concurrency: usize,
+    /// Time a seen transaction is ignored for.
+    pub dedup_ttl: Duration,
+    /// File the forwarded transactions are appended to, see `mempool_capture`.
+    pub capture_path: Option<PathBuf>,
+}
+
+impl MempoolConfig {
+    /// Creates the default settings for a single endpoint.
+    pub fn new(ws_url: impl Into<String>) -> Self {
+        Self {
+            endpoints: vec![ws_url.into()],
+            backoff: Backoff::default(),
+            channel_capacity: 100,
+            idle_timeout: Some(DEFAULT_IDLE_TIMEOUT),
+            full_transactions: true,
+            hydration_concurrency: 16,
+            dedup_ttl: DEFAULT_DEDUP_TTL,
+            capture_path: None,
+        }
+    }
+
+    /// Merges the pending transactions of another endpoint.
+    pub fn with_endpoint(mut self, ws_url: impl Into<String>) -> Self {
+        self.endpoints.push(ws_url.into());
+        self
+    }
+
+    /// Sets the delays between reconnection attempts.
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Sets the number of pending transactions buffered for the receiver.
+    pub fn with_channel_capacity(mut self, channel_capacity: usize) -> Self {
+        self.channel_capacity = channel_capacity;
+        self
+    }
+
+    /// Sets the time without an announcement after which an endpoint is reconnected.
+    pub fn with_idle_timeout(mut self, idle_timeout: Option<Duration>) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// Sets whether full pending transactions are subscribed to instead of hashes.
+    pub fn with_full_transactions(mut self, full_transactions: bool) -> Self {
+        self.full_transactions = full_transactions;
+        self
+    }
+
+    /// Sets the number of hashes fetched from a node at the same time.
+    pub fn with_hydration_concurrency(mut self, hydration_concurrency: usize) -> Self {
+        self.hydration_concurrency = hydration_concurrency;
+        self
+    }
+
+    /// Sets the time a seen transaction is ignored for.
+    pub fn with_dedup_ttl(mut self, dedup_ttl: Duration) -> Self {
+        self.dedup_ttl = dedup_ttl;
+        self
+    }
+
+    /// Records the forwarded transactions with their receive time and endpoint, for `MempoolWatcher::replay`.
+    pub fn with_capture(mut self, path: impl Into<PathBuf>) -> Self {
+        self.capture_path = Some(path.into());
+        self
+    }
+}
+
+/// Connection state change of an endpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceStatus {
+    /// WebSocket URL of the endpoint.
+    pub endpoint: String,
+    /// New connection state of the endpoint.
+    pub status: ConnectionStatus,
+}
+
+/// MempoolWatcher struct watches for pending transactions in the mempool.
 pub struct MempoolWatcher {
-    pub tx_receiver: mpsc::Receiver<Transaction>, 
+    /// Deduplicated pending transactions of every endpoint.
+    pub tx_receiver: mpsc::Receiver<Transaction>,
+    /// Connection state changes of every endpoint, starting with their first `Connected`.
+    pub status_receiver: broadcast::Receiver<SourceStatus>,
+    status_sender: broadcast::Sender<SourceStatus>,
+    aggregator: Arc<Mutex<Aggregator>>,
 }
 
 impl MempoolWatcher {
-    // Initializes a new MempoolWatcher instance with the provided WebSocket URL.
+    /// Initializes a new MempoolWatcher instance with the provided WebSocket URL.
     pub async fn new(ws_url: &str) -> Result<Self, MempoolError> {
-        // Create a provider for connecting to the mempool.
-        let provider = Provider::<Ws>::connect(ws_url)
-            .await
-            .map_err(|e| MempoolError::ConnectionFailure(e.to_string()))?;
+        Self::with_config(MempoolConfig::new(ws_url)).await
+    }
 
-        // Create a channel for receiving transactions.
-        let (tx_sender, tx_receiver) = mpsc::channel(100);
+    /// Connects to the endpoints and starts watching their pending transactions as one deduplicated stream.
+    ///
+    /// Fails if no endpoint can be connected to. Endpoints that fail, then or later, are retried with backoff
+    /// until the transaction receiver is dropped.
+    pub async fn with_config(config: MempoolConfig) -> Result<Self, MempoolError> {
+        let connections = join_all(config.endpoints.iter().map(|endpoint| connect(endpoint))).await;
+        if connections.iter().all(Result::is_err) {
+            return Err(connections
+                .into_iter()
+                .find_map(Result::err)
+                .unwrap_or_else(|| {
+                    MempoolError::ConnectionFailure("no endpoint configured".to_string())
+                }));
+        }
 
-        // Spawn a task to monitor pending transactions.
-        tokio::spawn(async move {
-            let mut stream = provider.subscribe_pending_txs().await.unwrap();
-            while let Some(tx_hash) = stream.next().await {
-                if let Ok(Some(tx)) = provider.get_transaction(tx_hash).await {
-                    let _ = tx_sender.send(tx).await; 
+        // Create channels for receiving transactions and connection state changes.
+        let (tx_sender, tx_receiver) = mpsc::channel(config.channel_capacity);
+        let (status_sender, status_receiver) = broadcast::channel(STATUS_CAPACITY);
+        let aggregator = Arc::new(Mutex::new(Aggregator::new(
+            &config.endpoints,
+            config.dedup_ttl,
+        )));
+        let capture = match &config.capture_path {
+            Some(path) => Some(CaptureWriter::create(path).await?),
+            None => None,
+        };
+
+        let config = Arc::new(config);
+        for (source, connection) in connections.into_iter().enumerate() {
+            tokio::spawn(watch(
+                Source {
+                    index: source,
+                    config: config.clone(),
+                    tx_sender: tx_sender.clone(),
+                    status: status_sender.clone(),
+                    aggregator: aggregator.clone(),
+                    capture: capture.clone(),
+                },
+                connection.ok(),
+            ));
+        }
+
+        Ok(Self {
+            tx_receiver,
+            status_receiver,
+            status_sender,
+            aggregator,
+        })
+    }
+
+    /// Replays a capture file through a watcher indistinguishable from a live one to the transaction receiver.
+    ///
+    /// The replay never reports a connection status and has no endpoint statistics.
+    pub async fn replay(path: impl AsRef<Path>, speed: ReplaySpeed) -> Result<Self, MempoolError> {
+        let tx_receiver = mempool_capture::replay(path, speed, 100).await?;
+        let (status_sender, status_receiver) = broadcast::channel(STATUS_CAPACITY);
+
+        Ok(Self {
+            tx_receiver,
+            status_receiver,
+            status_sender,
+            aggregator: Arc::new(Mutex::new(Aggregator::new(&[], DEFAULT_DEDUP_TTL))),
+        })
+    }
+
+    /// Returns a new receiver of the connection state changes from now on.
+    pub fn subscribe_status(&self) -> broadcast::Receiver<SourceStatus> {
+        self.status_sender.subscribe()
+    }
+
+    /// Returns the announcement counts of each endpoint, in configuration order.
+    pub fn source_stats(&self) -> Vec<SourceStats> {
+        self.aggregator.lock().unwrap().stats.clone()
+    }
+}
+
+async fn connect(ws_url: &str) -> Result<Provider<Ws>, MempoolError> {
+    Provider::<Ws>::connect(ws_url)
+        .await
+        .map_err(|e| MempoolError::ConnectionFailure(e.to_string()))
+}
+
+/// An endpoint and the state it shares with the other endpoints.
+struct Source {
+    index: usize,
+    config: Arc<MempoolConfig>,
+    tx_sender: mpsc::Sender<Transaction>,
+    status: broadcast::Sender<SourceStatus>,
+    aggregator: Arc<Mutex<Aggregator>>,
+    capture: Option<CaptureWriter>,
+}
+
+impl Source {
+    fn endpoint(&self) -> &str {
+        &self.config.endpoints[self.index]
+    }
+
+    fn send_status(&self, status: ConnectionStatus) {
+        let _ = self.status.send(SourceStatus {
+            endpoint: self.endpoint().to_string(),
+            status,
+        });
+    }
+}
+
+/// Forwards pending transactions of an endpoint, reconnecting whenever the connection is lost, until the
+/// receiver is dropped.
+async fn watch(source: Source, provider: Option<Provider<Ws>>) {
+    let mut provider = provider;
+    let mut attempt = 0;
+    loop {
+        let connected = match provider.take() {
+            Some(provider) => Ok(provider),
+            None => connect(source.endpoint()).await,
+        };
+        match connected {
+            Ok(provider) => match stream_pending(&provider, &source).await {
+                Ok(reason) => {
+                    if source.tx_sender.is_closed() {
+                        return;
+                    }
+                    attempt = 0;
+                    log::warn!(
+                        "Mempool subscription to {} lost: {reason}",
+                        source.endpoint()
+                    );
+                    source.send_status(ConnectionStatus::Disconnected { reason });
+                }
+                Err(reason) => {
+                    log::warn!(
+                        "Mempool subscription to {} failed: {reason}",
+                        source.endpoint()
+                    )
                 }
+            },
+            Err(err) => log::warn!("{err}"),
+        }
+        if source.tx_sender.is_closed() {
+            return;
+        }
+
+        attempt += 1;
+        let delay = source.config.backoff.delay(attempt);
+        source.send_status(ConnectionStatus::Reconnecting { attempt, delay });
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Subscribes to pending transactions and forwards the ones no endpoint forwarded before, until the
+/// subscription ends.
+///
+/// Returns why a live subscription ended, or an error if subscribing failed.
+async fn stream_pending(provider: &Provider<Ws>, source: &Source) -> Result<String, String> {
+    let config = &source.config;
+    let stream = subscribe_pending(provider, config.full_transactions).await?;
+    source.send_status(ConnectionStatus::Connected);
+
+    // Every announcement counts as activity, including the ones another endpoint made first.
+    let last_announcement = Mutex::new(Instant::now());
+    // Hashes are fetched concurrently, so transactions may be forwarded out of announcement order.
+    let mut pending = stream
+        .filter(|item| {
+            let now = Instant::now();
+            *last_announcement.lock().unwrap() = now;
+            let mut aggregator = source.aggregator.lock().unwrap();
+            aggregator.observe(item.hash(), source.index, now);
+            future::ready(aggregator.claim(item.hash(), source.index))
+        })
+        .map(|item| async move { (item.hash(), hydrate(provider, item).await) })
+        .buffer_unordered(config.hydration_concurrency.max(1));
+    let reason = loop {
+        let next = match config.idle_timeout {
+            Some(idle_timeout) => tokio::select! {
+                next = pending.next() => next,
+                _ = idle(&last_announcement, idle_timeout) => {
+                    break format!("no pending transaction for {idle_timeout:?}");
+                }
+            },
+            None => pending.next().await,
+        };
+        let Some((hash, tx)) = next else {
+            break "subscription stream ended".to_string();
+        };
+        // Dropped transactions are not found, and mined ones have a block number. A transaction that could not
+        // be fetched is left to the next endpoint announcing it.
+        source
+            .aggregator
+            .lock()
+            .unwrap()
+            .release(hash, tx.is_some(), Instant::now());
+        let Some(tx) = tx.filter(|tx| tx.block_number.is_none()) else {
+            continue;
+        };
+        if let Some(capture) = &source.capture {
+            capture.record(CaptureRecord::now(source.endpoint(), tx.clone()));
+        }
+        if source.tx_sender.send(tx).await.is_err() {
+            break "transaction receiver dropped".to_string();
+        }
+    };
+    source.aggregator.lock().unwrap().abandon(source.index);
+    Ok(reason)
+}
+
+/// Completes once `idle_timeout` has passed since the last announcement.
+async fn idle(last_announcement: &Mutex<Instant>, idle_timeout: Duration) {
+    loop {
+        let deadline = *last_announcement.lock().unwrap() + idle_timeout;
+        if deadline <= Instant::now() {
+            return;
+        }
+        tokio::time::sleep_until(deadline.into()).await;
+    }
+}
+
+async fn subscribe_pending(
+    provider: &Provider<Ws>,
+    full_transactions: bool,
+) -> Result<SubscriptionStream<'_, Ws, PendingItem>, String> {
+    if full_transactions {
+        match provider.subscribe(("newPendingTransactions", true)).await {
+            Ok(stream) => return Ok(stream),
+            Err(err) => {
+                log::debug!("Full pending transactions not supported, subscribing to hashes: {err}")
             }
-        });
+        }
+    }
+    provider
+        .subscribe(["newPendingTransactions"])
+        .await
+        .map_err(|e| e.to_string())
+}
 
-        Ok(Self { tx_receiver })
+async fn hydrate(provider: &Provider<Ws>, item: PendingItem) -> Option<Transaction> {
+    match item {
+        PendingItem::Full(tx) => Some(*tx),
+        PendingItem::Hash(hash) => provider.get_transaction(hash).await.ok().flatten(),
     }
 }
 
-// Function to initialize the mempool module.
+/// Function to initialize the mempool module.
 pub fn init_mempool() {
     println!("Mempool module initialized");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::U64;
+    use futures_util::SinkExt;
+    use serde_json::{json, Value};
+    use std::collections::HashSet;
+    use tokio::{net::TcpListener, task::JoinSet};
+    use tokio_tungstenite::tungstenite::Message;
+
+    fn pending_tx(byte: u8) -> Transaction {
+        Transaction {
+            hash: H256::repeat_byte(byte),
+            ..Default::default()
+        }
+    }
+
+    // A node that announces `announced` to each subscriber `delay` after it subscribes, as full transactions if
+    // `full` is set and the subscriber asks for them. Aborting the task drops every connection.
+    async fn spawn_node(
+        listener: TcpListener,
+        full: bool,
+        announced: Vec<Transaction>,
+        delay: Duration,
+    ) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut connections = JoinSet::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                let announced = announced.clone();
+                connections.spawn(async move {
+                    let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+                    while let Some(Ok(Message::Text(text))) = ws.next().await {
+                        let request: Value = serde_json::from_str(&text).unwrap();
+                        let id = request["id"].clone();
+                        let mut replies = Vec::new();
+                        let mut notifications = Vec::new();
+                        match request["method"].as_str().unwrap() {
+                            "eth_subscribe" => {
+                                let wants_full = request["params"][1] == json!(true);
+                                if wants_full && !full {
+                                    replies.push(json!({
+                                        "jsonrpc": "2.0",
+                                        "id": id,
+                                        "error": { "code": -32602, "message": "invalid params" }
+                                    }));
+                                } else {
+                                    replies.push(
+                                        json!({ "jsonrpc": "2.0", "id": id, "result": "0x1" }),
+                                    );
+                                    for tx in &announced {
+                                        let result = if wants_full {
+                                            json!(tx)
+                                        } else {
+                                            json!(tx.hash)
+                                        };
+                                        notifications.push(json!({
+                                            "jsonrpc": "2.0",
+                                            "method": "eth_subscription",
+                                            "params": { "subscription": "0x1", "result": result }
+                                        }));
+                                    }
+                                }
+                            }
+                            "eth_getTransactionByHash" => {
+                                let hash: H256 =
+                                    serde_json::from_value(request["params"][0].clone()).unwrap();
+                                let tx = announced.iter().find(|tx| tx.hash == hash);
+                                replies.push(json!({ "jsonrpc": "2.0", "id": id, "result": tx }));
+                            }
+                            _ => {
+                                replies.push(json!({ "jsonrpc": "2.0", "id": id, "result": null }))
+                            }
+                        }
+                        for reply in replies {
+                            ws.send(Message::Text(reply.to_string())).await.unwrap();
+                        }
+                        if !notifications.is_empty() {
+                            tokio::time::sleep(delay).await;
+                        }
+                        for notification in notifications {
+                            ws.send(Message::Text(notification.to_string()))
+                                .await
+                                .unwrap();
+                        }
+                    }
+                });
+            }
+        })
+    }
+
+    async fn next_status(watcher: &mut MempoolWatcher) -> ConnectionStatus {
+        tokio::time::timeout(Duration::from_secs(5), watcher.status_receiver.recv())
+            .await
+            .unwrap()
+            .unwrap()
+            .status
+    }
+
+    // Receives the transactions forwarded until the watcher has been quiet for a while.
+    async fn received_hashes(watcher: &mut MempoolWatcher) -> HashSet<H256> {
+        let mut hashes = HashSet::new();
+        while let Ok(Some(tx)) =
+            tokio::time::timeout(Duration::from_millis(200), watcher.tx_receiver.recv()).await
+        {
+            assert!(hashes.insert(tx.hash), "{:?} forwarded twice", tx.hash);
+        }
+        hashes
+    }
+
+    #[test]
+    fn test_backoff_grows_to_max() {
+        let backoff = Backoff {
+            jitter: 0.0,
+            ..Backoff::default()
+        };
+        assert_eq!(backoff.delay(1), Duration::from_millis(100));
+        assert_eq!(backoff.delay(3), Duration::from_millis(400));
+        assert_eq!(backoff.delay(100), Duration::from_secs(30));
+
+        let jittered = Backoff::default().delay(2);
+        assert!(jittered > Duration::from_millis(159) && jittered <= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_seen_set_forgets_after_ttl() {
+        let mut seen = SeenSet::new(Duration::from_secs(10));
+        let start = Instant::now();
+        assert_eq!(seen.observe(H256::repeat_byte(1), 0, start), None);
+        assert_eq!(
+            seen.observe(H256::repeat_byte(1), 1, start + Duration::from_secs(5)),
+            Some(Sighting {
+                source: 0,
+                at: start
+            })
+        );
+        assert_eq!(
+            seen.observe(H256::repeat_byte(2), 1, start + Duration::from_secs(5)),
+            None
+        );
+
+        assert_eq!(
+            seen.observe(H256::repeat_byte(1), 1, start + Duration::from_secs(10)),
+            None
+        );
+        assert_eq!(seen.len(), 2);
+        assert_eq!(seen.first_seen(&H256::repeat_byte(1)).unwrap().source, 1);
+        assert!(seen.contains(&H256::repeat_byte(2)));
+    }
+
+    #[test]
+    fn test_aggregator_records_first_source_and_delays() {
+        let endpoints = ["ws://a".to_string(), "ws://b".to_string()];
+        let mut aggregator = Aggregator::new(&endpoints, DEFAULT_DEDUP_TTL);
+        let start = Instant::now();
+        assert!(aggregator.observe(H256::repeat_byte(1), 0, start));
+        assert!(!aggregator.observe(H256::repeat_byte(1), 1, start + Duration::from_millis(30)));
+        assert!(aggregator.observe(H256::repeat_byte(2), 1, start + Duration::from_millis(40)));
+        assert!(!aggregator.observe(H256::repeat_byte(2), 0, start + Duration::from_millis(50)));
+        assert!(aggregator.observe(H256::repeat_byte(3), 0, start));
+        assert!(!aggregator.observe(H256::repeat_byte(1), 0, start + Duration::from_millis(60)));
+
+        let [a, b] = <[SourceStats; 2]>::try_from(aggregator.stats).unwrap();
+        assert_eq!(
+            (a.announced, a.first, a.max_delay),
+            (3, 2, Duration::from_millis(10))
+        );
+        assert_eq!((b.announced, b.first), (2, 1));
+        assert_eq!(b.mean_delay(), Some(Duration::from_millis(30)));
+        assert_eq!(b.first_ratio(), Some(0.5));
+    }
+
+    #[test]
+    fn test_failed_hydrations_are_retried_by_other_endpoints() {
+        let endpoints = ["ws://a".to_string(), "ws://b".to_string()];
+        let mut aggregator = Aggregator::new(&endpoints, DEFAULT_DEDUP_TTL);
+        let start = Instant::now();
+        let (failed, fetched, abandoned) = (
+            H256::repeat_byte(1),
+            H256::repeat_byte(2),
+            H256::repeat_byte(3),
+        );
+
+        assert!(aggregator.claim(failed, 0));
+        assert!(!aggregator.claim(failed, 1));
+        aggregator.release(failed, false, start);
+        assert!(aggregator.claim(failed, 1));
+
+        assert!(aggregator.claim(fetched, 0));
+        aggregator.release(fetched, true, start);
+        assert!(!aggregator.claim(fetched, 1));
+
+        assert!(aggregator.claim(abandoned, 0));
+        aggregator.abandon(0);
+        assert!(aggregator.claim(abandoned, 1));
+        assert!(!aggregator.claim(failed, 0));
+    }
+
+    #[tokio::test]
+    async fn test_full_transactions_are_deduplicated() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let mined = Transaction {
+            block_number: Some(U64::from(1)),
+            ..pending_tx(3)
+        };
+        let _node = spawn_node(
+            listener,
+            true,
+            vec![pending_tx(1), pending_tx(2), pending_tx(1), mined],
+            Duration::ZERO,
+        )
+        .await;
+
+        let mut watcher = MempoolWatcher::new(&format!("ws://{address}"))
+            .await
+            .unwrap();
+        assert_eq!(
+            received_hashes(&mut watcher).await,
+            HashSet::from([H256::repeat_byte(1), H256::repeat_byte(2)])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hashes_are_hydrated_without_full_transaction_support() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let announced = (1..=20).map(pending_tx).collect::<Vec<_>>();
+        let _node = spawn_node(listener, false, announced.clone(), Duration::ZERO).await;
+
+        let config = MempoolConfig::new(format!("ws://{address}")).with_hydration_concurrency(4);
+        let mut watcher = MempoolWatcher::with_config(config).await.unwrap();
+        assert_eq!(next_status(&mut watcher).await, ConnectionStatus::Connected);
+        assert_eq!(
+            received_hashes(&mut watcher).await,
+            announced.iter().map(|tx| tx.hash).collect()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reconnects_after_node_restart() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let node = spawn_node(listener, true, vec![pending_tx(1)], Duration::ZERO).await;
+
+        let config = MempoolConfig::new(format!("ws://{address}")).with_backoff(Backoff {
+            initial: Duration::from_millis(20),
+            max: Duration::from_millis(100),
+            ..Backoff::default()
+        });
+        let mut watcher = MempoolWatcher::with_config(config).await.unwrap();
+        assert_eq!(next_status(&mut watcher).await, ConnectionStatus::Connected);
+        assert_eq!(
+            watcher.tx_receiver.recv().await.unwrap().hash,
+            H256::repeat_byte(1)
+        );
+
+        node.abort();
+        assert!(matches!(
+            next_status(&mut watcher).await,
+            ConnectionStatus::Disconnected { .. }
+        ));
+        assert!(matches!(
+            next_status(&mut watcher).await,
+            ConnectionStatus::Reconnecting { attempt: 1, .. }
+        ));
+
+        // The restarted node announces the seen transaction again, which is dropped.
+        let listener = TcpListener::bind(address).await.unwrap();
+        let _node = spawn_node(
+            listener,
+            true,
+            vec![pending_tx(1), pending_tx(2)],
+            Duration::ZERO,
+        )
+        .await;
+        loop {
+            match next_status(&mut watcher).await {
+                ConnectionStatus::Connected => break,
+                ConnectionStatus::Reconnecting { .. } => continue,
+                status => panic!("unexpected status {status:?}"),
+            }
+        }
+        assert_eq!(
+            watcher.tx_receiver.recv().await.unwrap().hash,
+            H256::repeat_byte(2)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_endpoints_are_merged_with_first_seen_stats() {
+        let fast = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let slow = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = MempoolConfig::new(format!("ws://{}", fast.local_addr().unwrap()))
+            .with_endpoint(format!("ws://{}", slow.local_addr().unwrap()));
+        let _fast = spawn_node(
+            fast,
+            true,
+            vec![pending_tx(1), pending_tx(2)],
+            Duration::ZERO,
+        )
+        .await;
+        let _slow = spawn_node(
+            slow,
+            true,
+            vec![pending_tx(2), pending_tx(3)],
+            Duration::from_millis(100),
+        )
+        .await;
+
+        let mut watcher = MempoolWatcher::with_config(config).await.unwrap();
+        assert_eq!(
+            received_hashes(&mut watcher).await,
+            HashSet::from([1, 2, 3].map(H256::repeat_byte))
+        );
+
+        let stats = watcher.source_stats();
+        assert_eq!((stats[0].announced, stats[0].first), (2, 2));
+        assert_eq!((stats[1].announced, stats[1].first), (2, 1));
+        assert!(stats[1].max_delay >= Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn test_captured_stream_replays_through_watcher() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("ws://{}", listener.local_addr().unwrap());
+        let announced = (1..=3).map(pending_tx).collect::<Vec<_>>();
+        let _node = spawn_node(listener, true, announced, Duration::ZERO).await;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("capture.jsonl");
+
+        let mut watcher =
+            MempoolWatcher::with_config(MempoolConfig::new(&endpoint).with_capture(&path))
+                .await
+                .unwrap();
+        let live = received_hashes(&mut watcher).await;
+        drop(watcher);
+        let records = mempool_capture::read_capture(&path).await.unwrap();
+        assert_eq!(records.len(), 3);
+        assert!(records.iter().all(|record| record.source == endpoint));
+
+        let mut replayed = MempoolWatcher::replay(&path, ReplaySpeed::AsFastAsPossible)
+            .await
+            .unwrap();
+        assert_eq!(received_hashes(&mut replayed).await, live);
+    }
+}
@@ -0,0 +1,248 @@
+// This module records the pending transaction stream to disk and replays it.
+// Captures are JSON lines, one `CaptureRecord` per transaction in the order they were forwarded.
+
+use std::{
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use ethers::types::Transaction;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs::File,
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter},
+    sync::mpsc,
+    time::Instant,
+};
+
+use crate::mempool::MempoolError;
+
+/// Records waiting to be written before new ones are dropped, so a slow disk never stalls the live stream.
+const CAPTURE_CAPACITY: usize = 1024;
+
+/// A pending transaction as received from an endpoint.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptureRecord {
+    /// Unix time the transaction was received at, in milliseconds.
+    pub received_at: u64,
+    /// Endpoint the transaction was received from.
+    pub source: String,
+    /// Transaction as the endpoint sent it.
+    pub tx: Transaction,
+}
+
+impl CaptureRecord {
+    /// Creates a record of a transaction received now.
+    pub fn now(source: impl Into<String>, tx: Transaction) -> Self {
+        Self {
+            received_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            source: source.into(),
+            tx,
+        }
+    }
+}
+
+/// Pace of a replay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplaySpeed {
+    /// Keeps the gaps between the receive times of the records.
+    RealTime,
+    /// Sends every record as soon as the receiver takes it.
+    AsFastAsPossible,
+}
+
+/// Appends records to a capture file from a background task.
+///
+/// Records sent with `record` are dropped if the writer falls behind. The file is flushed whenever the writer
+/// catches up and when every sender is dropped.
+#[derive(Debug, Clone)]
+pub struct CaptureWriter {
+    sender: mpsc::Sender<CaptureRecord>,
+}
+
+impl CaptureWriter {
+    /// Opens `path` for appending and starts the writer task.
+    pub async fn create(path: impl AsRef<Path>) -> Result<Self, MempoolError> {
+        let path = path.as_ref().to_path_buf();
+        let file = File::options()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await?;
+        let (sender, mut receiver) = mpsc::channel::<CaptureRecord>(CAPTURE_CAPACITY);
+
+        tokio::spawn(async move {
+            let mut writer = BufWriter::new(file);
+            while let Some(record) = receiver.recv().await {
+                let mut line = serde_json::to_vec(&record).expect("capture records serialize");
+                line.push(b'\n');
+                let mut result = writer.write_all(&line).await;
+                if result.is_ok() && receiver.is_empty() {
+                    result = writer.flush().await;
+                }
+                if let Err(err) = result {
+                    log::error!("Stopped mempool capture to {}: {err}", path.display());
+                    return;
+                }
+            }
+            let _ = writer.flush().await;
+        });
+
+        Ok(Self { sender })
+    }
+
+    /// Queues a record, dropping it if the writer is behind or stopped.
+    pub fn record(&self, record: CaptureRecord) {
+        if self.sender.try_send(record).is_err() {
+            log::warn!("Mempool capture is behind, dropped a record");
+        }
+    }
+}
+
+/// Reads every record of a capture file.
+pub async fn read_capture(path: impl AsRef<Path>) -> Result<Vec<CaptureRecord>, MempoolError> {
+    let mut lines = BufReader::new(File::open(path).await?).lines();
+    let mut records = Vec::new();
+    while let Some(line) = lines.next_line().await? {
+        records.push(parse_record(records.len() + 1, &line)?);
+    }
+    Ok(records)
+}
+
+/// Sends the transactions of a capture file to the returned receiver, in capture order.
+///
+/// The file is read as the replay goes, so a file that is still being captured is replayed up to where it
+/// was when the replay reached its end. The replay stops at the first invalid line.
+pub async fn replay(
+    path: impl AsRef<Path>,
+    speed: ReplaySpeed,
+    channel_capacity: usize,
+) -> Result<mpsc::Receiver<Transaction>, MempoolError> {
+    let path = path.as_ref().to_path_buf();
+    let mut lines = BufReader::new(File::open(&path).await?).lines();
+    let (sender, receiver) = mpsc::channel(channel_capacity);
+
+    tokio::spawn(async move {
+        // Receive time of the first record and the instant it was replayed at.
+        let mut start: Option<(u64, Instant)> = None;
+        let mut line_number = 0;
+        loop {
+            let line = match lines.next_line().await {
+                Ok(Some(line)) => line,
+                Ok(None) => return,
+                Err(err) => {
+                    log::error!("Stopped replay of {}: {err}", path.display());
+                    return;
+                }
+            };
+            line_number += 1;
+            let record = match parse_record(line_number, &line) {
+                Ok(record) => record,
+                Err(err) => {
+                    log::error!("Stopped replay of {}: {err}", path.display());
+                    return;
+                }
+            };
+
+            if speed == ReplaySpeed::RealTime {
+                let (first_received_at, started) =
+                    *start.get_or_insert((record.received_at, Instant::now()));
+                let offset = record.received_at.saturating_sub(first_received_at);
+                tokio::time::sleep_until(started + Duration::from_millis(offset)).await;
+            }
+            if sender.send(record.tx).await.is_err() {
+                return;
+            }
+        }
+    });
+
+    Ok(receiver)
+}
+
+fn parse_record(line: usize, text: &str) -> Result<CaptureRecord, MempoolError> {
+    serde_json::from_str(text).map_err(|err| MempoolError::InvalidCapture {
+        line,
+        reason: err.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::H256;
+
+    fn record(byte: u8, received_at: u64) -> CaptureRecord {
+        CaptureRecord {
+            received_at,
+            source: "ws://node".to_string(),
+            tx: Transaction {
+                hash: H256::repeat_byte(byte),
+                ..Default::default()
+            },
+        }
+    }
+
+    async fn write(path: &Path, records: Vec<CaptureRecord>) {
+        let writer = CaptureWriter::create(path).await.unwrap();
+        let count = records.len();
+        for record in records {
+            writer.record(record);
+        }
+        drop(writer);
+        // The writer task flushes once it catches up.
+        for _ in 0..100 {
+            if read_capture(path)
+                .await
+                .is_ok_and(|records| records.len() == count)
+            {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replay_keeps_receive_gaps() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("capture.jsonl");
+        write(
+            &path,
+            vec![record(1, 1_000), record(2, 1_200), record(3, 1_200)],
+        )
+        .await;
+        assert_eq!(read_capture(&path).await.unwrap()[1], record(2, 1_200));
+
+        let start = std::time::Instant::now();
+        let mut receiver = replay(&path, ReplaySpeed::RealTime, 10).await.unwrap();
+        let mut hashes = Vec::new();
+        while let Some(tx) = receiver.recv().await {
+            hashes.push(tx.hash);
+        }
+        assert_eq!(hashes, [1, 2, 3].map(H256::repeat_byte));
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn test_replay_stops_at_invalid_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("capture.jsonl");
+        let mut text = serde_json::to_string(&record(1, 1_000)).unwrap();
+        text.push_str("\nnot json\n");
+        text.push_str(&serde_json::to_string(&record(2, 1_000)).unwrap());
+        std::fs::write(&path, text).unwrap();
+
+        assert!(matches!(
+            read_capture(&path).await,
+            Err(MempoolError::InvalidCapture { line: 2, .. })
+        ));
+        let mut receiver = replay(&path, ReplaySpeed::AsFastAsPossible, 10)
+            .await
+            .unwrap();
+        assert_eq!(receiver.recv().await.unwrap().hash, H256::repeat_byte(1));
+        assert!(receiver.recv().await.is_none());
+    }
+}
@@ -1,3 +1,10 @@
-pub fn init_risk() {
-    println!("Risk module initialized");
-}
+//! Risk management shared by the strategies and the circuit breaker.
+//!
+//! One `RiskEngine` checks trades before they are sent and records them once settled; share it in an `Arc`
+//! so every check sees the trades and open positions of all strategies.
+
+pub use mev_risk::store::{RiskStore, StoreError};
+pub use mev_risk::{
+    LimitBreach, RiskEngine, RiskError, RiskLimits, RiskParameters, StrategyPnl, Trade,
+    TradeProposal,
+};
@@ -41,6 +41,16 @@ impl SecureVault {
             .map_err(|_| SecurityError::InvalidPrivateKey("Invalid private key".to_string()))
     }
 
+    /// Returns the key signing relay requests, which identifies the searcher without holding funds.
+    pub fn get_bundle_signer(&self) -> Result<ethers::signers::LocalWallet, SecurityError> {
+        self.flashbots_secret
+            .unsecure()
+            .parse::<ethers::signers::LocalWallet>()
+            .map_err(|_| {
+                SecurityError::InvalidPrivateKey("Invalid Flashbots signing key".to_string())
+            })
+    }
+
     pub fn rpc_url(&self) -> &SecUtf8 {
         &self.rpc_url
     }
@@ -1,20 +1,91 @@
 use ethers::{
+    abi::{decode, ParamType},
     providers::{Provider, Middleware},
-    types::{Bytes, BlockId, H160, U256},
+    types::{
+        transaction::eip2718::TypedTransaction, Address, Block, BlockId, BlockNumber, Bytes, Log,
+        NameOrAddress, H160, H256, I256, U256, U64,
+    },
+    utils::{keccak256, rlp},
 };
 use revm::{
-    db::{CacheDB, EmptyDB},
-    primitives::{Address as rAddress, U256 as rU256, TransactTo},
+    db::{CacheDB, Database, DatabaseCommit, DatabaseRef},
+    primitives::{
+        Address as rAddress, CreateScheme, EVMError, ExecutionResult, Log as rLog, Output,
+        ResultAndState, State, B256, U256 as rU256, TransactTo,
+    },
     EVM,
 };
-use std::convert;
-use std::sync::Arc;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+use crate::call_tracer::{CallFrame, CallTracer};
+use crate::fork_db::{ForkDB, ForkDbError};
+
+/// Seconds between Ethereum blocks, used for chains without a known block time.
+const DEFAULT_BLOCK_TIME: u64 = 12;
+/// EIP-1559 gas target divisor and maximum base fee change per block.
+const ELASTICITY_MULTIPLIER: u64 = 2;
+const BASE_FEE_MAX_CHANGE_DENOMINATOR: u64 = 8;
+
+pub(crate) const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+/// keccak256("Transfer(address,address,uint256)"), shared by ERC-20 and ERC-721.
+const TRANSFER_TOPIC: [u8; 32] = [
+    0xdd, 0xf2, 0x52, 0xad, 0x1b, 0xe2, 0xc8, 0x9b, 0x69, 0xc2, 0xb0, 0x68, 0xfc, 0x37, 0x8d, 0xaa,
+    0x95, 0x2b, 0xa7, 0xf1, 0x63, 0xc4, 0xa1, 0x16, 0x28, 0xf5, 0x5a, 0x4d, 0xf5, 0x23, 0xb3, 0xef,
+];
 
 #[derive(Debug, PartialEq)]
 pub struct SimulationResult {
     pub gas_used: U256,
     pub success: bool,
-    pub logs: Vec<String>,
+    /// Logs emitted by the call, none if it failed.
+    pub logs: Vec<Log>,
+    /// Changes of every account the call touched.
+    pub state_diff: BTreeMap<Address, AccountDiff>,
+    /// ERC-20 balance changes of the watched addresses, from `Transfer` events.
+    pub token_deltas: Vec<TokenDelta>,
+    /// Call tree of the call, if call tracing is enabled.
+    pub call_trace: Option<CallFrame>,
+}
+
+/// A value before and after a simulation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Change<T> {
+    /// Value before the simulation.
+    pub before: T,
+    /// Value after the simulation.
+    pub after: T,
+}
+
+/// Changes of one account, with only the fields and slots that changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccountDiff {
+    /// Balance change, None if the balance did not change.
+    pub balance: Option<Change<U256>>,
+    /// Nonce change, None if the nonce did not change.
+    pub nonce: Option<Change<u64>>,
+    /// Changed storage slots.
+    pub storage: BTreeMap<H256, Change<H256>>,
+}
+
+impl AccountDiff {
+    /// Returns true if nothing of the account changed.
+    pub fn is_empty(&self) -> bool {
+        self.balance.is_none() && self.nonce.is_none() && self.storage.is_empty()
+    }
+}
+
+/// Net ERC-20 balance change of a holder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenDelta {
+    /// ERC-20 token contract.
+    pub token: Address,
+    /// Address whose balance changed.
+    pub holder: Address,
+    /// Net change of the balance, negative if it decreased.
+    pub delta: I256,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -23,29 +94,280 @@ pub enum SimulationError {
     ExecutionError(String),
     #[error("Provider error: {0}")]
     ProviderError(#[from] ethers::providers::ProviderError),
+    /// A bundle transaction could not be decoded or recovered.
+    #[error("Invalid transaction: {0}")]
+    InvalidTransaction(String),
+    /// The provider does not know the block the bundle is simulated on.
+    #[error("Block not found: {0:?}")]
+    BlockNotFound(BlockId),
+    /// Reading the forked state failed.
+    #[error("Fork database error: {0}")]
+    ForkDbError(#[from] ForkDbError),
+}
+
+/// Block a bundle is simulated in, derived from the state block as `eth_callBundle` does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BundleBlock {
+    /// Block number.
+    pub number: u64,
+    /// Block timestamp in seconds.
+    pub timestamp: u64,
+    /// Fee recipient of the block.
+    pub coinbase: Address,
+    /// EIP-1559 base fee of the block.
+    pub base_fee: U256,
+    /// Gas limit of the block.
+    pub gas_limit: U256,
+    /// Randomness of the block, the mix hash before the merge.
+    pub prevrandao: H256,
+    /// Block whose post-state the bundle runs on.
+    pub state_block_number: u64,
+}
+
+impl BundleBlock {
+    /// Returns the block following `parent` by `block_time` seconds, with the parent's coinbase and gas limit and
+    /// the next EIP-1559 base fee.
+    pub fn after(parent: &Block<H256>, block_time: u64) -> Self {
+        let number = parent.number.unwrap_or_default().as_u64();
+        Self {
+            number: number + 1,
+            timestamp: parent.timestamp.as_u64() + block_time,
+            coinbase: parent.author.unwrap_or_default(),
+            base_fee: next_base_fee(
+                parent.base_fee_per_gas.unwrap_or_default(),
+                parent.gas_used,
+                parent.gas_limit,
+            ),
+            gas_limit: parent.gas_limit,
+            prevrandao: parent.mix_hash.unwrap_or_default(),
+            state_block_number: number,
+        }
+    }
+}
+
+/// Result of one transaction of a simulated bundle, with the fields of an `eth_callBundle` result.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BundleTxResult {
+    /// Hash of the transaction.
+    pub tx_hash: H256,
+    /// Sender of the transaction.
+    pub from: Address,
+    /// Recipient of the transaction, None for a contract creation.
+    pub to: Option<Address>,
+    /// Gas used by the transaction.
+    pub gas_used: u64,
+    /// Effective gas price paid by the transaction.
+    pub gas_price: U256,
+    /// Priority fees paid to the coinbase.
+    pub gas_fees: U256,
+    /// Balance change of the coinbase.
+    pub coinbase_diff: U256,
+    /// Ether transferred to the coinbase directly, the coinbase difference less the gas fees.
+    pub eth_sent_to_coinbase: U256,
+    /// Return or revert data.
+    pub output: Bytes,
+    /// Logs emitted by the transaction, none if it failed.
+    pub logs: Vec<Log>,
+    /// "execution reverted" or the halt reason of a failed transaction.
+    pub error: Option<String>,
+    /// Decoded revert reason of a reverted transaction.
+    pub revert: Option<String>,
+    /// Call tree of the transaction, if call tracing is enabled.
+    pub call_trace: Option<CallFrame>,
+}
+
+impl BundleTxResult {
+    /// Returns true if the transaction neither reverted nor halted.
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Result of a bundle executed in order on one state, with the fields of an `eth_callBundle` response.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BundleSimulation {
+    /// Keccak256 hash of the concatenated transaction hashes.
+    pub bundle_hash: H256,
+    /// Results of the transactions, in bundle order.
+    pub results: Vec<BundleTxResult>,
+    /// Gas used by every transaction of the bundle.
+    pub total_gas_used: u64,
+    /// Balance change of the coinbase over the bundle.
+    pub coinbase_diff: U256,
+    /// Priority fees paid to the coinbase by the bundle.
+    pub gas_fees: U256,
+    /// Ether transferred to the coinbase directly by the bundle.
+    pub eth_sent_to_coinbase: U256,
+    /// Coinbase difference per unit of gas, the price builders rank bundles by.
+    pub bundle_gas_price: U256,
+    /// Block whose post-state the bundle ran on.
+    pub state_block_number: u64,
+}
+
+impl BundleSimulation {
+    /// Returns the first transaction that failed, if any.
+    pub fn first_failure(&self) -> Option<&BundleTxResult> {
+        self.results.iter().find(|result| !result.is_success())
+    }
+
+    /// Returns the net ERC-20 balance changes of the watched addresses over the whole bundle.
+    pub fn token_deltas(&self, watched: &HashSet<Address>) -> Vec<TokenDelta> {
+        let logs: Vec<Log> = self
+            .results
+            .iter()
+            .flat_map(|result| result.logs.iter().cloned())
+            .collect();
+        token_deltas(&logs, watched)
+    }
+}
+
+/// Seconds between blocks of the chain, as the protocol targets them.
+pub fn block_time(chain_id: u64) -> u64 {
+    match chain_id {
+        // Polygon PoS mainnet, Mumbai and Amoy.
+        137 | 80001 | 80002 => 2,
+        _ => DEFAULT_BLOCK_TIME,
+    }
 }
 
 pub struct ForkSimulator {
     provider: Provider<ethers::providers::Http>,
+    // State of the last simulated block, shared by every simulation on it.
+    fork: Mutex<Option<ForkDB<Provider<ethers::providers::Http>>>>,
+    watched: HashSet<Address>,
+    trace_calls: bool,
+    // Seconds between blocks, derived from the chain id if None.
+    block_time: Option<u64>,
 }
 
 impl ForkSimulator {
     pub fn new(provider: Provider<ethers::providers::Http>) -> Self {
-        Self { provider }
+        Self {
+            provider,
+            fork: Mutex::new(None),
+            watched: HashSet::new(),
+            trace_calls: false,
+            block_time: None,
+        }
+    }
+
+    /// Reports the ERC-20 balance changes of these addresses in simulation results.
+    pub fn with_watched_addresses(mut self, addresses: impl IntoIterator<Item = Address>) -> Self {
+        self.watched.extend(addresses);
+        self
+    }
+
+    /// Records the call tree of every simulated transaction, at the cost of slower execution.
+    pub fn with_call_tracing(mut self) -> Self {
+        self.trace_calls = true;
+        self
+    }
+
+    /// Sets the seconds between blocks, instead of deriving them from the chain id.
+    pub fn with_block_time(mut self, seconds: u64) -> Self {
+        self.block_time = Some(seconds);
+        self
+    }
+
+    /// Returns a database of the state after `block_number`, the latest block if None.
+    ///
+    /// Databases of the same block share their cache, which is dropped when a different block is requested.
+    pub async fn fork_db(
+        &self,
+        block_number: Option<BlockId>,
+    ) -> Result<ForkDB<Provider<ethers::providers::Http>>, SimulationError> {
+        let number = self.resolve_block_number(block_number).await?;
+        let mut fork = self.fork.lock().unwrap();
+        match fork.as_mut() {
+            Some(db) => {
+                db.on_new_block(number);
+                Ok(db.clone())
+            }
+            None => Ok(fork
+                .insert(ForkDB::new(Arc::new(self.provider.clone()), number))
+                .clone()),
+        }
+    }
+
+    async fn resolve_block_number(&self, block_number: Option<BlockId>) -> Result<u64, SimulationError> {
+        match block_number {
+            None => Ok(self.provider.get_block_number().await?.as_u64()),
+            Some(BlockId::Number(BlockNumber::Number(number))) => Ok(number.as_u64()),
+            Some(block) => self
+                .provider
+                .get_block(block)
+                .await?
+                .and_then(|block| block.number)
+                .map(|number| number.as_u64())
+                .ok_or(SimulationError::BlockNotFound(block)),
+        }
+    }
+
+    /// Simulates a bundle on the forked state after `state_block`, in the block that follows it.
+    pub async fn simulate_bundle_at(
+        &self,
+        txs: &[Bytes],
+        state_block: BlockId,
+    ) -> Result<BundleSimulation, SimulationError> {
+        let block = self.bundle_block(state_block).await?;
+        let db = self
+            .fork_db(Some(BlockId::from(block.state_block_number)))
+            .await?;
+        self.simulate_bundle(CacheDB::new(db), txs, &block).await
+    }
+
+    /// Fetches the state block and derives the block a bundle on top of it is simulated in.
+    pub async fn bundle_block(&self, state_block: BlockId) -> Result<BundleBlock, SimulationError> {
+        let parent = self
+            .provider
+            .get_block(state_block)
+            .await?
+            .ok_or(SimulationError::BlockNotFound(state_block))?;
+        let block_time = match self.block_time {
+            Some(block_time) => block_time,
+            None => block_time(self.provider.get_chainid().await?.as_u64()),
+        };
+        Ok(BundleBlock::after(&parent, block_time))
+    }
+
+    /// Runs signed raw transactions in order on `db`, which must hold the post-state of the state block.
+    ///
+    /// Use `simulate_bundle_at` to run them on the forked state.
+    ///
+    /// Each transaction sees the state the previous ones left behind. A transaction that reverts is reported in
+    /// its result, while one that cannot be included (bad nonce, insufficient balance) fails the whole bundle.
+    pub async fn simulate_bundle<ExtDB>(
+        &self,
+        db: CacheDB<ExtDB>,
+        txs: &[Bytes],
+        block: &BundleBlock,
+    ) -> Result<BundleSimulation, SimulationError>
+    where
+        ExtDB: DatabaseRef,
+        ExtDB::Error: Debug,
+    {
+        let chain_id = self.provider.get_chainid().await?.as_u64();
+        execute_bundle(db, chain_id, txs, block, self.trace_calls).map(|(simulation, _)| simulation)
     }
 
     pub async fn simulate(
         &self,
         tx: Bytes,
-        _block_number: Option<BlockId>,
+        block_number: Option<BlockId>,
     ) -> Result<SimulationResult, SimulationError> {
         let mut evm = EVM::new();
         
-        evm.database(CacheDB::new(EmptyDB::default()));
+        let db = self.fork_db(block_number).await?;
+        evm.env.block.number = rU256::from(db.block_number());
+        evm.database(CacheDB::new(db));
         
         evm.env.cfg.chain_id = self.provider.get_chainid().await?.as_u64();
-        evm.env.block.number = rU256::from(self.provider.get_block_number().await?.as_u64());
         
+        if tx.len() < 20 {
+            return Err(SimulationError::ExecutionError(
+                "call must start with the target address".to_string(),
+            ));
+        }
         let to_addr = H160::from_slice(&tx[..20]);
         let data = tx[20..].to_vec();
         
@@ -56,20 +378,404 @@ impl ForkSimulator {
         evm.env.tx.caller = rAddress::repeat_byte(0);
         evm.env.tx.transact_to = TransactTo::Call(to_revm_addr);
         
-        match evm.transact() {
-            Ok(result) => Ok(SimulationResult {
-                gas_used: U256::from(result.result.gas_used()),
-                success: !result.result.is_success(),
-                logs: vec![]
-            }),
-            Err(e) => Err(SimulationError::ExecutionError(e.to_string()))
+        let (ResultAndState { result, state }, call_trace) = transact(&mut evm, self.trace_calls)
+            .map_err(|e| SimulationError::ExecutionError(e.to_string()))?;
+        let state_diff = state_diff(evm.db.as_mut().expect("database is set"), &state)?;
+        let logs: Vec<Log> = result.logs().iter().map(to_ethers_log).collect();
+
+        Ok(SimulationResult {
+            gas_used: U256::from(result.gas_used()),
+            success: result.is_success(),
+            token_deltas: token_deltas(&logs, &self.watched),
+            logs,
+            state_diff,
+            call_trace,
+        })
+    }
+}
+
+/// Executes signed raw transactions in order on `db`, returning the simulation and the state after the bundle.
+///
+/// With `trace_calls`, each result carries the call tree of its transaction.
+pub fn execute_bundle<ExtDB>(
+    db: CacheDB<ExtDB>,
+    chain_id: u64,
+    txs: &[Bytes],
+    block: &BundleBlock,
+    trace_calls: bool,
+) -> Result<(BundleSimulation, CacheDB<ExtDB>), SimulationError>
+where
+    ExtDB: DatabaseRef,
+    ExtDB::Error: Debug,
+{
+    let mut evm = EVM::new();
+    evm.database(db);
+
+    evm.env.cfg.chain_id = chain_id;
+    evm.env.block.number = rU256::from(block.number);
+    evm.env.block.timestamp = rU256::from(block.timestamp);
+    evm.env.block.coinbase = to_revm_address(block.coinbase);
+    evm.env.block.basefee = to_revm_u256(block.base_fee);
+    evm.env.block.gas_limit = to_revm_u256(block.gas_limit);
+    evm.env.block.difficulty = rU256::ZERO;
+    evm.env.block.prevrandao = Some(B256::from(block.prevrandao.0));
+
+    let mut results = Vec::with_capacity(txs.len());
+    let mut log_index = 0u64;
+    for (index, raw) in txs.iter().enumerate() {
+        let (tx_hash, from, tx) = decode_signed_transaction(raw)?;
+        let gas_price = effective_gas_price(&tx, block.base_fee);
+        set_tx_env(&mut evm, &tx, from);
+
+        let balance_before = coinbase_balance(&mut evm, block.coinbase)?;
+        let (ResultAndState { result, state }, call_trace) = transact(&mut evm, trace_calls)
+            .map_err(|e| SimulationError::ExecutionError(format!("{e:?}")))?;
+        evm.db.as_mut().expect("database is set").commit(state);
+        let coinbase_diff = coinbase_balance(&mut evm, block.coinbase)?.saturating_sub(balance_before);
+
+        let (gas_used, output, logs, error, revert) = match result {
+            ExecutionResult::Success {
+                gas_used,
+                logs,
+                output,
+                ..
+            } => {
+                let output = match output {
+                    Output::Call(data) => data,
+                    Output::Create(data, _) => data,
+                };
+                (gas_used, output, logs, None, None)
+            }
+            ExecutionResult::Revert { gas_used, output } => {
+                let revert = decode_revert_reason(&output);
+                (
+                    gas_used,
+                    output,
+                    Vec::new(),
+                    Some("execution reverted".to_string()),
+                    revert,
+                )
+            }
+            ExecutionResult::Halt { reason, gas_used } => {
+                (gas_used, Default::default(), Vec::new(), Some(format!("{reason:?}")), None)
+            }
+        };
+
+        let logs = logs
+            .into_iter()
+            .map(|log| {
+                let log = Log {
+                    transaction_hash: Some(tx_hash),
+                    transaction_index: Some(U64::from(index)),
+                    log_index: Some(U256::from(log_index)),
+                    ..to_ethers_log(&log)
+                };
+                log_index += 1;
+                log
+            })
+            .collect();
+
+        let gas_fees = U256::from(gas_used) * gas_price.saturating_sub(block.base_fee);
+        results.push(BundleTxResult {
+            tx_hash,
+            from,
+            to: match tx.to() {
+                Some(NameOrAddress::Address(to)) => Some(*to),
+                _ => None,
+            },
+            gas_used,
+            gas_price,
+            gas_fees,
+            coinbase_diff,
+            eth_sent_to_coinbase: coinbase_diff.saturating_sub(gas_fees),
+            output: Bytes::from(output.to_vec()),
+            logs,
+            error,
+            revert,
+            call_trace,
+        });
+    }
+
+    let total_gas_used = results.iter().map(|result| result.gas_used).sum::<u64>();
+    let coinbase_diff = results
+        .iter()
+        .fold(U256::zero(), |total, result| total + result.coinbase_diff);
+    let gas_fees = results
+        .iter()
+        .fold(U256::zero(), |total, result| total + result.gas_fees);
+    let hashes: Vec<u8> = results
+        .iter()
+        .flat_map(|result| result.tx_hash.0)
+        .collect();
+
+    let simulation = BundleSimulation {
+        bundle_hash: H256::from(keccak256(hashes)),
+        total_gas_used,
+        coinbase_diff,
+        gas_fees,
+        eth_sent_to_coinbase: coinbase_diff.saturating_sub(gas_fees),
+        bundle_gas_price: if total_gas_used == 0 {
+            U256::zero()
+        } else {
+            coinbase_diff / total_gas_used
+        },
+        state_block_number: block.state_block_number,
+        results,
+    };
+    let db = evm.db.take().expect("database is set");
+    Ok((simulation, db))
+}
+
+/// Returns the changes of the accounts in `state`, reading their prior values from `db`.
+fn state_diff<DB>(db: &mut DB, state: &State) -> Result<BTreeMap<Address, AccountDiff>, SimulationError>
+where
+    DB: Database,
+    DB::Error: Debug,
+{
+    let mut diffs = BTreeMap::new();
+    for (address, account) in state {
+        let before = db
+            .basic(*address)
+            .map_err(|e| SimulationError::ExecutionError(format!("{e:?}")))?
+            .unwrap_or_default();
+
+        let mut diff = AccountDiff::default();
+        if before.balance != account.info.balance {
+            diff.balance = Some(Change {
+                before: from_revm_u256(before.balance),
+                after: from_revm_u256(account.info.balance),
+            });
+        }
+        if before.nonce != account.info.nonce {
+            diff.nonce = Some(Change {
+                before: before.nonce,
+                after: account.info.nonce,
+            });
+        }
+        for (slot, value) in &account.storage {
+            if value.original_value() != value.present_value {
+                diff.storage.insert(
+                    H256::from(slot.to_be_bytes::<32>()),
+                    Change {
+                        before: H256::from(value.original_value().to_be_bytes::<32>()),
+                        after: H256::from(value.present_value.to_be_bytes::<32>()),
+                    },
+                );
+            }
+        }
+
+        if !diff.is_empty() {
+            diffs.insert(from_revm_address(*address), diff);
         }
     }
+    Ok(diffs)
+}
+
+/// Sums the ERC-20 `Transfer` events of `logs` into the net balance change of each watched holder.
+///
+/// ERC-721 transfers share the event signature but index the token id, so events without exactly three topics
+/// are skipped. Holders whose transfers cancel out are omitted.
+pub fn token_deltas(logs: &[Log], watched: &HashSet<Address>) -> Vec<TokenDelta> {
+    let mut deltas: BTreeMap<(Address, Address), I256> = BTreeMap::new();
+    for log in logs {
+        if log.topics.len() != 3 || log.topics[0].0 != TRANSFER_TOPIC || log.data.len() != 32 {
+            continue;
+        }
+        let from = Address::from(log.topics[1]);
+        let to = Address::from(log.topics[2]);
+        // Amounts above I256::MAX are not real balances, so they are clamped rather than wrapped.
+        let amount = I256::try_from(U256::from_big_endian(&log.data)).unwrap_or(I256::MAX);
+
+        if watched.contains(&from) {
+            let delta = deltas.entry((log.address, from)).or_insert_with(I256::zero);
+            *delta = delta.saturating_sub(amount);
+        }
+        if watched.contains(&to) {
+            let delta = deltas.entry((log.address, to)).or_insert_with(I256::zero);
+            *delta = delta.saturating_add(amount);
+        }
+    }
+
+    deltas
+        .into_iter()
+        .filter(|(_, delta)| !delta.is_zero())
+        .map(|((token, holder), delta)| TokenDelta {
+            token,
+            holder,
+            delta,
+        })
+        .collect()
+}
+
+/// Computes the base fee of the block after a parent with the given base fee, gas used and gas limit, per EIP-1559.
+pub fn next_base_fee(base_fee: U256, gas_used: U256, gas_limit: U256) -> U256 {
+    let target = gas_limit / ELASTICITY_MULTIPLIER;
+    if target.is_zero() || gas_used == target {
+        return base_fee;
+    }
+    if gas_used > target {
+        let delta = base_fee * (gas_used - target) / target / BASE_FEE_MAX_CHANGE_DENOMINATOR;
+        base_fee + delta.max(U256::one())
+    } else {
+        let delta = base_fee * (target - gas_used) / target / BASE_FEE_MAX_CHANGE_DENOMINATOR;
+        base_fee.saturating_sub(delta)
+    }
+}
+
+/// Decodes the reason of a revert from `Error(string)`, `Panic(uint256)` or custom error data.
+///
+/// Returns None if the revert has no data.
+pub fn decode_revert_reason(output: &[u8]) -> Option<String> {
+    let (selector, data) = (output.get(..4)?, &output[4..]);
+    if selector == ERROR_SELECTOR {
+        if let Some(reason) = decode(&[ParamType::String], data)
+            .ok()
+            .and_then(|tokens| tokens.into_iter().next())
+            .and_then(|token| token.into_string())
+        {
+            return Some(reason);
+        }
+    }
+    if selector == PANIC_SELECTOR {
+        if let Some(code) = decode(&[ParamType::Uint(256)], data)
+            .ok()
+            .and_then(|tokens| tokens.into_iter().next())
+            .and_then(|token| token.into_uint())
+        {
+            return Some(format!("panic: {code:#x}"));
+        }
+    }
+    Some(format!("{:?}", Bytes::from(output.to_vec())))
+}
+
+/// Decodes a signed raw transaction, returning its hash, its recovered sender and the transaction.
+fn decode_signed_transaction(raw: &Bytes) -> Result<(H256, Address, TypedTransaction), SimulationError> {
+    let (tx, signature) = TypedTransaction::decode_signed(&rlp::Rlp::new(raw))
+        .map_err(|e| SimulationError::InvalidTransaction(e.to_string()))?;
+    let from = signature
+        .recover(tx.sighash())
+        .map_err(|e| SimulationError::InvalidTransaction(e.to_string()))?;
+    Ok((H256::from(keccak256(raw)), from, tx))
+}
+
+/// Returns the gas price a transaction pays in a block with the given base fee.
+fn effective_gas_price(tx: &TypedTransaction, base_fee: U256) -> U256 {
+    match tx {
+        TypedTransaction::Eip1559(tx) => {
+            let max_fee = tx.max_fee_per_gas.unwrap_or_default();
+            let priority_fee = tx.max_priority_fee_per_gas.unwrap_or_default();
+            max_fee.min(base_fee + priority_fee)
+        }
+        tx => tx.gas_price().unwrap_or_default(),
+    }
+}
+
+/// Runs the transaction in the environment, under a call tracer if `trace_calls` is set.
+fn transact<DB: Database>(
+    evm: &mut EVM<DB>,
+    trace_calls: bool,
+) -> Result<(ResultAndState, Option<CallFrame>), EVMError<DB::Error>> {
+    if !trace_calls {
+        return Ok((evm.transact()?, None));
+    }
+    let mut tracer = CallTracer::default();
+    let result = evm.inspect(&mut tracer)?;
+    Ok((result, tracer.into_trace()))
+}
+
+fn set_tx_env<DB>(evm: &mut EVM<DB>, tx: &TypedTransaction, from: Address) {
+    evm.env.tx.caller = to_revm_address(from);
+    evm.env.tx.gas_limit = tx
+        .gas()
+        .map(|gas| u64::try_from(*gas).unwrap_or(u64::MAX))
+        .unwrap_or_default();
+    evm.env.tx.transact_to = match tx.to() {
+        Some(NameOrAddress::Address(to)) => TransactTo::Call(to_revm_address(*to)),
+        _ => TransactTo::Create(CreateScheme::Create),
+    };
+    evm.env.tx.value = to_revm_u256(tx.value().copied().unwrap_or_default());
+    evm.env.tx.data = tx.data().map(|data| data.to_vec()).unwrap_or_default().into();
+    evm.env.tx.nonce = tx.nonce().map(|nonce| nonce.as_u64());
+    evm.env.tx.chain_id = tx.chain_id().map(|chain_id| chain_id.as_u64());
+    (evm.env.tx.gas_price, evm.env.tx.gas_priority_fee) = match tx {
+        TypedTransaction::Eip1559(tx) => (
+            to_revm_u256(tx.max_fee_per_gas.unwrap_or_default()),
+            tx.max_priority_fee_per_gas.map(to_revm_u256),
+        ),
+        tx => (to_revm_u256(tx.gas_price().unwrap_or_default()), None),
+    };
+    evm.env.tx.access_list = tx
+        .access_list()
+        .map(|list| {
+            list.0
+                .iter()
+                .map(|item| {
+                    (
+                        to_revm_address(item.address),
+                        item.storage_keys
+                            .iter()
+                            .map(|key| rU256::from_be_bytes(key.0))
+                            .collect(),
+                    )
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+}
+
+fn coinbase_balance<ExtDB>(
+    evm: &mut EVM<CacheDB<ExtDB>>,
+    coinbase: Address,
+) -> Result<U256, SimulationError>
+where
+    ExtDB: DatabaseRef,
+    ExtDB::Error: Debug,
+{
+    let account = evm
+        .db
+        .as_mut()
+        .expect("database is set")
+        .basic(to_revm_address(coinbase))
+        .map_err(|e| SimulationError::ExecutionError(format!("{e:?}")))?;
+    Ok(account.map_or_else(U256::zero, |info| from_revm_u256(info.balance)))
+}
+
+fn to_ethers_log(log: &rLog) -> Log {
+    Log {
+        address: from_revm_address(log.address),
+        topics: log.topics.iter().map(|topic| H256::from(topic.0)).collect(),
+        data: Bytes::from(log.data.to_vec()),
+        ..Default::default()
+    }
+}
+
+pub(crate) fn to_revm_address(address: Address) -> rAddress {
+    rAddress::from_slice(address.as_bytes())
+}
+
+pub(crate) fn from_revm_address(address: rAddress) -> Address {
+    H160::from_slice(address.as_slice())
+}
+
+fn to_revm_u256(value: U256) -> rU256 {
+    rU256::from_limbs(value.0)
+}
+
+fn from_revm_u256(value: rU256) -> U256 {
+    U256(value.into_limbs())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use ethers::{
+        signers::{LocalWallet, Signer},
+        types::TransactionRequest,
+    };
+    use revm::{
+        db::EmptyDB,
+        primitives::{AccountInfo, Bytecode},
+    };
     use std::str::FromStr;
     use tokio::test;
 
@@ -85,7 +791,7 @@ mod tests {
         Bytes::from(tx)
     }
 
-    #[test]
+    #[test(flavor = "multi_thread")]
     async fn test_simulation_success() {
         let provider = create_test_provider();
         let simulator = ForkSimulator::new(provider);
@@ -96,7 +802,7 @@ mod tests {
         assert!(result.gas_used > U256::zero(), "Gas used should be non-zero");
     }
 
-    #[test]
+    #[test(flavor = "multi_thread")]
     async fn test_invalid_transaction() {
         let provider = create_test_provider();
         let simulator = ForkSimulator::new(provider);
@@ -106,7 +812,202 @@ mod tests {
         assert!(matches!(result, Err(SimulationError::ExecutionError(_))));
     }
 
+    fn bundle_block() -> BundleBlock {
+        BundleBlock {
+            number: 101,
+            timestamp: 1_700_000_012,
+            coinbase: H160::repeat_byte(0xcb),
+            base_fee: U256::from(1_000_000_000u64),
+            gas_limit: U256::from(30_000_000u64),
+            prevrandao: H256::zero(),
+            state_block_number: 100,
+        }
+    }
+
+    fn test_wallet() -> LocalWallet {
+        "0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318"
+            .parse::<LocalWallet>()
+            .unwrap()
+            .with_chain_id(137u64)
+    }
+
+    fn signed_transfer(wallet: &LocalWallet, nonce: u64, to: H160, value: U256) -> Bytes {
+        let tx: TypedTransaction = TransactionRequest::new()
+            .from(wallet.address())
+            .to(to)
+            .value(value)
+            .nonce(nonce)
+            .gas(21_000)
+            .gas_price(2_000_000_000u64)
+            .chain_id(137)
+            .into();
+        let signature = wallet.sign_transaction_sync(&tx).unwrap();
+        tx.rlp_signed(&signature)
+    }
+
     #[test]
+    async fn test_bundle_runs_on_shared_state() {
+        let wallet = test_wallet();
+        let block = bundle_block();
+        let mut db = CacheDB::new(EmptyDB::default());
+        db.insert_account_info(
+            to_revm_address(wallet.address()),
+            AccountInfo {
+                balance: to_revm_u256(U256::exp10(19)),
+                ..Default::default()
+            },
+        );
+
+        // The second transfer is only valid once the first one has used nonce zero.
+        let payment = U256::exp10(18);
+        let txs = vec![
+            signed_transfer(&wallet, 0, block.coinbase, payment),
+            signed_transfer(&wallet, 1, H160::repeat_byte(1), U256::one()),
+        ];
+        let (simulation, _) = execute_bundle(db, 137, &txs, &block, false).unwrap();
+
+        let priority_fees = U256::from(21_000u64) * U256::from(1_000_000_000u64);
+        assert_eq!(simulation.results.len(), 2);
+        assert!(simulation.first_failure().is_none());
+        assert_eq!(simulation.total_gas_used, 42_000);
+        assert_eq!(simulation.results[0].from, wallet.address());
+        assert_eq!(simulation.results[0].eth_sent_to_coinbase, payment);
+        assert_eq!(simulation.results[1].coinbase_diff, priority_fees);
+        assert_eq!(simulation.coinbase_diff, payment + priority_fees * 2);
+        assert_eq!(simulation.bundle_gas_price, simulation.coinbase_diff / 42_000u64);
+    }
+
+    #[test]
+    async fn test_bundle_rejects_invalid_nonce() {
+        let wallet = test_wallet();
+        let mut db = CacheDB::new(EmptyDB::default());
+        db.insert_account_info(
+            to_revm_address(wallet.address()),
+            AccountInfo {
+                balance: to_revm_u256(U256::exp10(19)),
+                ..Default::default()
+            },
+        );
+
+        let txs = vec![signed_transfer(&wallet, 1, H160::repeat_byte(1), U256::one())];
+        let result = execute_bundle(db, 137, &txs, &bundle_block(), false);
+        assert!(matches!(result, Err(SimulationError::ExecutionError(_))));
+    }
+
+    #[test]
+    async fn test_state_diff_reports_changed_fields() {
+        let caller = H160::repeat_byte(0xca);
+        let target = H160::repeat_byte(0x5e);
+        let mut db = CacheDB::new(EmptyDB::default());
+        db.insert_account_info(
+            to_revm_address(caller),
+            AccountInfo {
+                balance: to_revm_u256(U256::exp10(18)),
+                ..Default::default()
+            },
+        );
+        // PUSH1 0x2a PUSH1 0x01 SSTORE STOP
+        db.insert_account_info(
+            to_revm_address(target),
+            AccountInfo {
+                code: Some(Bytecode::new_raw(vec![0x60, 0x2a, 0x60, 0x01, 0x55, 0x00].into())),
+                ..Default::default()
+            },
+        );
+
+        let mut evm = EVM::new();
+        evm.database(db);
+        evm.env.tx.caller = to_revm_address(caller);
+        evm.env.tx.transact_to = TransactTo::Call(to_revm_address(target));
+        evm.env.tx.value = rU256::from(5);
+        evm.env.tx.gas_limit = 100_000;
+        let ResultAndState { result, state } = evm.transact().unwrap();
+        let diff = state_diff(evm.db.as_mut().unwrap(), &state).unwrap();
+
+        assert!(result.is_success());
+        assert_eq!(
+            diff[&target].storage[&H256::from_low_u64_be(1)],
+            Change {
+                before: H256::zero(),
+                after: H256::from_low_u64_be(42),
+            }
+        );
+        assert_eq!(
+            diff[&target].balance,
+            Some(Change {
+                before: U256::zero(),
+                after: U256::from(5),
+            })
+        );
+        assert_eq!(diff[&caller].nonce, Some(Change { before: 0, after: 1 }));
+    }
+
+    #[test]
+    async fn test_token_deltas_from_transfer_events() {
+        let token = H160::repeat_byte(0x70);
+        let searcher = H160::repeat_byte(0x01);
+        let pool = H160::repeat_byte(0x02);
+        let transfer = |from: H160, to: H160, amount: u64| Log {
+            address: token,
+            topics: vec![H256(TRANSFER_TOPIC), H256::from(from), H256::from(to)],
+            data: Bytes::from(ethers::abi::encode(&[ethers::abi::Token::Uint(U256::from(amount))])),
+            ..Default::default()
+        };
+        let nft_transfer = Log {
+            topics: vec![
+                H256(TRANSFER_TOPIC),
+                H256::from(pool),
+                H256::from(searcher),
+                H256::from_low_u64_be(7),
+            ],
+            data: Bytes::default(),
+            ..transfer(pool, searcher, 0)
+        };
+
+        let logs = vec![
+            transfer(searcher, pool, 100),
+            transfer(pool, searcher, 130),
+            nft_transfer,
+            transfer(pool, H160::repeat_byte(0x03), 50),
+        ];
+        let watched = HashSet::from([searcher, pool]);
+
+        assert_eq!(
+            token_deltas(&logs, &watched),
+            vec![
+                TokenDelta {
+                    token,
+                    holder: searcher,
+                    delta: I256::from(30),
+                },
+                TokenDelta {
+                    token,
+                    holder: pool,
+                    delta: I256::from(-80),
+                },
+            ]
+        );
+        assert!(token_deltas(&logs, &HashSet::new()).is_empty());
+    }
+
+    #[test]
+    async fn test_next_base_fee_and_revert_reason() {
+        let base_fee = U256::from(1_000_000_000u64);
+        let gas_limit = U256::from(30_000_000u64);
+        assert_eq!(next_base_fee(base_fee, gas_limit / 2, gas_limit), base_fee);
+        assert_eq!(next_base_fee(base_fee, gas_limit, gas_limit), U256::from(1_125_000_000u64));
+        assert_eq!(next_base_fee(base_fee, U256::zero(), gas_limit), U256::from(875_000_000u64));
+
+        let mut output = ERROR_SELECTOR.to_vec();
+        output.extend(ethers::abi::encode(&[ethers::abi::Token::String("TooLittleReceived".into())]));
+        assert_eq!(decode_revert_reason(&output).as_deref(), Some("TooLittleReceived"));
+        let mut output = PANIC_SELECTOR.to_vec();
+        output.extend(ethers::abi::encode(&[ethers::abi::Token::Uint(U256::from(0x11))]));
+        assert_eq!(decode_revert_reason(&output).as_deref(), Some("panic: 0x11"));
+        assert_eq!(decode_revert_reason(&[]), None);
+    }
+
+    #[test(flavor = "multi_thread")]
     async fn test_gas_limit() {
         let provider = create_test_provider();
         let simulator = ForkSimulator::new(provider);
@@ -1,39 +1,207 @@
-use ethers::{prelude::*, utils::parse_ether};
+use ethers::prelude::*;
+use mev_core::circuit_breaker::{CircuitBreaker, TradePermit};
+use mev_risk::TradeProposal;
 use std::sync::Arc;
 
+abigen!(
+    AavePool,
+    r#"[
+        function flashLoan(address receiverAddress, address[] assets, uint256[] amounts, uint256[] interestRateModes, address onBehalfOf, bytes params, uint16 referralCode)
+    ]"#
+);
+
 pub struct FlashloanExecutor {
     client: Arc<SignerMiddleware<Provider<Http>, LocalWallet>>,
     aave_pool: Address,
+    receiver: Address,
+    breaker: Arc<CircuitBreaker>,
+}
+
+/// A sent arbitrage, holding its position and circuit breaker permit until `settle_arbitrage`.
+#[derive(Debug)]
+pub struct SentArbitrage {
+    /// Hash of the flash loan transaction.
+    pub tx_hash: TxHash,
+    asset: Address,
+    amount: U256,
+    permit: TradePermit,
 }
 
 impl FlashloanExecutor {
-    pub async fn new(rpc_url: &str, chain_id: u64) -> Result<Self> {
-        let provider = Provider::<Http>::connect(rpc_url).await;
+    /// Creates an executor whose loans are received by the `receiver` contract, which runs the arbitrage.
+    pub async fn new(
+        rpc_url: &str,
+        chain_id: u64,
+        receiver: Address,
+        breaker: Arc<CircuitBreaker>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let provider = Provider::<Http>::try_from(rpc_url)?;
         let wallet = LocalWallet::new(&mut rand::thread_rng()).with_chain_id(chain_id);
         let client = Arc::new(SignerMiddleware::new(provider, wallet));
 
         Ok(Self {
             client,
             aave_pool: "0x794a61358D6845594F94dc1DB02A252b5b4814aD".parse()?,
+            receiver,
+            breaker,
         })
     }
 
-    pub async fn execute_arbitrage(&self, targets: Vec<Address>, data: Vec<Bytes>) {
-        let loan_amount = parse_ether(1000).unwrap();
+    /// Borrows `amount` of `asset` and runs the arbitrage of `calls`, each a target and its calldata, with it if
+    /// the circuit breaker allows it.
+    ///
+    /// `value` is the value of the loan, `expected_profit` the profit of the arbitrage and `gas_cost` the cost
+    /// of the transaction, all in the quote token of the breaker's risk engine. A flash loan that is not repaid reverts, so at worst the arbitrage loses its
+    /// gas. The loan is opened as a position in `asset` before it is sent, and released if sending fails. Once
+    /// the transaction has settled, pass it with its outcome to `settle_arbitrage`.
+    pub async fn execute_arbitrage(
+        &self,
+        asset: Address,
+        amount: U256,
+        value: U256,
+        expected_profit: U256,
+        gas_cost: U256,
+        calls: Vec<(Address, Bytes)>,
+    ) -> Result<SentArbitrage, Box<dyn std::error::Error>> {
+        let proposal = Self::proposal(asset, amount)
+            .with_value(value)
+            .with_expected_profit(expected_profit)
+            .with_cost(gas_cost)
+            .with_max_loss(gas_cost);
+        let Some(permit) = self.breaker.check_trade(&proposal) else {
+            return Err("trade rejected by the circuit breaker".into());
+        };
+        if let Err(err) = self.breaker.risk_engine().open_position(&proposal) {
+            self.breaker.release_trade(permit);
+            return Err(err.into());
+        }
+
+        match self.send_flash_loan(asset, amount, calls).await {
+            Ok(tx_hash) => Ok(SentArbitrage {
+                tx_hash,
+                asset,
+                amount,
+                permit,
+            }),
+            Err(err) => {
+                // Nothing was sent, so the position and the permit are released right away.
+                self.breaker.risk_engine().release_position(&proposal);
+                self.breaker.release_trade(permit);
+                Err(err)
+            }
+        }
+    }
+
+    /// Closes the position of a sent arbitrage and records its realized PnL, or releases it if it did not land.
+    pub fn settle_arbitrage(&self, arbitrage: SentArbitrage, profit_loss: Option<I256>) {
+        let proposal = Self::proposal(arbitrage.asset, arbitrage.amount);
+        self.breaker.risk_engine().release_position(&proposal);
+        match profit_loss {
+            Some(profit_loss) => {
+                self.breaker
+                    .record_trade(arbitrage.permit, proposal.strategy, profit_loss)
+            }
+            None => self.breaker.release_trade(arbitrage.permit),
+        }
+    }
+
+    /// Sends the flash loan, passing the targets and their calldata to the receiver.
+    async fn send_flash_loan(
+        &self,
+        asset: Address,
+        amount: U256,
+        calls: Vec<(Address, Bytes)>,
+    ) -> Result<TxHash, Box<dyn std::error::Error>> {
+        let (targets, data): (Vec<_>, Vec<_>) = calls.into_iter().unzip();
+        let params = ethers::abi::encode(&[
+            ethers::abi::Token::Array(
+                targets
+                    .into_iter()
+                    .map(ethers::abi::Token::Address)
+                    .collect(),
+            ),
+            ethers::abi::Token::Array(
+                data.into_iter()
+                    .map(|call| ethers::abi::Token::Bytes(call.to_vec()))
+                    .collect(),
+            ),
+        ]);
 
-        let params = (
-            targets,
-            vec![loan_amount],
-            vec![0],
+        // Interest rate mode 0 repays the loan within the transaction instead of opening a debt.
+        let pool = AavePool::new(self.aave_pool, self.client.clone());
+        let call = pool.flash_loan(
+            self.receiver,
+            vec![asset],
+            vec![amount],
+            vec![U256::zero()],
             Address::zero(),
-            data,
+            params.into(),
             0,
         );
+        let pending = call.send().await?;
+        Ok(pending.tx_hash())
+    }
+
+    fn proposal(asset: Address, amount: U256) -> TradeProposal {
+        TradeProposal::new(asset, amount).with_strategy("flashloan")
+    }
+}
 
-        let tx = TransactionRequest::new()
-            .to(self.aave_pool)
-            .data(encode_function_data("flashLoan", params));
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mev_risk::RiskLimits;
+    use std::time::Duration;
 
-        self.client.send_transaction(tx, None).await.unwrap();
+    #[tokio::test]
+    async fn test_failed_send_releases_the_position_and_the_permit() {
+        let breaker = Arc::new(CircuitBreaker::new(
+            Address::repeat_byte(1),
+            RiskLimits::new(U256::from(100)),
+            Duration::from_secs(1),
+        ));
+        // Nothing listens on the port, so sending fails once the trade is allowed.
+        let executor = FlashloanExecutor::new(
+            "http://127.0.0.1:1",
+            1,
+            Address::repeat_byte(3),
+            breaker.clone(),
+        )
+        .await
+        .unwrap();
+        let asset = Address::repeat_byte(2);
+
+        let sent = executor
+            .execute_arbitrage(
+                asset,
+                U256::from(1_000),
+                U256::from(1_000),
+                U256::from(20),
+                U256::from(10),
+                vec![(Address::repeat_byte(4), Bytes::from(vec![1, 2, 3, 4]))],
+            )
+            .await;
+        assert_ne!(
+            sent.unwrap_err().to_string(),
+            "trade rejected by the circuit breaker"
+        );
+        assert_eq!(breaker.risk_engine().exposure(asset), U256::zero());
+
+        // A loan whose gas alone exceeds the loss allowed on its value is rejected before it is opened.
+        let rejected = executor
+            .execute_arbitrage(
+                asset,
+                U256::from(1_000),
+                U256::from(1_000),
+                U256::from(1_000),
+                U256::from(500),
+                vec![],
+            )
+            .await;
+        assert_eq!(
+            rejected.unwrap_err().to_string(),
+            "trade rejected by the circuit breaker"
+        );
+        assert_eq!(breaker.risk_engine().exposure(asset), U256::zero());
     }
 }
@@ -0,0 +1,146 @@
+// Port of Balancer's FixedPoint library.
+// Rounds 18-decimal fixed-point products, quotients and powers up or down, as the pools do in their favor.
+
+use ethers::types::U256;
+
+use super::log_exp_math;
+use crate::error::MathError;
+
+/// One in 18-decimal fixed point.
+pub const ONE: u64 = 1_000_000_000_000_000_000;
+/// Relative error bound of `LogExpMath.pow` (1e-14), added to or subtracted from its result.
+pub const MAX_POW_RELATIVE_ERROR: u64 = 10_000;
+
+fn one() -> U256 {
+    U256::from(ONE)
+}
+
+/// Returns a + b, or Err(MathError::Overflow) if the sum overflows.
+pub fn add(a: U256, b: U256) -> Result<U256, MathError> {
+    a.checked_add(b).ok_or(MathError::Overflow)
+}
+
+/// Returns a - b, or Err(MathError::Overflow) if b is greater than a.
+pub fn sub(a: U256, b: U256) -> Result<U256, MathError> {
+    a.checked_sub(b).ok_or(MathError::Overflow)
+}
+
+/// Multiplies two fixed-point numbers, rounding down.
+pub fn mul_down(a: U256, b: U256) -> Result<U256, MathError> {
+    Ok(a.checked_mul(b).ok_or(MathError::Overflow)? / one())
+}
+
+/// Multiplies two fixed-point numbers, rounding up.
+pub fn mul_up(a: U256, b: U256) -> Result<U256, MathError> {
+    let product = a.checked_mul(b).ok_or(MathError::Overflow)?;
+    if product.is_zero() {
+        return Ok(U256::zero());
+    }
+    Ok((product - 1) / one() + 1)
+}
+
+/// Divides two fixed-point numbers, rounding down.
+pub fn div_down(a: U256, b: U256) -> Result<U256, MathError> {
+    if b.is_zero() {
+        return Err(MathError::DivisionByZero);
+    }
+    Ok(a.checked_mul(one()).ok_or(MathError::Overflow)? / b)
+}
+
+/// Divides two fixed-point numbers, rounding up.
+pub fn div_up(a: U256, b: U256) -> Result<U256, MathError> {
+    if b.is_zero() {
+        return Err(MathError::DivisionByZero);
+    }
+    if a.is_zero() {
+        return Ok(U256::zero());
+    }
+    Ok((a.checked_mul(one()).ok_or(MathError::Overflow)? - 1) / b + 1)
+}
+
+/// Computes x ^ y rounding down, so the result is at most the exact power.
+///
+/// Exponents of one, two and four are computed by multiplication, which is exact up to rounding.
+pub fn pow_down(x: U256, y: U256) -> Result<U256, MathError> {
+    if y == one() {
+        return Ok(x);
+    }
+    if y == one() * 2 {
+        return mul_down(x, x);
+    }
+    if y == one() * 4 {
+        let square = mul_down(x, x)?;
+        return mul_down(square, square);
+    }
+
+    let raw = log_exp_math::pow(x, y)?;
+    let max_error = add(
+        mul_up(raw, U256::from(MAX_POW_RELATIVE_ERROR))?,
+        U256::one(),
+    )?;
+    Ok(raw.saturating_sub(max_error))
+}
+
+/// Computes x ^ y rounding up, so the result is at least the exact power.
+///
+/// Exponents of one, two and four are computed by multiplication, which is exact up to rounding.
+pub fn pow_up(x: U256, y: U256) -> Result<U256, MathError> {
+    if y == one() {
+        return Ok(x);
+    }
+    if y == one() * 2 {
+        return mul_up(x, x);
+    }
+    if y == one() * 4 {
+        let square = mul_up(x, x)?;
+        return mul_up(square, square);
+    }
+
+    let raw = log_exp_math::pow(x, y)?;
+    let max_error = add(
+        mul_up(raw, U256::from(MAX_POW_RELATIVE_ERROR))?,
+        U256::one(),
+    )?;
+    add(raw, max_error)
+}
+
+/// Returns 1 - x, or zero if x is greater than one.
+pub fn complement(x: U256) -> U256 {
+    one().saturating_sub(x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rounding_directions() {
+        let third = div_down(U256::one(), U256::from(3)).unwrap();
+        assert_eq!(third, U256::from(333_333_333_333_333_333_u64));
+        assert_eq!(div_up(U256::one(), U256::from(3)).unwrap(), third + 1);
+
+        assert_eq!(mul_down(U256::one(), U256::one()).unwrap(), U256::zero());
+        assert_eq!(mul_up(U256::one(), U256::one()).unwrap(), U256::one());
+        assert_eq!(mul_up(U256::zero(), U256::one()).unwrap(), U256::zero());
+    }
+
+    #[test]
+    fn test_pow_bounds_exact_power() {
+        let x = U256::from(1_050_000_000_000_000_000_u64);
+        let y = U256::from(2_500_000_000_000_000_000_u64);
+        let raw = log_exp_math::pow(x, y).unwrap();
+
+        assert!(pow_down(x, y).unwrap() < raw);
+        assert!(pow_up(x, y).unwrap() > raw);
+        assert_eq!(
+            pow_up(x, U256::from(ONE) * 2).unwrap(),
+            mul_up(x, x).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_complement() {
+        assert_eq!(complement(U256::from(ONE / 4)), U256::from(ONE / 4 * 3));
+        assert_eq!(complement(U256::from(ONE) * 2), U256::zero());
+    }
+}
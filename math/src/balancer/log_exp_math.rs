@@ -0,0 +1,289 @@
+// Port of Balancer's LogExpMath library.
+// Computes exponentials and logarithms of 18-decimal fixed-point numbers with the contract's exact truncation.
+
+use ethers::types::{I256, U256};
+
+use crate::error::MathError;
+
+/// Bounds of the natural exponent accepted by `exp`, in 18 decimals.
+const MAX_NATURAL_EXPONENT: i128 = 130_000_000_000_000_000_000;
+const MIN_NATURAL_EXPONENT: i128 = -41_000_000_000_000_000_000;
+
+/// `pow` computes ln with 36 decimals of precision when x is within these bounds, so x ^ y is precise for x close to one.
+const LN_36_LOWER_BOUND: i128 = ONE_18 - 100_000_000_000_000_000;
+const LN_36_UPPER_BOUND: i128 = ONE_18 + 100_000_000_000_000_000;
+
+const ONE_18: i128 = 1_000_000_000_000_000_000;
+const ONE_20: i128 = 100_000_000_000_000_000_000;
+
+// 18 decimal constants: x0 = 2^7 and x1 = 2^6, with e^x0 and e^x1 stored without decimals.
+const X0: i128 = 128_000_000_000_000_000_000;
+const A0: &str = "38877084059945950922200000000000000000000000000000000000";
+const X1: i128 = 64_000_000_000_000_000_000;
+const A1: &str = "6235149080811616882910000000";
+
+// 20 decimal constants: x_n = 2^(7 - n) and a_n = e^x_n.
+const X: [i128; 10] = [
+    3_200_000_000_000_000_000_000,
+    1_600_000_000_000_000_000_000,
+    800_000_000_000_000_000_000,
+    400_000_000_000_000_000_000,
+    200_000_000_000_000_000_000,
+    100_000_000_000_000_000_000,
+    50_000_000_000_000_000_000,
+    25_000_000_000_000_000_000,
+    12_500_000_000_000_000_000,
+    6_250_000_000_000_000_000,
+];
+const A: [&str; 10] = [
+    "7896296018268069516100000000000000",
+    "888611052050787263676000000",
+    "298095798704172827474000",
+    "5459815003314423907810",
+    "738905609893065022723",
+    "271828182845904523536",
+    "164872127070012814685",
+    "128402541668774148407",
+    "113314845306682631683",
+    "106449445891785942956",
+];
+
+fn int(value: i128) -> I256 {
+    I256::from(value)
+}
+
+fn constant(value: &str) -> I256 {
+    I256::from_dec_str(value).expect("valid constant")
+}
+
+/// Computes x ^ y for 18-decimal fixed-point numbers, as `LogExpMath.pow`.
+///
+/// Returns the power, or Err(MathError::Overflow) if an operand or the product ln(x) * y is out of bounds.
+pub fn pow(x: U256, y: U256) -> Result<U256, MathError> {
+    if y.is_zero() {
+        return Ok(U256::from(ONE_18 as u128));
+    }
+    if x.is_zero() {
+        return Ok(U256::zero());
+    }
+
+    // x must fit an int256, and y must be small enough for ln(x) * y not to overflow.
+    let x = I256::try_from(x).map_err(|_| MathError::Overflow)?;
+    let mild_exponent_bound = (U256::one() << 254) / U256::from(ONE_20 as u128);
+    if y >= mild_exponent_bound {
+        return Err(MathError::Overflow);
+    }
+    let y = I256::from_raw(y);
+
+    let logx_times_y = if int(LN_36_LOWER_BOUND) < x && x < int(LN_36_UPPER_BOUND) {
+        let ln_36_x = ln_36(x);
+        // ln_36_x has 36 decimals, so split it to multiply by y without overflowing.
+        (ln_36_x / int(ONE_18)) * y + ((ln_36_x % int(ONE_18)) * y) / int(ONE_18)
+    } else {
+        ln_unchecked(x) * y
+    } / int(ONE_18);
+
+    if logx_times_y < int(MIN_NATURAL_EXPONENT) || logx_times_y > int(MAX_NATURAL_EXPONENT) {
+        return Err(MathError::Overflow);
+    }
+    Ok(exp(logx_times_y)?.into_raw())
+}
+
+/// Computes e ^ x for an 18-decimal fixed-point exponent, as `LogExpMath.exp`.
+///
+/// Returns the exponential, or Err(MathError::Overflow) if x is outside [-41, 130].
+pub fn exp(x: I256) -> Result<I256, MathError> {
+    if x < int(MIN_NATURAL_EXPONENT) || x > int(MAX_NATURAL_EXPONENT) {
+        return Err(MathError::Overflow);
+    }
+    if x.is_negative() {
+        // e^(-x) = 1 / e^x, which keeps the intermediate values within bounds.
+        return Ok(int(ONE_18) * int(ONE_18) / exp(-x)?);
+    }
+
+    // Take out the largest power of two terms that fit, using the 18 decimal constants first.
+    let mut x = x;
+    let first_an = if x >= int(X0) {
+        x -= int(X0);
+        constant(A0)
+    } else if x >= int(X1) {
+        x -= int(X1);
+        constant(A1)
+    } else {
+        I256::one()
+    };
+
+    // Switch to 20 decimals for higher precision.
+    x *= int(100);
+    let mut product = int(ONE_20);
+    for (x_n, a_n) in X.iter().zip(A).take(8) {
+        if x >= int(*x_n) {
+            x -= int(*x_n);
+            product = product * constant(a_n) / int(ONE_20);
+        }
+    }
+
+    // x is now below 0.25, so the Taylor series converges within 12 terms.
+    let mut series_sum = int(ONE_20);
+    let mut term = x;
+    series_sum += term;
+    for n in 2..=12 {
+        term = term * x / int(ONE_20) / int(n);
+        series_sum += term;
+    }
+
+    Ok(product * series_sum / int(ONE_20) * first_an / int(100))
+}
+
+/// Computes the natural logarithm of an 18-decimal fixed-point number, as `LogExpMath.ln`.
+///
+/// Returns the logarithm, or Err(MathError::Overflow) if a is not positive.
+pub fn ln(a: I256) -> Result<I256, MathError> {
+    if a <= I256::zero() {
+        return Err(MathError::Overflow);
+    }
+    if int(LN_36_LOWER_BOUND) < a && a < int(LN_36_UPPER_BOUND) {
+        Ok(ln_36(a) / int(ONE_18))
+    } else {
+        Ok(ln_unchecked(a))
+    }
+}
+
+/// Natural logarithm with 18 decimals, by taking out powers of two and a short atanh series.
+fn ln_unchecked(a: I256) -> I256 {
+    if a < int(ONE_18) {
+        // ln(a) = -ln(1 / a), which keeps a above one.
+        return -ln_unchecked(int(ONE_18) * int(ONE_18) / a);
+    }
+
+    let mut a = a;
+    let mut sum = I256::zero();
+    if a >= constant(A0) * int(ONE_18) {
+        a /= constant(A0);
+        sum += int(X0);
+    }
+    if a >= constant(A1) * int(ONE_18) {
+        a /= constant(A1);
+        sum += int(X1);
+    }
+
+    // Switch to 20 decimals for higher precision.
+    sum *= int(100);
+    a *= int(100);
+    for (x_n, a_n) in X.iter().zip(A) {
+        if a >= constant(a_n) {
+            a = a * int(ONE_20) / constant(a_n);
+            sum += int(*x_n);
+        }
+    }
+
+    // a is now below a_11 (about 1.06), so ln(a) = 2 * atanh(z) with z = (a - 1) / (a + 1) converges quickly.
+    let z = (a - int(ONE_20)) * int(ONE_20) / (a + int(ONE_20));
+    let z_squared = z * z / int(ONE_20);
+    let mut num = z;
+    let mut series_sum = num;
+    for n in [3, 5, 7, 9, 11] {
+        num = num * z_squared / int(ONE_20);
+        series_sum += num / int(n);
+    }
+    series_sum *= int(2);
+
+    (sum + series_sum) / int(100)
+}
+
+/// Natural logarithm with 36 decimals for x close to one, where the 18-decimal series loses precision.
+fn ln_36(x: I256) -> I256 {
+    let one_36 = int(ONE_18) * int(ONE_18);
+    let x = x * int(ONE_18);
+
+    let z = (x - one_36) * one_36 / (x + one_36);
+    let z_squared = z * z / one_36;
+    let mut num = z;
+    let mut series_sum = num;
+    for n in [3, 5, 7, 9, 11, 13, 15] {
+        num = num * z_squared / one_36;
+        series_sum += num / int(n);
+    }
+
+    series_sum * int(2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u(value: &str) -> U256 {
+        U256::from_dec_str(value).unwrap()
+    }
+
+    fn i(value: &str) -> I256 {
+        I256::from_dec_str(value).unwrap()
+    }
+
+    // Expected values were computed with a line-by-line Python port of LogExpMath.sol and agree with
+    // arbitrary-precision results to within 1e-17.
+    #[test]
+    fn test_pow_vectors() {
+        let vectors = [
+            (
+                "2000000000000000000",
+                "2000000000000000000",
+                "3999999999999999996",
+            ),
+            (
+                "500000000000000000",
+                "3000000000000000000",
+                "125000000000000000",
+            ),
+            (
+                "1050000000000000000",
+                "2500000000000000000",
+                "1129726321947045720",
+            ),
+            (
+                "123456789000000000000000000",
+                "700000000000000000",
+                "461381826548226381267804",
+            ),
+        ];
+
+        for (x, y, expected) in vectors {
+            assert_eq!(pow(u(x), u(y)).unwrap(), u(expected));
+        }
+    }
+
+    #[test]
+    fn test_exp_and_ln() {
+        assert_eq!(
+            exp(i("1000000000000000000")).unwrap(),
+            i("2718281828459045235")
+        );
+        assert_eq!(
+            ln(i("10000000000000000000")).unwrap(),
+            i("2302585092994045683")
+        );
+        assert_eq!(ln(i("999000000000000000")).unwrap(), i("-1000500333583533"));
+        assert_eq!(exp(I256::zero()).unwrap(), i("1000000000000000000"));
+    }
+
+    #[test]
+    fn test_out_of_bounds() {
+        assert!(matches!(
+            exp(i("131000000000000000000")),
+            Err(MathError::Overflow)
+        ));
+        assert!(matches!(ln(I256::zero()), Err(MathError::Overflow)));
+        assert!(matches!(
+            pow(
+                u("1000000000000000000000000000000"),
+                u("10000000000000000000")
+            ),
+            Err(MathError::Overflow)
+        ));
+        assert_eq!(pow(U256::zero(), U256::one()).unwrap(), U256::zero());
+        assert_eq!(
+            pow(U256::zero(), U256::zero()).unwrap(),
+            u("1000000000000000000")
+        );
+    }
+}
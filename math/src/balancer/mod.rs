@@ -0,0 +1,365 @@
+// This module models Balancer V2 weighted pools.
+// Swaps follow the pool's `onSwap`: fees, scaling and WeightedMath rounding match the vault's accounting.
+
+pub mod fixed_point;
+pub mod log_exp_math;
+pub mod weighted_math;
+
+use ethers::types::{Address, U256};
+
+use crate::error::MathError;
+use crate::pool::{Pool, PRICE_SCALE};
+use crate::uniswap_v3::full_math::mul_div;
+use fixed_point::{complement, div_down, div_up, mul_down, mul_up, sub, ONE};
+use weighted_math::{calc_in_given_out, calc_out_given_in};
+
+/// Smallest normalized weight a token can have (1%).
+pub const MIN_WEIGHT: u64 = 10_000_000_000_000_000;
+
+/// Scaling factor of a token with the given decimals, so that `mul_down(amount, factor)` has 18 decimals.
+pub fn scaling_factor_for_decimals(decimals: u8) -> U256 {
+    U256::exp10(36 - decimals as usize)
+}
+
+/// Structure representing a Balancer V2 weighted pool.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WeightedPool {
+    /// Addresses of the pool's tokens, in the vault's registration order.
+    pub tokens: Vec<Address>,
+    /// Vault balances of the pool's tokens, in raw units.
+    pub balances: Vec<U256>,
+    /// Normalized weights in 18-decimal fixed point, summing to one.
+    pub weights: Vec<U256>,
+    /// Scaling factors upscaling raw amounts to 18 decimals, see `scaling_factor_for_decimals`.
+    pub scaling_factors: Vec<U256>,
+    /// Swap fee percentage in 18-decimal fixed point, as `getSwapFeePercentage()`.
+    pub swap_fee_percentage: U256,
+}
+
+impl WeightedPool {
+    /// Creates a pool from its balances, normalized weights, scaling factors and swap fee percentage.
+    ///
+    /// Returns the pool, or Err(MathError::InvalidPoolParameters) if the weights are not normalized or the
+    /// lengths do not match.
+    pub fn new(
+        balances: Vec<U256>,
+        weights: Vec<U256>,
+        scaling_factors: Vec<U256>,
+        swap_fee_percentage: U256,
+    ) -> Result<Self, MathError> {
+        let tokens = balances.len();
+        let weight_sum = weights
+            .iter()
+            .try_fold(U256::zero(), |sum, weight| sum.checked_add(*weight));
+        if tokens < 2
+            || weights.len() != tokens
+            || scaling_factors.len() != tokens
+            || scaling_factors.iter().any(|factor| factor.is_zero())
+            || weights
+                .iter()
+                .any(|weight| *weight < U256::from(MIN_WEIGHT))
+            || weight_sum != Some(U256::from(ONE))
+            || swap_fee_percentage >= U256::from(ONE)
+        {
+            return Err(MathError::InvalidPoolParameters);
+        }
+
+        Ok(Self {
+            tokens: vec![Address::zero(); tokens],
+            balances,
+            weights,
+            scaling_factors,
+            swap_fee_percentage,
+        })
+    }
+
+    /// Sets the addresses of the pool's tokens, needed to use the pool through the `Pool` trait.
+    pub fn with_tokens(mut self, tokens: Vec<Address>) -> Self {
+        self.tokens = tokens;
+        self
+    }
+
+    /// Quotes a GIVEN_IN swap of `amount_in` of token `i` for token `j`, as `onSwap` would.
+    ///
+    /// The fee is taken from the input before scaling, and the output is downscaled rounding down.
+    pub fn get_amount_out(&self, i: usize, j: usize, amount_in: U256) -> Result<U256, MathError> {
+        self.check_indexes(i, j)?;
+        if amount_in.is_zero() {
+            return Err(MathError::ZeroAmount);
+        }
+
+        let fee_amount = mul_up(amount_in, self.swap_fee_percentage)?;
+        let amount_in = mul_down(sub(amount_in, fee_amount)?, self.scaling_factors[i])?;
+        let amount_out = calc_out_given_in(
+            mul_down(self.balances[i], self.scaling_factors[i])?,
+            self.weights[i],
+            mul_down(self.balances[j], self.scaling_factors[j])?,
+            self.weights[j],
+            amount_in,
+        )?;
+
+        div_down(amount_out, self.scaling_factors[j])
+    }
+
+    /// Quotes a GIVEN_OUT swap receiving `amount_out` of token `j` for token `i`, as `onSwap` would.
+    ///
+    /// The input is downscaled rounding up, and the fee is added on top of it.
+    pub fn get_amount_in(&self, i: usize, j: usize, amount_out: U256) -> Result<U256, MathError> {
+        self.check_indexes(i, j)?;
+        if amount_out.is_zero() {
+            return Err(MathError::ZeroAmount);
+        }
+
+        let amount_in = calc_in_given_out(
+            mul_down(self.balances[i], self.scaling_factors[i])?,
+            self.weights[i],
+            mul_down(self.balances[j], self.scaling_factors[j])?,
+            self.weights[j],
+            mul_down(amount_out, self.scaling_factors[j])?,
+        )?;
+        let amount_in = div_up(amount_in, self.scaling_factors[i])?;
+
+        div_up(amount_in, complement(self.swap_fee_percentage))
+    }
+
+    /// Swaps `amount_in` of token `i` for token `j` and updates the vault balances.
+    ///
+    /// Returns the output amount, or Err(MathError) if the quote fails.
+    pub fn swap(&mut self, i: usize, j: usize, amount_in: U256) -> Result<U256, MathError> {
+        let amount_out = self.get_amount_out(i, j, amount_in)?;
+
+        // The vault credits the whole input, fee included; protocol fees are only collected on joins and exits.
+        self.balances[i] = self.balances[i]
+            .checked_add(amount_in)
+            .ok_or(MathError::Overflow)?;
+        self.balances[j] = sub(self.balances[j], amount_out)?;
+
+        Ok(amount_out)
+    }
+
+    fn check_indexes(&self, i: usize, j: usize) -> Result<(), MathError> {
+        if i == j || i >= self.balances.len() || j >= self.balances.len() {
+            return Err(MathError::InvalidPoolParameters);
+        }
+        Ok(())
+    }
+
+    /// Resolves a token pair to token indexes.
+    fn indexes(&self, token_in: Address, token_out: Address) -> Result<(usize, usize), MathError> {
+        let position = |token: Address| {
+            self.tokens
+                .iter()
+                .position(|candidate| *candidate == token)
+                .ok_or(MathError::UnknownToken(token))
+        };
+
+        let (i, j) = (position(token_in)?, position(token_out)?);
+        if i == j {
+            return Err(MathError::UnknownToken(token_out));
+        }
+        Ok((i, j))
+    }
+}
+
+impl Pool for WeightedPool {
+    fn tokens(&self) -> Vec<Address> {
+        self.tokens.clone()
+    }
+
+    fn fee(&self) -> u32 {
+        (self.swap_fee_percentage / 1_000_000_000_000_u64).low_u32()
+    }
+
+    fn quote_exact_in(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+    ) -> Result<U256, MathError> {
+        let (i, j) = self.indexes(token_in, token_out)?;
+        self.get_amount_out(i, j, amount_in)
+    }
+
+    fn quote_exact_out(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_out: U256,
+    ) -> Result<U256, MathError> {
+        let (i, j) = self.indexes(token_in, token_out)?;
+        self.get_amount_in(i, j, amount_out)
+    }
+
+    fn apply_swap(
+        &mut self,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+    ) -> Result<U256, MathError> {
+        let (i, j) = self.indexes(token_in, token_out)?;
+        self.swap(i, j, amount_in)
+    }
+
+    /// Marginal price (balanceOut / weightOut) / (balanceIn / weightIn), in raw units.
+    fn spot_price(&self, token_in: Address, token_out: Address) -> Result<U256, MathError> {
+        let (i, j) = self.indexes(token_in, token_out)?;
+        if self.balances[i].is_zero() {
+            return Err(MathError::InsufficientLiquidity);
+        }
+
+        let price = mul_div(U256::from(PRICE_SCALE), self.balances[j], self.balances[i])?;
+        mul_div(price, self.weights[i], self.weights[j])
+    }
+
+    /// Pools with equal weights are constant-product pools, so their balances are the virtual reserves.
+    fn virtual_reserves(&self, token_in: Address, token_out: Address) -> Option<(U256, U256)> {
+        let (i, j) = self.indexes(token_in, token_out).ok()?;
+        (self.weights[i] == self.weights[j]).then(|| (self.balances[i], self.balances[j]))
+    }
+
+    fn clone_box(&self) -> Box<dyn Pool> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u(value: &str) -> U256 {
+        U256::from_dec_str(value).unwrap()
+    }
+
+    fn token(byte: u8) -> Address {
+        Address::repeat_byte(byte)
+    }
+
+    /// 80/20 BAL/WETH pool with a 0.25% swap fee.
+    fn create_80_20_pool() -> WeightedPool {
+        WeightedPool::new(
+            vec![u("2534112000000000123456789"), u("1203000000000987654321")],
+            vec![u("800000000000000000"), u("200000000000000000")],
+            vec![
+                scaling_factor_for_decimals(18),
+                scaling_factor_for_decimals(18),
+            ],
+            u("2500000000000000"),
+        )
+        .unwrap()
+        .with_tokens(vec![token(1), token(2)])
+    }
+
+    /// 50/50 WMATIC/USDC pool with a 0.3% swap fee and a 6-decimal token.
+    fn create_50_50_pool() -> WeightedPool {
+        WeightedPool::new(
+            vec![u("3150000000000000000000011"), u("2580000000007")],
+            vec![u("500000000000000000"), u("500000000000000000")],
+            vec![
+                scaling_factor_for_decimals(18),
+                scaling_factor_for_decimals(6),
+            ],
+            u("3000000000000000"),
+        )
+        .unwrap()
+        .with_tokens(vec![token(1), token(2)])
+    }
+
+    // Expected values were computed with a line-by-line Python port of FixedPoint, LogExpMath,
+    // WeightedMath and BaseMinimalSwapInfoPool.onSwap.
+    #[test]
+    fn test_given_in_vectors() {
+        let pool = create_80_20_pool();
+        assert_eq!(
+            pool.get_amount_out(0, 1, u("10000000000000000000000"))
+                .unwrap(),
+            u("18756487997259240937")
+        );
+        assert_eq!(
+            pool.get_amount_out(1, 0, u("5000000000000000000")).unwrap(),
+            u("2619749686022490134348")
+        );
+
+        let pool = create_50_50_pool();
+        assert_eq!(
+            pool.get_amount_out(0, 1, u("25000000000000000000000"))
+                .unwrap(),
+            u("20254494200")
+        );
+        assert_eq!(
+            pool.get_amount_out(1, 0, u("12345000000")).unwrap(),
+            u("14955819398524012050000")
+        );
+    }
+
+    #[test]
+    fn test_given_out_vectors() {
+        let pool = create_80_20_pool();
+        assert_eq!(
+            pool.get_amount_in(0, 1, u("3000000000000000000")).unwrap(),
+            u("1586303088158580248062")
+        );
+        assert_eq!(
+            pool.get_amount_in(1, 0, u("40000000000000000000000"))
+                .unwrap(),
+            u("79248352741598741160")
+        );
+
+        let pool = create_50_50_pool();
+        assert_eq!(
+            pool.get_amount_in(1, 0, u("25000000000000000000000"))
+                .unwrap(),
+            u("20702106320")
+        );
+    }
+
+    #[test]
+    fn test_swap_updates_balances() {
+        let mut pool = create_80_20_pool();
+        let amount_in = u("5000000000000000000");
+        let quote = pool.get_amount_out(1, 0, amount_in).unwrap();
+
+        assert_eq!(pool.swap(1, 0, amount_in).unwrap(), quote);
+        assert_eq!(pool.balances[1], u("1208000000000987654321"));
+        assert_eq!(pool.balances[0], u("2534112000000000123456789") - quote);
+    }
+
+    #[test]
+    fn test_pool_interface() {
+        let pool = create_50_50_pool();
+
+        assert_eq!(pool.fee(), 3000);
+        assert!(pool.virtual_reserves(token(1), token(2)).is_some());
+        assert!(create_80_20_pool()
+            .virtual_reserves(token(1), token(2))
+            .is_none());
+
+        // 80/20 pools price the heavy token at four times its balance ratio.
+        let price = create_80_20_pool().spot_price(token(1), token(2)).unwrap();
+        let balance_ratio = mul_div(
+            U256::from(PRICE_SCALE),
+            u("1203000000000987654321"),
+            u("2534112000000000123456789"),
+        )
+        .unwrap();
+        assert_eq!(price, balance_ratio * 4);
+
+        assert!(matches!(
+            pool.quote_exact_in(token(1), token(3), U256::one()),
+            Err(MathError::UnknownToken(address)) if address == token(3)
+        ));
+    }
+
+    #[test]
+    fn test_invalid_weights() {
+        let result = WeightedPool::new(
+            vec![U256::one(), U256::one()],
+            vec![u("500000000000000000"), u("400000000000000000")],
+            vec![
+                scaling_factor_for_decimals(18),
+                scaling_factor_for_decimals(18),
+            ],
+            U256::zero(),
+        );
+        assert!(matches!(result, Err(MathError::InvalidPoolParameters)));
+    }
+}
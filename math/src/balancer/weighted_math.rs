@@ -0,0 +1,89 @@
+// Port of Balancer's WeightedMath library.
+// Prices swaps against the weighted product invariant, with all amounts upscaled to 18 decimals.
+
+use ethers::types::U256;
+
+use super::fixed_point::{complement, div_down, div_up, mul_down, mul_up, pow_up, sub, ONE};
+use crate::error::MathError;
+
+/// Swaps cannot take in more than 30% of the input token's balance.
+pub const MAX_IN_RATIO: u64 = 300_000_000_000_000_000;
+/// Swaps cannot take out more than 30% of the output token's balance.
+pub const MAX_OUT_RATIO: u64 = 300_000_000_000_000_000;
+
+/// Computes the output of swapping `amount_in` after fees, as `_calcOutGivenIn`.
+///
+/// outGivenIn = balanceOut * (1 - (balanceIn / (balanceIn + amountIn)) ^ (weightIn / weightOut)), rounded
+/// down so the pool never pays out more than the invariant allows.
+///
+/// Returns the output amount, or Err(MathError::MaxInRatio) if the input exceeds 30% of the balance.
+pub fn calc_out_given_in(
+    balance_in: U256,
+    weight_in: U256,
+    balance_out: U256,
+    weight_out: U256,
+    amount_in: U256,
+) -> Result<U256, MathError> {
+    if amount_in > mul_down(balance_in, U256::from(MAX_IN_RATIO))? {
+        return Err(MathError::MaxInRatio);
+    }
+
+    let denominator = balance_in
+        .checked_add(amount_in)
+        .ok_or(MathError::Overflow)?;
+    let base = div_up(balance_in, denominator)?;
+    let exponent = div_down(weight_in, weight_out)?;
+    let power = pow_up(base, exponent)?;
+
+    mul_down(balance_out, complement(power))
+}
+
+/// Computes the input before fees required to receive `amount_out`, as `_calcInGivenOut`.
+///
+/// inGivenOut = balanceIn * ((balanceOut / (balanceOut - amountOut)) ^ (weightOut / weightIn) - 1), rounded
+/// up so the pool never takes in less than the invariant requires.
+///
+/// Returns the input amount, or Err(MathError::MaxOutRatio) if the output exceeds 30% of the balance.
+pub fn calc_in_given_out(
+    balance_in: U256,
+    weight_in: U256,
+    balance_out: U256,
+    weight_out: U256,
+    amount_out: U256,
+) -> Result<U256, MathError> {
+    if amount_out > mul_down(balance_out, U256::from(MAX_OUT_RATIO))? {
+        return Err(MathError::MaxOutRatio);
+    }
+
+    let base = div_up(balance_out, sub(balance_out, amount_out)?)?;
+    let exponent = div_up(weight_out, weight_in)?;
+    let power = pow_up(base, exponent)?;
+    let ratio = sub(power, U256::from(ONE))?;
+
+    mul_up(balance_in, ratio)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u(value: &str) -> U256 {
+        U256::from_dec_str(value).unwrap()
+    }
+
+    #[test]
+    fn test_max_ratios() {
+        let balance = u("1000000000000000000000");
+        let weight = u("500000000000000000");
+
+        assert!(calc_out_given_in(balance, weight, balance, weight, balance * 3 / 10).is_ok());
+        assert!(matches!(
+            calc_out_given_in(balance, weight, balance, weight, balance * 3 / 10 + 1),
+            Err(MathError::MaxInRatio)
+        ));
+        assert!(matches!(
+            calc_in_given_out(balance, weight, balance, weight, balance * 3 / 10 + 1),
+            Err(MathError::MaxOutRatio)
+        ));
+    }
+}
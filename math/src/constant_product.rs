@@ -0,0 +1,313 @@
+// This module models constant-product (x * y = k) pools such as Uniswap V2 and its forks.
+// Quotes reproduce each fork's `getAmountOut`/`getAmountIn` integer rounding exactly.
+
+use ethers::types::{Address, U256};
+
+use crate::error::MathError;
+use crate::pool::{zero_for_one, Pool, PRICE_SCALE};
+use crate::uniswap_v3::full_math::mul_div;
+
+/// Uniswap V2: 0.3% fee, applied as `amountIn * 997 / 1000`.
+pub const UNISWAP_V2_FEE: (u32, u32) = (997, 1000);
+/// QuickSwap: 0.3% fee, applied as `amountIn * 997 / 1000`.
+pub const QUICKSWAP_FEE: (u32, u32) = (997, 1000);
+/// SushiSwap: 0.3% fee, applied as `amountIn * 997 / 1000`.
+pub const SUSHISWAP_FEE: (u32, u32) = (997, 1000);
+/// PancakeSwap V2: 0.25% fee, applied as `amountIn * 9975 / 10000`.
+pub const PANCAKESWAP_FEE: (u32, u32) = (9975, 10000);
+/// ApeSwap: 0.2% fee, applied as `amountIn * 998 / 1000`.
+pub const APESWAP_FEE: (u32, u32) = (998, 1000);
+
+/// Structure representing a constant-product pool.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConstantProductPool {
+    /// Address of token0.
+    pub token0: Address,
+    /// Address of token1.
+    pub token1: Address,
+    /// Reserve of token0.
+    pub reserve0: U256,
+    /// Reserve of token1.
+    pub reserve1: U256,
+    /// Share of the input amount that is swapped after fees, over `fee_denominator`.
+    pub fee_numerator: u32,
+    /// Denominator of the fee fraction, as used by the fork's library (1000 or 10000).
+    pub fee_denominator: u32,
+}
+
+impl ConstantProductPool {
+    /// Creates a pool with a fork's fee fraction, e.g. `ConstantProductPool::new(r0, r1, APESWAP_FEE)`.
+    pub fn new(reserve0: U256, reserve1: U256, fee: (u32, u32)) -> Self {
+        Self {
+            reserve0,
+            reserve1,
+            fee_numerator: fee.0,
+            fee_denominator: fee.1,
+            ..Default::default()
+        }
+    }
+
+    /// Sets the pair's token addresses, needed to use the pool through the `Pool` trait.
+    pub fn with_tokens(mut self, token0: Address, token1: Address) -> Self {
+        self.token0 = token0;
+        self.token1 = token1;
+        self
+    }
+
+    /// Fee of the pool in hundredths of a bip, the unit Uniswap V3 uses.
+    pub fn fee(&self) -> u32 {
+        if self.fee_denominator == 0 {
+            return 0;
+        }
+        ((self.fee_denominator - self.fee_numerator) as u64 * 1_000_000
+            / self.fee_denominator as u64) as u32
+    }
+
+    /// Returns the reserves ordered as (reserve_in, reserve_out) for a swap direction.
+    pub fn reserves(&self, zero_for_one: bool) -> (U256, U256) {
+        if zero_for_one {
+            (self.reserve0, self.reserve1)
+        } else {
+            (self.reserve1, self.reserve0)
+        }
+    }
+
+    /// Computes the output amount for an exact input, as the fork's `getAmountOut`.
+    ///
+    /// Returns the output amount, or Err(MathError) if the pool is empty or the amounts overflow.
+    pub fn get_amount_out(&self, amount_in: U256, zero_for_one: bool) -> Result<U256, MathError> {
+        if amount_in.is_zero() {
+            return Err(MathError::ZeroAmount);
+        }
+        let (reserve_in, reserve_out) = self.reserves(zero_for_one);
+        if reserve_in.is_zero() || reserve_out.is_zero() {
+            return Err(MathError::InsufficientLiquidity);
+        }
+
+        let amount_in_with_fee = amount_in
+            .checked_mul(U256::from(self.fee_numerator))
+            .ok_or(MathError::Overflow)?;
+        let numerator = amount_in_with_fee
+            .checked_mul(reserve_out)
+            .ok_or(MathError::Overflow)?;
+        let denominator = reserve_in
+            .checked_mul(U256::from(self.fee_denominator))
+            .and_then(|value| value.checked_add(amount_in_with_fee))
+            .ok_or(MathError::Overflow)?;
+
+        Ok(numerator / denominator)
+    }
+
+    /// Computes the input amount required for an exact output, as the fork's `getAmountIn`.
+    ///
+    /// Returns the input amount, or Err(MathError) if the pool cannot provide the output.
+    pub fn get_amount_in(&self, amount_out: U256, zero_for_one: bool) -> Result<U256, MathError> {
+        if amount_out.is_zero() {
+            return Err(MathError::ZeroAmount);
+        }
+        let (reserve_in, reserve_out) = self.reserves(zero_for_one);
+        if reserve_in.is_zero() || reserve_out <= amount_out {
+            return Err(MathError::InsufficientLiquidity);
+        }
+
+        let numerator = reserve_in
+            .checked_mul(amount_out)
+            .and_then(|value| value.checked_mul(U256::from(self.fee_denominator)))
+            .ok_or(MathError::Overflow)?;
+        let denominator = (reserve_out - amount_out)
+            .checked_mul(U256::from(self.fee_numerator))
+            .ok_or(MathError::Overflow)?;
+        if denominator.is_zero() {
+            return Err(MathError::DivisionByZero);
+        }
+
+        Ok(numerator / denominator + 1)
+    }
+
+    /// Swaps an exact input through the pool and updates its reserves, as the pair's `swap` would.
+    ///
+    /// Returns the output amount, or Err(MathError) if the quote fails.
+    pub fn swap(&mut self, amount_in: U256, zero_for_one: bool) -> Result<U256, MathError> {
+        let amount_out = self.get_amount_out(amount_in, zero_for_one)?;
+        let (reserve_in, reserve_out) = self.reserves(zero_for_one);
+
+        let reserve_in = reserve_in
+            .checked_add(amount_in)
+            .ok_or(MathError::Overflow)?;
+        let reserve_out = reserve_out - amount_out;
+
+        if zero_for_one {
+            self.reserve0 = reserve_in;
+            self.reserve1 = reserve_out;
+        } else {
+            self.reserve0 = reserve_out;
+            self.reserve1 = reserve_in;
+        }
+
+        Ok(amount_out)
+    }
+}
+
+impl Pool for ConstantProductPool {
+    fn tokens(&self) -> Vec<Address> {
+        vec![self.token0, self.token1]
+    }
+
+    fn fee(&self) -> u32 {
+        ConstantProductPool::fee(self)
+    }
+
+    fn quote_exact_in(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+    ) -> Result<U256, MathError> {
+        let zero_for_one = zero_for_one(self.token0, self.token1, token_in, token_out)?;
+        self.get_amount_out(amount_in, zero_for_one)
+    }
+
+    fn quote_exact_out(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_out: U256,
+    ) -> Result<U256, MathError> {
+        let zero_for_one = zero_for_one(self.token0, self.token1, token_in, token_out)?;
+        self.get_amount_in(amount_out, zero_for_one)
+    }
+
+    fn apply_swap(
+        &mut self,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+    ) -> Result<U256, MathError> {
+        let zero_for_one = zero_for_one(self.token0, self.token1, token_in, token_out)?;
+        self.swap(amount_in, zero_for_one)
+    }
+
+    fn spot_price(&self, token_in: Address, token_out: Address) -> Result<U256, MathError> {
+        let zero_for_one = zero_for_one(self.token0, self.token1, token_in, token_out)?;
+        let (reserve_in, reserve_out) = self.reserves(zero_for_one);
+        if reserve_in.is_zero() {
+            return Err(MathError::InsufficientLiquidity);
+        }
+
+        mul_div(reserve_out, U256::from(PRICE_SCALE), reserve_in)
+    }
+
+    fn virtual_reserves(&self, token_in: Address, token_out: Address) -> Option<(U256, U256)> {
+        let zero_for_one = zero_for_one(self.token0, self.token1, token_in, token_out).ok()?;
+        Some(self.reserves(zero_for_one))
+    }
+
+    fn clone_box(&self) -> Box<dyn Pool> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u(value: &str) -> U256 {
+        U256::from_dec_str(value).unwrap()
+    }
+
+    #[test]
+    fn test_router_library_vectors() {
+        let pool = ConstantProductPool::new(U256::from(100), U256::from(100), UNISWAP_V2_FEE);
+
+        assert_eq!(
+            pool.get_amount_out(U256::from(2), true).unwrap(),
+            U256::from(1)
+        );
+        assert_eq!(
+            pool.get_amount_in(U256::from(1), true).unwrap(),
+            U256::from(2)
+        );
+    }
+
+    #[test]
+    fn test_fork_rounding() {
+        let vectors = [
+            (
+                UNISWAP_V2_FEE,
+                "71357141579073979454900",
+                "29426082102132045761",
+            ),
+            (
+                PANCAKESWAP_FEE,
+                "71392714603100443843596",
+                "29411332186291378068",
+            ),
+            (
+                APESWAP_FEE,
+                "71428287414959224165695",
+                "29396597049925500625",
+            ),
+        ];
+
+        for (fee, amount_out, amount_in) in vectors {
+            let pool = ConstantProductPool::new(
+                u("5000000000000000000000"),
+                u("12000000000000000000000000"),
+                fee,
+            );
+
+            assert_eq!(
+                pool.get_amount_out(u("30000000000000000000"), true)
+                    .unwrap(),
+                u(amount_out)
+            );
+            assert_eq!(
+                pool.get_amount_in(u("70000000000000000000000"), true)
+                    .unwrap(),
+                u(amount_in)
+            );
+        }
+    }
+
+    #[test]
+    fn test_fee_in_pips() {
+        assert_eq!(
+            ConstantProductPool::new(U256::zero(), U256::zero(), QUICKSWAP_FEE).fee(),
+            3000
+        );
+        assert_eq!(
+            ConstantProductPool::new(U256::zero(), U256::zero(), PANCAKESWAP_FEE).fee(),
+            2500
+        );
+        assert_eq!(
+            ConstantProductPool::new(U256::zero(), U256::zero(), APESWAP_FEE).fee(),
+            2000
+        );
+    }
+
+    #[test]
+    fn test_swap_updates_reserves() {
+        let mut pool = ConstantProductPool::new(u("1000000"), u("2000000"), SUSHISWAP_FEE);
+        let quote = pool.get_amount_out(U256::from(1000), false).unwrap();
+
+        let amount_out = pool.swap(U256::from(1000), false).unwrap();
+
+        assert_eq!(amount_out, quote);
+        assert_eq!(pool.reserve1, u("2001000"));
+        assert_eq!(pool.reserve0, u("1000000") - amount_out);
+    }
+
+    #[test]
+    fn test_insufficient_liquidity() {
+        let pool = ConstantProductPool::new(U256::from(100), U256::from(100), UNISWAP_V2_FEE);
+
+        assert!(matches!(
+            pool.get_amount_in(U256::from(100), true),
+            Err(MathError::InsufficientLiquidity)
+        ));
+        assert!(matches!(
+            pool.get_amount_out(U256::zero(), true),
+            Err(MathError::ZeroAmount)
+        ));
+    }
+}
@@ -0,0 +1,74 @@
+// This module decodes victim swaps from router calldata.
+// Every supported router is reduced to the same `DecodedSwap`, so sizing code does not care how a swap was sent.
+
+pub mod router;
+pub mod universal_router;
+
+use ethers::types::{Address, U256};
+
+pub use crate::error::DecodeError;
+pub use router::decode_router_call;
+pub use universal_router::decode_universal_router_call;
+
+/// AMM a decoded swap trades through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    /// Uniswap V2 style constant-product pairs, addressed by token path.
+    UniswapV2,
+    /// Uniswap V3 pools, addressed by token path and fee tier.
+    UniswapV3,
+}
+
+/// Whether the input or the output amount of a swap is fixed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapKind {
+    /// `amount_in` is spent exactly and `amount_out` is the minimum accepted output.
+    ExactInput,
+    /// `amount_out` is received exactly and `amount_in` is the maximum accepted input.
+    ExactOutput,
+}
+
+/// A swap decoded from router calldata.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedSwap {
+    /// AMM the swap is routed through.
+    pub protocol: Protocol,
+    /// Whether the input or the output amount is fixed.
+    pub kind: SwapKind,
+    /// Tokens from input to output, whatever order the calldata encodes them in.
+    pub path: Vec<Address>,
+    /// Fee tier of each hop in pips, for V3 swaps; empty for V2 swaps.
+    pub fees: Vec<u32>,
+    /// Exact input, or the maximum input of exact-output swaps.
+    pub amount_in: U256,
+    /// Minimum output, or the exact output of exact-output swaps.
+    pub amount_out: U256,
+    /// Recipient of the output, as encoded; routers use small sentinel addresses for the sender and themselves.
+    pub recipient: Address,
+    /// Deadline of the swap or its multicall, if the call has one.
+    pub deadline: Option<U256>,
+    /// Whether the router carries on if the swap reverts, as Universal Router commands flagged allow-revert.
+    pub allow_revert: bool,
+}
+
+impl DecodedSwap {
+    /// Token sold by the swap.
+    pub fn token_in(&self) -> Option<Address> {
+        self.path.first().copied()
+    }
+
+    /// Token bought by the swap.
+    pub fn token_out(&self) -> Option<Address> {
+        self.path.last().copied()
+    }
+}
+
+/// Decodes the swaps of a classic router or Universal Router call sent with `value` wei.
+///
+/// Returns the swaps in execution order, or Err(DecodeError) if the call is not a supported swap.
+pub fn decode_swaps(input: &[u8], value: U256) -> Result<Vec<DecodedSwap>, DecodeError> {
+    match decode_universal_router_call(input, value) {
+        Err(DecodeError::UnknownSelector(_)) => decode_router_call(input, value),
+        result => result,
+    }
+}
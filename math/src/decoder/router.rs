@@ -0,0 +1,604 @@
+// Decodes calls to UniswapV2Router02, the V3 SwapRouter and SwapRouter02, including their multicalls.
+// Selectors are derived from the function signatures, so the table stays readable and verifiable.
+
+use ethers::abi::{decode, ParamType, Token};
+use ethers::types::{Address, U256};
+use ethers::utils::id;
+
+use super::{DecodedSwap, Protocol, SwapKind};
+use crate::error::DecodeError;
+
+/// Length of an address in a packed V3 path.
+const ADDRESS_SIZE: usize = 20;
+/// Length of a fee tier in a packed V3 path.
+const FEE_SIZE: usize = 3;
+
+/// Shape of a supported router function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Call {
+    /// V2 swap; `native` swaps take their input from msg.value.
+    V2 {
+        kind: SwapKind,
+        native: bool,
+        deadline: bool,
+    },
+    /// V3 `exactInputSingle`/`exactOutputSingle`.
+    V3Single { kind: SwapKind, deadline: bool },
+    /// V3 `exactInput`/`exactOutput` over a packed path.
+    V3Path { kind: SwapKind, deadline: bool },
+    /// `multicall` with an optional leading deadline or previous block hash.
+    Multicall { prefix: Option<ParamKind> },
+}
+
+/// Leading argument of a multicall variant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ParamKind {
+    Deadline,
+    BlockHash,
+}
+
+const EXACT_IN: SwapKind = SwapKind::ExactInput;
+const EXACT_OUT: SwapKind = SwapKind::ExactOutput;
+
+/// Supported router functions.
+const CALLS: &[(&str, Call)] = &[
+    // UniswapV2Router02.
+    (
+        "swapExactTokensForTokens(uint256,uint256,address[],address,uint256)",
+        Call::V2 { kind: EXACT_IN, native: false, deadline: true },
+    ),
+    (
+        "swapTokensForExactTokens(uint256,uint256,address[],address,uint256)",
+        Call::V2 { kind: EXACT_OUT, native: false, deadline: true },
+    ),
+    (
+        "swapExactETHForTokens(uint256,address[],address,uint256)",
+        Call::V2 { kind: EXACT_IN, native: true, deadline: true },
+    ),
+    (
+        "swapTokensForExactETH(uint256,uint256,address[],address,uint256)",
+        Call::V2 { kind: EXACT_OUT, native: false, deadline: true },
+    ),
+    (
+        "swapExactTokensForETH(uint256,uint256,address[],address,uint256)",
+        Call::V2 { kind: EXACT_IN, native: false, deadline: true },
+    ),
+    (
+        "swapETHForExactTokens(uint256,address[],address,uint256)",
+        Call::V2 { kind: EXACT_OUT, native: true, deadline: true },
+    ),
+    (
+        "swapExactTokensForTokensSupportingFeeOnTransferTokens(uint256,uint256,address[],address,uint256)",
+        Call::V2 { kind: EXACT_IN, native: false, deadline: true },
+    ),
+    (
+        "swapExactETHForTokensSupportingFeeOnTransferTokens(uint256,address[],address,uint256)",
+        Call::V2 { kind: EXACT_IN, native: true, deadline: true },
+    ),
+    (
+        "swapExactTokensForETHSupportingFeeOnTransferTokens(uint256,uint256,address[],address,uint256)",
+        Call::V2 { kind: EXACT_IN, native: false, deadline: true },
+    ),
+    // SwapRouter02 V2 swaps, which rely on the multicall deadline.
+    (
+        "swapExactTokensForTokens(uint256,uint256,address[],address)",
+        Call::V2 { kind: EXACT_IN, native: false, deadline: false },
+    ),
+    (
+        "swapTokensForExactTokens(uint256,uint256,address[],address)",
+        Call::V2 { kind: EXACT_OUT, native: false, deadline: false },
+    ),
+    // SwapRouter.
+    (
+        "exactInputSingle((address,address,uint24,address,uint256,uint256,uint256,uint160))",
+        Call::V3Single { kind: EXACT_IN, deadline: true },
+    ),
+    (
+        "exactOutputSingle((address,address,uint24,address,uint256,uint256,uint256,uint160))",
+        Call::V3Single { kind: EXACT_OUT, deadline: true },
+    ),
+    (
+        "exactInput((bytes,address,uint256,uint256,uint256))",
+        Call::V3Path { kind: EXACT_IN, deadline: true },
+    ),
+    (
+        "exactOutput((bytes,address,uint256,uint256,uint256))",
+        Call::V3Path { kind: EXACT_OUT, deadline: true },
+    ),
+    // SwapRouter02, whose parameter structs dropped the deadline.
+    (
+        "exactInputSingle((address,address,uint24,address,uint256,uint256,uint160))",
+        Call::V3Single { kind: EXACT_IN, deadline: false },
+    ),
+    (
+        "exactOutputSingle((address,address,uint24,address,uint256,uint256,uint160))",
+        Call::V3Single { kind: EXACT_OUT, deadline: false },
+    ),
+    (
+        "exactInput((bytes,address,uint256,uint256))",
+        Call::V3Path { kind: EXACT_IN, deadline: false },
+    ),
+    (
+        "exactOutput((bytes,address,uint256,uint256))",
+        Call::V3Path { kind: EXACT_OUT, deadline: false },
+    ),
+    // Multicall wrappers.
+    ("multicall(bytes[])", Call::Multicall { prefix: None }),
+    (
+        "multicall(uint256,bytes[])",
+        Call::Multicall { prefix: Some(ParamKind::Deadline) },
+    ),
+    (
+        "multicall(bytes32,bytes[])",
+        Call::Multicall { prefix: Some(ParamKind::BlockHash) },
+    ),
+];
+
+/// Decodes the swaps of a call to a classic Uniswap router sent with `value` wei.
+///
+/// Multicalls are flattened in execution order; their non-swap calls, such as `refundETH` or `sweepToken`,
+/// are skipped.
+///
+/// Returns the swaps, or Err(DecodeError) if the call is not a supported swap or multicall.
+pub fn decode_router_call(input: &[u8], value: U256) -> Result<Vec<DecodedSwap>, DecodeError> {
+    decode_call(input, value, None)
+}
+
+fn decode_call(
+    input: &[u8],
+    value: U256,
+    outer_deadline: Option<U256>,
+) -> Result<Vec<DecodedSwap>, DecodeError> {
+    if input.len() < 4 {
+        return Err(DecodeError::TooShort);
+    }
+    let selector: [u8; 4] = input[..4].try_into().expect("four bytes");
+    let call = CALLS
+        .iter()
+        .find(|(signature, _)| id(signature) == selector)
+        .map(|(_, call)| *call)
+        .ok_or(DecodeError::UnknownSelector(selector))?;
+    let data = &input[4..];
+
+    let mut swap = match call {
+        Call::V2 {
+            kind,
+            native,
+            deadline,
+        } => decode_v2(data, value, kind, native, deadline)?,
+        Call::V3Single { kind, deadline } => decode_v3_single(data, kind, deadline)?,
+        Call::V3Path { kind, deadline } => decode_v3_path(data, kind, deadline)?,
+        Call::Multicall { prefix } => return decode_multicall(data, value, prefix, outer_deadline),
+    };
+
+    swap.deadline = swap.deadline.or(outer_deadline);
+    Ok(vec![swap])
+}
+
+fn decode_multicall(
+    data: &[u8],
+    value: U256,
+    prefix: Option<ParamKind>,
+    outer_deadline: Option<U256>,
+) -> Result<Vec<DecodedSwap>, DecodeError> {
+    let calls = ParamType::Array(Box::new(ParamType::Bytes));
+    let (tokens, deadline) = match prefix {
+        None => (decode(&[calls], data)?, outer_deadline),
+        Some(ParamKind::BlockHash) => {
+            let mut tokens = decode(&[ParamType::FixedBytes(32), calls], data)?;
+            (tokens.split_off(1), outer_deadline)
+        }
+        Some(ParamKind::Deadline) => {
+            let mut tokens = decode(&[ParamType::Uint(256), calls], data)?;
+            let calls = tokens.split_off(1);
+            (calls, Some(uint(&tokens[0])?))
+        }
+    };
+
+    let mut swaps = Vec::new();
+    for call in array(&tokens[0])? {
+        let call = call.into_bytes().ok_or(DecodeError::UnexpectedArgument)?;
+        match decode_call(&call, value, deadline) {
+            Ok(decoded) => swaps.extend(decoded),
+            // Multicalls also carry permits, refunds and sweeps, which are not swaps.
+            Err(DecodeError::UnknownSelector(_)) => continue,
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(swaps)
+}
+
+fn decode_v2(
+    data: &[u8],
+    value: U256,
+    kind: SwapKind,
+    native: bool,
+    deadline: bool,
+) -> Result<DecodedSwap, DecodeError> {
+    let mut params = Vec::new();
+    // Native swaps take the fixed input, or the maximum input, from msg.value.
+    if !(native && kind == SwapKind::ExactInput) {
+        params.push(ParamType::Uint(256));
+    }
+    if !(native && kind == SwapKind::ExactOutput) {
+        params.push(ParamType::Uint(256));
+    }
+    params.push(ParamType::Array(Box::new(ParamType::Address)));
+    params.push(ParamType::Address);
+    if deadline {
+        params.push(ParamType::Uint(256));
+    }
+
+    let tokens = decode(&params, data)?;
+    let (amounts, rest) = tokens.split_at(params.len() - 2 - deadline as usize);
+    let (amount_in, amount_out) = match (kind, native) {
+        (SwapKind::ExactInput, false) => (uint(&amounts[0])?, uint(&amounts[1])?),
+        (SwapKind::ExactInput, true) => (value, uint(&amounts[0])?),
+        (SwapKind::ExactOutput, false) => (uint(&amounts[1])?, uint(&amounts[0])?),
+        (SwapKind::ExactOutput, true) => (value, uint(&amounts[0])?),
+    };
+
+    let path = array(&rest[0])?
+        .into_iter()
+        .map(|token| address(&token))
+        .collect::<Result<Vec<_>, _>>()?;
+    if path.len() < 2 {
+        return Err(DecodeError::InvalidPath);
+    }
+
+    Ok(DecodedSwap {
+        protocol: Protocol::UniswapV2,
+        kind,
+        path,
+        fees: Vec::new(),
+        amount_in,
+        amount_out,
+        recipient: address(&rest[1])?,
+        deadline: rest.get(2).map(uint).transpose()?,
+        allow_revert: false,
+    })
+}
+
+fn decode_v3_single(
+    data: &[u8],
+    kind: SwapKind,
+    deadline: bool,
+) -> Result<DecodedSwap, DecodeError> {
+    let mut fields = vec![
+        ParamType::Address,
+        ParamType::Address,
+        ParamType::Uint(24),
+        ParamType::Address,
+    ];
+    if deadline {
+        fields.push(ParamType::Uint(256));
+    }
+    fields.extend([
+        ParamType::Uint(256),
+        ParamType::Uint(256),
+        ParamType::Uint(160),
+    ]);
+
+    let params = tuple(decode(&[ParamType::Tuple(fields)], data)?)?;
+    let offset = deadline as usize;
+    let (fixed, limit) = (uint(&params[4 + offset])?, uint(&params[5 + offset])?);
+    let (amount_in, amount_out) = match kind {
+        SwapKind::ExactInput => (fixed, limit),
+        SwapKind::ExactOutput => (limit, fixed),
+    };
+
+    Ok(DecodedSwap {
+        protocol: Protocol::UniswapV3,
+        kind,
+        path: vec![address(&params[0])?, address(&params[1])?],
+        fees: vec![uint(&params[2])?.low_u32()],
+        amount_in,
+        amount_out,
+        recipient: address(&params[3])?,
+        deadline: deadline.then(|| uint(&params[4])).transpose()?,
+        allow_revert: false,
+    })
+}
+
+fn decode_v3_path(data: &[u8], kind: SwapKind, deadline: bool) -> Result<DecodedSwap, DecodeError> {
+    let mut fields = vec![ParamType::Bytes, ParamType::Address];
+    if deadline {
+        fields.push(ParamType::Uint(256));
+    }
+    fields.extend([ParamType::Uint(256), ParamType::Uint(256)]);
+
+    let params = tuple(decode(&[ParamType::Tuple(fields)], data)?)?;
+    let offset = deadline as usize;
+    let (fixed, limit) = (uint(&params[2 + offset])?, uint(&params[3 + offset])?);
+    let (amount_in, amount_out) = match kind {
+        SwapKind::ExactInput => (fixed, limit),
+        SwapKind::ExactOutput => (limit, fixed),
+    };
+
+    let encoded_path = params[0]
+        .clone()
+        .into_bytes()
+        .ok_or(DecodeError::UnexpectedArgument)?;
+    let (mut path, mut fees) = decode_v3_packed_path(&encoded_path)?;
+    // Exact-output paths are encoded from the output token back to the input token.
+    if kind == SwapKind::ExactOutput {
+        path.reverse();
+        fees.reverse();
+    }
+
+    Ok(DecodedSwap {
+        protocol: Protocol::UniswapV3,
+        kind,
+        path,
+        fees,
+        amount_in,
+        amount_out,
+        recipient: address(&params[1])?,
+        deadline: deadline.then(|| uint(&params[2])).transpose()?,
+        allow_revert: false,
+    })
+}
+
+/// Splits a packed V3 path `token (fee token)*` into its tokens and fee tiers.
+pub fn decode_v3_packed_path(path: &[u8]) -> Result<(Vec<Address>, Vec<u32>), DecodeError> {
+    let hop = FEE_SIZE + ADDRESS_SIZE;
+    if path.len() < ADDRESS_SIZE + hop {
+        return Err(DecodeError::InvalidPath);
+    }
+    let hops = path[ADDRESS_SIZE..].chunks_exact(hop);
+    if !hops.remainder().is_empty() {
+        return Err(DecodeError::InvalidPath);
+    }
+
+    let mut tokens = vec![Address::from_slice(&path[..ADDRESS_SIZE])];
+    let mut fees = Vec::new();
+    for chunk in hops {
+        fees.push(u32::from_be_bytes([0, chunk[0], chunk[1], chunk[2]]));
+        tokens.push(Address::from_slice(&chunk[FEE_SIZE..]));
+    }
+    Ok((tokens, fees))
+}
+
+pub(crate) fn uint(token: &Token) -> Result<U256, DecodeError> {
+    token
+        .clone()
+        .into_uint()
+        .ok_or(DecodeError::UnexpectedArgument)
+}
+
+pub(crate) fn address(token: &Token) -> Result<Address, DecodeError> {
+    token
+        .clone()
+        .into_address()
+        .ok_or(DecodeError::UnexpectedArgument)
+}
+
+pub(crate) fn array(token: &Token) -> Result<Vec<Token>, DecodeError> {
+    token
+        .clone()
+        .into_array()
+        .ok_or(DecodeError::UnexpectedArgument)
+}
+
+fn tuple(mut tokens: Vec<Token>) -> Result<Vec<Token>, DecodeError> {
+    tokens
+        .pop()
+        .and_then(Token::into_tuple)
+        .ok_or(DecodeError::UnexpectedArgument)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::decode_swaps;
+    use ethers::abi::encode;
+
+    fn token(byte: u8) -> Address {
+        Address::repeat_byte(byte)
+    }
+
+    fn calldata(signature: &str, args: &[Token]) -> Vec<u8> {
+        let mut data = id(signature).to_vec();
+        data.extend(encode(args));
+        data
+    }
+
+    fn packed_path(tokens: &[Address], fees: &[u32]) -> Vec<u8> {
+        let mut path = tokens[0].as_bytes().to_vec();
+        for (fee, token) in fees.iter().zip(&tokens[1..]) {
+            path.extend(&fee.to_be_bytes()[1..]);
+            path.extend(token.as_bytes());
+        }
+        path
+    }
+
+    fn addresses(tokens: &[Address]) -> Token {
+        Token::Array(tokens.iter().copied().map(Token::Address).collect())
+    }
+
+    #[test]
+    fn test_selectors() {
+        assert_eq!(
+            id("swapExactTokensForTokens(uint256,uint256,address[],address,uint256)"),
+            [0x38, 0xed, 0x17, 0x39]
+        );
+        assert_eq!(
+            id("exactInputSingle((address,address,uint24,address,uint256,uint256,uint256,uint160))"),
+            [0x41, 0x4b, 0xf3, 0x89]
+        );
+        assert_eq!(id("multicall(uint256,bytes[])"), [0x5a, 0xe4, 0x01, 0xdc]);
+    }
+
+    #[test]
+    fn test_v2_swaps() {
+        let data = calldata(
+            "swapExactTokensForTokens(uint256,uint256,address[],address,uint256)",
+            &[
+                Token::Uint(U256::exp10(18)),
+                Token::Uint(U256::from(990)),
+                addresses(&[token(1), token(2), token(3)]),
+                Token::Address(token(9)),
+                Token::Uint(U256::from(1_700_000_000)),
+            ],
+        );
+        let swaps = decode_swaps(&data, U256::zero()).unwrap();
+
+        assert_eq!(
+            swaps,
+            vec![DecodedSwap {
+                protocol: Protocol::UniswapV2,
+                kind: SwapKind::ExactInput,
+                path: vec![token(1), token(2), token(3)],
+                fees: vec![],
+                amount_in: U256::exp10(18),
+                amount_out: U256::from(990),
+                recipient: token(9),
+                deadline: Some(U256::from(1_700_000_000)),
+                allow_revert: false,
+            }]
+        );
+
+        // Native swaps take the input from msg.value.
+        let data = calldata(
+            "swapETHForExactTokens(uint256,address[],address,uint256)",
+            &[
+                Token::Uint(U256::from(500)),
+                addresses(&[token(1), token(2)]),
+                Token::Address(token(9)),
+                Token::Uint(U256::from(1_700_000_000)),
+            ],
+        );
+        let swap = &decode_swaps(&data, U256::from(600)).unwrap()[0];
+        assert_eq!(swap.kind, SwapKind::ExactOutput);
+        assert_eq!(
+            (swap.amount_in, swap.amount_out),
+            (U256::from(600), U256::from(500))
+        );
+
+        let data = calldata(
+            "swapTokensForExactTokens(uint256,uint256,address[],address,uint256)",
+            &[
+                Token::Uint(U256::from(500)),
+                Token::Uint(U256::from(700)),
+                addresses(&[token(1), token(2)]),
+                Token::Address(token(9)),
+                Token::Uint(U256::from(1_700_000_000)),
+            ],
+        );
+        let swap = &decode_swaps(&data, U256::zero()).unwrap()[0];
+        assert_eq!(
+            (swap.amount_in, swap.amount_out),
+            (U256::from(700), U256::from(500))
+        );
+    }
+
+    #[test]
+    fn test_v3_single() {
+        let data = calldata(
+            "exactInputSingle((address,address,uint24,address,uint256,uint256,uint256,uint160))",
+            &[Token::Tuple(vec![
+                Token::Address(token(1)),
+                Token::Address(token(2)),
+                Token::Uint(U256::from(500)),
+                Token::Address(token(9)),
+                Token::Uint(U256::from(1_700_000_000)),
+                Token::Uint(U256::exp10(18)),
+                Token::Uint(U256::from(990)),
+                Token::Uint(U256::zero()),
+            ])],
+        );
+        let swap = &decode_swaps(&data, U256::zero()).unwrap()[0];
+
+        assert_eq!(swap.protocol, Protocol::UniswapV3);
+        assert_eq!(swap.path, vec![token(1), token(2)]);
+        assert_eq!(swap.fees, vec![500]);
+        assert_eq!(
+            (swap.amount_in, swap.amount_out),
+            (U256::exp10(18), U256::from(990))
+        );
+        assert_eq!(swap.deadline, Some(U256::from(1_700_000_000)));
+    }
+
+    #[test]
+    fn test_v3_exact_output_path_is_reversed() {
+        let data = calldata(
+            "exactOutput((bytes,address,uint256,uint256,uint256))",
+            &[Token::Tuple(vec![
+                Token::Bytes(packed_path(&[token(3), token(2), token(1)], &[3000, 500])),
+                Token::Address(token(9)),
+                Token::Uint(U256::from(1_700_000_000)),
+                Token::Uint(U256::from(1000)),
+                Token::Uint(U256::from(1100)),
+            ])],
+        );
+        let swap = &decode_swaps(&data, U256::zero()).unwrap()[0];
+
+        assert_eq!(swap.kind, SwapKind::ExactOutput);
+        assert_eq!(swap.path, vec![token(1), token(2), token(3)]);
+        assert_eq!(swap.fees, vec![500, 3000]);
+        assert_eq!(
+            (swap.amount_in, swap.amount_out),
+            (U256::from(1100), U256::from(1000))
+        );
+    }
+
+    #[test]
+    fn test_multicall_with_deadline() {
+        let exact_input = calldata(
+            "exactInput((bytes,address,uint256,uint256))",
+            &[Token::Tuple(vec![
+                Token::Bytes(packed_path(&[token(1), token(2), token(3)], &[500, 10000])),
+                Token::Address(Address::from_low_u64_be(1)),
+                Token::Uint(U256::exp10(18)),
+                Token::Uint(U256::from(990)),
+            ])],
+        );
+        let v2 = calldata(
+            "swapExactTokensForTokens(uint256,uint256,address[],address)",
+            &[
+                Token::Uint(U256::from(5)),
+                Token::Uint(U256::from(4)),
+                addresses(&[token(3), token(4)]),
+                Token::Address(token(9)),
+            ],
+        );
+        let refund = id("refundETH()").to_vec();
+        let data = calldata(
+            "multicall(uint256,bytes[])",
+            &[
+                Token::Uint(U256::from(1_800_000_000)),
+                Token::Array(vec![
+                    Token::Bytes(exact_input),
+                    Token::Bytes(refund),
+                    Token::Bytes(v2),
+                ]),
+            ],
+        );
+
+        let swaps = decode_swaps(&data, U256::zero()).unwrap();
+
+        assert_eq!(swaps.len(), 2);
+        assert_eq!(swaps[0].path, vec![token(1), token(2), token(3)]);
+        assert_eq!(swaps[0].fees, vec![500, 10000]);
+        assert_eq!(swaps[1].protocol, Protocol::UniswapV2);
+        assert!(swaps
+            .iter()
+            .all(|swap| swap.deadline == Some(U256::from(1_800_000_000))));
+    }
+
+    #[test]
+    fn test_invalid_calldata() {
+        assert!(matches!(
+            decode_swaps(&[0x12, 0x34], U256::zero()),
+            Err(DecodeError::TooShort)
+        ));
+        assert!(matches!(
+            decode_swaps(&id("refundETH()"), U256::zero()),
+            Err(DecodeError::UnknownSelector(_))
+        ));
+        assert!(matches!(
+            decode_v3_packed_path(&[0; 30]),
+            Err(DecodeError::InvalidPath)
+        ));
+    }
+}
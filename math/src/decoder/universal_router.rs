@@ -0,0 +1,559 @@
+// Decodes Uniswap Universal Router `execute` calls by walking their command byte stream.
+// Swap commands become `DecodedSwap`s; wraps, Permit2 transfers and sweeps are decoded to resolve router balances.
+
+use ethers::abi::{decode, ParamType};
+use ethers::types::{Address, U256};
+use ethers::utils::id;
+
+use super::router::{address, array, decode_v3_packed_path, uint};
+use super::{DecodedSwap, Protocol, SwapKind};
+use crate::error::DecodeError;
+
+/// Command flag telling the router to continue if the command reverts.
+pub const FLAG_ALLOW_REVERT: u8 = 0x80;
+/// Mask of the command type within a command byte.
+pub const COMMAND_TYPE_MASK: u8 = 0x3f;
+
+// Command types, as in the router's Commands library.
+pub const V3_SWAP_EXACT_IN: u8 = 0x00;
+pub const V3_SWAP_EXACT_OUT: u8 = 0x01;
+pub const PERMIT2_TRANSFER_FROM: u8 = 0x02;
+pub const PERMIT2_PERMIT_BATCH: u8 = 0x03;
+pub const SWEEP: u8 = 0x04;
+pub const TRANSFER: u8 = 0x05;
+pub const PAY_PORTION: u8 = 0x06;
+pub const V2_SWAP_EXACT_IN: u8 = 0x08;
+pub const V2_SWAP_EXACT_OUT: u8 = 0x09;
+pub const PERMIT2_PERMIT: u8 = 0x0a;
+pub const WRAP_ETH: u8 = 0x0b;
+pub const UNWRAP_WETH: u8 = 0x0c;
+pub const PERMIT2_TRANSFER_FROM_BATCH: u8 = 0x0d;
+
+/// Recipient sentinel for the caller of `execute`.
+pub const MSG_SENDER: u64 = 1;
+/// Recipient sentinel for the router itself.
+pub const ADDRESS_THIS: u64 = 2;
+
+const EXECUTE: &str = "execute(bytes,bytes[])";
+const EXECUTE_WITH_DEADLINE: &str = "execute(bytes,bytes[],uint256)";
+
+/// Amount sentinel for "the router's whole balance", 2^255.
+pub fn contract_balance() -> U256 {
+    U256::one() << 255
+}
+
+/// A decoded Universal Router command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// V2 or V3 swap.
+    Swap(DecodedSwap),
+    /// Wraps ETH held by the router into WETH.
+    WrapEth { recipient: Address, amount: U256 },
+    /// Unwraps the router's WETH, requiring at least `amount_min`.
+    UnwrapWeth {
+        recipient: Address,
+        amount_min: U256,
+    },
+    /// Pulls tokens from the caller through Permit2.
+    Permit2TransferFrom {
+        token: Address,
+        recipient: Address,
+        amount: U256,
+    },
+    /// Pulls several tokens through Permit2, as (from, to, amount, token).
+    Permit2TransferFromBatch {
+        transfers: Vec<(Address, Address, U256, Address)>,
+    },
+    /// Sets a Permit2 allowance from a signature.
+    Permit2Permit,
+    /// Sends the router's whole balance of a token, requiring at least `amount_min`.
+    Sweep {
+        token: Address,
+        recipient: Address,
+        amount_min: U256,
+    },
+    /// Sends a fixed amount of the router's balance of a token.
+    Transfer {
+        token: Address,
+        recipient: Address,
+        value: U256,
+    },
+    /// Sends a share of the router's balance of a token, in bips.
+    PayPortion {
+        token: Address,
+        recipient: Address,
+        bips: U256,
+    },
+    /// Command this decoder does not model, such as NFT marketplace calls.
+    Other(u8),
+}
+
+/// A command of an `execute` call with its flags.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouterCommand {
+    /// The decoded command.
+    pub command: Command,
+    /// Whether `execute` continues if the command reverts.
+    pub allow_revert: bool,
+}
+
+/// Decodes the swaps of an `execute` call sent with `value` wei.
+///
+/// Swap amounts of `contract_balance()` and V2 amounts of zero, which mean "whatever the router holds" and
+/// "already paid to the pair", are resolved from the preceding wrap or Permit2 transfer where possible. That
+/// funding is spent by the first swap paid from it, so a later hop spending an earlier swap's output keeps
+/// `contract_balance()`, its amount being unknown until the route is simulated.
+///
+/// Returns the swaps in execution order, or Err(DecodeError) if the call is not an `execute`.
+pub fn decode_universal_router_call(
+    input: &[u8],
+    value: U256,
+) -> Result<Vec<DecodedSwap>, DecodeError> {
+    let (commands, deadline) = decode_execute(input)?;
+
+    let mut funded = None;
+    let mut swaps = Vec::new();
+    for RouterCommand {
+        command,
+        allow_revert,
+    } in commands
+    {
+        match command {
+            Command::WrapEth { amount, .. } => {
+                funded = Some(if amount == contract_balance() {
+                    value
+                } else {
+                    amount
+                });
+            }
+            Command::Permit2TransferFrom { amount, .. } => funded = Some(amount),
+            Command::Swap(mut swap) => {
+                let paid_by_router = swap.amount_in == contract_balance()
+                    || (swap.protocol == Protocol::UniswapV2
+                        && swap.kind == SwapKind::ExactInput
+                        && swap.amount_in.is_zero());
+                if paid_by_router {
+                    if let Some(amount) = funded.take() {
+                        swap.amount_in = amount;
+                    }
+                }
+                swap.deadline = deadline;
+                swap.allow_revert = allow_revert;
+                swaps.push(swap);
+            }
+            _ => {}
+        }
+    }
+    Ok(swaps)
+}
+
+/// Decodes an `execute` call into its commands and its deadline.
+///
+/// Returns Err(DecodeError::UnknownSelector) if the call is not an `execute`.
+pub fn decode_execute(input: &[u8]) -> Result<(Vec<RouterCommand>, Option<U256>), DecodeError> {
+    if input.len() < 4 {
+        return Err(DecodeError::TooShort);
+    }
+    let selector: [u8; 4] = input[..4].try_into().expect("four bytes");
+    let data = &input[4..];
+    let commands_and_inputs = [
+        ParamType::Bytes,
+        ParamType::Array(Box::new(ParamType::Bytes)),
+    ];
+
+    let (tokens, deadline) = if selector == id(EXECUTE_WITH_DEADLINE) {
+        let params = [commands_and_inputs.as_slice(), &[ParamType::Uint(256)]].concat();
+        let tokens = decode(&params, data)?;
+        let deadline = uint(&tokens[2])?;
+        (tokens, Some(deadline))
+    } else if selector == id(EXECUTE) {
+        (decode(&commands_and_inputs, data)?, None)
+    } else {
+        return Err(DecodeError::UnknownSelector(selector));
+    };
+
+    let commands = tokens[0]
+        .clone()
+        .into_bytes()
+        .ok_or(DecodeError::UnexpectedArgument)?;
+    let inputs = array(&tokens[1])?;
+    if commands.len() != inputs.len() {
+        return Err(DecodeError::UnexpectedArgument);
+    }
+
+    commands
+        .iter()
+        .zip(inputs)
+        .map(|(command, input)| {
+            let input = input.into_bytes().ok_or(DecodeError::UnexpectedArgument)?;
+            Ok(RouterCommand {
+                command: decode_command(command & COMMAND_TYPE_MASK, &input)?,
+                allow_revert: command & FLAG_ALLOW_REVERT != 0,
+            })
+        })
+        .collect::<Result<Vec<_>, DecodeError>>()
+        .map(|commands| (commands, deadline))
+}
+
+fn decode_command(command_type: u8, input: &[u8]) -> Result<Command, DecodeError> {
+    let transfer = [ParamType::Address, ParamType::Address, ParamType::Uint(256)];
+
+    let command = match command_type {
+        V3_SWAP_EXACT_IN | V3_SWAP_EXACT_OUT | V2_SWAP_EXACT_IN | V2_SWAP_EXACT_OUT => {
+            Command::Swap(decode_swap(command_type, input)?)
+        }
+        WRAP_ETH => {
+            let tokens = decode(&[ParamType::Address, ParamType::Uint(256)], input)?;
+            Command::WrapEth {
+                recipient: address(&tokens[0])?,
+                amount: uint(&tokens[1])?,
+            }
+        }
+        UNWRAP_WETH => {
+            let tokens = decode(&[ParamType::Address, ParamType::Uint(256)], input)?;
+            Command::UnwrapWeth {
+                recipient: address(&tokens[0])?,
+                amount_min: uint(&tokens[1])?,
+            }
+        }
+        PERMIT2_TRANSFER_FROM => {
+            let tokens = decode(
+                &[ParamType::Address, ParamType::Address, ParamType::Uint(160)],
+                input,
+            )?;
+            Command::Permit2TransferFrom {
+                token: address(&tokens[0])?,
+                recipient: address(&tokens[1])?,
+                amount: uint(&tokens[2])?,
+            }
+        }
+        PERMIT2_TRANSFER_FROM_BATCH => {
+            let details = ParamType::Tuple(vec![
+                ParamType::Address,
+                ParamType::Address,
+                ParamType::Uint(160),
+                ParamType::Address,
+            ]);
+            let tokens = decode(&[ParamType::Array(Box::new(details))], input)?;
+            let transfers = array(&tokens[0])?
+                .into_iter()
+                .map(|detail| {
+                    let fields = detail.into_tuple().ok_or(DecodeError::UnexpectedArgument)?;
+                    Ok((
+                        address(&fields[0])?,
+                        address(&fields[1])?,
+                        uint(&fields[2])?,
+                        address(&fields[3])?,
+                    ))
+                })
+                .collect::<Result<Vec<_>, DecodeError>>()?;
+            Command::Permit2TransferFromBatch { transfers }
+        }
+        PERMIT2_PERMIT | PERMIT2_PERMIT_BATCH => Command::Permit2Permit,
+        SWEEP => {
+            let tokens = decode(&transfer, input)?;
+            Command::Sweep {
+                token: address(&tokens[0])?,
+                recipient: address(&tokens[1])?,
+                amount_min: uint(&tokens[2])?,
+            }
+        }
+        TRANSFER => {
+            let tokens = decode(&transfer, input)?;
+            Command::Transfer {
+                token: address(&tokens[0])?,
+                recipient: address(&tokens[1])?,
+                value: uint(&tokens[2])?,
+            }
+        }
+        PAY_PORTION => {
+            let tokens = decode(&transfer, input)?;
+            Command::PayPortion {
+                token: address(&tokens[0])?,
+                recipient: address(&tokens[1])?,
+                bips: uint(&tokens[2])?,
+            }
+        }
+        other => Command::Other(other),
+    };
+    Ok(command)
+}
+
+/// Decodes a swap command: (recipient, amount, limit, path, payerIsUser), where V3 paths are packed bytes.
+fn decode_swap(command_type: u8, input: &[u8]) -> Result<DecodedSwap, DecodeError> {
+    let v3 = matches!(command_type, V3_SWAP_EXACT_IN | V3_SWAP_EXACT_OUT);
+    let kind = if matches!(command_type, V3_SWAP_EXACT_IN | V2_SWAP_EXACT_IN) {
+        SwapKind::ExactInput
+    } else {
+        SwapKind::ExactOutput
+    };
+    let path_type = if v3 {
+        ParamType::Bytes
+    } else {
+        ParamType::Array(Box::new(ParamType::Address))
+    };
+
+    let tokens = decode(
+        &[
+            ParamType::Address,
+            ParamType::Uint(256),
+            ParamType::Uint(256),
+            path_type,
+            ParamType::Bool,
+        ],
+        input,
+    )?;
+    let (fixed, limit) = (uint(&tokens[1])?, uint(&tokens[2])?);
+    let (amount_in, amount_out) = match kind {
+        SwapKind::ExactInput => (fixed, limit),
+        SwapKind::ExactOutput => (limit, fixed),
+    };
+
+    let (path, fees) = if v3 {
+        let encoded_path = tokens[3]
+            .clone()
+            .into_bytes()
+            .ok_or(DecodeError::UnexpectedArgument)?;
+        let (mut path, mut fees) = decode_v3_packed_path(&encoded_path)?;
+        // Exact-output paths are encoded from the output token back to the input token.
+        if kind == SwapKind::ExactOutput {
+            path.reverse();
+            fees.reverse();
+        }
+        (path, fees)
+    } else {
+        let path = array(&tokens[3])?
+            .iter()
+            .map(address)
+            .collect::<Result<Vec<_>, _>>()?;
+        if path.len() < 2 {
+            return Err(DecodeError::InvalidPath);
+        }
+        (path, Vec::new())
+    };
+
+    Ok(DecodedSwap {
+        protocol: if v3 {
+            Protocol::UniswapV3
+        } else {
+            Protocol::UniswapV2
+        },
+        kind,
+        path,
+        fees,
+        amount_in,
+        amount_out,
+        recipient: address(&tokens[0])?,
+        deadline: None,
+        allow_revert: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::decode_swaps;
+    use ethers::abi::{encode, Token};
+
+    fn token(byte: u8) -> Address {
+        Address::repeat_byte(byte)
+    }
+
+    fn execute(commands: &[(u8, Vec<Token>)], deadline: u64) -> Vec<u8> {
+        let mut data = id(EXECUTE_WITH_DEADLINE).to_vec();
+        data.extend(encode(&[
+            Token::Bytes(commands.iter().map(|(command, _)| *command).collect()),
+            Token::Array(
+                commands
+                    .iter()
+                    .map(|(_, args)| Token::Bytes(encode(args)))
+                    .collect(),
+            ),
+            Token::Uint(U256::from(deadline)),
+        ]));
+        data
+    }
+
+    fn packed_path(tokens: &[Address], fees: &[u32]) -> Vec<u8> {
+        let mut path = tokens[0].as_bytes().to_vec();
+        for (fee, token) in fees.iter().zip(&tokens[1..]) {
+            path.extend(&fee.to_be_bytes()[1..]);
+            path.extend(token.as_bytes());
+        }
+        path
+    }
+
+    #[test]
+    fn test_selectors() {
+        assert_eq!(id(EXECUTE_WITH_DEADLINE), [0x35, 0x93, 0x56, 0x4c]);
+        assert_eq!(id(EXECUTE), [0x24, 0x85, 0x6b, 0xc3]);
+    }
+
+    #[test]
+    fn test_wrap_then_v3_multi_hop() {
+        let router = Address::from_low_u64_be(ADDRESS_THIS);
+        let data = execute(
+            &[
+                (
+                    WRAP_ETH,
+                    vec![Token::Address(router), Token::Uint(contract_balance())],
+                ),
+                (
+                    V3_SWAP_EXACT_IN,
+                    vec![
+                        Token::Address(Address::from_low_u64_be(MSG_SENDER)),
+                        Token::Uint(contract_balance()),
+                        Token::Uint(U256::from(990)),
+                        Token::Bytes(packed_path(&[token(1), token(2), token(3)], &[500, 3000])),
+                        Token::Bool(false),
+                    ],
+                ),
+            ],
+            1_700_000_000,
+        );
+
+        let swaps = decode_swaps(&data, U256::exp10(18)).unwrap();
+
+        assert_eq!(
+            swaps,
+            vec![DecodedSwap {
+                protocol: Protocol::UniswapV3,
+                kind: SwapKind::ExactInput,
+                path: vec![token(1), token(2), token(3)],
+                fees: vec![500, 3000],
+                // The router's balance is the ETH wrapped from msg.value.
+                amount_in: U256::exp10(18),
+                amount_out: U256::from(990),
+                recipient: Address::from_low_u64_be(MSG_SENDER),
+                deadline: Some(U256::from(1_700_000_000)),
+                allow_revert: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_chained_hop_does_not_reuse_the_wrapped_amount() {
+        let router = Address::from_low_u64_be(ADDRESS_THIS);
+        let data = execute(
+            &[
+                (
+                    WRAP_ETH,
+                    vec![Token::Address(router), Token::Uint(contract_balance())],
+                ),
+                (
+                    V2_SWAP_EXACT_IN,
+                    vec![
+                        Token::Address(router),
+                        Token::Uint(contract_balance()),
+                        Token::Uint(U256::zero()),
+                        Token::Array(vec![Token::Address(token(1)), Token::Address(token(2))]),
+                        Token::Bool(false),
+                    ],
+                ),
+                (
+                    V3_SWAP_EXACT_IN,
+                    vec![
+                        Token::Address(Address::from_low_u64_be(MSG_SENDER)),
+                        Token::Uint(contract_balance()),
+                        Token::Uint(U256::from(990)),
+                        Token::Bytes(packed_path(&[token(2), token(3)], &[500])),
+                        Token::Bool(false),
+                    ],
+                ),
+            ],
+            1_700_000_000,
+        );
+
+        let swaps = decode_swaps(&data, U256::exp10(18)).unwrap();
+
+        assert_eq!(swaps.len(), 2);
+        assert_eq!(swaps[0].amount_in, U256::exp10(18));
+        // The second hop spends the first hop's output, which calldata does not reveal.
+        assert_eq!(swaps[1].amount_in, contract_balance());
+    }
+
+    #[test]
+    fn test_permit2_transfer_then_v2_swap_with_allow_revert() {
+        let pair = token(7);
+        let data = execute(
+            &[
+                (
+                    PERMIT2_TRANSFER_FROM,
+                    vec![
+                        Token::Address(token(1)),
+                        Token::Address(pair),
+                        Token::Uint(U256::from(5000)),
+                    ],
+                ),
+                (
+                    V2_SWAP_EXACT_IN | FLAG_ALLOW_REVERT,
+                    vec![
+                        Token::Address(token(9)),
+                        Token::Uint(U256::zero()),
+                        Token::Uint(U256::from(4000)),
+                        Token::Array(vec![Token::Address(token(1)), Token::Address(token(2))]),
+                        Token::Bool(false),
+                    ],
+                ),
+                (
+                    SWEEP,
+                    vec![
+                        Token::Address(token(2)),
+                        Token::Address(token(9)),
+                        Token::Uint(U256::zero()),
+                    ],
+                ),
+            ],
+            1_700_000_000,
+        );
+
+        let (commands, _) = decode_execute(&data).unwrap();
+        assert_eq!(commands.len(), 3);
+        assert!(matches!(commands[2].command, Command::Sweep { .. }));
+        assert!(commands[1].allow_revert);
+
+        let swaps = decode_swaps(&data, U256::zero()).unwrap();
+        assert_eq!(swaps.len(), 1);
+        assert!(swaps[0].allow_revert);
+        assert_eq!(swaps[0].protocol, Protocol::UniswapV2);
+        // Zero means the input was already paid to the pair by the Permit2 transfer.
+        assert_eq!(swaps[0].amount_in, U256::from(5000));
+    }
+
+    #[test]
+    fn test_v3_exact_out_then_unwrap() {
+        let data = execute(
+            &[
+                (
+                    V3_SWAP_EXACT_OUT,
+                    vec![
+                        Token::Address(Address::from_low_u64_be(ADDRESS_THIS)),
+                        Token::Uint(U256::from(1000)),
+                        Token::Uint(U256::from(1100)),
+                        Token::Bytes(packed_path(&[token(2), token(1)], &[100])),
+                        Token::Bool(true),
+                    ],
+                ),
+                (
+                    UNWRAP_WETH,
+                    vec![Token::Address(token(9)), Token::Uint(U256::from(1000))],
+                ),
+                (0x10, vec![]),
+            ],
+            1_700_000_000,
+        );
+
+        let (commands, deadline) = decode_execute(&data).unwrap();
+        assert_eq!(deadline, Some(U256::from(1_700_000_000)));
+        assert!(matches!(commands[1].command, Command::UnwrapWeth { .. }));
+        assert_eq!(commands[2].command, Command::Other(0x10));
+
+        let swap = &decode_swaps(&data, U256::zero()).unwrap()[0];
+        assert_eq!(swap.kind, SwapKind::ExactOutput);
+        assert_eq!(swap.path, vec![token(1), token(2)]);
+        assert_eq!(
+            (swap.amount_in, swap.amount_out),
+            (U256::from(1100), U256::from(1000))
+        );
+    }
+}
@@ -1,9 +1,86 @@
+/// Error types for mathematical operations.
 #[derive(Debug, thiserror::Error)]
 pub enum MathError {
-    #[error("Invalid transaction input")]
+    /// Invalid transaction input.
+    #[error("Invalid transaction")]
     InvalidTransaction,
-    #[error("Risk validation failed: {0}")]
-    RiskValidationFailed(#[from] crate::risk::Error),
+    /// Arithmetic overflow occurred during calculation.
     #[error("Arithmetic overflow")]
     Overflow,
+    /// Division by zero occurred during calculation.
+    #[error("Division by zero")]
+    DivisionByZero,
+    /// Trade does not meet profit requirements.
+    #[error("Trade doesn't meet profit requirements")]
+    UnprofitableTrade,
+    /// Tick is outside of the supported range or not aligned to the tick spacing.
+    #[error("Invalid tick: {0}")]
+    InvalidTick(i32),
+    /// Tick spacing is zero or negative.
+    #[error("Invalid tick spacing: {0}")]
+    InvalidTickSpacing(i32),
+    /// Square root price is outside of the supported range.
+    #[error("Invalid sqrt price")]
+    InvalidSqrtPrice,
+    /// Sqrt price limit is on the wrong side of the current price or out of range.
+    #[error("Invalid sqrt price limit")]
+    InvalidPriceLimit,
+    /// Swap amount is zero.
+    #[error("Swap amount must be non-zero")]
+    ZeroAmount,
+    /// Token is not traded by the pool.
+    #[error("Unknown token: {0:?}")]
+    UnknownToken(ethers::types::Address),
+    /// Pool parameters or coin indexes are invalid.
+    #[error("Invalid pool parameters")]
+    InvalidPoolParameters,
+    /// Newton iteration did not converge.
+    #[error("Newton iteration did not converge")]
+    NoConvergence,
+    /// Swap input exceeds the share of the balance a Balancer pool accepts.
+    #[error("Swap input exceeds the pool's max in ratio")]
+    MaxInRatio,
+    /// Swap output exceeds the share of the balance a Balancer pool pays out.
+    #[error("Swap output exceeds the pool's max out ratio")]
+    MaxOutRatio,
+    /// Pool does not hold enough liquidity to fill the requested amount.
+    #[error("Insufficient liquidity")]
+    InsufficientLiquidity,
+    /// Risk validation failed.
+    #[error("Risk validation failed")]
+    RiskValidationFailed(#[from] mev_risk::RiskError),
+}
+
+/// Error types for decoding swap calldata.
+#[derive(Debug, thiserror::Error)]
+pub enum DecodeError {
+    /// Calldata is shorter than a function selector.
+    #[error("Calldata too short")]
+    TooShort,
+    /// Selector does not belong to a supported swap function.
+    #[error("Unknown selector: 0x{}", ethers::utils::hex::encode(.0))]
+    UnknownSelector([u8; 4]),
+    /// Arguments do not match the function's ABI.
+    #[error("ABI decoding failed: {0}")]
+    Abi(#[from] ethers::abi::Error),
+    /// Decoded argument does not have the type the function declares.
+    #[error("Unexpected argument type")]
+    UnexpectedArgument,
+    /// Swap path is empty or malformed.
+    #[error("Invalid swap path")]
+    InvalidPath,
+}
+
+/// Error types for tracking pool states.
+#[derive(Debug, thiserror::Error)]
+pub enum StateError {
+    /// Applying a confirmed event failed.
+    #[error("Failed to apply event of pool {pool:?}: {source}")]
+    Event {
+        pool: ethers::types::Address,
+        source: MathError,
+    },
+    /// Reorg reaches back further than the blocks kept for rewinding.
+    #[error("Cannot rewind to block {0}: reorg is deeper than the kept history")]
+    ReorgTooDeep(u64),
 }
@@ -1 +1,9 @@
+pub mod balancer;
+pub mod constant_product;
+pub mod decoder;
+pub mod error;
+pub mod pool;
+pub mod pool_state;
 pub mod sandwich;
+pub mod stable_swap;
+pub mod uniswap_v3;
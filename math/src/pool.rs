@@ -0,0 +1,175 @@
+// This module defines the interface shared by every AMM model in mev-math.
+// Strategies, routers and the simulator quote and mutate pools through `Pool` without knowing the AMM kind.
+
+use ethers::types::{Address, U256};
+use std::fmt::Debug;
+
+use crate::error::MathError;
+
+/// Fixed-point scale of spot prices: 1e18 means one unit of `token_out` per unit of `token_in`.
+pub const PRICE_SCALE: u64 = 1_000_000_000_000_000_000;
+
+/// Common interface of AMM pool models.
+///
+/// Amounts are raw token units. Swaps are addressed by token, so multi-asset pools can implement the
+/// trait for any pair of their coins.
+pub trait Pool: Debug + Send + Sync {
+    /// Tokens that can be swapped through the pool, in the pool's coin order.
+    fn tokens(&self) -> Vec<Address>;
+
+    /// Swap fee in hundredths of a bip (1e-6), the unit Uniswap V3 uses.
+    fn fee(&self) -> u32;
+
+    /// Quotes the output of swapping exactly `amount_in` of `token_in` for `token_out`.
+    fn quote_exact_in(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+    ) -> Result<U256, MathError>;
+
+    /// Quotes the input of `token_in` required to receive exactly `amount_out` of `token_out`.
+    fn quote_exact_out(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_out: U256,
+    ) -> Result<U256, MathError>;
+
+    /// Swaps exactly `amount_in` of `token_in` for `token_out` and moves the pool to the post-swap state.
+    ///
+    /// Callers simulating hypothetical swaps should apply them to a clone, see `clone_box`.
+    fn apply_swap(
+        &mut self,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+    ) -> Result<U256, MathError>;
+
+    /// Marginal price of `token_in` in `token_out` before fees, scaled by `PRICE_SCALE`.
+    fn spot_price(&self, token_in: Address, token_out: Address) -> Result<U256, MathError>;
+
+    /// Reserves of an equivalent constant-product pool around the current price, as (reserve_in, reserve_out).
+    ///
+    /// Pools whose curve is not locally constant-product return None.
+    fn virtual_reserves(&self, _token_in: Address, _token_out: Address) -> Option<(U256, U256)> {
+        None
+    }
+
+    /// Clones the pool behind a trait object.
+    fn clone_box(&self) -> Box<dyn Pool>;
+}
+
+impl Clone for Box<dyn Pool> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// Resolves the swap direction of a two-token pool.
+///
+/// Returns true if `token_in` is token0 and `token_out` token1, false for the opposite direction, or
+/// Err(MathError::UnknownToken) if the pair does not match the pool.
+pub fn zero_for_one(
+    token0: Address,
+    token1: Address,
+    token_in: Address,
+    token_out: Address,
+) -> Result<bool, MathError> {
+    if token_in == token0 && token_out == token1 {
+        Ok(true)
+    } else if token_in == token1 && token_out == token0 {
+        Ok(false)
+    } else if token_in != token0 && token_in != token1 {
+        Err(MathError::UnknownToken(token_in))
+    } else {
+        Err(MathError::UnknownToken(token_out))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constant_product::{ConstantProductPool, QUICKSWAP_FEE};
+    use crate::uniswap_v3::UniswapV3Pool;
+
+    fn token(byte: u8) -> Address {
+        Address::repeat_byte(byte)
+    }
+
+    fn create_pools() -> Vec<Box<dyn Pool>> {
+        let v2 = ConstantProductPool::new(U256::exp10(24), U256::exp10(24), QUICKSWAP_FEE)
+            .with_tokens(token(1), token(2));
+
+        let mut v3 = UniswapV3Pool::new(U256::one() << 96, 3000)
+            .unwrap()
+            .with_tokens(token(1), token(2));
+        v3.modify_liquidity(-887220, 887220, 10_i128.pow(24))
+            .unwrap();
+
+        vec![Box::new(v2), Box::new(v3)]
+    }
+
+    #[test]
+    fn test_pools_share_interface() {
+        for pool in create_pools() {
+            assert_eq!(pool.tokens(), vec![token(1), token(2)]);
+            assert_eq!(pool.fee(), 3000);
+            assert_eq!(
+                pool.spot_price(token(1), token(2)).unwrap(),
+                U256::from(PRICE_SCALE)
+            );
+
+            let amount_out = pool
+                .quote_exact_in(token(1), token(2), U256::exp10(18))
+                .unwrap();
+            assert!(amount_out > U256::exp10(18) * 996 / 1000 && amount_out < U256::exp10(18));
+
+            let amount_in = pool
+                .quote_exact_out(token(1), token(2), amount_out)
+                .unwrap();
+            assert!(amount_in <= U256::exp10(18));
+        }
+    }
+
+    #[test]
+    fn test_apply_swap_on_clone_leaves_original() {
+        for pool in create_pools() {
+            let quote = pool
+                .quote_exact_in(token(2), token(1), U256::exp10(20))
+                .unwrap();
+
+            let mut cloned = pool.clone();
+            assert_eq!(
+                cloned
+                    .apply_swap(token(2), token(1), U256::exp10(20))
+                    .unwrap(),
+                quote
+            );
+
+            assert!(
+                cloned.spot_price(token(2), token(1)).unwrap()
+                    < pool.spot_price(token(2), token(1)).unwrap()
+            );
+            assert_eq!(
+                pool.quote_exact_in(token(2), token(1), U256::exp10(20))
+                    .unwrap(),
+                quote
+            );
+        }
+    }
+
+    #[test]
+    fn test_unknown_token() {
+        for pool in create_pools() {
+            assert!(matches!(
+                pool.quote_exact_in(token(1), token(3), U256::one()),
+                Err(MathError::UnknownToken(address)) if address == token(3)
+            ));
+            assert!(matches!(
+                pool.quote_exact_in(token(1), token(1), U256::one()),
+                Err(MathError::UnknownToken(_))
+            ));
+        }
+    }
+}
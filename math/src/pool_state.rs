@@ -0,0 +1,827 @@
+// This module tracks what pools look like once the pending swaps ahead of a transaction have landed.
+// Confirmed state follows pool logs block by block; pending swaps are replayed on clones of it in gas-price order.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use ethers::types::{Address, Log, H256, U256};
+use ethers::utils::keccak256;
+
+use crate::constant_product::ConstantProductPool;
+use crate::decoder::universal_router::contract_balance;
+use crate::decoder::{DecodedSwap, Protocol, SwapKind};
+use crate::error::{MathError, StateError};
+use crate::pool::Pool;
+use crate::uniswap_v3::UniswapV3Pool;
+
+/// Number of recent blocks whose changes are kept to rewind reorgs.
+pub const DEFAULT_REORG_DEPTH: usize = 64;
+
+/// A state change decoded from a pool log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolEvent {
+    /// Uniswap V2 `Sync`, emitted with the new reserves after every swap, mint and burn of a pair.
+    Sync { reserve0: U256, reserve1: U256 },
+    /// Uniswap V3 `Swap`, carrying the pool's price, active liquidity and tick after the swap.
+    Swap {
+        sqrt_price_x96: U256,
+        liquidity: U256,
+        tick: i32,
+    },
+    /// Uniswap V3 `Mint` of liquidity between two ticks.
+    Mint {
+        tick_lower: i32,
+        tick_upper: i32,
+        amount: u128,
+    },
+    /// Uniswap V3 `Burn` of liquidity between two ticks.
+    Burn {
+        tick_lower: i32,
+        tick_upper: i32,
+        amount: u128,
+    },
+}
+
+impl PoolEvent {
+    /// Decodes a pool log.
+    ///
+    /// Returns None for other events, including the V2 `Swap`, `Mint` and `Burn`, whose effect the pair's
+    /// following `Sync` already reports.
+    pub fn decode(log: &Log) -> Option<Self> {
+        let topic = *log.topics.first()?;
+        let word = |index: usize| log.data.get(index * 32..(index + 1) * 32);
+        let tick_topic = |index: usize| log.topics.get(index).map(|topic| to_i32(topic.as_bytes()));
+
+        if topic == event_topic("Sync(uint112,uint112)") {
+            Some(Self::Sync {
+                reserve0: U256::from_big_endian(word(0)?),
+                reserve1: U256::from_big_endian(word(1)?),
+            })
+        } else if topic == event_topic("Swap(address,address,int256,int256,uint160,uint128,int24)")
+        {
+            Some(Self::Swap {
+                sqrt_price_x96: U256::from_big_endian(word(2)?),
+                liquidity: U256::from_big_endian(word(3)?),
+                tick: to_i32(word(4)?),
+            })
+        } else if topic == event_topic("Mint(address,address,int24,int24,uint128,uint256,uint256)")
+        {
+            Some(Self::Mint {
+                tick_lower: tick_topic(2)?,
+                tick_upper: tick_topic(3)?,
+                amount: u128::try_from(U256::from_big_endian(word(1)?)).ok()?,
+            })
+        } else if topic == event_topic("Burn(address,int24,int24,uint128,uint256,uint256)") {
+            Some(Self::Burn {
+                tick_lower: tick_topic(2)?,
+                tick_upper: tick_topic(3)?,
+                amount: u128::try_from(U256::from_big_endian(word(0)?)).ok()?,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+fn event_topic(signature: &str) -> H256 {
+    H256(keccak256(signature))
+}
+
+/// Reads an ABI word holding a sign-extended int24.
+fn to_i32(word: &[u8]) -> i32 {
+    i32::from_be_bytes([word[28], word[29], word[30], word[31]])
+}
+
+/// A pool whose state can follow its logs.
+#[derive(Debug, Clone)]
+pub enum TrackedPool {
+    ConstantProduct(ConstantProductPool),
+    UniswapV3(UniswapV3Pool),
+}
+
+impl TrackedPool {
+    /// The pool behind the common AMM interface.
+    pub fn as_pool(&self) -> &dyn Pool {
+        match self {
+            Self::ConstantProduct(pool) => pool,
+            Self::UniswapV3(pool) => pool,
+        }
+    }
+
+    /// Applies an event emitted by the pool.
+    ///
+    /// Returns Ok(true) if the event changed the pool, Ok(false) if it belongs to another AMM, or
+    /// Err(MathError) if the liquidity change is invalid for the pool.
+    pub fn apply_event(&mut self, event: &PoolEvent) -> Result<bool, MathError> {
+        match (self, *event) {
+            (Self::ConstantProduct(pool), PoolEvent::Sync { reserve0, reserve1 }) => {
+                pool.reserve0 = reserve0;
+                pool.reserve1 = reserve1;
+            }
+            (
+                Self::UniswapV3(pool),
+                PoolEvent::Swap {
+                    sqrt_price_x96,
+                    liquidity,
+                    tick,
+                },
+            ) => {
+                pool.sqrt_price_x96 = sqrt_price_x96;
+                pool.liquidity = liquidity;
+                pool.tick = tick;
+            }
+            (
+                Self::UniswapV3(pool),
+                PoolEvent::Mint {
+                    tick_lower,
+                    tick_upper,
+                    amount,
+                },
+            ) => {
+                let delta = i128::try_from(amount).map_err(|_| MathError::Overflow)?;
+                pool.modify_liquidity(tick_lower, tick_upper, delta)?;
+            }
+            (
+                Self::UniswapV3(pool),
+                PoolEvent::Burn {
+                    tick_lower,
+                    tick_upper,
+                    amount,
+                },
+            ) => {
+                let delta = i128::try_from(amount).map_err(|_| MathError::Overflow)?;
+                pool.modify_liquidity(tick_lower, tick_upper, -delta)?;
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Whether a hop of `protocol` with the given fee tier trades through this kind of pool.
+    fn serves(&self, protocol: Protocol, fee: Option<u32>) -> bool {
+        match (self, protocol) {
+            (Self::ConstantProduct(_), Protocol::UniswapV2) => true,
+            (Self::UniswapV3(pool), Protocol::UniswapV3) => fee == Some(pool.fee),
+            _ => false,
+        }
+    }
+}
+
+impl From<ConstantProductPool> for TrackedPool {
+    fn from(pool: ConstantProductPool) -> Self {
+        Self::ConstantProduct(pool)
+    }
+}
+
+impl From<UniswapV3Pool> for TrackedPool {
+    fn from(pool: UniswapV3Pool) -> Self {
+        Self::UniswapV3(pool)
+    }
+}
+
+/// A decoded swap resolved to the tracked pools of its hops.
+#[derive(Debug, Clone)]
+struct RoutedSwap {
+    kind: SwapKind,
+    amount_in: U256,
+    amount_out: U256,
+    path: Vec<Address>,
+    pools: Vec<Address>,
+    allow_revert: bool,
+}
+
+/// Position of a pending transaction: highest gas price first, then earliest arrival.
+type PendingKey = (Reverse<U256>, u64);
+
+/// Confirmed pool states plus the pending swaps expected to land on top of them.
+///
+/// Feed every block's transactions and logs to `apply_block` and every pending swap to `insert_pending`.
+/// Projections are computed on clones of the confirmed pools, so removing a pending transaction rolls them back.
+#[derive(Debug)]
+pub struct PoolStateTracker {
+    pools: HashMap<Address, TrackedPool>,
+    block: Option<u64>,
+    /// State of the pools each recent block changed from before that block, oldest block first.
+    history: VecDeque<(u64, Vec<(Address, TrackedPool)>)>,
+    reorg_depth: usize,
+    pending: BTreeMap<PendingKey, Vec<RoutedSwap>>,
+    pending_keys: HashMap<H256, PendingKey>,
+    arrivals: u64,
+}
+
+impl Default for PoolStateTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PoolStateTracker {
+    /// Creates a tracker without pools.
+    pub fn new() -> Self {
+        Self {
+            pools: HashMap::new(),
+            block: None,
+            history: VecDeque::new(),
+            reorg_depth: DEFAULT_REORG_DEPTH,
+            pending: BTreeMap::new(),
+            pending_keys: HashMap::new(),
+            arrivals: 0,
+        }
+    }
+
+    /// Sets how many recent blocks can be rewound when a reorg replaces them.
+    pub fn with_reorg_depth(mut self, depth: usize) -> Self {
+        self.reorg_depth = depth;
+        self
+    }
+
+    /// Starts tracking a pool from its state at the current block.
+    pub fn insert_pool(&mut self, address: Address, pool: impl Into<TrackedPool>) {
+        self.pools.insert(address, pool.into());
+    }
+
+    /// Last block applied.
+    pub fn block(&self) -> Option<u64> {
+        self.block
+    }
+
+    /// Confirmed state of a pool as of the last block.
+    pub fn confirmed(&self, address: Address) -> Option<&dyn Pool> {
+        self.pools.get(&address).map(TrackedPool::as_pool)
+    }
+
+    /// Applies the logs of a block, in log order, and drops the pending transactions it mined.
+    ///
+    /// `transactions` are the hashes of every transaction in the block, as reverted swaps and transactions
+    /// replaced by a same-nonce one that emitted no pool log are mined too.
+    ///
+    /// A block at or below the last one applied is a reorg: the replaced blocks are rewound first. The
+    /// pending transactions they mined are not restored and have to be inserted again if they reappear.
+    ///
+    /// Returns Ok(()) if the block was applied, or Err(StateError) if the reorg is too deep to rewind or an
+    /// event is invalid, in which case the confirmed state is left as it was.
+    pub fn apply_block(
+        &mut self,
+        number: u64,
+        transactions: &[H256],
+        logs: &[Log],
+    ) -> Result<(), StateError> {
+        if self.block.is_some_and(|block| number <= block) {
+            self.rewind(number)?;
+        }
+
+        let mut previous: Vec<(Address, TrackedPool)> = Vec::new();
+        for log in logs.iter().filter(|log| log.removed != Some(true)) {
+            let Some(pool) = self.pools.get_mut(&log.address) else {
+                continue;
+            };
+            let Some(event) = PoolEvent::decode(log) else {
+                continue;
+            };
+
+            if !previous.iter().any(|(address, _)| *address == log.address) {
+                previous.push((log.address, pool.clone()));
+            }
+            if let Err(source) = pool.apply_event(&event) {
+                self.restore(previous);
+                return Err(StateError::Event {
+                    pool: log.address,
+                    source,
+                });
+            }
+        }
+
+        for hash in transactions {
+            self.remove_pending(*hash);
+        }
+
+        self.history.push_back((number, previous));
+        while self.history.len() > self.reorg_depth {
+            self.history.pop_front();
+        }
+        self.block = Some(number);
+        Ok(())
+    }
+
+    /// Undoes the blocks from `number` on.
+    fn rewind(&mut self, number: u64) -> Result<(), StateError> {
+        if self
+            .history
+            .front()
+            .is_none_or(|(oldest, _)| *oldest > number)
+        {
+            return Err(StateError::ReorgTooDeep(number));
+        }
+
+        while self
+            .history
+            .back()
+            .is_some_and(|(block, _)| *block >= number)
+        {
+            if let Some((_, previous)) = self.history.pop_back() {
+                self.restore(previous);
+            }
+        }
+        self.block = self.history.back().map(|(block, _)| *block);
+        Ok(())
+    }
+
+    fn restore(&mut self, previous: Vec<(Address, TrackedPool)>) {
+        self.pools.extend(previous);
+    }
+
+    /// Adds the swaps of a pending transaction to the overlay, replacing an earlier version of it.
+    ///
+    /// `gas_price` orders the transaction against the other pending ones and should be the price it pays
+    /// the block builder. Swaps with a hop that no tracked pool, or several, could serve are ignored.
+    ///
+    /// Returns true if any swap trades through tracked pools.
+    pub fn insert_pending(&mut self, hash: H256, gas_price: U256, swaps: &[DecodedSwap]) -> bool {
+        self.remove_pending(hash);
+
+        let routed: Vec<RoutedSwap> = swaps.iter().filter_map(|swap| self.route(swap)).collect();
+        if routed.is_empty() {
+            return false;
+        }
+
+        let key = (Reverse(gas_price), self.arrivals);
+        self.arrivals += 1;
+        self.pending.insert(key, routed);
+        self.pending_keys.insert(hash, key);
+        true
+    }
+
+    /// Removes a pending transaction that was dropped, replaced or mined.
+    ///
+    /// Returns true if the transaction was pending.
+    pub fn remove_pending(&mut self, hash: H256) -> bool {
+        match self.pending_keys.remove(&hash) {
+            Some(key) => self.pending.remove(&key).is_some(),
+            None => false,
+        }
+    }
+
+    /// Number of pending transactions in the overlay.
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Address of the tracked pool a hop between `token_in` and `token_out` is routed through, None if no pool
+    /// or more than one matches.
+    pub fn route_hop(
+        &self,
+        protocol: Protocol,
+        token_in: Address,
+        token_out: Address,
+        fee: Option<u32>,
+    ) -> Option<Address> {
+        let mut candidates = self.pools.iter().filter(|(_, pool)| {
+            let tokens = pool.as_pool().tokens();
+            pool.serves(protocol, fee) && tokens.contains(&token_in) && tokens.contains(&token_out)
+        });
+
+        let (address, _) = candidates.next()?;
+        if candidates.next().is_some() {
+            return None;
+        }
+        Some(*address)
+    }
+
+    fn route(&self, swap: &DecodedSwap) -> Option<RoutedSwap> {
+        let mut pools = Vec::with_capacity(swap.path.len().saturating_sub(1));
+        for (hop, pair) in swap.path.windows(2).enumerate() {
+            let fee = swap.fees.get(hop).copied();
+            pools.push(self.route_hop(swap.protocol, pair[0], pair[1], fee)?);
+        }
+
+        (!pools.is_empty()).then(|| RoutedSwap {
+            kind: swap.kind,
+            amount_in: swap.amount_in,
+            amount_out: swap.amount_out,
+            path: swap.path.clone(),
+            pools,
+            allow_revert: swap.allow_revert,
+        })
+    }
+
+    /// State of a pool after every pending swap.
+    pub fn pending_state(&self, address: Address) -> Option<Box<dyn Pool>> {
+        self.project(address, None)
+    }
+
+    /// State of a pool after the pending swaps paying more than `gas_price`.
+    ///
+    /// Swaps at the same price are left out: builders order them by arrival, which this tracker only knows
+    /// for the transactions it was given.
+    pub fn state_ahead_of(&self, address: Address, gas_price: U256) -> Option<Box<dyn Pool>> {
+        self.project(address, Some(gas_price))
+    }
+
+    fn project(&self, address: Address, gas_price: Option<U256>) -> Option<Box<dyn Pool>> {
+        let confirmed = self.pools.get(&address)?;
+
+        // Swaps of other pools matter too, as they change the amounts later hops pass on.
+        let mut overlay: HashMap<Address, Box<dyn Pool>> = HashMap::new();
+        for ((Reverse(price), _), swaps) in &self.pending {
+            if gas_price.is_some_and(|gas_price| *price <= gas_price) {
+                break;
+            }
+            self.apply_pending(&mut overlay, swaps);
+        }
+
+        Some(
+            overlay
+                .remove(&address)
+                .unwrap_or_else(|| confirmed.as_pool().clone_box()),
+        )
+    }
+
+    /// Applies the swaps of one transaction to the overlay, or none of them if the transaction would revert.
+    fn apply_pending(&self, overlay: &mut HashMap<Address, Box<dyn Pool>>, swaps: &[RoutedSwap]) {
+        let mut staged: HashMap<Address, Box<dyn Pool>> = HashMap::new();
+        let mut previous_output = None;
+        for swap in swaps {
+            let mut states: Vec<Box<dyn Pool>> = swap
+                .pools
+                .iter()
+                .map(
+                    |address| match staged.get(address).or(overlay.get(address)) {
+                        Some(state) => state.clone(),
+                        None => self.pools[address].as_pool().clone_box(),
+                    },
+                )
+                .collect();
+
+            previous_output = execute(swap, &mut states, previous_output);
+            if previous_output.is_some() {
+                staged.extend(swap.pools.iter().copied().zip(states));
+            } else if !swap.allow_revert {
+                return;
+            }
+        }
+        overlay.extend(staged);
+    }
+}
+
+/// Executes a swap through its hop states, honouring its slippage limit.
+///
+/// An input of `contract_balance()` spends `previous_output`, the output of the transaction's previous swap.
+/// Returns the output amount, or None if the swap would revert.
+fn execute(
+    swap: &RoutedSwap,
+    states: &mut [Box<dyn Pool>],
+    previous_output: Option<U256>,
+) -> Option<U256> {
+    let hops: Vec<(Address, Address)> = swap
+        .path
+        .windows(2)
+        .map(|pair| (pair[0], pair[1]))
+        .collect();
+
+    let amount_in = match swap.kind {
+        SwapKind::ExactInput if swap.amount_in == contract_balance() => previous_output?,
+        SwapKind::ExactInput => swap.amount_in,
+        SwapKind::ExactOutput => {
+            let mut amount = swap.amount_out;
+            for (state, (token_in, token_out)) in states.iter().zip(&hops).rev() {
+                amount = state.quote_exact_out(*token_in, *token_out, amount).ok()?;
+            }
+            if amount > swap.amount_in {
+                return None;
+            }
+            amount
+        }
+    };
+
+    let mut amount = amount_in;
+    for (state, (token_in, token_out)) in states.iter_mut().zip(&hops) {
+        amount = state.apply_swap(*token_in, *token_out, amount).ok()?;
+    }
+    (amount >= swap.amount_out).then_some(amount)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constant_product::UNISWAP_V2_FEE;
+    use ethers::abi::{encode, Token};
+    use ethers::types::{BigEndianHash, I256};
+
+    fn token(byte: u8) -> Address {
+        Address::repeat_byte(byte)
+    }
+
+    fn pair() -> ConstantProductPool {
+        ConstantProductPool::new(U256::exp10(24), U256::exp10(24), UNISWAP_V2_FEE)
+            .with_tokens(token(1), token(2))
+    }
+
+    fn log(pool: Address, tx: u8, topics: Vec<H256>, data: &[Token]) -> Log {
+        Log {
+            address: pool,
+            topics,
+            data: encode(data).into(),
+            transaction_hash: Some(H256::repeat_byte(tx)),
+            ..Default::default()
+        }
+    }
+
+    fn sync(pool: Address, tx: u8, reserve0: U256, reserve1: U256) -> Log {
+        log(
+            pool,
+            tx,
+            vec![event_topic("Sync(uint112,uint112)")],
+            &[Token::Uint(reserve0), Token::Uint(reserve1)],
+        )
+    }
+
+    fn tick_topic(tick: i32) -> H256 {
+        H256::from_uint(&I256::from(tick).into_raw())
+    }
+
+    fn swap(kind: SwapKind, amount_in: U256, amount_out: U256) -> DecodedSwap {
+        DecodedSwap {
+            protocol: Protocol::UniswapV2,
+            kind,
+            path: vec![token(1), token(2)],
+            fees: vec![],
+            amount_in,
+            amount_out,
+            recipient: Address::zero(),
+            deadline: None,
+            allow_revert: false,
+        }
+    }
+
+    fn price(pool: &dyn Pool) -> U256 {
+        pool.spot_price(token(1), token(2)).unwrap()
+    }
+
+    #[test]
+    fn test_confirmed_state_follows_logs() {
+        let mut v3 = UniswapV3Pool::new(U256::one() << 96, 3000)
+            .unwrap()
+            .with_tokens(token(1), token(3));
+        v3.modify_liquidity(-600, 600, 1_000_000).unwrap();
+
+        let mut tracker = PoolStateTracker::new();
+        tracker.insert_pool(token(10), pair());
+        tracker.insert_pool(token(11), v3);
+
+        let sqrt_price = (U256::one() << 96) * 11 / 10;
+        let logs = vec![
+            sync(token(10), 1, U256::exp10(20), U256::exp10(21)),
+            log(
+                token(11),
+                2,
+                vec![
+                    event_topic("Swap(address,address,int256,int256,uint160,uint128,int24)"),
+                    H256::zero(),
+                    H256::zero(),
+                ],
+                &[
+                    Token::Int(I256::from(5).into_raw()),
+                    Token::Int(I256::from(-4).into_raw()),
+                    Token::Uint(sqrt_price),
+                    Token::Uint(U256::from(1_000_000)),
+                    Token::Int(I256::from(1906).into_raw()),
+                ],
+            ),
+            log(
+                token(11),
+                3,
+                vec![
+                    event_topic("Mint(address,address,int24,int24,uint128,uint256,uint256)"),
+                    H256::zero(),
+                    tick_topic(-1200),
+                    tick_topic(2400),
+                ],
+                &[
+                    Token::Address(Address::zero()),
+                    Token::Uint(U256::from(500)),
+                    Token::Uint(U256::one()),
+                    Token::Uint(U256::one()),
+                ],
+            ),
+            log(
+                token(11),
+                4,
+                vec![
+                    event_topic("Burn(address,int24,int24,uint128,uint256,uint256)"),
+                    H256::zero(),
+                    tick_topic(-600),
+                    tick_topic(600),
+                ],
+                &[
+                    Token::Uint(U256::from(200)),
+                    Token::Uint(U256::one()),
+                    Token::Uint(U256::one()),
+                ],
+            ),
+        ];
+        tracker.apply_block(7, &[], &logs).unwrap();
+        assert_eq!(tracker.block(), Some(7));
+
+        let (reserve_in, reserve_out) = tracker
+            .confirmed(token(10))
+            .unwrap()
+            .virtual_reserves(token(1), token(2))
+            .unwrap();
+        assert_eq!(
+            (reserve_in, reserve_out),
+            (U256::exp10(20), U256::exp10(21))
+        );
+
+        let Some(TrackedPool::UniswapV3(v3)) = tracker.pools.get(&token(11)) else {
+            panic!("expected a V3 pool");
+        };
+        assert_eq!(v3.sqrt_price_x96, sqrt_price);
+        assert_eq!(v3.tick, 1906);
+        // Tick 1906 is outside the burnt range and inside the minted one.
+        assert_eq!(v3.liquidity, U256::from(1_000_500));
+        assert_eq!(v3.ticks[&600].liquidity_gross, 999_800);
+        assert_eq!(v3.ticks[&-1200].liquidity_net, 500);
+    }
+
+    #[test]
+    fn test_route_hop_needs_a_single_matching_pool() {
+        let mut tracker = PoolStateTracker::new();
+        tracker.insert_pool(token(10), pair());
+        let v3 = UniswapV3Pool::new(U256::one() << 96, 3000)
+            .unwrap()
+            .with_tokens(token(1), token(2));
+        tracker.insert_pool(token(11), v3);
+
+        let hop = |tracker: &PoolStateTracker, protocol, fee| {
+            tracker.route_hop(protocol, token(2), token(1), fee)
+        };
+        assert_eq!(hop(&tracker, Protocol::UniswapV2, None), Some(token(10)));
+        assert_eq!(
+            hop(&tracker, Protocol::UniswapV3, Some(3000)),
+            Some(token(11))
+        );
+        assert_eq!(hop(&tracker, Protocol::UniswapV3, Some(500)), None);
+        assert_eq!(
+            tracker.route_hop(Protocol::UniswapV2, token(1), token(3), None),
+            None
+        );
+
+        // A second pair of the same tokens makes the hop ambiguous.
+        tracker.insert_pool(token(12), pair());
+        assert_eq!(hop(&tracker, Protocol::UniswapV2, None), None);
+    }
+
+    #[test]
+    fn test_overlay_orders_by_gas_price_and_rolls_back() {
+        let mut tracker = PoolStateTracker::new();
+        tracker.insert_pool(token(10), pair());
+        let confirmed = price(tracker.confirmed(token(10)).unwrap());
+
+        let low = H256::repeat_byte(1);
+        let high = H256::repeat_byte(2);
+        let buy = swap(SwapKind::ExactInput, U256::exp10(21), U256::zero());
+        assert!(tracker.insert_pending(low, U256::from(10), std::slice::from_ref(&buy)));
+        assert!(tracker.insert_pending(high, U256::from(30), std::slice::from_ref(&buy)));
+        assert!(!tracker.insert_pending(
+            H256::repeat_byte(3),
+            U256::from(50),
+            &[DecodedSwap {
+                path: vec![token(1), token(4)],
+                ..buy.clone()
+            }]
+        ));
+
+        let mut expected = pair();
+        expected
+            .apply_swap(token(1), token(2), U256::exp10(21))
+            .unwrap();
+        let after_high = price(&expected);
+        expected
+            .apply_swap(token(1), token(2), U256::exp10(21))
+            .unwrap();
+        let after_both = price(&expected);
+
+        let ahead = tracker.state_ahead_of(token(10), U256::from(20)).unwrap();
+        assert_eq!(price(ahead.as_ref()), after_high);
+        let ahead = tracker.state_ahead_of(token(10), U256::from(30)).unwrap();
+        assert_eq!(price(ahead.as_ref()), confirmed);
+        assert_eq!(
+            price(tracker.pending_state(token(10)).unwrap().as_ref()),
+            after_both
+        );
+
+        // Dropping a transaction rolls its swap back.
+        assert!(tracker.remove_pending(high));
+        assert_eq!(
+            price(tracker.pending_state(token(10)).unwrap().as_ref()),
+            after_high
+        );
+
+        // Mining one moves it from the overlay into the confirmed state.
+        let mut mined = pair();
+        mined
+            .apply_swap(token(1), token(2), U256::exp10(21))
+            .unwrap();
+        tracker
+            .apply_block(
+                1,
+                &[low],
+                &[sync(token(10), 1, mined.reserve0, mined.reserve1)],
+            )
+            .unwrap();
+        assert_eq!(tracker.pending_len(), 0);
+        assert_eq!(
+            price(tracker.pending_state(token(10)).unwrap().as_ref()),
+            after_high
+        );
+        assert_eq!(price(tracker.confirmed(token(10)).unwrap()), after_high);
+    }
+
+    #[test]
+    fn test_mined_transactions_without_logs_leave_the_overlay() {
+        let mut tracker = PoolStateTracker::new();
+        tracker.insert_pool(token(10), pair());
+        let reverted = H256::repeat_byte(1);
+        tracker.insert_pending(
+            reverted,
+            U256::from(10),
+            &[swap(SwapKind::ExactInput, U256::exp10(21), U256::zero())],
+        );
+
+        tracker.apply_block(1, &[reverted], &[]).unwrap();
+        assert_eq!(tracker.pending_len(), 0);
+        assert_eq!(
+            price(tracker.pending_state(token(10)).unwrap().as_ref()),
+            price(&pair())
+        );
+    }
+
+    #[test]
+    fn test_reverting_swaps_are_skipped() {
+        let mut tracker = PoolStateTracker::new();
+        tracker.insert_pool(token(10), pair());
+        let quote = pair()
+            .quote_exact_in(token(1), token(2), U256::exp10(21))
+            .unwrap();
+
+        // Minimum output above the quote.
+        tracker.insert_pending(
+            H256::repeat_byte(1),
+            U256::from(10),
+            &[swap(SwapKind::ExactInput, U256::exp10(21), quote + 1)],
+        );
+        // Maximum input below what the exact output costs.
+        tracker.insert_pending(
+            H256::repeat_byte(2),
+            U256::from(10),
+            &[swap(SwapKind::ExactOutput, U256::exp10(20), quote)],
+        );
+        assert_eq!(
+            price(tracker.pending_state(token(10)).unwrap().as_ref()),
+            price(&pair())
+        );
+
+        tracker.insert_pending(
+            H256::repeat_byte(3),
+            U256::from(10),
+            &[swap(SwapKind::ExactOutput, U256::exp10(21), quote)],
+        );
+        let mut expected = pair();
+        let amount_in = expected.quote_exact_out(token(1), token(2), quote).unwrap();
+        expected.apply_swap(token(1), token(2), amount_in).unwrap();
+        assert_eq!(
+            price(tracker.pending_state(token(10)).unwrap().as_ref()),
+            price(&expected)
+        );
+    }
+
+    #[test]
+    fn test_reorg_rewinds_replaced_blocks() {
+        let mut tracker = PoolStateTracker::new().with_reorg_depth(2);
+        tracker.insert_pool(token(10), pair());
+        let reserves = |tracker: &PoolStateTracker| {
+            tracker
+                .confirmed(token(10))
+                .unwrap()
+                .virtual_reserves(token(1), token(2))
+                .unwrap()
+        };
+
+        for block in 1..=3 {
+            let reserve = U256::from(block * 1000);
+            tracker
+                .apply_block(block, &[], &[sync(token(10), 1, reserve, reserve)])
+                .unwrap();
+        }
+
+        // Block 3 is replaced by one without pool logs.
+        tracker.apply_block(3, &[], &[]).unwrap();
+        assert_eq!(reserves(&tracker), (U256::from(2000), U256::from(2000)));
+
+        assert!(matches!(
+            tracker.apply_block(1, &[], &[]),
+            Err(StateError::ReorgTooDeep(1))
+        ));
+        assert_eq!(tracker.block(), Some(3));
+    }
+}
@@ -1,54 +1,27 @@
-// This module contains mathematical functions related to sandwich attacks in Uniswap V3.
+// This module contains mathematical functions related to sandwich attacks on constant-product and Uniswap V3 pools.
 // It provides structures and methods for calculating reserves and validating trades.
 
 #![allow(non_snake_case)]
 #![allow(non_camel_case_types)]
 #![allow(unused_variables)]
 
-use ethers::types::{Transaction, U256, Bytes};
-use mev_risk::{RiskError, RiskParameters};
+use ethers::types::{Address, Transaction, U256, U512, Bytes};
+use mev_risk::{RiskEngine, RiskError};
 use std::error::Error;
 
-/// Constant used for reserve calculations, representing 2^96.
-const Q96: u128 = 0x1000000000000000000000000;
-
-/// Structure representing a Uniswap V3 pool.
-#[derive(Debug, Clone, Default)]
-pub struct UniswapV3Pool {
-    /// Square root of the price in x96 format.
-    pub sqrt_price_x96: U256,
-    /// Liquidity of the pool.
-    pub liquidity: U256,
-    /// Fee tier of the pool.
-    pub fee: u32,
-}
-
-impl UniswapV3Pool {
-    /// Calculates the reserves of the pool based on liquidity and price.
-    ///
-    /// Returns a tuple of (reserve0, reserve1) as U256.
-    pub fn get_reserves(&self) -> (U256, U256) {
-        let sqrt_price = self.sqrt_price_x96.as_u128();
-        let liquidity = self.liquidity.as_u128();
-
-        // Calculate reserve0 by multiplying liquidity with Q96 and dividing by sqrt_price.
-        let reserve0 = (liquidity * Q96) / sqrt_price;
-        // Calculate reserve1 by multiplying liquidity with sqrt_price and dividing by Q96.
-        let reserve1 = (liquidity * sqrt_price) / Q96;
-
-        (U256::from(reserve0), U256::from(reserve1)) // Return reserves as U256.
-    }
-}
+pub use crate::constant_product::ConstantProductPool;
+pub use crate::error::MathError;
+pub use crate::pool::Pool;
+pub use crate::uniswap_v3::UniswapV3Pool;
+use crate::decoder::decode_swaps;
+use crate::uniswap_v3::swap_math::FEE_DENOMINATOR;
 
 /// Structure for sandwich attack calculations and risk management.
+///
+/// Risk is checked against the risk engine the strategies share, such as `CircuitBreaker::risk_engine`, so
+/// every limit applies to the sandwiches too.
 #[derive(Debug, Default)]
 pub struct SandwichMath {
-    /// The Uniswap V3 pool associated with the calculations.
-    pub pool: UniswapV3Pool,
-    /// Risk parameters for trade validation.
-    pub risk_params: RiskParameters,
-    /// Risk engine for managing trade risks.
-    pub risk_engine: mev_risk::RiskEngine,
     /// Slippage tolerance for sandwich calculations.
     pub slippage_tolerance: U256,
     /// Minimum profit threshold for sandwich calculations.
@@ -56,35 +29,38 @@ pub struct SandwichMath {
 }
 
 impl SandwichMath {
-    /// Extracts the input amount from a transaction.
+    /// Extracts the input amount of the first swap in a router transaction.
     ///
-    /// Returns the input amount as U256 if successful, or None if the transaction input is invalid.
+    /// Exact-output swaps report their maximum input. Returns the input amount as U256 if successful, or None
+    /// if the transaction is not a supported router swap.
     pub fn extract_input_amount(&self, tx: &Transaction) -> Option<U256> {
-        // Get first 32 bytes of input (typical parameter size).
-        let bytes = tx.input.get(0..32)?;
-
-        // Convert byte slice to U256 using big-endian encoding.
-        Some(U256::from_big_endian(bytes))
+        let swaps = decode_swaps(&tx.input, tx.value).ok()?;
+        swaps.first().map(|swap| swap.amount_in)
     }
 
-    /// Validates the risk of a trade based on the input amount.
+    /// Validates the risk of a trade based on the input amount, against the shared `risk_engine`.
     ///
     /// Returns Ok(()) if the trade is valid, or Err(RiskError) if the trade is invalid.
-    pub fn validate_risk(&self, amount: U256) -> Result<(), RiskError> {
-        self.risk_engine.validate_trade(amount, U256::zero()) // Validate trade with zero output.
+    pub fn validate_risk(&self, risk_engine: &RiskEngine, amount: U256) -> Result<(), RiskError> {
+        risk_engine.check_size(amount)
     }
 
-    /// Calculates the optimal size for a trade based on the target transaction.
+    /// Calculates the optimal size for a trade based on the target transaction, capped by the shared
+    /// `risk_engine`.
     ///
     /// Returns the optimal size as U256 if successful, or Err(MathError) if the calculation fails.
-    pub fn calculate_optimal_size(&self, target_tx: &Transaction) -> Result<U256, MathError> {
+    pub fn calculate_optimal_size(
+        &self,
+        risk_engine: &RiskEngine,
+        target_tx: &Transaction,
+    ) -> Result<U256, MathError> {
         // Extract input amount from the target transaction.
         let input_amount = self
             .extract_input_amount(target_tx)
             .ok_or(MathError::InvalidTransaction)?; // Extract input amount or return error.
 
         // Calculate the maximum position using the risk engine.
-        self.risk_engine
+        risk_engine
             .calculate_max_position(input_amount)
             .map_err(MathError::RiskValidationFailed)
     }
@@ -102,53 +78,277 @@ impl SandwichMath {
         Ok((Bytes::new(), Bytes::new()))
     }
 
-    /// Calculates the optimal amounts for a sandwich trade.
+    /// Calculates the largest frontrun that still lets the victim's swap clear its minimum output, and the
+    /// profit of the resulting sandwich.
+    ///
+    /// The victim sells `victim_amount_in` of `token_in` for `token_out` and requires at least
+    /// `victim_amount_out_min` of `token_out`. `gas_cost` is the cost of the frontrun and backrun, denominated
+    /// in `token_in`. Pools with virtual reserves are seeded with the constant-product closed form, and the
+    /// boundary is then settled by bisection over the pool's exact swap path, so fork rounding and tick
+    /// crossings are priced correctly. Victims without a minimum are frontrun with at most the input reserve,
+    /// and the frontrun shrinks to the profit peak if that lies below the boundary.
     ///
-    /// Returns a tuple of (frontrun_amount, backrun_amount) as U256 if successful, or Err(Box<dyn Error>) if the calculation fails.
+    /// Returns the sandwich amounts if successful, or Err(MathError::UnprofitableTrade) if the net profit does
+    /// not reach `min_profit_threshold`.
     pub fn calculate_optimal_amounts(
         &self,
-        _pool_reserves0: U256,
-        _pool_reserves1: U256,
-        target_amount: U256,
-    ) -> Result<(U256, U256), Box<dyn Error>> {
-        // Calculate optimal amounts for frontrun and backrun
-        // This is a simplified implementation
-        let frontrun_amount = target_amount
-            .checked_mul(self.slippage_tolerance)
-            .ok_or("Overflow in frontrun calculation")?
-            .checked_div(U256::from(1000))
-            .ok_or("Division by zero in frontrun calculation")?;
-
-        let backrun_amount = frontrun_amount
-            .checked_mul(U256::from(2))
-            .ok_or("Overflow in backrun calculation")?;
-
-        Ok((frontrun_amount, backrun_amount))
+        pool: &dyn Pool,
+        token_in: Address,
+        token_out: Address,
+        victim_amount_in: U256,
+        victim_amount_out_min: U256,
+        gas_cost: U256,
+    ) -> Result<SandwichAmounts, MathError> {
+        let simulate = |frontrun_amount_in: U256| {
+            simulate_sandwich(pool, token_in, token_out, frontrun_amount_in, victim_amount_in)
+        };
+        let victim_clears = |frontrun_amount_in: U256| {
+            simulate(frontrun_amount_in)
+                .map(|amounts| amounts.victim_amount_out >= victim_amount_out_min)
+                .unwrap_or(false)
+        };
+
+        // Validate the pair before searching, so unknown tokens are not reported as unprofitable.
+        pool.spot_price(token_in, token_out)?;
+        let virtual_reserves = pool.virtual_reserves(token_in, token_out);
+        let estimate = match virtual_reserves {
+            Some((reserve_in, reserve_out)) => constant_product_frontrun_estimate(
+                reserve_in,
+                reserve_out,
+                pool.fee(),
+                victim_amount_in,
+                victim_amount_out_min,
+            )?,
+            None => victim_amount_in.max(U256::one()),
+        };
+
+        // The closed form ignores the fees left in the pool, so settle on the exact boundary around it.
+        let frontrun_amount_in = if victim_amount_out_min.is_zero() && virtual_reserves.is_some() {
+            estimate
+        } else if victim_clears(estimate) {
+            let mut step = U256::one();
+            while victim_clears(estimate.saturating_add(step)) && step < U256::MAX >> 1 {
+                step <<= 1;
+            }
+            largest_satisfying(estimate, estimate.saturating_add(step), victim_clears)
+        } else if victim_clears(U256::zero()) {
+            largest_satisfying(U256::zero(), estimate, victim_clears)
+        } else {
+            return Err(MathError::UnprofitableTrade);
+        };
+
+        let frontrun_amount_in = most_profitable_below(frontrun_amount_in, |amount| simulate(amount).ok());
+        self.finalize_amounts(simulate(frontrun_amount_in)?, gas_cost)
     }
+
+    /// Applies gas cost and the profit threshold to simulated sandwich amounts.
+    fn finalize_amounts(
+        &self,
+        mut amounts: SandwichAmounts,
+        gas_cost: U256,
+    ) -> Result<SandwichAmounts, MathError> {
+        amounts.gross_profit = amounts
+            .backrun_amount_out
+            .checked_sub(amounts.frontrun_amount_in)
+            .ok_or(MathError::UnprofitableTrade)?;
+        amounts.net_profit = amounts
+            .gross_profit
+            .checked_sub(gas_cost)
+            .ok_or(MathError::UnprofitableTrade)?;
+
+        if amounts.net_profit.is_zero() || amounts.net_profit < self.min_profit_threshold {
+            return Err(MathError::UnprofitableTrade);
+        }
+
+        Ok(amounts)
+    }
+}
+
+/// Amounts of a sized sandwich around a victim swap.
+///
+/// Input amounts and profits are denominated in the victim's input token, output amounts in the victim's
+/// output token. The backrun sells exactly `frontrun_amount_out`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SandwichAmounts {
+    /// Amount of the input token sold by the frontrun.
+    pub frontrun_amount_in: U256,
+    /// Amount of the output token bought by the frontrun.
+    pub frontrun_amount_out: U256,
+    /// Amount of the output token the victim receives after the frontrun.
+    pub victim_amount_out: U256,
+    /// Amount of the input token bought back by the backrun.
+    pub backrun_amount_out: U256,
+    /// Backrun output minus frontrun input, after both pool fees.
+    pub gross_profit: U256,
+    /// Gross profit minus gas cost.
+    pub net_profit: U256,
 }
 
-/// Error types for mathematical operations.
-#[derive(Debug, thiserror::Error)]
-pub enum MathError {
-    /// Invalid transaction input.
-    #[error("Invalid transaction")]
-    InvalidTransaction,
-    /// Arithmetic overflow occurred during calculation.
-    #[error("Arithmetic overflow")]
-    Overflow,
-    /// Trade does not meet profit requirements.
-    #[error("Trade doesn't meet profit requirements")]
-    UnprofitableTrade,
-    /// Risk validation failed.
-    #[error("Risk validation failed")]
-    RiskValidationFailed(#[from] RiskError),
+/// Estimates the largest frontrun with the closed form of the victim's constraint.
+///
+/// Treating k = reserve_in * reserve_out as constant, the victim clears its minimum `m` as long as the input
+/// reserve after the frontrun `a` satisfies m * a^2 + m * g * a - g * k <= 0, where g is the victim's input
+/// after the `fee` in pips. The positive root of that quadratic bounds the post-frontrun reserve.
+fn constant_product_frontrun_estimate(
+    reserve_in: U256,
+    reserve_out: U256,
+    fee: u32,
+    victim_amount_in: U256,
+    victim_amount_out_min: U256,
+) -> Result<U256, MathError> {
+    if reserve_in.is_zero() || reserve_out.is_zero() || fee >= FEE_DENOMINATOR {
+        return Err(MathError::InsufficientLiquidity);
+    }
+    if victim_amount_out_min.is_zero() {
+        // Without a minimum the victim never reverts, so the frontrun is only bounded by the pool.
+        return Ok(reserve_in);
+    }
+
+    let victim_in_after_fee = U512::from(victim_amount_in) * U512::from(FEE_DENOMINATOR - fee)
+        / U512::from(FEE_DENOMINATOR);
+    let min_out = U512::from(victim_amount_out_min);
+    let k = U512::from(reserve_in) * U512::from(reserve_out);
+
+    let linear = min_out
+        .checked_mul(victim_in_after_fee)
+        .ok_or(MathError::Overflow)?;
+    let discriminant = linear
+        .checked_mul(k)
+        .and_then(|product| product.checked_mul(U512::from(4)))
+        .and_then(|product| linear.checked_mul(linear)?.checked_add(product))
+        .ok_or(MathError::Overflow)?;
+    let reserve_after_frontrun = (discriminant.integer_sqrt() - linear) / (U512::from(2) * min_out);
+
+    let estimate = reserve_after_frontrun.saturating_sub(U512::from(reserve_in));
+    U256::try_from(estimate).map_err(|_| MathError::Overflow)
+}
+
+/// Simulates frontrun, victim and backrun on a clone of the pool.
+fn simulate_sandwich(
+    pool: &dyn Pool,
+    token_in: Address,
+    token_out: Address,
+    frontrun_amount_in: U256,
+    victim_amount_in: U256,
+) -> Result<SandwichAmounts, MathError> {
+    let mut pool = pool.clone_box();
+    let mut swap = |amount_in: U256, token_in: Address, token_out: Address| {
+        // A zero frontrun or backrun is skipped rather than sent.
+        if amount_in.is_zero() {
+            Ok(U256::zero())
+        } else {
+            pool.apply_swap(token_in, token_out, amount_in)
+        }
+    };
+
+    let frontrun_amount_out = swap(frontrun_amount_in, token_in, token_out)?;
+    let victim_amount_out = swap(victim_amount_in, token_in, token_out)?;
+    let backrun_amount_out = swap(frontrun_amount_out, token_out, token_in)?;
+
+    Ok(SandwichAmounts {
+        frontrun_amount_in,
+        frontrun_amount_out,
+        victim_amount_out,
+        backrun_amount_out,
+        ..Default::default()
+    })
+}
+
+/// Returns the frontrun size in `[0, upper]` with the highest gross profit, preferring `upper`.
+///
+/// Victims with loose minimums tolerate frontruns past the point where the extra pool fees outweigh the
+/// extra price impact, so the profit curve is searched by golden-section when it already peaked below `upper`.
+fn most_profitable_below(
+    upper: U256,
+    simulate: impl Fn(U256) -> Option<SandwichAmounts>,
+) -> U256 {
+    // Compare profits as backrun_a + frontrun_b against backrun_b + frontrun_a to stay unsigned.
+    let more_profitable = |a: U256, b: U256| match (simulate(a), simulate(b)) {
+        (Some(a), Some(b)) => {
+            a.backrun_amount_out.saturating_add(b.frontrun_amount_in)
+                > b.backrun_amount_out.saturating_add(a.frontrun_amount_in)
+        }
+        (Some(_), None) => true,
+        _ => false,
+    };
+
+    // Single-wei steps are lost in rounding, so probe the slope over a small fraction of the range.
+    let probe = upper - (upper >> 10).max(U256::one()).min(upper);
+    if upper.is_zero() || !more_profitable(probe, upper) {
+        return upper;
+    }
+
+    // Golden-section search over integers, with the interior points at 0.382 and 0.618 of the range.
+    let (mut lower, mut upper) = (U256::zero(), upper);
+    while upper - lower > U256::from(2) {
+        let span = upper - lower;
+        let left = lower + golden_fraction(span, 382);
+        let right = (lower + golden_fraction(span, 618)).max(left + 1);
+        if more_profitable(left, right) {
+            upper = right;
+        } else {
+            lower = left;
+        }
+    }
+
+    let mut best = lower;
+    while best < upper {
+        if more_profitable(best + 1, best) {
+            best += U256::one();
+        } else {
+            break;
+        }
+    }
+    best
+}
+
+/// Computes span * per_mille / 1000 without overflowing for large spans.
+fn golden_fraction(span: U256, per_mille: u64) -> U256 {
+    let per_mille = U256::from(per_mille);
+    span / 1000 * per_mille + span % 1000 * per_mille / 1000
+}
+
+/// Bisects for the largest value in `[lower, upper]` satisfying a monotonically decreasing predicate.
+///
+/// `predicate(lower)` is assumed to hold.
+fn largest_satisfying(mut lower: U256, mut upper: U256, predicate: impl Fn(U256) -> bool) -> U256 {
+    while lower < upper {
+        let mid = lower + (upper - lower + 1) / 2;
+        if predicate(mid) {
+            lower = mid;
+        } else {
+            upper = mid - 1;
+        }
+    }
+    lower
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::constant_product::{APESWAP_FEE, UNISWAP_V2_FEE};
+    use ethers::types::I256;
     use tokio::test;
 
+    fn token(byte: u8) -> Address {
+        Address::repeat_byte(byte)
+    }
+
+    /// Constant-product pool at 1:1 with 1M tokens of 18 decimals on each side.
+    fn create_v2_pool(fee: (u32, u32)) -> ConstantProductPool {
+        ConstantProductPool::new(U256::exp10(24), U256::exp10(24), fee).with_tokens(token(1), token(2))
+    }
+
+    /// 0.3% pool at 1:1 with full-range liquidity plus one concentrated position.
+    fn create_v3_pool(position: (i32, i32, i128)) -> UniswapV3Pool {
+        let mut pool = UniswapV3Pool::new(U256::one() << 96, 3000)
+            .unwrap()
+            .with_tokens(token(1), token(2));
+        pool.modify_liquidity(-887220, 887220, 10_i128.pow(24)).unwrap();
+        pool.modify_liquidity(position.0, position.1, position.2).unwrap();
+        pool
+    }
+
     #[test]
     async fn test_build_sandwich_data() {
         let math = SandwichMath::default();
@@ -161,17 +361,215 @@ mod tests {
         assert_eq!(result.1.len(), 0);
     }
 
+    #[test]
+    async fn test_risk_is_checked_against_the_shared_engine() {
+        let math = SandwichMath::default();
+        let risk_engine = RiskEngine::new(mev_risk::RiskParameters {
+            max_position_size: U256::from(1_000),
+            ..Default::default()
+        });
+
+        assert!(math.validate_risk(&risk_engine, U256::from(1_000)).is_ok());
+        assert!(matches!(
+            math.validate_risk(&risk_engine, U256::from(1_001)),
+            Err(RiskError::PositionSizeExceeded)
+        ));
+    }
+
+    #[test]
+    async fn test_extract_input_amount() {
+        let math = SandwichMath::default();
+        let mut input = ethers::utils::id("swapExactTokensForTokens(uint256,uint256,address[],address,uint256)").to_vec();
+        input.extend(ethers::abi::encode(&[
+            ethers::abi::Token::Uint(U256::exp10(18)),
+            ethers::abi::Token::Uint(U256::from(990)),
+            ethers::abi::Token::Array(vec![
+                ethers::abi::Token::Address(token(1)),
+                ethers::abi::Token::Address(token(2)),
+            ]),
+            ethers::abi::Token::Address(token(9)),
+            ethers::abi::Token::Uint(U256::from(1_700_000_000)),
+        ]));
+        let tx = Transaction {
+            input: input.into(),
+            ..Default::default()
+        };
+
+        // The amount is the first argument, after the selector.
+        assert_eq!(math.extract_input_amount(&tx), Some(U256::exp10(18)));
+        assert_eq!(math.extract_input_amount(&Transaction::default()), None);
+    }
+
     #[test]
     async fn test_calculate_optimal_amounts() {
         let math = SandwichMath::default();
+        let pool = create_v2_pool(UNISWAP_V2_FEE);
+        let victim_amount_in = U256::exp10(22);
+        // Victim accepts 1% slippage on the undisturbed quote.
+        let quote = pool.get_amount_out(victim_amount_in, true).unwrap();
+        let victim_amount_out_min = quote * 99 / 100;
+
+        let result = math
+            .calculate_optimal_amounts(
+                &pool,
+                token(1),
+                token(2),
+                victim_amount_in,
+                victim_amount_out_min,
+                U256::zero(),
+            )
+            .unwrap();
 
+        // The frontrun is the largest the victim tolerates: one more wei would make the victim revert.
+        assert!(result.frontrun_amount_in > U256::zero());
+        assert!(result.victim_amount_out >= victim_amount_out_min);
+        let larger =
+            simulate_sandwich(&pool, token(1), token(2), result.frontrun_amount_in + 1, victim_amount_in)
+                .unwrap();
+        assert!(larger.victim_amount_out < victim_amount_out_min);
+        assert_eq!(result.gross_profit, result.backrun_amount_out - result.frontrun_amount_in);
+        assert_eq!(result.net_profit, result.gross_profit);
+    }
+
+    #[test]
+    async fn test_calculate_optimal_amounts_known_vector() {
+        let math = SandwichMath::default();
+
+        let result = math
+            .calculate_optimal_amounts(
+                &create_v2_pool(UNISWAP_V2_FEE),
+                token(1),
+                token(2),
+                U256::exp10(22),
+                U256::from_dec_str("9772864540530906858618").unwrap(),
+                U256::exp10(15),
+            )
+            .unwrap();
+
+        assert_eq!(result.frontrun_amount_in, U256::from_dec_str("5070447747980987943455").unwrap());
+        assert_eq!(result.net_profit, U256::from_dec_str("70161485508261318025").unwrap());
+    }
+
+    #[test]
+    async fn test_calculate_optimal_amounts_fork_fees() {
+        let math = SandwichMath::default();
+        let victim_amount_in = U256::exp10(22);
+        let victim_amount_out_min = U256::from_dec_str("9772864540530906858618").unwrap();
+
+        let uniswap = math
+            .calculate_optimal_amounts(
+                &create_v2_pool(UNISWAP_V2_FEE),
+                token(1),
+                token(2),
+                victim_amount_in,
+                victim_amount_out_min,
+                U256::zero(),
+            )
+            .unwrap();
+        let apeswap = math
+            .calculate_optimal_amounts(
+                &create_v2_pool(APESWAP_FEE),
+                token(2),
+                token(1),
+                victim_amount_in,
+                victim_amount_out_min,
+                U256::zero(),
+            )
+            .unwrap();
+
+        // A cheaper pool gives the victim a better quote, leaving more room to frontrun.
+        assert!(apeswap.frontrun_amount_in > uniswap.frontrun_amount_in);
+        assert!(apeswap.net_profit > uniswap.net_profit);
+    }
+
+    #[test]
+    async fn test_calculate_optimal_amounts_unprofitable() {
+        let math = SandwichMath::default();
+        let pool = create_v2_pool(UNISWAP_V2_FEE);
+        let victim_amount_in = U256::exp10(22);
+        let quote = pool.get_amount_out(victim_amount_in, true).unwrap();
+
+        // A victim without slippage room leaves nothing to extract.
+        let result =
+            math.calculate_optimal_amounts(&pool, token(1), token(2), victim_amount_in, quote, U256::zero());
+        assert!(matches!(result, Err(MathError::UnprofitableTrade)));
+
+        // Gas that exceeds the gross profit makes the sandwich unprofitable.
         let result = math.calculate_optimal_amounts(
-            U256::from(1000000),
-            U256::from(1000000),
-            U256::from(100000),
-        ).unwrap();
+            &pool,
+            token(1),
+            token(2),
+            victim_amount_in,
+            quote * 99 / 100,
+            U256::exp10(22),
+        );
+        assert!(matches!(result, Err(MathError::UnprofitableTrade)));
+
+        // A pair the pool does not trade is rejected as such.
+        let result =
+            math.calculate_optimal_amounts(&pool, token(1), token(3), victim_amount_in, quote, U256::zero());
+        assert!(matches!(result, Err(MathError::UnknownToken(_))));
+    }
+
+    #[test]
+    async fn test_calculate_optimal_amounts_without_minimum_is_capped_by_reserves() {
+        let math = SandwichMath::default();
+        let pool = create_v2_pool(UNISWAP_V2_FEE);
+        let victim_amount_in = U256::exp10(22);
+
+        let result = math
+            .calculate_optimal_amounts(&pool, token(1), token(2), victim_amount_in, U256::zero(), U256::zero())
+            .unwrap();
+
+        // Without a minimum profit keeps growing with size, so the frontrun is capped at the input reserve.
+        assert_eq!(result.frontrun_amount_in, pool.reserve0);
+        let smaller =
+            simulate_sandwich(&pool, token(1), token(2), pool.reserve0 * 9 / 10, victim_amount_in).unwrap();
+        assert!(smaller.backrun_amount_out - smaller.frontrun_amount_in < result.gross_profit);
+    }
+
+    #[test]
+    async fn test_most_profitable_below_stops_at_peak() {
+        let pool = create_v3_pool((-600, 600, 10_i128.pow(25)));
+        let simulate = |amount| simulate_sandwich(&pool, token(1), token(2), amount, U256::exp10(22)).ok();
+
+        // Deep liquidity around the price makes large frontruns pay more in fees than they extract.
+        let best = most_profitable_below(U256::exp10(23), simulate);
+        let profit = |amount| {
+            let amounts = simulate(amount).unwrap();
+            I256::from_raw(amounts.backrun_amount_out) - I256::from_raw(amounts.frontrun_amount_in)
+        };
+
+        assert!(best < U256::exp10(23));
+        assert!(profit(best) >= profit(U256::exp10(23)));
+        assert!(profit(best) >= profit(best * 2));
+    }
+
+    #[test]
+    async fn test_calculate_optimal_amounts_v3() {
+        let math = SandwichMath::default();
+        let pool = create_v3_pool((-180, -60, 2 * 10_i128.pow(24)));
+
+        let victim_amount_in = U256::exp10(22);
+        let quote = pool.get_amount_out(victim_amount_in, true).unwrap();
+        let victim_amount_out_min = quote * 99 / 100;
+
+        let result = math
+            .calculate_optimal_amounts(
+                &pool,
+                token(1),
+                token(2),
+                victim_amount_in,
+                victim_amount_out_min,
+                U256::exp10(15),
+            )
+            .unwrap();
 
-        assert!(result.0 > U256::zero());
-        assert!(result.1 > result.0);
+        assert!(result.victim_amount_out >= victim_amount_out_min);
+        let larger =
+            simulate_sandwich(&pool, token(1), token(2), result.frontrun_amount_in + 1, victim_amount_in)
+                .unwrap();
+        assert!(larger.victim_amount_out < victim_amount_out_min);
+        assert_eq!(result.net_profit, result.gross_profit - U256::exp10(15));
     }
 }
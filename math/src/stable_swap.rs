@@ -0,0 +1,668 @@
+// This module models Curve StableSwap pools, such as the aave pool on Polygon.
+// The invariant, `get_D`/`get_y` Newton iterations and fee handling follow Curve's Vyper contracts to the wei.
+
+use ethers::types::{Address, U256};
+
+use crate::error::MathError;
+use crate::pool::{Pool, PRICE_SCALE};
+use crate::uniswap_v3::full_math::mul_div;
+
+/// Scale of the amplification coefficient, as `A_PRECISION` in Curve's contracts.
+///
+/// Pools that predate it compute exactly the same results with `amp = A * A_PRECISION`.
+pub const A_PRECISION: u64 = 100;
+/// Denominator of Curve fees: a fee of 4_000_000 is 0.04%.
+pub const FEE_DENOMINATOR: u64 = 10_000_000_000;
+/// Precision of rates and virtual balances.
+pub const PRECISION: u64 = 1_000_000_000_000_000_000;
+/// Largest number of coins a StableSwap pool holds.
+pub const MAX_COINS: usize = 8;
+
+/// Newton iterations before giving up, as the contracts' `range(255)` loops.
+const MAX_ITERATIONS: usize = 255;
+
+/// Rate multiplier of a coin with the given decimals, so that `balance * rate / PRECISION` has 18 decimals.
+pub fn rate_for_decimals(decimals: u8) -> U256 {
+    U256::exp10(36 - decimals as usize)
+}
+
+/// Structure representing a Curve StableSwap pool.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StableSwapPool {
+    /// Addresses of the pool's coins, as `coins(i)`.
+    pub coins: Vec<Address>,
+    /// Addresses of the underlying coins of lending pools, as `underlying_coins(i)`; empty for plain pools.
+    pub underlying_coins: Vec<Address>,
+    /// Balances of the pool's coins, in raw units.
+    pub balances: Vec<U256>,
+    /// Rate multipliers converting balances to 18-decimal virtual balances, see `rate_for_decimals`.
+    pub rates: Vec<U256>,
+    /// Amplification coefficient multiplied by `A_PRECISION`, as `A_precise()`.
+    pub amp: U256,
+    /// Swap fee over `FEE_DENOMINATOR`.
+    pub fee: U256,
+    /// Share of the swap fee paid to the admin over `FEE_DENOMINATOR`, removed from the pool's balances.
+    pub admin_fee: U256,
+    /// Multiplier raising the fee as the pool moves off peg over `FEE_DENOMINATOR`; disabled at or below it.
+    pub offpeg_fee_multiplier: U256,
+    /// Whether `get_D` divides by `x * N_COINS + 1`, as the aave pool and other lending pools do.
+    pub lending_invariant: bool,
+}
+
+impl StableSwapPool {
+    /// Creates a plain pool from its balances, rate multipliers, `A_precise()` and fee.
+    ///
+    /// Returns the pool, or Err(MathError::InvalidPoolParameters) if the coin count, rates or parameters are invalid.
+    pub fn new(
+        balances: Vec<U256>,
+        rates: Vec<U256>,
+        amp: U256,
+        fee: U256,
+    ) -> Result<Self, MathError> {
+        let coins = balances.len();
+        if !(2..=MAX_COINS).contains(&coins)
+            || rates.len() != coins
+            || rates.iter().any(|rate| rate.is_zero())
+            || amp.is_zero()
+            || fee >= U256::from(FEE_DENOMINATOR)
+        {
+            return Err(MathError::InvalidPoolParameters);
+        }
+
+        Ok(Self {
+            coins: vec![Address::zero(); coins],
+            balances,
+            rates,
+            amp,
+            fee,
+            ..Default::default()
+        })
+    }
+
+    /// Sets the addresses of the pool's coins, needed to use the pool through the `Pool` trait.
+    pub fn with_coins(mut self, coins: Vec<Address>) -> Self {
+        self.coins = coins;
+        self
+    }
+
+    /// Sets the underlying coins of a lending pool whose wrapped coins track them 1:1, such as aTokens.
+    ///
+    /// `exchange_underlying` on such pools moves the wrapped balances at the same indexes, so underlying
+    /// swaps are priced exactly like wrapped ones.
+    pub fn with_underlying_coins(mut self, underlying_coins: Vec<Address>) -> Self {
+        self.underlying_coins = underlying_coins;
+        self
+    }
+
+    /// Sets the admin share of the swap fee.
+    pub fn with_admin_fee(mut self, admin_fee: U256) -> Self {
+        self.admin_fee = admin_fee;
+        self
+    }
+
+    /// Sets the off-peg fee multiplier of pools with dynamic fees.
+    pub fn with_offpeg_fee_multiplier(mut self, offpeg_fee_multiplier: U256) -> Self {
+        self.offpeg_fee_multiplier = offpeg_fee_multiplier;
+        self
+    }
+
+    /// Uses the invariant of lending pools such as aave, whose `get_D` adds one to every divisor so that an
+    /// empty balance does not divide by zero.
+    pub fn with_lending_invariant(mut self) -> Self {
+        self.lending_invariant = true;
+        self
+    }
+
+    /// Number of coins in the pool.
+    pub fn n_coins(&self) -> usize {
+        self.balances.len()
+    }
+
+    /// Virtual balances: balances scaled to 18 decimals by the rate multipliers, as `_xp()`.
+    pub fn xp(&self) -> Result<Vec<U256>, MathError> {
+        self.balances
+            .iter()
+            .zip(&self.rates)
+            .map(|(balance, rate)| mul_div(*balance, *rate, U256::from(PRECISION)))
+            .collect()
+    }
+
+    /// Current value of the invariant, as `get_D(_xp(), A_precise())`.
+    pub fn get_d(&self) -> Result<U256, MathError> {
+        self.invariant(&self.xp()?)
+    }
+
+    /// Quotes the output of swapping `dx` of coin `i` for coin `j`, as `exchange` would pay out.
+    ///
+    /// Returns Err(MathError) if the indexes are invalid or the pool cannot provide the output.
+    pub fn get_dy(&self, i: usize, j: usize, dx: U256) -> Result<U256, MathError> {
+        Ok(self.exchange_amounts(i, j, dx)?.0)
+    }
+
+    /// Quotes the input of coin `i` required to receive `dy` of coin `j`.
+    ///
+    /// Starts from stableswap-ng's `get_dx` estimate and settles on the smallest input `get_dy` pays `dy` for.
+    pub fn get_dx(&self, i: usize, j: usize, dy: U256) -> Result<U256, MathError> {
+        self.check_indexes(i, j)?;
+        if dy.is_zero() {
+            return Err(MathError::ZeroAmount);
+        }
+
+        let xp = self.xp()?;
+        let d = self.invariant(&xp)?;
+        let fee = dynamic_fee(xp[i], xp[j], self.fee, self.offpeg_fee_multiplier)?;
+
+        let dy_xp = mul_div(dy, self.rates[j], U256::from(PRECISION))? + 1;
+        let dy_with_fee = mul_div(
+            dy_xp,
+            U256::from(FEE_DENOMINATOR),
+            U256::from(FEE_DENOMINATOR) - fee,
+        )?;
+        let y = xp[j]
+            .checked_sub(dy_with_fee)
+            .filter(|y| !y.is_zero())
+            .ok_or(MathError::InsufficientLiquidity)?;
+        let x = get_y(j, i, y, &xp, self.amp, d)?;
+        let estimate = mul_div(x - xp[i], U256::from(PRECISION), self.rates[i])?.max(U256::one());
+
+        let pays = |dx: U256| {
+            self.get_dy(i, j, dx)
+                .map(|amount_out| amount_out >= dy)
+                .unwrap_or(false)
+        };
+
+        // The estimate is off by rounding only, so gallop from it to bracket the smallest paying input.
+        let (mut lower, mut upper) = (estimate, estimate);
+        let mut step = U256::one();
+        if pays(estimate) {
+            while !lower.is_zero() && pays(lower) {
+                lower = lower.saturating_sub(step);
+                step <<= 1;
+            }
+        } else {
+            while !pays(upper) {
+                if step.bits() > 128 {
+                    return Err(MathError::InsufficientLiquidity);
+                }
+                upper = upper.checked_add(step).ok_or(MathError::Overflow)?;
+                step <<= 1;
+            }
+        }
+
+        // Bisect down to the smallest paying input.
+        while upper - lower > U256::one() {
+            let mid = lower + (upper - lower) / 2;
+            if pays(mid) {
+                upper = mid;
+            } else {
+                lower = mid;
+            }
+        }
+        Ok(upper)
+    }
+
+    /// Swaps `dx` of coin `i` for coin `j` and updates the balances, as `exchange` would.
+    ///
+    /// Returns the output amount, or Err(MathError) if the quote fails.
+    pub fn exchange(&mut self, i: usize, j: usize, dx: U256) -> Result<U256, MathError> {
+        let (dy, dy_admin_fee) = self.exchange_amounts(i, j, dx)?;
+
+        self.balances[i] = self.balances[i]
+            .checked_add(dx)
+            .ok_or(MathError::Overflow)?;
+        self.balances[j] = self.balances[j]
+            .checked_sub(dy + dy_admin_fee)
+            .ok_or(MathError::InsufficientLiquidity)?;
+
+        Ok(dy)
+    }
+
+    /// Computes the output of `exchange` and the admin fee it removes from the pool, in raw units of coin `j`.
+    fn exchange_amounts(&self, i: usize, j: usize, dx: U256) -> Result<(U256, U256), MathError> {
+        self.check_indexes(i, j)?;
+        if dx.is_zero() {
+            return Err(MathError::ZeroAmount);
+        }
+
+        let xp = self.xp()?;
+        let d = self.invariant(&xp)?;
+        let x = xp[i]
+            .checked_add(mul_div(dx, self.rates[i], U256::from(PRECISION))?)
+            .ok_or(MathError::Overflow)?;
+        let y = get_y(i, j, x, &xp, self.amp, d)?;
+
+        // Subtract one in case of rounding errors, as the contract does.
+        let dy = xp[j]
+            .checked_sub(y)
+            .and_then(|dy| dy.checked_sub(U256::one()))
+            .ok_or(MathError::InsufficientLiquidity)?;
+        let fee = dynamic_fee(
+            (xp[i] + x) / 2,
+            (xp[j] + y) / 2,
+            self.fee,
+            self.offpeg_fee_multiplier,
+        )?;
+        let dy_fee = mul_div(dy, fee, U256::from(FEE_DENOMINATOR))?;
+
+        let amount_out = mul_div(dy - dy_fee, U256::from(PRECISION), self.rates[j])?;
+        let dy_admin_fee = mul_div(dy_fee, self.admin_fee, U256::from(FEE_DENOMINATOR))?;
+        let dy_admin_fee = mul_div(dy_admin_fee, U256::from(PRECISION), self.rates[j])?;
+
+        Ok((amount_out, dy_admin_fee))
+    }
+
+    /// Invariant of virtual balances with the pool's variant of `get_D`.
+    fn invariant(&self, xp: &[U256]) -> Result<U256, MathError> {
+        if self.lending_invariant {
+            get_d_lending(xp, self.amp)
+        } else {
+            get_d(xp, self.amp)
+        }
+    }
+
+    fn check_indexes(&self, i: usize, j: usize) -> Result<(), MathError> {
+        if i == j || i >= self.n_coins() || j >= self.n_coins() {
+            return Err(MathError::InvalidPoolParameters);
+        }
+        Ok(())
+    }
+
+    /// Resolves a token pair to coin indexes, trading either wrapped or underlying coins.
+    fn indexes(&self, token_in: Address, token_out: Address) -> Result<(usize, usize), MathError> {
+        let position =
+            |coins: &[Address], token: Address| coins.iter().position(|coin| *coin == token);
+
+        let wrapped = (
+            position(&self.coins, token_in),
+            position(&self.coins, token_out),
+        );
+        let underlying = (
+            position(&self.underlying_coins, token_in),
+            position(&self.underlying_coins, token_out),
+        );
+
+        // Swaps between a wrapped and an underlying coin are not offered by the pool.
+        match (wrapped, underlying) {
+            ((Some(i), Some(j)), _) | (_, (Some(i), Some(j))) if i != j => Ok((i, j)),
+            ((None, _), (None, _)) => Err(MathError::UnknownToken(token_in)),
+            _ => Err(MathError::UnknownToken(token_out)),
+        }
+    }
+}
+
+impl Pool for StableSwapPool {
+    fn tokens(&self) -> Vec<Address> {
+        self.coins
+            .iter()
+            .chain(&self.underlying_coins)
+            .copied()
+            .collect()
+    }
+
+    fn fee(&self) -> u32 {
+        (self.fee / 10_000).low_u32()
+    }
+
+    fn quote_exact_in(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+    ) -> Result<U256, MathError> {
+        let (i, j) = self.indexes(token_in, token_out)?;
+        self.get_dy(i, j, amount_in)
+    }
+
+    fn quote_exact_out(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_out: U256,
+    ) -> Result<U256, MathError> {
+        let (i, j) = self.indexes(token_in, token_out)?;
+        self.get_dx(i, j, amount_out)
+    }
+
+    fn apply_swap(
+        &mut self,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+    ) -> Result<U256, MathError> {
+        let (i, j) = self.indexes(token_in, token_out)?;
+        self.exchange(i, j, amount_in)
+    }
+
+    /// Marginal price from the partial derivatives of the invariant, as stableswap-ng's `get_p`.
+    fn spot_price(&self, token_in: Address, token_out: Address) -> Result<U256, MathError> {
+        let (i, j) = self.indexes(token_in, token_out)?;
+        let xp = self.xp()?;
+        let d = self.invariant(&xp)?;
+        let n = U256::from(self.n_coins());
+
+        // dr = D^(n+1) / (n^n * prod(xp)), the same term as D_P in `get_D`.
+        let mut dr = d;
+        for x in &xp {
+            dr = mul_div(dr, d, *x * n)?;
+        }
+        let ann = self.amp * n;
+        let weight_in = mul_div(ann, xp[i], U256::from(A_PRECISION))? + dr;
+        let weight_out = mul_div(ann, xp[j], U256::from(A_PRECISION))? + dr;
+
+        // price = xp_j * (Ann * xp_i + dr) / (xp_i * (Ann * xp_j + dr)), then converted back to raw units.
+        let price = mul_div(U256::from(PRICE_SCALE), weight_in, weight_out)?;
+        let price = mul_div(price, xp[j], xp[i])?;
+        mul_div(price, self.rates[i], self.rates[j])
+    }
+
+    fn clone_box(&self) -> Box<dyn Pool> {
+        Box::new(self.clone())
+    }
+}
+
+/// Computes the invariant D of virtual balances by Newton's method, as `get_D`.
+///
+/// Returns D, or Err(MathError::NoConvergence) if the iteration does not converge within 255 steps.
+pub fn get_d(xp: &[U256], amp: U256) -> Result<U256, MathError> {
+    compute_d(xp, amp, U256::zero())
+}
+
+/// Computes the invariant D like `get_d`, dividing by `x * N_COINS + 1` as the `get_D` of lending pools.
+pub fn get_d_lending(xp: &[U256], amp: U256) -> Result<U256, MathError> {
+    compute_d(xp, amp, U256::one())
+}
+
+fn compute_d(xp: &[U256], amp: U256, offset: U256) -> Result<U256, MathError> {
+    let n = U256::from(xp.len());
+    let a_precision = U256::from(A_PRECISION);
+    let s = xp
+        .iter()
+        .try_fold(U256::zero(), |sum, x| sum.checked_add(*x))
+        .ok_or(MathError::Overflow)?;
+    if s.is_zero() {
+        return Ok(U256::zero());
+    }
+
+    let ann = amp * n;
+    let mut d = s;
+    for _ in 0..MAX_ITERATIONS {
+        let mut d_p = d;
+        for x in xp {
+            let denominator = *x * n + offset;
+            if denominator.is_zero() {
+                return Err(MathError::DivisionByZero);
+            }
+            d_p = d_p.checked_mul(d).ok_or(MathError::Overflow)? / denominator;
+        }
+        let d_prev = d;
+
+        let numerator = (ann * s / a_precision)
+            .checked_add(d_p * n)
+            .and_then(|value| value.checked_mul(d))
+            .ok_or(MathError::Overflow)?;
+        let denominator = ((ann - a_precision)
+            .checked_mul(d)
+            .ok_or(MathError::Overflow)?
+            / a_precision)
+            .checked_add(d_p.checked_mul(n + 1).ok_or(MathError::Overflow)?)
+            .ok_or(MathError::Overflow)?;
+        d = numerator / denominator;
+
+        if d.abs_diff(d_prev) <= U256::one() {
+            return Ok(d);
+        }
+    }
+
+    Err(MathError::NoConvergence)
+}
+
+/// Computes the virtual balance of coin `j` that keeps the invariant `d` after coin `i` moves to `x`, as `get_y`.
+///
+/// Returns the new balance of coin `j`, or Err(MathError) if the indexes are invalid or the iteration diverges.
+pub fn get_y(
+    i: usize,
+    j: usize,
+    x: U256,
+    xp: &[U256],
+    amp: U256,
+    d: U256,
+) -> Result<U256, MathError> {
+    let n = U256::from(xp.len());
+    if i == j || i >= xp.len() || j >= xp.len() {
+        return Err(MathError::InvalidPoolParameters);
+    }
+
+    let ann = amp * n;
+    let mut c = d;
+    let mut s = U256::zero();
+    for (k, balance) in xp.iter().enumerate() {
+        let x_k = if k == i {
+            x
+        } else if k != j {
+            *balance
+        } else {
+            continue;
+        };
+        if x_k.is_zero() {
+            return Err(MathError::DivisionByZero);
+        }
+        s = s.checked_add(x_k).ok_or(MathError::Overflow)?;
+        c = c.checked_mul(d).ok_or(MathError::Overflow)? / (x_k * n);
+    }
+    c = c
+        .checked_mul(d)
+        .and_then(|value| value.checked_mul(U256::from(A_PRECISION)))
+        .ok_or(MathError::Overflow)?
+        / (ann * n);
+    let b = s + d * A_PRECISION / ann;
+
+    let mut y = d;
+    for _ in 0..MAX_ITERATIONS {
+        let y_prev = y;
+        let denominator = (y * U256::from(2) + b)
+            .checked_sub(d)
+            .filter(|value| !value.is_zero())
+            .ok_or(MathError::DivisionByZero)?;
+        y = y
+            .checked_mul(y)
+            .and_then(|value| value.checked_add(c))
+            .ok_or(MathError::Overflow)?
+            / denominator;
+
+        if y.abs_diff(y_prev) <= U256::one() {
+            return Ok(y);
+        }
+    }
+
+    Err(MathError::NoConvergence)
+}
+
+/// Raises the fee as the two balances move apart, as `_dynamic_fee` of pools with an off-peg multiplier.
+fn dynamic_fee(xpi: U256, xpj: U256, fee: U256, multiplier: U256) -> Result<U256, MathError> {
+    let fee_denominator = U256::from(FEE_DENOMINATOR);
+    if multiplier <= fee_denominator {
+        return Ok(fee);
+    }
+
+    let sum = xpi.checked_add(xpj).ok_or(MathError::Overflow)?;
+    let xps2 = sum.checked_mul(sum).ok_or(MathError::Overflow)?;
+    let imbalance = (multiplier - fee_denominator)
+        .checked_mul(U256::from(4))
+        .and_then(|value| value.checked_mul(xpi))
+        .and_then(|value| value.checked_mul(xpj))
+        .ok_or(MathError::Overflow)?
+        / xps2;
+
+    mul_div(multiplier, fee, imbalance + fee_denominator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u(value: &str) -> U256 {
+        U256::from_dec_str(value).unwrap()
+    }
+
+    fn token(byte: u8) -> Address {
+        Address::repeat_byte(byte)
+    }
+
+    /// Three-coin DAI/USDC/USDT pool configured like the aave pool: A = 2000, 0.03% fee, 50% admin fee
+    /// and a 2x off-peg multiplier.
+    fn create_aave_pool(balances: [&str; 3]) -> StableSwapPool {
+        StableSwapPool::new(
+            balances.iter().map(|balance| u(balance)).collect(),
+            vec![
+                rate_for_decimals(18),
+                rate_for_decimals(6),
+                rate_for_decimals(6),
+            ],
+            U256::from(2000 * A_PRECISION),
+            U256::from(3_000_000),
+        )
+        .unwrap()
+        .with_admin_fee(U256::from(5_000_000_000_u64))
+        .with_offpeg_fee_multiplier(U256::from(20_000_000_000_u64))
+        .with_coins(vec![token(1), token(2), token(3)])
+        .with_underlying_coins(vec![token(11), token(12), token(13)])
+        .with_lending_invariant()
+    }
+
+    // Expected values were computed on synthetic balances with a line-by-line Python port of the Vyper `get_D`,
+    // `get_y`, `_dynamic_fee` and `exchange`; they are not snapshots of the pool on chain.
+    #[test]
+    fn test_get_d_fixtures() {
+        let balanced = create_aave_pool([
+            "1000000000000000000000000",
+            "1000000000000",
+            "1000000000000",
+        ]);
+        assert_eq!(balanced.get_d().unwrap(), u("3000000000000000000000000"));
+
+        let pool = create_aave_pool([
+            "4213520683124581340561231",
+            "5104876324512",
+            "3921034212387",
+        ]);
+        assert_eq!(pool.get_d().unwrap(), u("13239389114570156559064753"));
+    }
+
+    #[test]
+    fn test_lending_invariant_allows_an_empty_balance() {
+        let pool = create_aave_pool(["1000000000000000000000", "0", "2000000000"]);
+        assert_eq!(pool.get_d().unwrap(), u("134162578891605349"));
+
+        let plain = StableSwapPool {
+            lending_invariant: false,
+            ..pool
+        };
+        assert!(matches!(plain.get_d(), Err(MathError::DivisionByZero)));
+    }
+
+    #[test]
+    fn test_exchange_fixtures() {
+        let vectors = [
+            (0, 1, "100000000000000000000000", "99978128013"),
+            (1, 2, "250000000000", "249883075323"),
+            (2, 0, "1000000000", "999739446840186665424"),
+        ];
+
+        for (i, j, dx, dy) in vectors {
+            let mut pool = create_aave_pool([
+                "4213520683124581340561231",
+                "5104876324512",
+                "3921034212387",
+            ]);
+            assert_eq!(pool.get_dy(i, j, u(dx)).unwrap(), u(dy));
+
+            let balance_j = pool.balances[j];
+            assert_eq!(pool.exchange(i, j, u(dx)).unwrap(), u(dy));
+            // The admin share of the fee leaves the pool on top of the output.
+            assert!(pool.balances[j] < balance_j - u(dy));
+        }
+    }
+
+    #[test]
+    fn test_get_dx_is_smallest_paying_input() {
+        let pool = create_aave_pool([
+            "4213520683124581340561231",
+            "5104876324512",
+            "3921034212387",
+        ]);
+        let dy = u("5000000000");
+
+        let dx = pool.get_dx(0, 2, dy).unwrap();
+
+        assert!(pool.get_dy(0, 2, dx).unwrap() >= dy);
+        assert!(pool.get_dy(0, 2, dx - 1).unwrap() < dy);
+    }
+
+    #[test]
+    fn test_underlying_coins_use_wrapped_indexes() {
+        let pool = create_aave_pool([
+            "4213520683124581340561231",
+            "5104876324512",
+            "3921034212387",
+        ]);
+        let amount_in = u("1000000000");
+
+        assert_eq!(
+            pool.quote_exact_in(token(13), token(11), amount_in)
+                .unwrap(),
+            pool.get_dy(2, 0, amount_in).unwrap()
+        );
+        assert!(matches!(
+            pool.quote_exact_in(token(13), token(1), amount_in),
+            Err(MathError::UnknownToken(address)) if address == token(1)
+        ));
+        assert_eq!(pool.tokens().len(), 6);
+    }
+
+    #[test]
+    fn test_spot_price() {
+        let balanced = create_aave_pool([
+            "1000000000000000000000000",
+            "1000000000000",
+            "1000000000000",
+        ]);
+        // One DAI buys one USDC at peg, in USDC's 6 decimals.
+        assert_eq!(
+            balanced.spot_price(token(1), token(2)).unwrap(),
+            U256::from(PRICE_SCALE) / U256::exp10(12)
+        );
+
+        let pool = create_aave_pool([
+            "4213520683124581340561231",
+            "5104876324512",
+            "3921034212387",
+        ]);
+        assert_eq!(
+            pool.spot_price(token(2), token(3)).unwrap(),
+            u("999867081717343016")
+        );
+    }
+
+    #[test]
+    fn test_invalid_parameters() {
+        assert!(matches!(
+            StableSwapPool::new(
+                vec![U256::one()],
+                vec![rate_for_decimals(18)],
+                U256::one(),
+                U256::zero()
+            ),
+            Err(MathError::InvalidPoolParameters)
+        ));
+
+        let pool = create_aave_pool(["1000", "1000", "1000"]);
+        assert!(matches!(
+            pool.get_dy(0, 0, U256::one()),
+            Err(MathError::InvalidPoolParameters)
+        ));
+        assert!(matches!(
+            pool.get_dy(0, 1, U256::zero()),
+            Err(MathError::ZeroAmount)
+        ));
+    }
+}
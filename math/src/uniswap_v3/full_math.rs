@@ -0,0 +1,38 @@
+// Port of Uniswap's FullMath and UnsafeMath libraries.
+// Products are widened to 512 bits so intermediate results never overflow.
+
+use ethers::types::{U256, U512};
+
+use crate::error::MathError;
+
+/// Calculates floor(a * b / denominator) with full precision.
+pub fn mul_div(a: U256, b: U256, denominator: U256) -> Result<U256, MathError> {
+    if denominator.is_zero() {
+        return Err(MathError::DivisionByZero);
+    }
+
+    let result = a.full_mul(b) / U512::from(denominator);
+    U256::try_from(result).map_err(|_| MathError::Overflow)
+}
+
+/// Calculates ceil(a * b / denominator) with full precision.
+pub fn mul_div_rounding_up(a: U256, b: U256, denominator: U256) -> Result<U256, MathError> {
+    let result = mul_div(a, b, denominator)?;
+
+    if (a.full_mul(b) % U512::from(denominator)).is_zero() {
+        Ok(result)
+    } else {
+        result.checked_add(U256::one()).ok_or(MathError::Overflow)
+    }
+}
+
+/// Calculates ceil(x / y). Panics if `y` is zero, matching the callers' preconditions.
+pub fn div_rounding_up(x: U256, y: U256) -> U256 {
+    let quotient = x / y;
+
+    if (x % y).is_zero() {
+        quotient
+    } else {
+        quotient + 1
+    }
+}
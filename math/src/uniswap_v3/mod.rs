@@ -0,0 +1,644 @@
+// This module contains an exact Uniswap V3 swap engine.
+// It mirrors the on-chain pool's swap loop, including tick crossing, so quotes match `amountOut` to the wei.
+
+pub mod full_math;
+pub mod sqrt_price_math;
+pub mod swap_math;
+pub mod tick_bitmap;
+pub mod tick_math;
+
+use ethers::types::{Address, I256, U256};
+use std::collections::HashMap;
+
+use crate::error::MathError;
+use crate::pool::{zero_for_one, Pool, PRICE_SCALE};
+use full_math::mul_div;
+use swap_math::compute_swap_step;
+use tick_bitmap::TickBitmap;
+use tick_math::{
+    get_sqrt_ratio_at_tick, get_tick_at_sqrt_ratio, MAX_SQRT_RATIO, MAX_TICK, MIN_SQRT_RATIO,
+    MIN_TICK,
+};
+
+/// Returns the tick spacing Uniswap V3 enables for a fee tier, if it is a standard one.
+pub fn tick_spacing_for_fee(fee: u32) -> Option<i32> {
+    match fee {
+        100 => Some(1),
+        500 => Some(10),
+        3000 => Some(60),
+        10000 => Some(200),
+        _ => None,
+    }
+}
+
+/// Liquidity state stored for an initialized tick.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TickInfo {
+    /// Total position liquidity that references this tick.
+    pub liquidity_gross: u128,
+    /// Liquidity added (subtracted) when the tick is crossed from left to right (right to left).
+    pub liquidity_net: i128,
+}
+
+/// Outcome of a swap against a Uniswap V3 pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwapResult {
+    /// Delta of the pool's token0 balance: positive if the pool received token0.
+    pub amount0: I256,
+    /// Delta of the pool's token1 balance: positive if the pool received token1.
+    pub amount1: I256,
+    /// Square root of the price after the swap.
+    pub sqrt_price_x96: U256,
+    /// Active liquidity after the swap.
+    pub liquidity: U256,
+    /// Current tick after the swap.
+    pub tick: i32,
+}
+
+/// Structure representing a Uniswap V3 pool.
+#[derive(Debug, Clone, Default)]
+pub struct UniswapV3Pool {
+    /// Address of token0.
+    pub token0: Address,
+    /// Address of token1.
+    pub token1: Address,
+    /// Square root of the price in x96 format.
+    pub sqrt_price_x96: U256,
+    /// Liquidity of the pool.
+    pub liquidity: U256,
+    /// Fee tier of the pool.
+    pub fee: u32,
+    /// Current tick of the pool.
+    pub tick: i32,
+    /// Spacing between usable ticks.
+    pub tick_spacing: i32,
+    /// Initialized ticks and their liquidity.
+    pub ticks: HashMap<i32, TickInfo>,
+    /// Bitmap of initialized ticks, used to find the next tick to cross.
+    pub tick_bitmap: TickBitmap,
+}
+
+impl UniswapV3Pool {
+    /// Creates an empty pool at the given price for a standard fee tier.
+    ///
+    /// Returns the pool, or Err(MathError) if the price is out of range or the fee tier is unknown.
+    pub fn new(sqrt_price_x96: U256, fee: u32) -> Result<Self, MathError> {
+        let tick_spacing = tick_spacing_for_fee(fee).ok_or(MathError::InvalidTickSpacing(0))?;
+
+        Ok(Self {
+            sqrt_price_x96,
+            fee,
+            tick: get_tick_at_sqrt_ratio(sqrt_price_x96)?,
+            tick_spacing,
+            ..Default::default()
+        })
+    }
+
+    /// Sets the pool's token addresses, needed to use the pool through the `Pool` trait.
+    pub fn with_tokens(mut self, token0: Address, token1: Address) -> Self {
+        self.token0 = token0;
+        self.token1 = token1;
+        self
+    }
+
+    /// Calculates the virtual reserves of the active range based on liquidity and price.
+    ///
+    /// Returns a tuple of (reserve0, reserve1) as U256.
+    pub fn get_reserves(&self) -> (U256, U256) {
+        let q96 = U256::one() << 96;
+
+        // reserve0 = L / sqrt(P) and reserve1 = L * sqrt(P), both in Q96.
+        let reserve0 = mul_div(self.liquidity, q96, self.sqrt_price_x96).unwrap_or_default();
+        let reserve1 = mul_div(self.liquidity, self.sqrt_price_x96, q96).unwrap_or_default();
+
+        (reserve0, reserve1)
+    }
+
+    /// Adds or removes liquidity between two ticks, as `mint` and `burn` do on-chain.
+    ///
+    /// Returns Ok(()) if the position was updated, or Err(MathError) if the ticks or liquidity are invalid.
+    pub fn modify_liquidity(
+        &mut self,
+        tick_lower: i32,
+        tick_upper: i32,
+        liquidity_delta: i128,
+    ) -> Result<(), MathError> {
+        if tick_lower >= tick_upper || tick_lower < MIN_TICK {
+            return Err(MathError::InvalidTick(tick_lower));
+        }
+        if tick_upper > MAX_TICK {
+            return Err(MathError::InvalidTick(tick_upper));
+        }
+
+        self.update_tick(tick_lower, liquidity_delta, false)?;
+        self.update_tick(tick_upper, liquidity_delta, true)?;
+
+        if self.tick >= tick_lower && self.tick < tick_upper {
+            let liquidity = add_delta(self.active_liquidity()?, liquidity_delta)?;
+            self.liquidity = U256::from(liquidity);
+        }
+
+        Ok(())
+    }
+
+    fn update_tick(
+        &mut self,
+        tick: i32,
+        liquidity_delta: i128,
+        upper: bool,
+    ) -> Result<(), MathError> {
+        let info = self.ticks.get(&tick).copied().unwrap_or_default();
+        let liquidity_gross = add_delta(info.liquidity_gross, liquidity_delta)?;
+
+        // When the lower (upper) tick is crossed left to right (right to left), liquidity is added.
+        let liquidity_net = if upper {
+            info.liquidity_net.checked_sub(liquidity_delta)
+        } else {
+            info.liquidity_net.checked_add(liquidity_delta)
+        }
+        .ok_or(MathError::Overflow)?;
+
+        if (liquidity_gross == 0) != (info.liquidity_gross == 0) {
+            self.tick_bitmap.flip_tick(tick, self.tick_spacing)?;
+        }
+
+        if liquidity_gross == 0 {
+            self.ticks.remove(&tick);
+        } else {
+            self.ticks.insert(
+                tick,
+                TickInfo {
+                    liquidity_gross,
+                    liquidity_net,
+                },
+            );
+        }
+
+        Ok(())
+    }
+
+    fn active_liquidity(&self) -> Result<u128, MathError> {
+        if self.liquidity > U256::from(u128::MAX) {
+            return Err(MathError::Overflow);
+        }
+        Ok(self.liquidity.as_u128())
+    }
+
+    /// Simulates a swap without modifying the pool.
+    ///
+    /// A positive `amount_specified` is an exact input, a negative one an exact output. Without a
+    /// `sqrt_price_limit_x96` the swap may move the price all the way to the end of the tick range.
+    pub fn simulate_swap(
+        &self,
+        zero_for_one: bool,
+        amount_specified: I256,
+        sqrt_price_limit_x96: Option<U256>,
+    ) -> Result<SwapResult, MathError> {
+        if amount_specified.is_zero() {
+            return Err(MathError::ZeroAmount);
+        }
+        if self.tick_spacing <= 0 {
+            return Err(MathError::InvalidTickSpacing(self.tick_spacing));
+        }
+
+        let sqrt_price_limit_x96 = sqrt_price_limit_x96.unwrap_or(if zero_for_one {
+            MIN_SQRT_RATIO + 1
+        } else {
+            MAX_SQRT_RATIO - 1
+        });
+
+        let limit_valid = if zero_for_one {
+            sqrt_price_limit_x96 < self.sqrt_price_x96 && sqrt_price_limit_x96 > MIN_SQRT_RATIO
+        } else {
+            sqrt_price_limit_x96 > self.sqrt_price_x96 && sqrt_price_limit_x96 < MAX_SQRT_RATIO
+        };
+        if !limit_valid {
+            return Err(MathError::InvalidPriceLimit);
+        }
+
+        let exact_input = !amount_specified.is_negative();
+        let mut amount_specified_remaining = amount_specified;
+        let mut amount_calculated = I256::zero();
+        let mut sqrt_price_x96 = self.sqrt_price_x96;
+        let mut tick = self.tick;
+        let mut liquidity = self.active_liquidity()?;
+
+        // Continue swapping as long as we haven't used the entire input/output and haven't reached the price limit.
+        while !amount_specified_remaining.is_zero() && sqrt_price_x96 != sqrt_price_limit_x96 {
+            let sqrt_price_start_x96 = sqrt_price_x96;
+
+            let (tick_next, initialized) = self.tick_bitmap.next_initialized_tick_within_one_word(
+                tick,
+                self.tick_spacing,
+                zero_for_one,
+            )?;
+            // The bitmap is not aware of the tick bounds, so clamp the next tick to them.
+            let tick_next = tick_next.clamp(MIN_TICK, MAX_TICK);
+            let sqrt_price_next_x96 = get_sqrt_ratio_at_tick(tick_next)?;
+
+            let sqrt_price_target_x96 = if (zero_for_one
+                && sqrt_price_next_x96 < sqrt_price_limit_x96)
+                || (!zero_for_one && sqrt_price_next_x96 > sqrt_price_limit_x96)
+            {
+                sqrt_price_limit_x96
+            } else {
+                sqrt_price_next_x96
+            };
+
+            let step = compute_swap_step(
+                sqrt_price_x96,
+                sqrt_price_target_x96,
+                liquidity,
+                amount_specified_remaining,
+                self.fee,
+            )?;
+            sqrt_price_x96 = step.sqrt_price_next_x96;
+
+            let amount_in_with_fee = to_signed(
+                step.amount_in
+                    .checked_add(step.fee_amount)
+                    .ok_or(MathError::Overflow)?,
+            )?;
+            let amount_out = to_signed(step.amount_out)?;
+
+            if exact_input {
+                amount_specified_remaining = amount_specified_remaining
+                    .checked_sub(amount_in_with_fee)
+                    .ok_or(MathError::Overflow)?;
+                amount_calculated = amount_calculated
+                    .checked_sub(amount_out)
+                    .ok_or(MathError::Overflow)?;
+            } else {
+                amount_specified_remaining = amount_specified_remaining
+                    .checked_add(amount_out)
+                    .ok_or(MathError::Overflow)?;
+                amount_calculated = amount_calculated
+                    .checked_add(amount_in_with_fee)
+                    .ok_or(MathError::Overflow)?;
+            }
+
+            if sqrt_price_x96 == sqrt_price_next_x96 {
+                // Crossed into the next range, so apply the tick's net liquidity.
+                if initialized {
+                    let liquidity_net = self
+                        .ticks
+                        .get(&tick_next)
+                        .map(|info| info.liquidity_net)
+                        .unwrap_or_default();
+                    let liquidity_net = if zero_for_one {
+                        liquidity_net.checked_neg().ok_or(MathError::Overflow)?
+                    } else {
+                        liquidity_net
+                    };
+                    liquidity = add_delta(liquidity, liquidity_net)?;
+                }
+
+                tick = if zero_for_one {
+                    tick_next - 1
+                } else {
+                    tick_next
+                };
+            } else if sqrt_price_x96 != sqrt_price_start_x96 {
+                // Recompute unless we're on a lower tick boundary and haven't moved.
+                tick = get_tick_at_sqrt_ratio(sqrt_price_x96)?;
+            }
+        }
+
+        let amount_filled = amount_specified
+            .checked_sub(amount_specified_remaining)
+            .ok_or(MathError::Overflow)?;
+        let (amount0, amount1) = if zero_for_one == exact_input {
+            (amount_filled, amount_calculated)
+        } else {
+            (amount_calculated, amount_filled)
+        };
+
+        Ok(SwapResult {
+            amount0,
+            amount1,
+            sqrt_price_x96,
+            liquidity: U256::from(liquidity),
+            tick,
+        })
+    }
+
+    /// Executes a swap and updates the pool state to the post-swap price, tick and liquidity.
+    pub fn swap(
+        &mut self,
+        zero_for_one: bool,
+        amount_specified: I256,
+        sqrt_price_limit_x96: Option<U256>,
+    ) -> Result<SwapResult, MathError> {
+        let result = self.simulate_swap(zero_for_one, amount_specified, sqrt_price_limit_x96)?;
+        self.commit(&result);
+
+        Ok(result)
+    }
+
+    fn commit(&mut self, result: &SwapResult) {
+        self.sqrt_price_x96 = result.sqrt_price_x96;
+        self.liquidity = result.liquidity;
+        self.tick = result.tick;
+    }
+
+    /// Quotes the output of an exact-input swap, as `exactInputSingle` would pay out.
+    ///
+    /// Returns Err(MathError::InsufficientLiquidity) if the pool cannot absorb the whole input.
+    pub fn get_amount_out(&self, amount_in: U256, zero_for_one: bool) -> Result<U256, MathError> {
+        let result = self.simulate_swap(zero_for_one, to_signed(amount_in)?, None)?;
+        let (amount_paid, amount_received) = ordered(&result, zero_for_one);
+
+        if amount_paid.into_raw() != amount_in {
+            return Err(MathError::InsufficientLiquidity);
+        }
+        Ok(amount_received.unsigned_abs())
+    }
+
+    /// Quotes the input required by an exact-output swap, as `exactOutputSingle` would charge.
+    ///
+    /// Returns Err(MathError::InsufficientLiquidity) if the pool cannot provide the whole output.
+    pub fn get_amount_in(&self, amount_out: U256, zero_for_one: bool) -> Result<U256, MathError> {
+        let amount_specified = to_signed(amount_out)?
+            .checked_neg()
+            .ok_or(MathError::Overflow)?;
+        let result = self.simulate_swap(zero_for_one, amount_specified, None)?;
+        let (amount_paid, amount_received) = ordered(&result, zero_for_one);
+
+        if amount_received.unsigned_abs() != amount_out {
+            return Err(MathError::InsufficientLiquidity);
+        }
+        Ok(amount_paid.into_raw())
+    }
+}
+
+impl Pool for UniswapV3Pool {
+    fn tokens(&self) -> Vec<Address> {
+        vec![self.token0, self.token1]
+    }
+
+    fn fee(&self) -> u32 {
+        self.fee
+    }
+
+    fn quote_exact_in(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+    ) -> Result<U256, MathError> {
+        let zero_for_one = zero_for_one(self.token0, self.token1, token_in, token_out)?;
+        self.get_amount_out(amount_in, zero_for_one)
+    }
+
+    fn quote_exact_out(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_out: U256,
+    ) -> Result<U256, MathError> {
+        let zero_for_one = zero_for_one(self.token0, self.token1, token_in, token_out)?;
+        self.get_amount_in(amount_out, zero_for_one)
+    }
+
+    fn apply_swap(
+        &mut self,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+    ) -> Result<U256, MathError> {
+        let zero_for_one = zero_for_one(self.token0, self.token1, token_in, token_out)?;
+        let result = self.simulate_swap(zero_for_one, to_signed(amount_in)?, None)?;
+        let (amount_paid, amount_received) = ordered(&result, zero_for_one);
+
+        // Only commit swaps the router would not revert on.
+        if amount_paid.into_raw() != amount_in {
+            return Err(MathError::InsufficientLiquidity);
+        }
+        self.commit(&result);
+
+        Ok(amount_received.unsigned_abs())
+    }
+
+    fn spot_price(&self, token_in: Address, token_out: Address) -> Result<U256, MathError> {
+        let zero_for_one = zero_for_one(self.token0, self.token1, token_in, token_out)?;
+        let q96 = U256::one() << 96;
+        let scale = U256::from(PRICE_SCALE);
+
+        // price = sqrtP^2 / 2^192, in token1 per token0.
+        if zero_for_one {
+            let price_x96 = mul_div(self.sqrt_price_x96, self.sqrt_price_x96, q96)?;
+            mul_div(price_x96, scale, q96)
+        } else {
+            let inverse_x96 = mul_div(q96, q96, self.sqrt_price_x96)?;
+            mul_div(inverse_x96, scale, self.sqrt_price_x96)
+        }
+    }
+
+    fn virtual_reserves(&self, token_in: Address, token_out: Address) -> Option<(U256, U256)> {
+        let zero_for_one = zero_for_one(self.token0, self.token1, token_in, token_out).ok()?;
+        if self.liquidity.is_zero() {
+            return None;
+        }
+
+        let (reserve0, reserve1) = self.get_reserves();
+        if zero_for_one {
+            Some((reserve0, reserve1))
+        } else {
+            Some((reserve1, reserve0))
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn Pool> {
+        Box::new(self.clone())
+    }
+}
+
+/// Orders a swap's pool deltas as (amount paid in by the trader, amount paid out by the pool).
+fn ordered(result: &SwapResult, zero_for_one: bool) -> (I256, I256) {
+    if zero_for_one {
+        (result.amount0, result.amount1)
+    } else {
+        (result.amount1, result.amount0)
+    }
+}
+
+fn to_signed(value: U256) -> Result<I256, MathError> {
+    I256::try_from(value).map_err(|_| MathError::Overflow)
+}
+
+/// Adds a signed liquidity delta to a liquidity value, as `LiquidityMath.addDelta` does.
+fn add_delta(liquidity: u128, delta: i128) -> Result<u128, MathError> {
+    if delta < 0 {
+        liquidity
+            .checked_sub(delta.unsigned_abs())
+            .ok_or(MathError::InsufficientLiquidity)
+    } else {
+        liquidity
+            .checked_add(delta as u128)
+            .ok_or(MathError::Overflow)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u(value: &str) -> U256 {
+        U256::from_dec_str(value).unwrap()
+    }
+
+    fn i(value: &str) -> I256 {
+        I256::from_dec_str(value).unwrap()
+    }
+
+    /// 0.3% pool at 1:1 with a full-range position and three concentrated positions around the price.
+    fn create_test_pool() -> UniswapV3Pool {
+        let mut pool = UniswapV3Pool::new(U256::one() << 96, 3000).unwrap();
+        pool.modify_liquidity(-887220, 887220, 2_000_000_000_000_000_000)
+            .unwrap();
+        pool.modify_liquidity(-1200, 1200, 5_000_000_000_000_000_000)
+            .unwrap();
+        pool.modify_liquidity(-120, 60, 3_000_000_000_000_000_000)
+            .unwrap();
+        pool.modify_liquidity(600, 3000, 4_000_000_000_000_000_000)
+            .unwrap();
+        pool
+    }
+
+    #[test]
+    fn test_modify_liquidity_updates_active_range() {
+        let pool = create_test_pool();
+
+        assert_eq!(pool.tick, 0);
+        assert_eq!(pool.liquidity, u("10000000000000000000"));
+        assert_eq!(pool.ticks[&-120].liquidity_net, 3_000_000_000_000_000_000);
+        assert_eq!(pool.ticks[&60].liquidity_net, -3_000_000_000_000_000_000);
+        assert!(pool.tick_bitmap.is_initialized(600, 60));
+        assert_eq!(
+            pool.tick_bitmap
+                .next_initialized_tick_within_one_word(0, 60, false)
+                .unwrap(),
+            (60, true)
+        );
+    }
+
+    #[test]
+    fn test_exact_input_zero_for_one_crosses_ticks() {
+        let result = create_test_pool()
+            .simulate_swap(true, i("1000000000000000000"), None)
+            .unwrap();
+
+        assert_eq!(result.amount0, i("1000000000000000000"));
+        assert_eq!(result.amount1, i("-810857622522306809"));
+        assert_eq!(result.sqrt_price_x96, u("59351828827428206362893803850"));
+        assert_eq!(result.liquidity, u("2000000000000000000"));
+        assert_eq!(result.tick, -5778);
+    }
+
+    #[test]
+    fn test_exact_input_one_for_zero_crosses_ticks() {
+        let result = create_test_pool()
+            .simulate_swap(false, i("1000000000000000000"), None)
+            .unwrap();
+
+        assert_eq!(result.amount0, i("-888311642823452374"));
+        assert_eq!(result.amount1, i("1000000000000000000"));
+        assert_eq!(result.sqrt_price_x96, u("89800264005692540777128663603"));
+        assert_eq!(result.liquidity, u("6000000000000000000"));
+        assert_eq!(result.tick, 2505);
+    }
+
+    #[test]
+    fn test_exact_output_both_directions() {
+        let pool = create_test_pool();
+
+        let result = pool
+            .simulate_swap(true, i("-500000000000000000"), None)
+            .unwrap();
+        assert_eq!(result.amount0, i("539873504720953146"));
+        assert_eq!(result.amount1, i("-500000000000000000"));
+        assert_eq!(result.tick, -2007);
+
+        let result = pool
+            .simulate_swap(false, i("-500000000000000000"), None)
+            .unwrap();
+        assert_eq!(result.amount0, i("-500000000000000000"));
+        assert_eq!(result.amount1, i("533748124312533786"));
+        assert_eq!(result.liquidity, u("11000000000000000000"));
+        assert_eq!(result.tick, 1139);
+    }
+
+    #[test]
+    fn test_large_swaps_cross_bitmap_words() {
+        let pool = create_test_pool();
+
+        let result = pool
+            .simulate_swap(true, i("30000000000000000000"), None)
+            .unwrap();
+        assert_eq!(result.amount1, i("-2182457124355301536"));
+        assert_eq!(result.sqrt_price_x96, u("5017174709573950080555561015"));
+        assert_eq!(result.tick, -55193);
+
+        let result = pool
+            .simulate_swap(false, i("30000000000000000000"), None)
+            .unwrap();
+        assert_eq!(result.amount0, i("-2610322742297149258"));
+        assert_eq!(result.sqrt_price_x96, u("1230664119662175474595853497511"));
+        assert_eq!(result.tick, 54862);
+    }
+
+    #[test]
+    fn test_small_swap_stays_in_range() {
+        let result = create_test_pool()
+            .simulate_swap(true, i("1000"), None)
+            .unwrap();
+
+        assert_eq!(result.amount1, i("-996"));
+        assert_eq!(result.sqrt_price_x96, u("79228162514264329694496147664"));
+        assert_eq!(result.tick, -1);
+    }
+
+    #[test]
+    fn test_swap_applies_state() {
+        let mut pool = create_test_pool();
+        pool.swap(true, i("1000000000000000000"), None).unwrap();
+        let result = pool.swap(false, i("810857622522306809"), None).unwrap();
+
+        assert_eq!(result.amount0, i("-994566835247377113"));
+        assert_eq!(pool.sqrt_price_x96, u("79208889686416399347223343778"));
+        assert_eq!(pool.liquidity, u("10000000000000000000"));
+        assert_eq!(pool.tick, -5);
+    }
+
+    #[test]
+    fn test_quotes_match_swap_amounts() {
+        let pool = create_test_pool();
+
+        assert_eq!(
+            pool.get_amount_out(u("1000000000000000000"), true).unwrap(),
+            u("810857622522306809")
+        );
+        assert_eq!(
+            pool.get_amount_in(u("500000000000000000"), false).unwrap(),
+            u("533748124312533786")
+        );
+    }
+
+    #[test]
+    fn test_price_limit_and_liquidity_errors() {
+        let pool = create_test_pool();
+
+        assert!(matches!(
+            pool.simulate_swap(true, i("1000"), Some(pool.sqrt_price_x96 + 1)),
+            Err(MathError::InvalidPriceLimit)
+        ));
+        assert!(matches!(
+            pool.simulate_swap(true, I256::zero(), None),
+            Err(MathError::ZeroAmount)
+        ));
+        assert!(matches!(
+            pool.get_amount_in(u("1000000000000000000000"), true),
+            Err(MathError::InsufficientLiquidity)
+        ));
+    }
+}
@@ -0,0 +1,191 @@
+// Port of Uniswap's SqrtPriceMath library.
+// Computes next sqrt prices and token deltas between prices, rounding the same way the pool does.
+
+use ethers::types::U256;
+
+use super::full_math::{div_rounding_up, mul_div, mul_div_rounding_up};
+use crate::error::MathError;
+
+/// Resolution of the Q64.96 fixed-point format.
+pub const RESOLUTION: usize = 96;
+
+fn q96() -> U256 {
+    U256::one() << RESOLUTION
+}
+
+fn max_uint160() -> U256 {
+    (U256::one() << 160) - 1
+}
+
+fn to_uint160(value: U256) -> Result<U256, MathError> {
+    if value > max_uint160() {
+        Err(MathError::Overflow)
+    } else {
+        Ok(value)
+    }
+}
+
+/// Gets the next sqrt price given a delta of token0, always rounding up.
+pub fn get_next_sqrt_price_from_amount0_rounding_up(
+    sqrt_price_x96: U256,
+    liquidity: u128,
+    amount: U256,
+    add: bool,
+) -> Result<U256, MathError> {
+    if amount.is_zero() {
+        return Ok(sqrt_price_x96);
+    }
+
+    let numerator1 = U256::from(liquidity) << RESOLUTION;
+    let (product, overflowed) = amount.overflowing_mul(sqrt_price_x96);
+
+    if add {
+        if !overflowed {
+            let (denominator, overflowed) = numerator1.overflowing_add(product);
+            if !overflowed {
+                return mul_div_rounding_up(numerator1, sqrt_price_x96, denominator);
+            }
+        }
+
+        let denominator = (numerator1 / sqrt_price_x96)
+            .checked_add(amount)
+            .ok_or(MathError::Overflow)?;
+        Ok(div_rounding_up(numerator1, denominator))
+    } else {
+        if overflowed || numerator1 <= product {
+            return Err(MathError::InsufficientLiquidity);
+        }
+
+        to_uint160(mul_div_rounding_up(
+            numerator1,
+            sqrt_price_x96,
+            numerator1 - product,
+        )?)
+    }
+}
+
+/// Gets the next sqrt price given a delta of token1, always rounding down.
+pub fn get_next_sqrt_price_from_amount1_rounding_down(
+    sqrt_price_x96: U256,
+    liquidity: u128,
+    amount: U256,
+    add: bool,
+) -> Result<U256, MathError> {
+    let liquidity = U256::from(liquidity);
+
+    if add {
+        let quotient = if amount <= max_uint160() {
+            (amount << RESOLUTION) / liquidity
+        } else {
+            mul_div(amount, q96(), liquidity)?
+        };
+
+        to_uint160(
+            sqrt_price_x96
+                .checked_add(quotient)
+                .ok_or(MathError::Overflow)?,
+        )
+    } else {
+        let quotient = if amount <= max_uint160() {
+            div_rounding_up(amount << RESOLUTION, liquidity)
+        } else {
+            mul_div_rounding_up(amount, q96(), liquidity)?
+        };
+
+        if sqrt_price_x96 <= quotient {
+            return Err(MathError::InsufficientLiquidity);
+        }
+
+        Ok(sqrt_price_x96 - quotient)
+    }
+}
+
+/// Gets the next sqrt price after swapping `amount_in` of the input token.
+pub fn get_next_sqrt_price_from_input(
+    sqrt_price_x96: U256,
+    liquidity: u128,
+    amount_in: U256,
+    zero_for_one: bool,
+) -> Result<U256, MathError> {
+    if sqrt_price_x96.is_zero() {
+        return Err(MathError::InvalidSqrtPrice);
+    }
+    if liquidity == 0 {
+        return Err(MathError::InsufficientLiquidity);
+    }
+
+    if zero_for_one {
+        get_next_sqrt_price_from_amount0_rounding_up(sqrt_price_x96, liquidity, amount_in, true)
+    } else {
+        get_next_sqrt_price_from_amount1_rounding_down(sqrt_price_x96, liquidity, amount_in, true)
+    }
+}
+
+/// Gets the next sqrt price after receiving `amount_out` of the output token.
+pub fn get_next_sqrt_price_from_output(
+    sqrt_price_x96: U256,
+    liquidity: u128,
+    amount_out: U256,
+    zero_for_one: bool,
+) -> Result<U256, MathError> {
+    if sqrt_price_x96.is_zero() {
+        return Err(MathError::InvalidSqrtPrice);
+    }
+    if liquidity == 0 {
+        return Err(MathError::InsufficientLiquidity);
+    }
+
+    if zero_for_one {
+        get_next_sqrt_price_from_amount1_rounding_down(sqrt_price_x96, liquidity, amount_out, false)
+    } else {
+        get_next_sqrt_price_from_amount0_rounding_up(sqrt_price_x96, liquidity, amount_out, false)
+    }
+}
+
+/// Gets the amount of token0 between two prices: liquidity / sqrt(lower) - liquidity / sqrt(upper).
+pub fn get_amount0_delta(
+    mut sqrt_ratio_a_x96: U256,
+    mut sqrt_ratio_b_x96: U256,
+    liquidity: u128,
+    round_up: bool,
+) -> Result<U256, MathError> {
+    if sqrt_ratio_a_x96 > sqrt_ratio_b_x96 {
+        std::mem::swap(&mut sqrt_ratio_a_x96, &mut sqrt_ratio_b_x96);
+    }
+    if sqrt_ratio_a_x96.is_zero() {
+        return Err(MathError::InvalidSqrtPrice);
+    }
+
+    let numerator1 = U256::from(liquidity) << RESOLUTION;
+    let numerator2 = sqrt_ratio_b_x96 - sqrt_ratio_a_x96;
+
+    if round_up {
+        Ok(div_rounding_up(
+            mul_div_rounding_up(numerator1, numerator2, sqrt_ratio_b_x96)?,
+            sqrt_ratio_a_x96,
+        ))
+    } else {
+        Ok(mul_div(numerator1, numerator2, sqrt_ratio_b_x96)? / sqrt_ratio_a_x96)
+    }
+}
+
+/// Gets the amount of token1 between two prices: liquidity * (sqrt(upper) - sqrt(lower)).
+pub fn get_amount1_delta(
+    mut sqrt_ratio_a_x96: U256,
+    mut sqrt_ratio_b_x96: U256,
+    liquidity: u128,
+    round_up: bool,
+) -> Result<U256, MathError> {
+    if sqrt_ratio_a_x96 > sqrt_ratio_b_x96 {
+        std::mem::swap(&mut sqrt_ratio_a_x96, &mut sqrt_ratio_b_x96);
+    }
+
+    let liquidity = U256::from(liquidity);
+    let difference = sqrt_ratio_b_x96 - sqrt_ratio_a_x96;
+
+    if round_up {
+        mul_div_rounding_up(liquidity, difference, q96())
+    } else {
+        mul_div(liquidity, difference, q96())
+    }
+}
@@ -0,0 +1,290 @@
+// Port of Uniswap's SwapMath library.
+// Computes the result of swapping within a single tick range.
+
+use ethers::types::{I256, U256};
+
+use super::full_math::{mul_div, mul_div_rounding_up};
+use super::sqrt_price_math::{
+    get_amount0_delta, get_amount1_delta, get_next_sqrt_price_from_input,
+    get_next_sqrt_price_from_output,
+};
+use crate::error::MathError;
+
+/// Fee denominator: fees are expressed in hundredths of a bip.
+pub const FEE_DENOMINATOR: u32 = 1_000_000;
+
+/// Result of a single swap step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwapStep {
+    /// The price after swapping the amount in/out, not to exceed the price target.
+    pub sqrt_price_next_x96: U256,
+    /// The amount to be swapped in, of either token0 or token1, based on the direction of the swap.
+    pub amount_in: U256,
+    /// The amount to be received, of either token0 or token1, based on the direction of the swap.
+    pub amount_out: U256,
+    /// The amount of input that will be taken as a fee.
+    pub fee_amount: U256,
+}
+
+/// Computes the result of swapping some amount in, or amount out, given the parameters of the swap.
+///
+/// A positive `amount_remaining` is an exact input, a negative one an exact output.
+/// The fee plus the amount in will never exceed the amount remaining if the swap is exact input.
+pub fn compute_swap_step(
+    sqrt_ratio_current_x96: U256,
+    sqrt_ratio_target_x96: U256,
+    liquidity: u128,
+    amount_remaining: I256,
+    fee_pips: u32,
+) -> Result<SwapStep, MathError> {
+    let zero_for_one = sqrt_ratio_current_x96 >= sqrt_ratio_target_x96;
+    let exact_in = !amount_remaining.is_negative();
+    let amount_remaining_abs = amount_remaining.unsigned_abs();
+    let fee_complement = U256::from(FEE_DENOMINATOR - fee_pips);
+
+    let mut amount_in = U256::zero();
+    let mut amount_out = U256::zero();
+
+    let sqrt_price_next_x96 = if exact_in {
+        let amount_remaining_less_fee = mul_div(
+            amount_remaining_abs,
+            fee_complement,
+            U256::from(FEE_DENOMINATOR),
+        )?;
+        amount_in = if zero_for_one {
+            get_amount0_delta(
+                sqrt_ratio_target_x96,
+                sqrt_ratio_current_x96,
+                liquidity,
+                true,
+            )?
+        } else {
+            get_amount1_delta(
+                sqrt_ratio_current_x96,
+                sqrt_ratio_target_x96,
+                liquidity,
+                true,
+            )?
+        };
+
+        if amount_remaining_less_fee >= amount_in {
+            sqrt_ratio_target_x96
+        } else {
+            get_next_sqrt_price_from_input(
+                sqrt_ratio_current_x96,
+                liquidity,
+                amount_remaining_less_fee,
+                zero_for_one,
+            )?
+        }
+    } else {
+        amount_out = if zero_for_one {
+            get_amount1_delta(
+                sqrt_ratio_target_x96,
+                sqrt_ratio_current_x96,
+                liquidity,
+                false,
+            )?
+        } else {
+            get_amount0_delta(
+                sqrt_ratio_current_x96,
+                sqrt_ratio_target_x96,
+                liquidity,
+                false,
+            )?
+        };
+
+        if amount_remaining_abs >= amount_out {
+            sqrt_ratio_target_x96
+        } else {
+            get_next_sqrt_price_from_output(
+                sqrt_ratio_current_x96,
+                liquidity,
+                amount_remaining_abs,
+                zero_for_one,
+            )?
+        }
+    };
+
+    let max = sqrt_ratio_target_x96 == sqrt_price_next_x96;
+
+    // Get the input/output amounts for the part of the range that was actually traversed.
+    if zero_for_one {
+        if !max || !exact_in {
+            amount_in =
+                get_amount0_delta(sqrt_price_next_x96, sqrt_ratio_current_x96, liquidity, true)?;
+        }
+        if !max || exact_in {
+            amount_out = get_amount1_delta(
+                sqrt_price_next_x96,
+                sqrt_ratio_current_x96,
+                liquidity,
+                false,
+            )?;
+        }
+    } else {
+        if !max || !exact_in {
+            amount_in =
+                get_amount1_delta(sqrt_ratio_current_x96, sqrt_price_next_x96, liquidity, true)?;
+        }
+        if !max || exact_in {
+            amount_out = get_amount0_delta(
+                sqrt_ratio_current_x96,
+                sqrt_price_next_x96,
+                liquidity,
+                false,
+            )?;
+        }
+    }
+
+    // Cap the output amount to not exceed the remaining output amount.
+    if !exact_in && amount_out > amount_remaining_abs {
+        amount_out = amount_remaining_abs;
+    }
+
+    let fee_amount = if exact_in && sqrt_price_next_x96 != sqrt_ratio_target_x96 {
+        // We didn't reach the target, so take the remainder of the maximum input as fee.
+        amount_remaining_abs - amount_in
+    } else {
+        mul_div_rounding_up(amount_in, U256::from(fee_pips), fee_complement)?
+    };
+
+    Ok(SwapStep {
+        sqrt_price_next_x96,
+        amount_in,
+        amount_out,
+        fee_amount,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u(value: &str) -> U256 {
+        U256::from_dec_str(value).unwrap()
+    }
+
+    fn i(value: &str) -> I256 {
+        I256::from_dec_str(value).unwrap()
+    }
+
+    /// sqrt(reserve1 / reserve0) * 2^96, as `encodePriceSqrt` in the Uniswap test suite.
+    fn encode_price_sqrt(reserve1: u64, reserve0: u64) -> U256 {
+        let ratio_x192 = (U256::from(reserve1) << 192) / U256::from(reserve0);
+        ratio_x192.integer_sqrt()
+    }
+
+    #[test]
+    fn test_exact_in_capped_at_price_target_one_for_zero() {
+        let price = encode_price_sqrt(1, 1);
+        let target = encode_price_sqrt(101, 100);
+        let step = compute_swap_step(
+            price,
+            target,
+            2_000_000_000_000_000_000,
+            i("1000000000000000000"),
+            600,
+        )
+        .unwrap();
+
+        assert_eq!(step.amount_in, u("9975124224178055"));
+        assert_eq!(step.fee_amount, u("5988667735148"));
+        assert_eq!(step.amount_out, u("9925619580021728"));
+        assert_eq!(step.sqrt_price_next_x96, target);
+    }
+
+    #[test]
+    fn test_exact_out_capped_at_price_target_one_for_zero() {
+        let price = encode_price_sqrt(1, 1);
+        let target = encode_price_sqrt(101, 100);
+        let step = compute_swap_step(
+            price,
+            target,
+            2_000_000_000_000_000_000,
+            i("-1000000000000000000"),
+            600,
+        )
+        .unwrap();
+
+        assert_eq!(step.amount_in, u("9975124224178055"));
+        assert_eq!(step.fee_amount, u("5988667735148"));
+        assert_eq!(step.amount_out, u("9925619580021728"));
+        assert_eq!(step.sqrt_price_next_x96, target);
+    }
+
+    #[test]
+    fn test_exact_in_fully_spent_one_for_zero() {
+        let price = encode_price_sqrt(1, 1);
+        let target = encode_price_sqrt(1000, 100);
+        let step = compute_swap_step(
+            price,
+            target,
+            2_000_000_000_000_000_000,
+            i("1000000000000000000"),
+            600,
+        )
+        .unwrap();
+
+        assert_eq!(step.amount_in, u("999400000000000000"));
+        assert_eq!(step.fee_amount, u("600000000000000"));
+        assert_eq!(step.amount_out, u("666399946655997866"));
+        assert!(step.sqrt_price_next_x96 < target);
+    }
+
+    #[test]
+    fn test_exact_out_fully_received_one_for_zero() {
+        let price = encode_price_sqrt(1, 1);
+        let target = encode_price_sqrt(10000, 100);
+        let step = compute_swap_step(
+            price,
+            target,
+            2_000_000_000_000_000_000,
+            i("-1000000000000000000"),
+            600,
+        )
+        .unwrap();
+
+        assert_eq!(step.amount_in, u("2000000000000000000"));
+        assert_eq!(step.fee_amount, u("1200720432259356"));
+        assert_eq!(step.amount_out, u("1000000000000000000"));
+        assert!(step.sqrt_price_next_x96 < target);
+    }
+
+    #[test]
+    fn test_amount_out_capped_at_desired_amount_out() {
+        let step = compute_swap_step(
+            u("417332158212080721273783715441582"),
+            u("1452870262520218020823638996"),
+            159344665391607089467575320103,
+            i("-1"),
+            1,
+        )
+        .unwrap();
+
+        assert_eq!(step.amount_in, U256::one());
+        assert_eq!(step.fee_amount, U256::one());
+        assert_eq!(step.amount_out, U256::one());
+        assert_eq!(
+            step.sqrt_price_next_x96,
+            u("417332158212080721273783715441581")
+        );
+    }
+
+    #[test]
+    fn test_entire_input_amount_taken_as_fee() {
+        let step = compute_swap_step(
+            U256::from(2413),
+            u("79887613182836312"),
+            1985041575832132834610021537970,
+            i("10"),
+            1872,
+        )
+        .unwrap();
+
+        assert_eq!(step.amount_in, U256::zero());
+        assert_eq!(step.fee_amount, U256::from(10));
+        assert_eq!(step.amount_out, U256::zero());
+        assert_eq!(step.sqrt_price_next_x96, U256::from(2413));
+    }
+}
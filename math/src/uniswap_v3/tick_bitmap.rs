@@ -0,0 +1,113 @@
+// Port of Uniswap's TickBitmap library.
+// Stores a packed mapping of initialized ticks, one bit per compressed tick, 256 ticks per word.
+
+use ethers::types::U256;
+use std::collections::HashMap;
+
+use crate::error::MathError;
+
+/// Packed tick initialized state, keyed by word position like the pool's `tickBitmap` mapping.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TickBitmap {
+    words: HashMap<i16, U256>,
+}
+
+/// Computes the word and bit position of a compressed tick.
+pub fn position(tick: i32) -> (i16, u8) {
+    ((tick >> 8) as i16, (tick & 0xff) as u8)
+}
+
+impl TickBitmap {
+    /// Returns the bitmap word at `word_pos`, or zero if the word holds no initialized ticks.
+    pub fn word(&self, word_pos: i16) -> U256 {
+        self.words.get(&word_pos).copied().unwrap_or_default()
+    }
+
+    /// Replaces a whole bitmap word, e.g. with the value read from `tickBitmap(wordPos)` on-chain.
+    pub fn set_word(&mut self, word_pos: i16, word: U256) {
+        if word.is_zero() {
+            self.words.remove(&word_pos);
+        } else {
+            self.words.insert(word_pos, word);
+        }
+    }
+
+    /// Flips the initialized state for a given tick from false to true, or vice versa.
+    pub fn flip_tick(&mut self, tick: i32, tick_spacing: i32) -> Result<(), MathError> {
+        if tick_spacing <= 0 {
+            return Err(MathError::InvalidTickSpacing(tick_spacing));
+        }
+        if tick % tick_spacing != 0 {
+            return Err(MathError::InvalidTick(tick));
+        }
+
+        let (word_pos, bit_pos) = position(tick / tick_spacing);
+        let word = self.word(word_pos) ^ (U256::one() << bit_pos);
+        self.set_word(word_pos, word);
+
+        Ok(())
+    }
+
+    /// Returns whether `tick` is initialized.
+    pub fn is_initialized(&self, tick: i32, tick_spacing: i32) -> bool {
+        if tick_spacing <= 0 || tick % tick_spacing != 0 {
+            return false;
+        }
+
+        let (word_pos, bit_pos) = position(tick / tick_spacing);
+        self.word(word_pos).bit(bit_pos as usize)
+    }
+
+    /// Returns the next initialized tick contained in the same word (or adjacent word) as the tick
+    /// that is either to the left (less than or equal to) or right (greater than) of the given tick.
+    ///
+    /// Returns a tuple of (next tick, whether that tick is initialized).
+    pub fn next_initialized_tick_within_one_word(
+        &self,
+        tick: i32,
+        tick_spacing: i32,
+        lte: bool,
+    ) -> Result<(i32, bool), MathError> {
+        if tick_spacing <= 0 {
+            return Err(MathError::InvalidTickSpacing(tick_spacing));
+        }
+
+        let mut compressed = tick / tick_spacing;
+        if tick < 0 && tick % tick_spacing != 0 {
+            compressed -= 1; // Round towards negative infinity.
+        }
+
+        if lte {
+            let (word_pos, bit_pos) = position(compressed);
+            // All the 1s at or to the right of the current bit position.
+            let mask = (U256::one() << bit_pos) - 1 + (U256::one() << bit_pos);
+            let masked = self.word(word_pos) & mask;
+
+            let initialized = !masked.is_zero();
+            let next = if initialized {
+                let most_significant_bit = masked.bits() as i32 - 1;
+                (compressed - (bit_pos as i32 - most_significant_bit)) * tick_spacing
+            } else {
+                (compressed - bit_pos as i32) * tick_spacing
+            };
+
+            Ok((next, initialized))
+        } else {
+            // Start from the word of the next tick, since the current tick state doesn't matter.
+            let (word_pos, bit_pos) = position(compressed + 1);
+            // All the 1s at or to the left of the bit position.
+            let mask = !((U256::one() << bit_pos) - 1);
+            let masked = self.word(word_pos) & mask;
+
+            let initialized = !masked.is_zero();
+            let next = if initialized {
+                let least_significant_bit = masked.trailing_zeros() as i32;
+                (compressed + 1 + (least_significant_bit - bit_pos as i32)) * tick_spacing
+            } else {
+                (compressed + 1 + (255 - bit_pos as i32)) * tick_spacing
+            };
+
+            Ok((next, initialized))
+        }
+    }
+}
@@ -0,0 +1,208 @@
+// Port of Uniswap's TickMath library.
+// Converts between ticks and sqrt prices as Q64.96 values, bit-for-bit with the on-chain code.
+
+use ethers::types::{I256, U256};
+
+use crate::error::MathError;
+
+/// The minimum tick that may be passed to `get_sqrt_ratio_at_tick`, computed from log base 1.0001 of 2**-128.
+pub const MIN_TICK: i32 = -887272;
+/// The maximum tick that may be passed to `get_sqrt_ratio_at_tick`, computed from log base 1.0001 of 2**128.
+pub const MAX_TICK: i32 = -MIN_TICK;
+
+/// The minimum value that can be returned from `get_sqrt_ratio_at_tick`, equivalent to `get_sqrt_ratio_at_tick(MIN_TICK)`.
+pub const MIN_SQRT_RATIO: U256 = U256([4295128739, 0, 0, 0]);
+/// The maximum value that can be returned from `get_sqrt_ratio_at_tick`, equivalent to `get_sqrt_ratio_at_tick(MAX_TICK)`.
+pub const MAX_SQRT_RATIO: U256 = U256([0x5d951d5263988d26, 0xefd1fc6a50648849, 0xfffd8963, 0]);
+
+/// Per-bit multipliers of 1/sqrt(1.0001)^(2^i) in Q128.128, as in the Solidity implementation.
+const TICK_RATIOS: [(i32, u128); 19] = [
+    (0x2, 0xfff97272373d413259a46990580e213a),
+    (0x4, 0xfff2e50f5f656932ef12357cf3c7fdcc),
+    (0x8, 0xffe5caca7e10e4e61c3624eaa0941cd0),
+    (0x10, 0xffcb9843d60f6159c9db58835c926644),
+    (0x20, 0xff973b41fa98c081472e6896dfb254c0),
+    (0x40, 0xff2ea16466c96a3843ec78b326b52861),
+    (0x80, 0xfe5dee046a99a2a811c461f1969c3053),
+    (0x100, 0xfcbe86c7900a88aedcffc83b479aa3a4),
+    (0x200, 0xf987a7253ac413176f2b074cf7815e54),
+    (0x400, 0xf3392b0822b70005940c7a398e4b70f3),
+    (0x800, 0xe7159475a2c29b7443b29c7fa6e889d9),
+    (0x1000, 0xd097f3bdfd2022b8845ad8f792aa5825),
+    (0x2000, 0xa9f746462d870fdf8a65dc1f90e061e5),
+    (0x4000, 0x70d869a156d2a1b890bb3df62baf32f7),
+    (0x8000, 0x31be135f97d08fd981231505542fcfa6),
+    (0x10000, 0x9aa508b5b7a84e1c677de54f3e99bc9),
+    (0x20000, 0x5d6af8dedb81196699c329225ee604),
+    (0x40000, 0x2216e584f5fa1ea926041bedfe98),
+    (0x80000, 0x48a170391f7dc42444e8fa2),
+];
+
+/// Calculates sqrt(1.0001^tick) * 2^96.
+///
+/// Returns the sqrt price as a Q64.96 value, or `MathError::InvalidTick` if the tick is out of range.
+pub fn get_sqrt_ratio_at_tick(tick: i32) -> Result<U256, MathError> {
+    let abs_tick = tick.unsigned_abs() as i32;
+    if abs_tick > MAX_TICK {
+        return Err(MathError::InvalidTick(tick));
+    }
+
+    let mut ratio = if abs_tick & 0x1 != 0 {
+        U256::from(0xfffcb933bd6fad37aa2d162d1a594001u128)
+    } else {
+        U256::one() << 128
+    };
+
+    for (bit, multiplier) in TICK_RATIOS {
+        if abs_tick & bit != 0 {
+            ratio = (ratio * U256::from(multiplier)) >> 128;
+        }
+    }
+
+    if tick > 0 {
+        ratio = U256::MAX / ratio;
+    }
+
+    // Divide by 1<<32 rounding up to go from a Q128.128 to a Q128.96.
+    let remainder = ratio & U256::from(u32::MAX);
+    let sqrt_price_x96 = (ratio >> 32) + if remainder.is_zero() { 0 } else { 1 };
+
+    Ok(sqrt_price_x96)
+}
+
+/// Calculates the greatest tick value such that `get_sqrt_ratio_at_tick(tick) <= sqrt_price_x96`.
+///
+/// Returns the tick, or `MathError::InvalidSqrtPrice` if the price is outside `[MIN_SQRT_RATIO, MAX_SQRT_RATIO)`.
+pub fn get_tick_at_sqrt_ratio(sqrt_price_x96: U256) -> Result<i32, MathError> {
+    if sqrt_price_x96 < MIN_SQRT_RATIO || sqrt_price_x96 >= MAX_SQRT_RATIO {
+        return Err(MathError::InvalidSqrtPrice);
+    }
+
+    let ratio = sqrt_price_x96 << 32;
+    let msb = ratio.bits() - 1;
+
+    let mut r = if msb >= 128 {
+        ratio >> (msb - 127)
+    } else {
+        ratio << (127 - msb)
+    };
+
+    // Integer part of log2(ratio) in Q64.64, then 14 bits of the fractional part.
+    let mut log_2 = (msb as i128 - 128) << 64;
+    for shift in (50..=63).rev() {
+        r = (r * r) >> 127;
+        let f = (r >> 128).low_u32();
+        log_2 |= (f as i128) << shift;
+        r >>= f as usize;
+    }
+
+    let log_sqrt10001 = I256::from(log_2) * signed(255738958999603826347141);
+
+    let tick_low = (log_sqrt10001 - signed(3402992956809132418596140100660247210))
+        .asr(128)
+        .low_i32();
+    let tick_high = (log_sqrt10001 + signed(291339464771989622907027621153398088495))
+        .asr(128)
+        .low_i32();
+
+    if tick_low == tick_high || get_sqrt_ratio_at_tick(tick_high)? > sqrt_price_x96 {
+        Ok(tick_low)
+    } else {
+        Ok(tick_high)
+    }
+}
+
+fn signed(value: u128) -> I256 {
+    I256::from_raw(U256::from(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sqrt_ratio_at_boundary_ticks() {
+        assert_eq!(get_sqrt_ratio_at_tick(MIN_TICK).unwrap(), MIN_SQRT_RATIO);
+        assert_eq!(get_sqrt_ratio_at_tick(MAX_TICK).unwrap(), MAX_SQRT_RATIO);
+        assert_eq!(get_sqrt_ratio_at_tick(0).unwrap(), U256::one() << 96);
+        assert_eq!(
+            MAX_SQRT_RATIO,
+            U256::from_dec_str("1461446703485210103287273052203988822378723970342").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_sqrt_ratio_rejects_out_of_range_ticks() {
+        assert!(matches!(
+            get_sqrt_ratio_at_tick(MIN_TICK - 1),
+            Err(MathError::InvalidTick(_))
+        ));
+        assert!(matches!(
+            get_sqrt_ratio_at_tick(MAX_TICK + 1),
+            Err(MathError::InvalidTick(_))
+        ));
+    }
+
+    #[test]
+    fn test_sqrt_ratio_known_vectors() {
+        let vectors = [
+            (MIN_TICK + 1, "4295343490"),
+            (
+                MAX_TICK - 1,
+                "1461373636630004318706518188784493106690254656249",
+            ),
+            (-1, "79224201403219477170569942574"),
+            (1, "79232123823359799118286999568"),
+            (-50, "79030349367926598376800521322"),
+            (50, "79426470787362580746886972461"),
+        ];
+
+        for (tick, expected) in vectors {
+            assert_eq!(
+                get_sqrt_ratio_at_tick(tick).unwrap(),
+                U256::from_dec_str(expected).unwrap(),
+                "tick {}",
+                tick
+            );
+        }
+    }
+
+    #[test]
+    fn test_tick_at_sqrt_ratio_boundaries() {
+        assert_eq!(get_tick_at_sqrt_ratio(MIN_SQRT_RATIO).unwrap(), MIN_TICK);
+        assert_eq!(
+            get_tick_at_sqrt_ratio(MIN_SQRT_RATIO + 1).unwrap(),
+            MIN_TICK
+        );
+        assert_eq!(
+            get_tick_at_sqrt_ratio(MAX_SQRT_RATIO - 1).unwrap(),
+            MAX_TICK - 1
+        );
+        assert!(get_tick_at_sqrt_ratio(MIN_SQRT_RATIO - 1).is_err());
+        assert!(get_tick_at_sqrt_ratio(MAX_SQRT_RATIO).is_err());
+    }
+
+    #[test]
+    fn test_tick_round_trip() {
+        for tick in [
+            MIN_TICK,
+            -500_000,
+            -887,
+            -60,
+            -1,
+            0,
+            1,
+            59,
+            4_000,
+            123_456,
+            MAX_TICK - 1,
+        ] {
+            let sqrt_price = get_sqrt_ratio_at_tick(tick).unwrap();
+            assert_eq!(get_tick_at_sqrt_ratio(sqrt_price).unwrap(), tick);
+            assert_eq!(get_tick_at_sqrt_ratio(sqrt_price + 1).unwrap(), tick);
+            if tick > MIN_TICK {
+                assert_eq!(get_tick_at_sqrt_ratio(sqrt_price - 1).unwrap(), tick - 1);
+            }
+        }
+    }
+}
@@ -1,13 +1,30 @@
 #![allow(unused)]
 
-use ethers::types::U256;
+pub mod limits;
+pub mod store;
+
+use chrono::{DateTime, Duration, Utc};
+use ethers::types::{Address, I256, U256};
+use parking_lot::{Mutex, RwLock};
 use std::collections::HashMap;
+use std::path::Path;
+
+use limits::{daily_pnl, evaluate, settled_from};
+pub use limits::{LimitBreach, RiskLimits, StrategyPnl, Trade};
+use store::{RiskRecord, RiskStore, StoreError};
+
+/// Age after which trades are dropped when a persisted engine compacts its store.
+pub const DEFAULT_RETENTION: Duration = Duration::days(7);
+/// Basis points in a ratio of one.
+const BPS: u64 = 10_000;
 
 #[derive(Debug, Clone)]
 pub struct RiskParameters {
     pub max_position_size: U256,
     pub max_loss_percent: u8,
     pub min_profit_ratio: f64,
+    /// Largest total size of the open positions in one token.
+    pub max_token_exposure: U256,
 }
 
 impl Default for RiskParameters {
@@ -16,26 +33,107 @@ impl Default for RiskParameters {
             max_position_size: U256::from(500_000u64),
             max_loss_percent: 3,
             min_profit_ratio: 1.2,
+            max_token_exposure: U256::from(1_000_000u64),
         }
     }
 }
 
+/// A trade a strategy is about to send, as checked before sending it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TradeProposal {
+    /// Token the position is taken in.
+    pub token: Address,
+    /// Size of the position in units of `token`.
+    pub size: U256,
+    /// Value of the position in units of the quote token, which bounds `max_loss`.
+    pub value: U256,
+    /// Strategy sending the trade.
+    pub strategy: String,
+    /// Expected profit in units of the quote token.
+    pub expected_profit: U256,
+    /// Cost of the trade, such as gas and bribes, in units of the quote token. Zero skips the profit ratio check.
+    pub cost: U256,
+    /// Largest loss the trade can realize, in units of the quote token.
+    pub max_loss: U256,
+}
+
+impl TradeProposal {
+    pub fn new(token: Address, size: U256) -> Self {
+        Self {
+            token,
+            size,
+            value: U256::zero(),
+            strategy: String::new(),
+            expected_profit: U256::zero(),
+            cost: U256::zero(),
+            max_loss: U256::zero(),
+        }
+    }
+
+    pub fn with_value(mut self, value: U256) -> Self {
+        self.value = value;
+        self
+    }
+
+    pub fn with_strategy(mut self, strategy: impl Into<String>) -> Self {
+        self.strategy = strategy.into();
+        self
+    }
+
+    pub fn with_expected_profit(mut self, expected_profit: U256) -> Self {
+        self.expected_profit = expected_profit;
+        self
+    }
+
+    pub fn with_cost(mut self, cost: U256) -> Self {
+        self.cost = cost;
+        self
+    }
+
+    pub fn with_max_loss(mut self, max_loss: U256) -> Self {
+        self.max_loss = max_loss;
+        self
+    }
+}
+
+/// Realized trades and open positions.
+#[derive(Debug, Default)]
+struct Ledger {
+    /// Trades in settlement order.
+    trades: Vec<Trade>,
+    /// Trades settled before this time are left out of the limits.
+    evaluated_from: Option<DateTime<Utc>>,
+    /// Total size of the open positions by token.
+    exposure: HashMap<Address, U256>,
+}
+
+/// Checks trades before they are sent and records them once settled.
+///
+/// Shared by the strategies and the circuit breaker, so every check sees the trades and positions of all of
+/// them. PnL is denominated in `quote_token`.
 #[derive(Debug, Default)]
 pub struct RiskEngine {
     parameters: RiskParameters,
-    daily_losses: HashMap<u64, U256>,
+    limits: RiskLimits,
+    quote_token: Address,
+    ledger: RwLock<Ledger>,
+    store: Option<Mutex<RiskStore>>,
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum RiskError {
     #[error("Position size exceeded")]
     PositionSizeExceeded,
-    #[error("Daily loss limit exceeded")]
-    DailyLossLimitExceeded,
     #[error("Position size exceeds maximum allowed")]
     PositionTooLarge,
+    #[error("Potential loss exceeds maximum allowed")]
+    LossTooLarge,
     #[error("Insufficient profit margin")]
     InsufficientProfit,
+    #[error("Exposure to token {0:?} exceeded")]
+    TokenExposureExceeded(Address),
+    #[error(transparent)]
+    Limit(#[from] LimitBreach),
     #[error("Volatility threshold exceeded")]
     VolatilityThreshold,
     #[error("Invalid risk parameters")]
@@ -43,7 +141,125 @@ pub enum RiskError {
 }
 
 impl RiskEngine {
-    pub fn validate_trade(&self, size: U256, potential_loss: U256) -> Result<(), RiskError> {
+    pub fn new(parameters: RiskParameters) -> Self {
+        Self {
+            parameters,
+            ..Default::default()
+        }
+    }
+
+    /// Sets the loss limits evaluated over the recorded trades.
+    pub fn with_limits(mut self, limits: RiskLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Sets the token every PnL amount is denominated in.
+    pub fn with_quote_token(mut self, quote_token: Address) -> Self {
+        self.quote_token = quote_token;
+        self
+    }
+
+    /// Persists the trades to the store at `path`, reloading the ones recorded there.
+    ///
+    /// The store is compacted from time to time, dropping the trades older than `DEFAULT_RETENTION` or the
+    /// drawdown window, whichever is longer.
+    pub fn with_store(mut self, path: impl AsRef<Path>) -> Result<Self, StoreError> {
+        let (store, records) = RiskStore::open(path)?;
+        {
+            let ledger = self.ledger.get_mut();
+            for record in records {
+                match record {
+                    RiskRecord::Trade {
+                        at,
+                        strategy,
+                        profit_loss,
+                    } => ledger.trades.push(Trade {
+                        timestamp: at,
+                        strategy,
+                        profit_loss,
+                    }),
+                    RiskRecord::LimitsReset { at } => ledger.evaluated_from = Some(at),
+                }
+            }
+            ledger.trades.sort_by_key(|trade| trade.timestamp);
+        }
+        self.store = Some(Mutex::new(store));
+        Ok(self)
+    }
+
+    pub fn parameters(&self) -> &RiskParameters {
+        &self.parameters
+    }
+
+    pub fn limits(&self) -> &RiskLimits {
+        &self.limits
+    }
+
+    pub fn quote_token(&self) -> Address {
+        self.quote_token
+    }
+
+    /// Checks a trade before it is sent: its size, potential loss against its value, profit ratio, the exposure
+    /// to its token, and whether losing `max_loss` now would reach a loss limit.
+    pub fn check_trade(&self, proposal: &TradeProposal) -> Result<(), RiskError> {
+        let ledger = self.ledger.read();
+        self.check_open(&ledger, proposal)?;
+
+        let now = Utc::now();
+        let candidate = Trade {
+            timestamp: now,
+            strategy: proposal.strategy.clone(),
+            profit_loss: -I256::from_raw(proposal.max_loss.min(I256::MAX.into_raw())),
+        };
+        let trades = settled_from(&ledger.trades, ledger.evaluated_from);
+        evaluate(&self.limits, trades.iter().chain([&candidate]), now)?;
+        Ok(())
+    }
+
+    /// Checks a trade like `check_trade`, but leaves out the loss limits.
+    pub fn check_position(&self, proposal: &TradeProposal) -> Result<(), RiskError> {
+        self.check_open(&self.ledger.read(), proposal)
+    }
+
+    /// Checks a trade like `check_position` and counts its size against the exposure to its token until it is
+    /// closed or released.
+    pub fn open_position(&self, proposal: &TradeProposal) -> Result<(), RiskError> {
+        let mut ledger = self.ledger.write();
+        self.check_open(&ledger, proposal)?;
+        let exposure = ledger.exposure.entry(proposal.token).or_default();
+        *exposure = exposure.saturating_add(proposal.size);
+        Ok(())
+    }
+
+    /// Releases the exposure of a position that was not filled.
+    pub fn release_position(&self, proposal: &TradeProposal) {
+        let mut ledger = self.ledger.write();
+        if let Some(exposure) = ledger.exposure.get_mut(&proposal.token) {
+            *exposure = exposure.saturating_sub(proposal.size);
+            if exposure.is_zero() {
+                ledger.exposure.remove(&proposal.token);
+            }
+        }
+    }
+
+    /// Releases the exposure of a settled position and records its PnL for its strategy.
+    pub fn close_position(&self, proposal: &TradeProposal, profit_loss: I256) {
+        self.release_position(proposal);
+        self.record_trade(proposal.strategy.clone(), profit_loss);
+    }
+
+    /// Total size of the open positions in `token`.
+    pub fn exposure(&self, token: Address) -> U256 {
+        self.ledger
+            .read()
+            .exposure
+            .get(&token)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn check_size(&self, size: U256) -> Result<(), RiskError> {
         if size > self.parameters.max_position_size {
             return Err(RiskError::PositionSizeExceeded);
         }
@@ -59,13 +275,258 @@ impl RiskEngine {
         }
     }
 
-    pub fn validate_risk(&self, potential_loss: U256) -> Result<(), RiskError> {
-        if potential_loss > self.parameters.max_position_size {
-            return Err(RiskError::PositionSizeExceeded);
+    fn check_open(&self, ledger: &Ledger, proposal: &TradeProposal) -> Result<(), RiskError> {
+        self.check_size(proposal.size)?;
+
+        let max_loss = proposal
+            .value
+            .full_mul(self.parameters.max_loss_percent.into());
+        if proposal.max_loss.full_mul(100.into()) > max_loss {
+            return Err(RiskError::LossTooLarge);
         }
-        if potential_loss > U256::from(self.parameters.max_loss_percent) {
-            return Err(RiskError::DailyLossLimitExceeded);
+
+        if !proposal.cost.is_zero() {
+            let ratio_bps = (self.parameters.min_profit_ratio * BPS as f64).ceil() as u64;
+            if proposal.expected_profit.full_mul(BPS.into())
+                < proposal.cost.full_mul(ratio_bps.into())
+            {
+                return Err(RiskError::InsufficientProfit);
+            }
+        }
+
+        let exposure = ledger
+            .exposure
+            .get(&proposal.token)
+            .copied()
+            .unwrap_or_default();
+        if exposure.saturating_add(proposal.size) > self.parameters.max_token_exposure {
+            return Err(RiskError::TokenExposureExceeded(proposal.token));
         }
         Ok(())
     }
+
+    /// Checks the recorded trades against the limits at the current time.
+    pub fn check_limits(&self) -> Result<(), LimitBreach> {
+        let ledger = self.ledger.read();
+        let trades = settled_from(&ledger.trades, ledger.evaluated_from);
+        evaluate(&self.limits, trades.iter(), Utc::now())
+    }
+
+    /// Leaves the trades settled before `from` out of the limits, while keeping them in the PnL reports.
+    pub fn reset_limits(&self, from: DateTime<Utc>) {
+        let mut ledger = self.ledger.write();
+        self.persist(&RiskRecord::LimitsReset { at: from });
+        ledger.evaluated_from = Some(from);
+    }
+
+    /// Records a trade of `strategy` settled now.
+    pub fn record_trade(&self, strategy: impl Into<String>, profit_loss: I256) {
+        self.record(Trade::now(strategy, profit_loss));
+    }
+
+    /// Records a trade, keeping trades in settlement order.
+    ///
+    /// A trade that cannot be persisted is still counted, and the failure is logged.
+    pub fn record(&self, trade: Trade) {
+        let mut ledger = self.ledger.write();
+        self.persist(&RiskRecord::Trade {
+            at: trade.timestamp,
+            strategy: trade.strategy.clone(),
+            profit_loss: trade.profit_loss,
+        });
+        let index = ledger
+            .trades
+            .partition_point(|recorded| recorded.timestamp <= trade.timestamp);
+        ledger.trades.insert(index, trade);
+
+        if let Some(store) = &self.store {
+            let mut store = store.lock();
+            if store.needs_compaction() {
+                self.compact(&mut store, &mut ledger);
+            }
+        }
+    }
+
+    fn persist(&self, record: &RiskRecord) {
+        if let Some(store) = &self.store {
+            if let Err(err) = store.lock().append(record) {
+                log::error!("Failed to persist risk record: {err}");
+            }
+        }
+    }
+
+    /// Drops the trades past retention and rewrites the store with the remaining state.
+    fn compact(&self, store: &mut RiskStore, ledger: &mut Ledger) {
+        let retention = DEFAULT_RETENTION.max(self.limits.drawdown_window + Duration::days(1));
+        let start = ledger
+            .trades
+            .partition_point(|trade| trade.timestamp < Utc::now() - retention);
+        ledger.trades.drain(..start);
+
+        let records: Vec<RiskRecord> = ledger
+            .evaluated_from
+            .map(|at| RiskRecord::LimitsReset { at })
+            .into_iter()
+            .chain(ledger.trades.iter().map(|trade| RiskRecord::Trade {
+                at: trade.timestamp,
+                strategy: trade.strategy.clone(),
+                profit_loss: trade.profit_loss,
+            }))
+            .collect();
+        if let Err(err) = store.compact(&records) {
+            log::error!("Failed to compact risk store: {err}");
+        }
+    }
+
+    /// Realized PnL of each strategy over every recorded trade, or every retained one if persisted.
+    pub fn pnl_by_strategy(&self) -> HashMap<String, StrategyPnl> {
+        let ledger = self.ledger.read();
+        let mut pnl: HashMap<String, StrategyPnl> = HashMap::new();
+        for trade in &ledger.trades {
+            pnl.entry(trade.strategy.clone())
+                .or_default()
+                .add(trade.profit_loss);
+        }
+        pnl
+    }
+
+    /// Net realized PnL of the UTC day of `at`.
+    pub fn daily_pnl(&self, at: DateTime<Utc>) -> I256 {
+        daily_pnl(self.ledger.read().trades.iter(), at)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(hour: u32, minute: u32, second: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 1, hour, minute, second)
+            .unwrap()
+    }
+
+    fn trade(timestamp: DateTime<Utc>, strategy: &str, profit_loss: i64) -> Trade {
+        Trade {
+            timestamp,
+            strategy: strategy.to_string(),
+            profit_loss: I256::from(profit_loss),
+        }
+    }
+
+    #[test]
+    fn test_pre_trade_checks() {
+        let token = Address::repeat_byte(2);
+        let engine = RiskEngine::default().with_limits(RiskLimits::new(U256::from(100)));
+        let proposal = TradeProposal::new(token, U256::from(400_000))
+            .with_value(U256::from(4_000))
+            .with_strategy("sandwich")
+            .with_expected_profit(U256::from(120))
+            .with_cost(U256::from(100))
+            .with_max_loss(U256::from(99));
+
+        assert!(engine.check_trade(&proposal).is_ok());
+        assert!(matches!(
+            engine.check_trade(&proposal.clone().with_expected_profit(U256::from(119))),
+            Err(RiskError::InsufficientProfit)
+        ));
+        assert!(matches!(
+            engine.check_trade(&TradeProposal::new(token, U256::from(500_001))),
+            Err(RiskError::PositionSizeExceeded)
+        ));
+        // The loss is bounded by the value of the position, whatever its size in token units.
+        assert!(matches!(
+            engine.check_trade(&proposal.clone().with_value(U256::from(3_000))),
+            Err(RiskError::LossTooLarge)
+        ));
+        // Losing the whole 100 would reach the daily loss limit.
+        assert!(matches!(
+            engine.check_trade(&proposal.clone().with_max_loss(U256::from(100))),
+            Err(RiskError::Limit(LimitBreach::DailyLoss { .. }))
+        ));
+
+        // Open positions count against the exposure to their token until closed.
+        engine.open_position(&proposal).unwrap();
+        engine.open_position(&proposal).unwrap();
+        assert_eq!(engine.exposure(token), U256::from(800_000));
+        assert!(matches!(
+            engine.open_position(&proposal),
+            Err(RiskError::TokenExposureExceeded(exposed)) if exposed == token
+        ));
+        assert!(engine
+            .check_trade(&TradeProposal::new(
+                Address::repeat_byte(3),
+                U256::from(400_000)
+            ))
+            .is_ok());
+
+        engine.release_position(&proposal);
+        engine.close_position(&proposal, I256::from(-60));
+        assert_eq!(engine.exposure(token), U256::zero());
+        assert_eq!(engine.pnl_by_strategy()["sandwich"].net, I256::from(-60));
+        // The realized loss leaves room for a loss of at most 39 today.
+        assert!(engine
+            .check_trade(&proposal.clone().with_max_loss(U256::from(39)))
+            .is_ok());
+        assert!(engine
+            .check_trade(&proposal.with_max_loss(U256::from(40)))
+            .is_err());
+    }
+
+    #[test]
+    fn test_engine_breaks_pnl_down_per_strategy() {
+        let engine = RiskEngine::default();
+        engine.record(trade(at(3, 0, 0), "sandwich", 50));
+        engine.record(trade(at(1, 0, 0), "sandwich", -20));
+        engine.record(trade(at(2, 0, 0), "arbitrage", -70));
+
+        let pnl = engine.pnl_by_strategy();
+        assert_eq!(
+            pnl["sandwich"],
+            StrategyPnl {
+                net: I256::from(30),
+                gross_profit: U256::from(50),
+                gross_loss: U256::from(20),
+                trades: 2,
+                losses: 1,
+            }
+        );
+        assert_eq!(pnl["arbitrage"].net, I256::from(-70));
+        assert_eq!(engine.daily_pnl(at(23, 0, 0)), I256::from(-40));
+
+        let ledger = engine.ledger.read();
+        assert!(ledger
+            .trades
+            .windows(2)
+            .all(|pair| pair[0].timestamp <= pair[1].timestamp));
+    }
+
+    #[test]
+    fn test_persisted_trades_reload_into_their_utc_day() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("risk.jsonl");
+        let open = || {
+            RiskEngine::default()
+                .with_limits(RiskLimits::new(U256::from(100)))
+                .with_store(&path)
+                .unwrap()
+        };
+        let late = at(23, 59, 59);
+        let early = late + Duration::seconds(2);
+
+        let engine = open();
+        engine.record(trade(early, "sandwich", -35));
+        engine.record(trade(late, "sandwich", -50));
+        engine.record(trade(late, "arbitrage", 20));
+        engine.reset_limits(early);
+        drop(engine);
+
+        let engine = open();
+        assert_eq!(engine.daily_pnl(late), I256::from(-30));
+        assert_eq!(engine.daily_pnl(early), I256::from(-35));
+        assert_eq!(engine.pnl_by_strategy()["sandwich"].trades, 2);
+        let ledger = engine.ledger.read();
+        assert_eq!(ledger.evaluated_from, Some(early));
+        assert_eq!(ledger.trades.first().unwrap().timestamp, late);
+    }
 }
@@ -0,0 +1,311 @@
+// This module holds the post-trade side of risk: realized trades and the loss limits evaluated over them.
+// PnL is signed and denominated in a single quote token, so gains and losses of every strategy add up.
+
+use chrono::{DateTime, Duration as TimeDelta, Utc};
+use ethers::types::{I256, U256};
+
+/// A realized trade.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trade {
+    /// Time the trade was settled at.
+    pub timestamp: DateTime<Utc>,
+    /// Strategy that made the trade.
+    pub strategy: String,
+    /// Profit, or loss if negative, in units of the engine's quote token.
+    pub profit_loss: I256,
+}
+
+impl Trade {
+    /// Creates a trade settled now.
+    pub fn now(strategy: impl Into<String>, profit_loss: I256) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            strategy: strategy.into(),
+            profit_loss,
+        }
+    }
+}
+
+/// Loss limits enforced on the trades of every strategy together.
+///
+/// Amounts are in units of the quote token. Drawdown and consecutive-loss rules are off unless set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RiskLimits {
+    /// Largest net loss allowed over a UTC day.
+    pub daily_loss_limit: U256,
+    /// Largest drop from a cumulative PnL peak allowed within `drawdown_window`.
+    pub max_drawdown: Option<U256>,
+    /// Rolling window the drawdown is measured over.
+    pub drawdown_window: TimeDelta,
+    /// Number of losing trades in a row that stops trading.
+    pub max_consecutive_losses: Option<u32>,
+}
+
+impl Default for RiskLimits {
+    fn default() -> Self {
+        Self::new(U256::MAX)
+    }
+}
+
+impl RiskLimits {
+    /// Creates limits with only a daily loss limit.
+    pub fn new(daily_loss_limit: U256) -> Self {
+        Self {
+            daily_loss_limit,
+            max_drawdown: None,
+            drawdown_window: TimeDelta::hours(24),
+            max_consecutive_losses: None,
+        }
+    }
+
+    /// Limits the drop from a cumulative PnL peak within a rolling window.
+    pub fn with_max_drawdown(mut self, max_drawdown: U256, window: TimeDelta) -> Self {
+        self.max_drawdown = Some(max_drawdown);
+        self.drawdown_window = window;
+        self
+    }
+
+    /// Limits the number of losing trades in a row.
+    pub fn with_max_consecutive_losses(mut self, max_consecutive_losses: u32) -> Self {
+        self.max_consecutive_losses = Some(max_consecutive_losses);
+        self
+    }
+}
+
+/// A risk limit that trading has reached.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum LimitBreach {
+    /// Net loss of the current UTC day reached the daily limit.
+    #[error("Daily loss {loss} reached the limit of {limit}")]
+    DailyLoss {
+        /// Net loss of the day.
+        loss: U256,
+        /// Configured limit.
+        limit: U256,
+    },
+    /// Drop from the cumulative PnL peak within the rolling window reached the limit.
+    #[error("Drawdown {drawdown} reached the limit of {limit}")]
+    Drawdown {
+        /// Largest drop within the window.
+        drawdown: U256,
+        /// Configured limit.
+        limit: U256,
+    },
+    /// Latest trades were all losses.
+    #[error("{count} consecutive losses reached the limit of {limit}")]
+    ConsecutiveLosses {
+        /// Number of losing trades in a row.
+        count: u32,
+        /// Configured limit.
+        limit: u32,
+    },
+}
+
+/// Realized PnL of one strategy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StrategyPnl {
+    /// Sum of the strategy's gains and losses.
+    pub net: I256,
+    /// Sum of its profitable trades.
+    pub gross_profit: U256,
+    /// Sum of its losing trades, as a positive amount.
+    pub gross_loss: U256,
+    /// Number of trades.
+    pub trades: u64,
+    /// Number of losing trades.
+    pub losses: u64,
+}
+
+impl Default for StrategyPnl {
+    fn default() -> Self {
+        Self {
+            net: I256::zero(),
+            gross_profit: U256::zero(),
+            gross_loss: U256::zero(),
+            trades: 0,
+            losses: 0,
+        }
+    }
+}
+
+impl StrategyPnl {
+    pub(crate) fn add(&mut self, profit_loss: I256) {
+        self.net = self.net.saturating_add(profit_loss);
+        self.trades += 1;
+        if profit_loss.is_negative() {
+            self.gross_loss = self.gross_loss.saturating_add(profit_loss.unsigned_abs());
+            self.losses += 1;
+        } else {
+            self.gross_profit = self.gross_profit.saturating_add(profit_loss.into_raw());
+        }
+    }
+}
+
+/// Trades settled at or after `from`, out of trades in settlement order.
+pub(crate) fn settled_from(trades: &[Trade], from: Option<DateTime<Utc>>) -> &[Trade] {
+    let start = from.map_or(0, |from| {
+        trades.partition_point(|trade| trade.timestamp < from)
+    });
+    &trades[start..]
+}
+
+pub(crate) fn daily_pnl<'a>(
+    trades: impl IntoIterator<Item = &'a Trade>,
+    at: DateTime<Utc>,
+) -> I256 {
+    let day = at.date_naive();
+    trades
+        .into_iter()
+        .filter(|trade| trade.timestamp.date_naive() == day)
+        .fold(I256::zero(), |sum, trade| {
+            sum.saturating_add(trade.profit_loss)
+        })
+}
+
+/// Largest drop from a running peak of cumulative PnL, counted from zero at the start of the trades.
+fn max_drawdown<'a>(trades: impl IntoIterator<Item = &'a Trade>) -> U256 {
+    let mut cumulative = I256::zero();
+    let mut peak = I256::zero();
+    let mut drawdown = U256::zero();
+    for trade in trades {
+        cumulative = cumulative.saturating_add(trade.profit_loss);
+        peak = peak.max(cumulative);
+        drawdown = drawdown.max(peak.saturating_sub(cumulative).into_raw());
+    }
+    drawdown
+}
+
+/// Number of losing trades at the end of the sequence.
+fn consecutive_losses<'a>(trades: impl DoubleEndedIterator<Item = &'a Trade>) -> u32 {
+    trades
+        .rev()
+        .take_while(|trade| trade.profit_loss.is_negative())
+        .count() as u32
+}
+
+/// Evaluates the limits over trades in settlement order at time `now`.
+pub(crate) fn evaluate<'a>(
+    limits: &RiskLimits,
+    trades: impl DoubleEndedIterator<Item = &'a Trade> + Clone,
+    now: DateTime<Utc>,
+) -> Result<(), LimitBreach> {
+    let daily = daily_pnl(trades.clone(), now);
+    if daily.is_negative() && daily.unsigned_abs() >= limits.daily_loss_limit {
+        return Err(LimitBreach::DailyLoss {
+            loss: daily.unsigned_abs(),
+            limit: limits.daily_loss_limit,
+        });
+    }
+
+    if let Some(limit) = limits.max_drawdown {
+        let start = now - limits.drawdown_window;
+        let drawdown = max_drawdown(trades.clone().filter(|trade| trade.timestamp > start));
+        if drawdown >= limit {
+            return Err(LimitBreach::Drawdown { drawdown, limit });
+        }
+    }
+
+    if let Some(limit) = limits.max_consecutive_losses {
+        let count = consecutive_losses(trades);
+        if count >= limit {
+            return Err(LimitBreach::ConsecutiveLosses { count, limit });
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 1, hour, minute, 0).unwrap()
+    }
+
+    fn trades(sequence: &[(DateTime<Utc>, &str, i64)]) -> Vec<Trade> {
+        sequence
+            .iter()
+            .map(|&(timestamp, strategy, profit_loss)| Trade {
+                timestamp,
+                strategy: strategy.to_string(),
+                profit_loss: I256::from(profit_loss),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_daily_loss_nets_gains_of_the_same_utc_day() {
+        let limits = RiskLimits::new(U256::from(100));
+        let sequence = trades(&[
+            (at(0, 0) - TimeDelta::minutes(1), "sandwich", -500),
+            (at(1, 0), "sandwich", -80),
+            (at(2, 0), "arbitrage", 30),
+            (at(3, 0), "sandwich", -40),
+        ]);
+
+        // Yesterday's loss does not count, and today's gain offsets today's losses.
+        assert_eq!(daily_pnl(&sequence, at(4, 0)), I256::from(-90));
+        assert_eq!(evaluate(&limits, sequence.iter(), at(4, 0)), Ok(()));
+
+        let mut sequence = sequence;
+        sequence.extend(trades(&[(at(5, 0), "arbitrage", -10)]));
+        assert_eq!(
+            evaluate(&limits, sequence.iter(), at(5, 0)),
+            Err(LimitBreach::DailyLoss {
+                loss: U256::from(100),
+                limit: U256::from(100),
+            })
+        );
+        // The next UTC day starts from zero.
+        assert_eq!(
+            evaluate(&limits, sequence.iter(), at(5, 0) + TimeDelta::days(1)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_drawdown_is_measured_within_the_window() {
+        let limits =
+            RiskLimits::new(U256::MAX).with_max_drawdown(U256::from(100), TimeDelta::hours(2));
+        let sequence = trades(&[
+            (at(10, 0), "sandwich", -90),
+            (at(11, 0), "sandwich", 200),
+            (at(11, 30), "sandwich", -60),
+            (at(11, 45), "sandwich", 10),
+            (at(12, 30), "sandwich", -50),
+        ]);
+
+        // Peak of 200 after the first in-window trade, trough of 100 after the last.
+        assert_eq!(
+            evaluate(&limits, sequence.iter(), at(12, 30)),
+            Err(LimitBreach::Drawdown {
+                drawdown: U256::from(100),
+                limit: U256::from(100),
+            })
+        );
+        // Once the peak leaves the window the drop from it no longer counts.
+        assert_eq!(max_drawdown(&sequence[3..]), U256::from(50));
+        assert_eq!(evaluate(&limits, sequence.iter(), at(13, 40)), Ok(()));
+    }
+
+    #[test]
+    fn test_consecutive_losses_reset_on_a_gain() {
+        let limits = RiskLimits::new(U256::MAX).with_max_consecutive_losses(3);
+        let mut sequence = trades(&[
+            (at(1, 0), "sandwich", -1),
+            (at(2, 0), "sandwich", -1),
+            (at(3, 0), "arbitrage", 0),
+            (at(4, 0), "sandwich", -1),
+            (at(5, 0), "arbitrage", -1),
+        ]);
+        assert_eq!(evaluate(&limits, sequence.iter(), at(6, 0)), Ok(()));
+
+        sequence.extend(trades(&[(at(6, 0), "sandwich", -1)]));
+        assert_eq!(
+            evaluate(&limits, sequence.iter(), at(6, 0)),
+            Err(LimitBreach::ConsecutiveLosses { count: 3, limit: 3 })
+        );
+    }
+}
@@ -0,0 +1,319 @@
+// This module persists risk state so a restart does not forget the day's trades.
+// Records are appended as JSON lines and the file is periodically rewritten with only what is still needed.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use ethers::types::I256;
+use serde::{Deserialize, Serialize};
+
+/// Appends after which the store asks to be compacted.
+pub const DEFAULT_COMPACT_AFTER: usize = 10_000;
+
+/// Error types for the risk store.
+#[derive(Debug, thiserror::Error)]
+pub enum StoreError {
+    /// Reading or writing the store failed.
+    #[error("Risk store I/O failed: {0}")]
+    Io(#[from] io::Error),
+    /// A line of the store is not a valid record.
+    #[error("Invalid risk record on line {line}: {reason}")]
+    InvalidRecord { line: usize, reason: String },
+}
+
+/// A change to risk state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RiskRecord {
+    /// A realized trade with signed PnL in the quote token.
+    Trade {
+        at: DateTime<Utc>,
+        strategy: String,
+        profit_loss: I256,
+    },
+    /// Limits were reset to leave out the trades settled before `at`.
+    LimitsReset { at: DateTime<Utc> },
+}
+
+impl RiskRecord {
+    /// Time the record applies from.
+    pub fn at(&self) -> DateTime<Utc> {
+        match self {
+            Self::Trade { at, .. } | Self::LimitsReset { at } => *at,
+        }
+    }
+}
+
+/// A record as written to disk: times in unix milliseconds, amounts as decimal strings.
+#[derive(Serialize, Deserialize)]
+#[serde(
+    tag = "kind",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+enum Line {
+    Trade {
+        at: i64,
+        strategy: String,
+        profit_loss: String,
+    },
+    LimitsReset {
+        at: i64,
+    },
+}
+
+impl From<&RiskRecord> for Line {
+    fn from(record: &RiskRecord) -> Self {
+        match record {
+            RiskRecord::Trade {
+                at,
+                strategy,
+                profit_loss,
+            } => Self::Trade {
+                at: at.timestamp_millis(),
+                strategy: strategy.clone(),
+                profit_loss: profit_loss.to_string(),
+            },
+            RiskRecord::LimitsReset { at } => Self::LimitsReset {
+                at: at.timestamp_millis(),
+            },
+        }
+    }
+}
+
+impl TryFrom<Line> for RiskRecord {
+    type Error = String;
+
+    fn try_from(line: Line) -> Result<Self, String> {
+        let time = |at: i64| {
+            DateTime::from_timestamp_millis(at).ok_or_else(|| format!("invalid time {at}"))
+        };
+        Ok(match line {
+            Line::Trade {
+                at,
+                strategy,
+                profit_loss,
+            } => Self::Trade {
+                at: time(at)?,
+                strategy,
+                profit_loss: I256::from_dec_str(&profit_loss).map_err(|err| err.to_string())?,
+            },
+            Line::LimitsReset { at } => Self::LimitsReset { at: time(at)? },
+        })
+    }
+}
+
+/// Append-only file of risk records.
+///
+/// Every append is synced to disk before it returns. A line torn by a crash at the end of the file is dropped on
+/// open; invalid lines anywhere else are reported.
+#[derive(Debug)]
+pub struct RiskStore {
+    path: PathBuf,
+    file: File,
+    appended: usize,
+    compact_after: usize,
+}
+
+impl RiskStore {
+    /// Opens or creates the store at `path`.
+    ///
+    /// Returns the store and its records in append order, or Err(StoreError) if it cannot be read.
+    pub fn open(path: impl AsRef<Path>) -> Result<(Self, Vec<RiskRecord>), StoreError> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = File::options()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&path)?;
+
+        let mut text = String::new();
+        file.read_to_string(&mut text)?;
+        let (records, torn) = parse(&text)?;
+
+        // Records appended from now on must start on a line of their own.
+        if torn {
+            let complete = text.rfind('\n').map_or(0, |end| end + 1);
+            file.set_len(complete as u64)?;
+        } else if !text.is_empty() && !text.ends_with('\n') {
+            file.write_all(b"\n")?;
+        }
+
+        let store = Self {
+            path,
+            file,
+            appended: 0,
+            compact_after: DEFAULT_COMPACT_AFTER,
+        };
+        Ok((store, records))
+    }
+
+    /// Sets the number of appends after which `needs_compaction` returns true.
+    pub fn with_compact_after(mut self, appends: usize) -> Self {
+        self.compact_after = appends;
+        self
+    }
+
+    /// Path of the store.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends a record.
+    pub fn append(&mut self, record: &RiskRecord) -> Result<(), StoreError> {
+        let mut line = serde_json::to_vec(&Line::from(record)).expect("risk records serialize");
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.file.sync_data()?;
+        self.appended += 1;
+        Ok(())
+    }
+
+    /// Whether enough records were appended since the last compaction to rewrite the file.
+    pub fn needs_compaction(&self) -> bool {
+        self.appended >= self.compact_after
+    }
+
+    /// Replaces the content of the store with `records`.
+    ///
+    /// The records are written to a temporary file that then replaces the store, so a crash leaves either
+    /// the old or the new content.
+    pub fn compact(&mut self, records: &[RiskRecord]) -> Result<(), StoreError> {
+        let temporary = self.path.with_extension("compact");
+        {
+            let mut file = io::BufWriter::new(File::create(&temporary)?);
+            for record in records {
+                serde_json::to_writer(&mut file, &Line::from(record)).map_err(io::Error::from)?;
+                file.write_all(b"\n")?;
+            }
+            file.into_inner()
+                .map_err(|err| err.into_error())?
+                .sync_all()?;
+        }
+        fs::rename(&temporary, &self.path)?;
+        sync_parent(&self.path)?;
+
+        self.file = File::options().append(true).open(&self.path)?;
+        self.appended = 0;
+        Ok(())
+    }
+}
+
+/// Syncs the directory holding `path`, which makes a rename into it durable.
+fn sync_parent(path: &Path) -> io::Result<()> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    File::open(parent)?.sync_all()
+}
+
+/// Parses the records of a store, and whether its last line was torn and skipped.
+fn parse(text: &str) -> Result<(Vec<RiskRecord>, bool), StoreError> {
+    let lines: Vec<&str> = text.lines().collect();
+    let unterminated = !text.ends_with('\n');
+    let mut torn = false;
+
+    let mut records = Vec::with_capacity(lines.len());
+    for (index, text) in lines.iter().enumerate() {
+        if text.trim().is_empty() {
+            continue;
+        }
+        let record = serde_json::from_str::<Line>(text)
+            .map_err(|err| err.to_string())
+            .and_then(RiskRecord::try_from);
+        match record {
+            Ok(record) => records.push(record),
+            Err(_) if unterminated && index + 1 == lines.len() => {
+                log::warn!("Dropped a torn risk record at the end of the store");
+                torn = true;
+            }
+            Err(reason) => {
+                return Err(StoreError::InvalidRecord {
+                    line: index + 1,
+                    reason,
+                })
+            }
+        }
+    }
+    Ok((records, torn))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn trade(second: u32, profit_loss: i64) -> RiskRecord {
+        RiskRecord::Trade {
+            at: Utc.with_ymd_and_hms(2024, 3, 1, 23, 59, second).unwrap(),
+            strategy: "sandwich".to_string(),
+            profit_loss: I256::from(profit_loss),
+        }
+    }
+
+    #[test]
+    fn test_records_survive_reopen_and_compaction() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("risk.jsonl");
+        let loss = RiskRecord::Trade {
+            at: Utc.with_ymd_and_hms(2024, 3, 2, 0, 0, 0).unwrap(),
+            strategy: "arbitrage".to_string(),
+            profit_loss: I256::MIN,
+        };
+        let reset = RiskRecord::LimitsReset {
+            at: Utc.with_ymd_and_hms(2024, 3, 2, 0, 0, 1).unwrap(),
+        };
+
+        let (mut store, records) = RiskStore::open(&path).unwrap();
+        assert!(records.is_empty());
+        let mut store_records = vec![trade(1, -100), trade(2, 50), loss, reset];
+        for record in &store_records {
+            store.append(record).unwrap();
+        }
+        drop(store);
+
+        let (store, records) = RiskStore::open(&path).unwrap();
+        assert_eq!(records, store_records);
+
+        let mut store = store.with_compact_after(1);
+        assert!(!store.needs_compaction());
+        store.append(&trade(3, -1)).unwrap();
+        assert!(store.needs_compaction());
+        store_records.drain(..2);
+        store.compact(&store_records).unwrap();
+        assert!(!store.needs_compaction());
+        store.append(&trade(4, 7)).unwrap();
+
+        store_records.push(trade(4, 7));
+        assert_eq!(RiskStore::open(&path).unwrap().1, store_records);
+    }
+
+    #[test]
+    fn test_torn_tail_is_dropped_but_other_invalid_lines_are_not() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("risk.jsonl");
+        let (mut store, _) = RiskStore::open(&path).unwrap();
+        store.append(&trade(1, -100)).unwrap();
+        drop(store);
+        let mut text = fs::read_to_string(&path).unwrap();
+        text.push_str("{\"kind\":\"trade\",\"at\":17");
+        fs::write(&path, &text).unwrap();
+
+        let (mut store, records) = RiskStore::open(&path).unwrap();
+        assert_eq!(records, vec![trade(1, -100)]);
+        store.append(&trade(2, 5)).unwrap();
+        drop(store);
+        assert_eq!(
+            RiskStore::open(&path).unwrap().1,
+            vec![trade(1, -100), trade(2, 5)]
+        );
+
+        fs::write(&path, format!("not json\n{text}\n")).unwrap();
+        assert!(matches!(
+            RiskStore::open(&path),
+            Err(StoreError::InvalidRecord { line: 1, .. })
+        ));
+    }
+}
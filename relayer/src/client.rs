@@ -0,0 +1,440 @@
+//! JSON-RPC client authenticating requests with the bundle-signing key or a static header.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use ethers::{
+    signers::{LocalWallet, Signer},
+    types::{Address, H256, U64},
+    utils::keccak256,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::error::RelayError;
+use crate::types::{
+    BundleRequest, BundleStats, CallBundleRequest, CallBundleResponse, SendBundleResponse,
+};
+
+/// Header carrying the signature of the request body.
+pub const SIGNATURE_HEADER: &str = "X-Flashbots-Signature";
+
+/// How requests to a relay are authenticated.
+///
+/// Configuration names the method with a `type` of `flashbots`, `header` or `none`. A configured `flashbots`
+/// method is `BundleSigner`, so the key never appears in configuration files.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RelayAuth {
+    /// Sign every body with the bundle-signing key into the `X-Flashbots-Signature` header.
+    ///
+    /// The key identifies the searcher to the relay for reputation. It should not hold funds, see
+    /// `SecureVault::get_bundle_signer`.
+    #[serde(skip_deserializing)]
+    Flashbots(LocalWallet),
+    /// Sign like `Flashbots` with a key given later by `with_bundle_signer`; requests fail until it is.
+    #[serde(rename = "flashbots")]
+    BundleSigner,
+    /// Send a static header, such as an API key.
+    Header {
+        /// Header name.
+        name: String,
+        /// Header value.
+        value: String,
+    },
+    /// Send unauthenticated requests.
+    None,
+}
+
+impl RelayAuth {
+    /// Resolves `BundleSigner` to signing with `signer`, leaving other methods as they are.
+    pub fn with_bundle_signer(self, signer: &LocalWallet) -> Self {
+        match self {
+            Self::BundleSigner => Self::Flashbots(signer.clone()),
+            auth => auth,
+        }
+    }
+}
+
+/// JSON-RPC client of a Flashbots-compatible relay.
+#[derive(Debug)]
+pub struct RelayClient {
+    http: reqwest::Client,
+    url: String,
+    auth: RelayAuth,
+    next_id: AtomicU64,
+}
+
+#[derive(Deserialize)]
+struct RpcResponse {
+    #[serde(default)]
+    result: Option<Value>,
+    #[serde(default)]
+    error: Option<RpcErrorObject>,
+}
+
+#[derive(Deserialize)]
+struct RpcErrorObject {
+    code: i64,
+    message: String,
+}
+
+impl RelayClient {
+    /// Creates a client of the relay at `url` signing with `signer`.
+    pub fn new(url: impl Into<String>, signer: LocalWallet) -> Self {
+        Self::with_auth(url, RelayAuth::Flashbots(signer))
+    }
+
+    /// Creates a client of the relay at `url` authenticating with `auth`.
+    pub fn with_auth(url: impl Into<String>, auth: RelayAuth) -> Self {
+        Self::with_http_client(reqwest::Client::new(), url, auth)
+    }
+
+    /// Creates a client sending requests through an existing HTTP client.
+    pub fn with_http_client(
+        http: reqwest::Client,
+        url: impl Into<String>,
+        auth: RelayAuth,
+    ) -> Self {
+        Self {
+            http,
+            url: url.into(),
+            auth,
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// Returns the relay URL.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Returns the address of the bundle-signing key, None if requests are not signed.
+    pub fn signer_address(&self) -> Option<Address> {
+        match &self.auth {
+            RelayAuth::Flashbots(signer) => Some(signer.address()),
+            _ => None,
+        }
+    }
+
+    /// Submits a bundle for inclusion in its target block.
+    pub async fn send_bundle(
+        &self,
+        bundle: &BundleRequest,
+    ) -> Result<SendBundleResponse, RelayError> {
+        self.request("eth_sendBundle", [bundle]).await
+    }
+
+    /// Simulates a bundle on top of a block without submitting it.
+    pub async fn call_bundle(
+        &self,
+        bundle: &CallBundleRequest,
+    ) -> Result<CallBundleResponse, RelayError> {
+        self.request("eth_callBundle", [bundle]).await
+    }
+
+    /// Cancels the bundles submitted with a replacement id.
+    pub async fn cancel_bundle(&self, replacement_uuid: &str) -> Result<(), RelayError> {
+        self.request::<_, Value>(
+            "eth_cancelBundle",
+            [json!({ "replacementUuid": replacement_uuid })],
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Returns the relay and builder statistics of a bundle submitted for `block_number`.
+    pub async fn get_bundle_stats(
+        &self,
+        bundle_hash: H256,
+        block_number: U64,
+    ) -> Result<BundleStats, RelayError> {
+        self.request(
+            "flashbots_getBundleStatsV2",
+            [json!({ "bundleHash": bundle_hash, "blockNumber": block_number })],
+        )
+        .await
+    }
+
+    async fn request<P, R>(&self, method: &str, params: P) -> Result<R, RelayError>
+    where
+        P: Serialize,
+        R: DeserializeOwned,
+    {
+        let body = serde_json::to_string(&json!({
+            "jsonrpc": "2.0",
+            "id": self.next_id.fetch_add(1, Ordering::Relaxed),
+            "method": method,
+            "params": params,
+        }))?;
+
+        let mut request = self
+            .http
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json");
+        request = match &self.auth {
+            RelayAuth::Flashbots(signer) => {
+                request.header(SIGNATURE_HEADER, sign_body(signer, &body).await?)
+            }
+            RelayAuth::BundleSigner => return Err(RelayError::MissingSigner),
+            RelayAuth::Header { name, value } => request.header(name.as_str(), value.as_str()),
+            RelayAuth::None => request,
+        };
+        let response = request.body(body).send().await?;
+        let status = response.status();
+        let text = response.text().await?;
+
+        // Relays report JSON-RPC errors with 4xx statuses, so prefer the error object when there is one.
+        let response: RpcResponse = match serde_json::from_str(&text) {
+            Ok(response) => response,
+            Err(_) if !status.is_success() => {
+                return Err(RelayError::Status {
+                    status: status.as_u16(),
+                    body: text,
+                })
+            }
+            Err(err) => return Err(err.into()),
+        };
+        if let Some(error) = response.error {
+            return Err(RelayError::Rpc {
+                code: error.code,
+                message: error.message,
+            });
+        }
+        if !status.is_success() {
+            return Err(RelayError::Status {
+                status: status.as_u16(),
+                body: text,
+            });
+        }
+
+        Ok(serde_json::from_value(
+            response.result.unwrap_or(Value::Null),
+        )?)
+    }
+}
+
+/// Signs a request body, returning the `X-Flashbots-Signature` header value.
+///
+/// The header is `address:signature`, where the signature is an EIP-191 personal signature of the
+/// hex-encoded keccak256 hash of the body.
+pub async fn sign_body(signer: &LocalWallet, body: &str) -> Result<String, RelayError> {
+    let message = format!("{:?}", H256::from(keccak256(body.as_bytes())));
+    let signature = signer.sign_message(message).await?;
+    Ok(format!("{:?}:0x{}", signer.address(), signature))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::{Bytes, Signature};
+    use mockito::Matcher;
+
+    fn signer() -> LocalWallet {
+        "0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318"
+            .parse()
+            .unwrap()
+    }
+
+    fn signature_matcher() -> Matcher {
+        Matcher::Regex(format!("^{:?}:0x[0-9a-f]{{130}}$", signer().address()))
+    }
+
+    #[tokio::test]
+    async fn test_sign_body_recovers_to_signer() {
+        let body = r#"{"jsonrpc":"2.0","id":1,"method":"eth_sendBundle","params":[]}"#;
+
+        let header = sign_body(&signer(), body).await.unwrap();
+        let (address, signature) = header.split_once(':').unwrap();
+        let signature: Signature = signature.parse().unwrap();
+        let message = format!("{:?}", H256::from(keccak256(body.as_bytes())));
+
+        assert_eq!(address, format!("{:?}", signer().address()));
+        assert_eq!(signature.recover(message).unwrap(), signer().address());
+    }
+
+    #[tokio::test]
+    async fn test_send_bundle() {
+        let mut server = mockito::Server::new_async().await;
+        let bundle_hash = H256::repeat_byte(0xab);
+        let mock = server
+            .mock("POST", "/")
+            .match_header("x-flashbots-signature", signature_matcher())
+            .match_body(Matcher::PartialJson(json!({
+                "method": "eth_sendBundle",
+                "params": [{ "txs": ["0x02f8"], "blockNumber": "0x10" }],
+            })))
+            .with_body(
+                json!({ "jsonrpc": "2.0", "id": 1, "result": { "bundleHash": bundle_hash } })
+                    .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let client = RelayClient::new(server.url(), signer());
+        let bundle = BundleRequest::new(vec![Bytes::from(vec![0x02, 0xf8])], U64::from(0x10));
+        let response = client.send_bundle(&bundle).await.unwrap();
+
+        assert_eq!(response.bundle_hash, bundle_hash);
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_call_bundle() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/")
+            .match_header("x-flashbots-signature", signature_matcher())
+            .match_body(Matcher::PartialJson(json!({
+                "method": "eth_callBundle",
+                "params": [{ "blockNumber": "0x10", "stateBlockNumber": "latest" }],
+            })))
+            .with_body(
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": 1,
+                    "result": {
+                        "bundleGasPrice": "1000",
+                        "bundleHash": H256::repeat_byte(1),
+                        "coinbaseDiff": "21000000",
+                        "ethSentToCoinbase": "0",
+                        "gasFees": "21000000",
+                        "results": [{
+                            "coinbaseDiff": "21000000",
+                            "ethSentToCoinbase": "0",
+                            "fromAddress": Address::repeat_byte(2),
+                            "gasFees": "21000000",
+                            "gasPrice": "1000",
+                            "gasUsed": 21000,
+                            "toAddress": Address::repeat_byte(3),
+                            "txHash": H256::repeat_byte(4),
+                            "value": "0x"
+                        }],
+                        "stateBlockNumber": 15,
+                        "totalGasUsed": 21000
+                    }
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let client = RelayClient::new(server.url(), signer());
+        let request = CallBundleRequest::new(vec![Bytes::from(vec![0x02])], U64::from(0x10));
+        let response = client.call_bundle(&request).await.unwrap();
+
+        assert_eq!(response.total_gas_used, 21_000);
+        assert!(response.first_failure().is_none());
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_cancel_bundle_and_stats() {
+        let mut server = mockito::Server::new_async().await;
+        let cancel = server
+            .mock("POST", "/")
+            .match_body(Matcher::PartialJson(json!({
+                "method": "eth_cancelBundle",
+                "params": [{ "replacementUuid": "f3b9a2c4" }],
+            })))
+            .with_body(r#"{"jsonrpc":"2.0","id":1,"result":null}"#)
+            .create_async()
+            .await;
+        let stats = server
+            .mock("POST", "/")
+            .match_body(Matcher::PartialJson(json!({
+                "method": "flashbots_getBundleStatsV2",
+                "params": [{ "bundleHash": H256::repeat_byte(1), "blockNumber": "0x10" }],
+            })))
+            .with_body(
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": 2,
+                    "result": {
+                        "isHighPriority": true,
+                        "isSimulated": true,
+                        "simulatedAt": "2022-10-06T21:36:06.317Z",
+                        "receivedAt": "2022-10-06T21:36:06.250Z",
+                        "consideredByBuildersAt": [
+                            { "pubkey": "0x81babe", "timestamp": "2022-10-06T21:36:06.343Z" }
+                        ],
+                        "sealedByBuildersAt": []
+                    }
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let client = RelayClient::new(server.url(), signer());
+        client.cancel_bundle("f3b9a2c4").await.unwrap();
+        let response = client
+            .get_bundle_stats(H256::repeat_byte(1), U64::from(0x10))
+            .await
+            .unwrap();
+
+        assert!(response.is_simulated);
+        assert_eq!(response.considered_by_builders_at.len(), 1);
+        assert!(response.sealed_by_builders_at.is_empty());
+        cancel.assert_async().await;
+        stats.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_header_auth() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/")
+            .match_header("x-api-key", "secret")
+            .match_header("x-flashbots-signature", Matcher::Missing)
+            .with_body(r#"{"jsonrpc":"2.0","id":1,"result":null}"#)
+            .create_async()
+            .await;
+
+        let client = RelayClient::with_auth(
+            server.url(),
+            RelayAuth::Header {
+                name: "X-Api-Key".to_string(),
+                value: "secret".to_string(),
+            },
+        );
+        client.cancel_bundle("f3b9a2c4").await.unwrap();
+
+        assert_eq!(client.signer_address(), None);
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_rpc_and_status_errors() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/")
+            .match_body(Matcher::PartialJson(json!({ "method": "eth_sendBundle" })))
+            .with_status(400)
+            .with_body(
+                r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32000,"message":"unable to decode txs"}}"#,
+            )
+            .create_async()
+            .await;
+        server
+            .mock("POST", "/")
+            .match_body(Matcher::PartialJson(
+                json!({ "method": "eth_cancelBundle" }),
+            ))
+            .with_status(503)
+            .with_body("upstream unavailable")
+            .create_async()
+            .await;
+
+        let client = RelayClient::new(server.url(), signer());
+        let bundle = BundleRequest::new(vec![], U64::from(1));
+
+        assert!(matches!(
+            client.send_bundle(&bundle).await,
+            Err(RelayError::Rpc { code: -32000, .. })
+        ));
+        assert!(matches!(
+            client.cancel_bundle("f3b9a2c4").await,
+            Err(RelayError::Status { status: 503, .. })
+        ));
+    }
+}
@@ -0,0 +1,36 @@
+//! Errors of relay requests.
+
+use thiserror::Error;
+
+/// Errors returned by a relay.
+#[derive(Error, Debug)]
+pub enum RelayError {
+    /// The request could not be sent or its response could not be read.
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+    /// The relay answered with a non-success status and no JSON-RPC error.
+    #[error("Relay returned status {status}: {body}")]
+    Status {
+        /// HTTP status code.
+        status: u16,
+        /// Response body.
+        body: String,
+    },
+    /// The relay answered with a JSON-RPC error.
+    #[error("JSON-RPC error {code}: {message}")]
+    Rpc {
+        /// JSON-RPC error code.
+        code: i64,
+        /// JSON-RPC error message.
+        message: String,
+    },
+    /// The request or response is not valid JSON for the method.
+    #[error("Invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    /// The relay is configured to sign with the bundle-signing key, but none was given.
+    #[error("No bundle-signing key was given")]
+    MissingSigner,
+    /// The request body could not be signed.
+    #[error("Signing error: {0}")]
+    Signing(#[from] ethers::signers::WalletError),
+}
@@ -0,0 +1,443 @@
+//! Submission of one bundle to several builders in parallel, with per-builder outcome statistics.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use ethers::{signers::LocalWallet, types::H256};
+use futures_util::future::join_all;
+use serde::{Deserialize, Deserializer};
+
+use crate::client::{RelayAuth, RelayClient};
+use crate::error::RelayError;
+use crate::types::BundleRequest;
+
+/// Time a builder has to answer before its submission counts as timed out.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Maximum number of requests a builder accepts within a window.
+///
+/// Configured as `{ "max_requests": 10, "per_ms": 1000 }`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct RateLimit {
+    /// Requests allowed per window.
+    pub max_requests: usize,
+    /// Length of the window.
+    #[serde(rename = "per_ms", deserialize_with = "millis")]
+    pub per: Duration,
+}
+
+/// Endpoint and submission policy of a builder.
+///
+/// Configured as `{ "name": ..., "url": ..., "auth": { "type": "flashbots" }, "timeout_ms": 2000,
+/// "rate_limit": ... }`, where the timeout and rate limit are optional.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BuilderConfig {
+    /// Name the outcomes are recorded under.
+    pub name: String,
+    /// JSON-RPC endpoint.
+    pub url: String,
+    /// How requests are authenticated.
+    pub auth: RelayAuth,
+    /// Time the builder has to answer.
+    #[serde(
+        rename = "timeout_ms",
+        default = "default_timeout",
+        deserialize_with = "millis"
+    )]
+    pub timeout: Duration,
+    /// Requests allowed by the builder, unlimited if None.
+    #[serde(default)]
+    pub rate_limit: Option<RateLimit>,
+}
+
+impl BuilderConfig {
+    /// Creates a builder with the default timeout and no rate limit.
+    pub fn new(name: impl Into<String>, url: impl Into<String>, auth: RelayAuth) -> Self {
+        Self {
+            name: name.into(),
+            url: url.into(),
+            auth,
+            timeout: DEFAULT_TIMEOUT,
+            rate_limit: None,
+        }
+    }
+
+    /// Sets the time the builder has to answer.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Limits the requests sent to the builder.
+    pub fn with_rate_limit(mut self, max_requests: usize, per: Duration) -> Self {
+        self.rate_limit = Some(RateLimit { max_requests, per });
+        self
+    }
+
+    /// Signs with `signer` if the builder is configured to use the bundle-signing key.
+    pub fn with_bundle_signer(mut self, signer: &LocalWallet) -> Self {
+        self.auth = self.auth.with_bundle_signer(signer);
+        self
+    }
+}
+
+fn default_timeout() -> Duration {
+    DEFAULT_TIMEOUT
+}
+
+fn millis<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    u64::deserialize(deserializer).map(Duration::from_millis)
+}
+
+/// Outcome of a bundle submission to one builder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubmissionOutcome {
+    /// The builder accepted the bundle.
+    Accepted {
+        /// Bundle hash returned by the builder.
+        bundle_hash: H256,
+    },
+    /// The builder rejected the bundle or the request failed.
+    Rejected {
+        /// Error returned by the builder or the transport.
+        reason: String,
+    },
+    /// The builder did not answer within its timeout.
+    TimedOut,
+    /// The bundle was not sent because the builder's rate limit was reached.
+    RateLimited,
+}
+
+/// Outcome of a bundle submission, by builder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuilderResult {
+    /// Name of the builder.
+    pub builder: String,
+    /// Outcome of the submission.
+    pub outcome: SubmissionOutcome,
+    /// Time from sending the request to the outcome, zero if it was not sent.
+    pub latency: Duration,
+}
+
+/// Outcome counts of a builder.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BuilderStats {
+    /// Bundles accepted.
+    pub accepted: u64,
+    /// Bundles rejected.
+    pub rejected: u64,
+    /// Submissions that timed out.
+    pub timed_out: u64,
+    /// Submissions skipped by the rate limit.
+    pub rate_limited: u64,
+    /// Accepted bundles that landed in a block built by the builder.
+    pub landed: u64,
+    /// Reason of the last rejection.
+    pub last_rejection: Option<String>,
+}
+
+impl BuilderStats {
+    /// Returns the share of accepted bundles that landed, None if none were accepted.
+    pub fn landing_rate(&self) -> Option<f64> {
+        (self.accepted > 0).then(|| self.landed as f64 / self.accepted as f64)
+    }
+
+    fn record(&mut self, outcome: &SubmissionOutcome) {
+        match outcome {
+            SubmissionOutcome::Accepted { .. } => self.accepted += 1,
+            SubmissionOutcome::Rejected { reason } => {
+                self.rejected += 1;
+                self.last_rejection = Some(reason.clone());
+            }
+            SubmissionOutcome::TimedOut => self.timed_out += 1,
+            SubmissionOutcome::RateLimited => self.rate_limited += 1,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Builder {
+    name: String,
+    client: RelayClient,
+    timeout: Duration,
+    rate_limit: Option<RateLimit>,
+    sent_at: Mutex<VecDeque<Instant>>,
+}
+
+impl Builder {
+    /// Takes a slot of the rate limit window, returning false if there is none left.
+    fn try_acquire(&self, now: Instant) -> bool {
+        let Some(limit) = self.rate_limit else {
+            return true;
+        };
+        let mut sent_at = self.sent_at.lock().unwrap();
+        while sent_at
+            .front()
+            .is_some_and(|sent| now.duration_since(*sent) >= limit.per)
+        {
+            sent_at.pop_front();
+        }
+        if sent_at.len() >= limit.max_requests {
+            return false;
+        }
+        sent_at.push_back(now);
+        true
+    }
+
+    async fn send_bundle(&self, bundle: &BundleRequest) -> BuilderResult {
+        let start = Instant::now();
+        // A bundle targets a single block, so a builder at its limit is skipped rather than waited for.
+        let outcome = if !self.try_acquire(start) {
+            SubmissionOutcome::RateLimited
+        } else {
+            match tokio::time::timeout(self.timeout, self.client.send_bundle(bundle)).await {
+                Ok(Ok(response)) => SubmissionOutcome::Accepted {
+                    bundle_hash: response.bundle_hash,
+                },
+                Ok(Err(err)) => SubmissionOutcome::Rejected {
+                    reason: rejection_reason(err),
+                },
+                Err(_) => SubmissionOutcome::TimedOut,
+            }
+        };
+
+        BuilderResult {
+            builder: self.name.clone(),
+            latency: match outcome {
+                SubmissionOutcome::RateLimited => Duration::ZERO,
+                _ => start.elapsed(),
+            },
+            outcome,
+        }
+    }
+}
+
+fn rejection_reason(err: RelayError) -> String {
+    match err {
+        RelayError::Rpc { message, .. } => message,
+        err => err.to_string(),
+    }
+}
+
+/// Sends bundles to a list of builders in parallel and records the outcome of each submission.
+#[derive(Debug)]
+pub struct BundleFanout {
+    builders: Vec<Builder>,
+    stats: Mutex<HashMap<String, BuilderStats>>,
+}
+
+impl BundleFanout {
+    /// Creates a fan-out to the configured builders.
+    pub fn new(configs: Vec<BuilderConfig>) -> Self {
+        let builders = configs
+            .into_iter()
+            .map(|config| Builder {
+                client: RelayClient::with_auth(config.url, config.auth),
+                name: config.name,
+                timeout: config.timeout,
+                rate_limit: config.rate_limit,
+                sent_at: Mutex::new(VecDeque::new()),
+            })
+            .collect();
+
+        Self {
+            builders,
+            stats: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the names of the builders.
+    pub fn builders(&self) -> impl Iterator<Item = &str> {
+        self.builders.iter().map(|builder| builder.name.as_str())
+    }
+
+    /// Sends a bundle to every builder, returning the outcomes in the configured order.
+    pub async fn send_bundle(&self, bundle: &BundleRequest) -> Vec<BuilderResult> {
+        let results = join_all(
+            self.builders
+                .iter()
+                .map(|builder| builder.send_bundle(bundle)),
+        )
+        .await;
+
+        let mut stats = self.stats.lock().unwrap();
+        for result in &results {
+            stats
+                .entry(result.builder.clone())
+                .or_default()
+                .record(&result.outcome);
+        }
+        results
+    }
+
+    /// Records that a bundle accepted by `builder` landed in a block it built.
+    pub fn record_landed(&self, builder: &str) {
+        self.stats
+            .lock()
+            .unwrap()
+            .entry(builder.to_string())
+            .or_default()
+            .landed += 1;
+    }
+
+    /// Returns the outcome counts of a builder.
+    pub fn stats(&self, builder: &str) -> Option<BuilderStats> {
+        self.stats.lock().unwrap().get(builder).cloned()
+    }
+
+    /// Returns the outcome counts of every builder that was sent a bundle.
+    pub fn all_stats(&self) -> HashMap<String, BuilderStats> {
+        self.stats.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::{Bytes, U64};
+    use serde_json::json;
+
+    fn bundle() -> BundleRequest {
+        BundleRequest::new(vec![Bytes::from(vec![0x02, 0xf8])], U64::from(0x10))
+    }
+
+    fn signer() -> LocalWallet {
+        "0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318"
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_builders_deserialize_from_config() {
+        let configs: Vec<BuilderConfig> = serde_json::from_value(json!([
+            {
+                "name": "flashbots",
+                "url": "https://relay.example",
+                "auth": { "type": "flashbots" },
+                "rate_limit": { "max_requests": 10, "per_ms": 1000 },
+            },
+            {
+                "name": "keyed",
+                "url": "https://builder.example",
+                "auth": { "type": "header", "name": "X-Api-Key", "value": "secret" },
+                "timeout_ms": 500,
+            },
+        ]))
+        .unwrap();
+
+        assert!(matches!(configs[0].auth, RelayAuth::BundleSigner));
+        assert_eq!(configs[0].timeout, DEFAULT_TIMEOUT);
+        assert_eq!(
+            configs[0].rate_limit,
+            Some(RateLimit {
+                max_requests: 10,
+                per: Duration::from_secs(1),
+            })
+        );
+        assert_eq!(configs[1].timeout, Duration::from_millis(500));
+        assert!(matches!(configs[1].auth, RelayAuth::Header { .. }));
+
+        let signed = configs[0].clone().with_bundle_signer(&signer());
+        assert!(matches!(signed.auth, RelayAuth::Flashbots(_)));
+    }
+
+    #[tokio::test]
+    async fn test_fanout_records_each_builder() {
+        let mut accepting = mockito::Server::new_async().await;
+        accepting
+            .mock("POST", "/")
+            .match_header("x-flashbots-signature", mockito::Matcher::Any)
+            .with_body(
+                json!({ "jsonrpc": "2.0", "id": 1, "result": { "bundleHash": H256::repeat_byte(1) } })
+                    .to_string(),
+            )
+            .create_async()
+            .await;
+        let mut rejecting = mockito::Server::new_async().await;
+        rejecting
+            .mock("POST", "/")
+            .with_status(400)
+            .with_body(r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32000,"message":"bundle underpriced"}}"#)
+            .create_async()
+            .await;
+        // Connections are queued by the listener but never answered.
+        let silent = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+
+        let fanout = BundleFanout::new(vec![
+            BuilderConfig::new("flashbots", accepting.url(), RelayAuth::Flashbots(signer())),
+            BuilderConfig::new("rejecting", rejecting.url(), RelayAuth::None),
+            BuilderConfig::new(
+                "silent",
+                format!("http://{}", silent.local_addr().unwrap()),
+                RelayAuth::None,
+            )
+            .with_timeout(Duration::from_millis(100)),
+        ]);
+        let results = fanout.send_bundle(&bundle()).await;
+
+        assert_eq!(
+            results
+                .iter()
+                .map(|result| (result.builder.as_str(), result.outcome.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    "flashbots",
+                    SubmissionOutcome::Accepted {
+                        bundle_hash: H256::repeat_byte(1)
+                    }
+                ),
+                (
+                    "rejecting",
+                    SubmissionOutcome::Rejected {
+                        reason: "bundle underpriced".to_string()
+                    }
+                ),
+                ("silent", SubmissionOutcome::TimedOut),
+            ]
+        );
+
+        fanout.record_landed("flashbots");
+        let stats = fanout.stats("flashbots").unwrap();
+        assert_eq!(stats.accepted, 1);
+        assert_eq!(stats.landing_rate(), Some(1.0));
+        assert_eq!(
+            fanout.stats("rejecting").unwrap().last_rejection.as_deref(),
+            Some("bundle underpriced")
+        );
+        assert_eq!(fanout.stats("silent").unwrap().timed_out, 1);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_builder_is_skipped() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/")
+            .with_body(
+                json!({ "jsonrpc": "2.0", "id": 1, "result": { "bundleHash": H256::repeat_byte(1) } })
+                    .to_string(),
+            )
+            .expect(1)
+            .create_async()
+            .await;
+
+        let fanout = BundleFanout::new(vec![BuilderConfig::new(
+            "limited",
+            server.url(),
+            RelayAuth::None,
+        )
+        .with_rate_limit(1, Duration::from_secs(60))]);
+        fanout.send_bundle(&bundle()).await;
+        let results = fanout.send_bundle(&bundle()).await;
+
+        assert_eq!(results[0].outcome, SubmissionOutcome::RateLimited);
+        assert_eq!(results[0].latency, Duration::ZERO);
+        let stats = fanout.stats("limited").unwrap();
+        assert_eq!((stats.accepted, stats.rate_limited), (1, 1));
+        assert_eq!(stats.landing_rate(), Some(0.0));
+        mock.assert_async().await;
+    }
+}
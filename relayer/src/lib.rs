@@ -0,0 +1,19 @@
+//! Bundle relay client for Flashbots-compatible builders.
+//!
+//! Provides:
+//! - Signed JSON-RPC requests with the `X-Flashbots-Signature` header
+//! - Bundle submission, simulation and cancellation
+//! - Parallel submission to several builders with per-builder outcomes
+//! - Bundle inclusion statistics
+
+#![warn(missing_docs)]
+#![forbid(unsafe_code)]
+
+pub mod client;
+pub mod error;
+pub mod fanout;
+pub mod types;
+
+pub use client::{RelayAuth, RelayClient};
+pub use error::RelayError;
+pub use fanout::{BuilderConfig, BundleFanout};
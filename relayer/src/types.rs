@@ -0,0 +1,293 @@
+//! Request and response types of the Flashbots bundle JSON-RPC methods.
+//! Wei amounts in `eth_callBundle` results are decimal strings, so they are parsed with `decimal_u256`.
+
+use ethers::types::{Address, BlockNumber, Bytes, H256, U256, U64};
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// Parameters of `eth_sendBundle`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleRequest {
+    /// Signed raw transactions, executed in order.
+    pub txs: Vec<Bytes>,
+    /// Block the bundle targets.
+    pub block_number: U64,
+    /// Earliest block timestamp the bundle is valid for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_timestamp: Option<u64>,
+    /// Latest block timestamp the bundle is valid for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_timestamp: Option<u64>,
+    /// Hashes of the transactions allowed to revert.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reverting_tx_hashes: Vec<H256>,
+    /// Id that lets the bundle be replaced or cancelled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replacement_uuid: Option<String>,
+}
+
+impl BundleRequest {
+    /// Creates a bundle of signed raw transactions targeting a block.
+    pub fn new(txs: Vec<Bytes>, block_number: U64) -> Self {
+        Self {
+            txs,
+            block_number,
+            min_timestamp: None,
+            max_timestamp: None,
+            reverting_tx_hashes: Vec::new(),
+            replacement_uuid: None,
+        }
+    }
+
+    /// Restricts the bundle to blocks with timestamps in [min, max].
+    pub fn with_timestamps(mut self, min_timestamp: u64, max_timestamp: u64) -> Self {
+        self.min_timestamp = Some(min_timestamp);
+        self.max_timestamp = Some(max_timestamp);
+        self
+    }
+
+    /// Allows the transactions with these hashes to revert without invalidating the bundle.
+    pub fn with_reverting_tx_hashes(mut self, hashes: Vec<H256>) -> Self {
+        self.reverting_tx_hashes = hashes;
+        self
+    }
+
+    /// Sets the id used to replace or cancel the bundle.
+    pub fn with_replacement_uuid(mut self, uuid: impl Into<String>) -> Self {
+        self.replacement_uuid = Some(uuid.into());
+        self
+    }
+}
+
+/// Result of `eth_sendBundle`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SendBundleResponse {
+    /// Hash identifying the bundle at the relay.
+    pub bundle_hash: H256,
+}
+
+/// Parameters of `eth_callBundle`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallBundleRequest {
+    /// Signed raw transactions, executed in order.
+    pub txs: Vec<Bytes>,
+    /// Block number the bundle is simulated as.
+    pub block_number: U64,
+    /// Block whose state the simulation starts from.
+    pub state_block_number: BlockNumber,
+    /// Timestamp the bundle is simulated at, the parent timestamp plus 12 seconds if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+}
+
+impl CallBundleRequest {
+    /// Creates a simulation of the transactions in `block_number` on top of the latest state.
+    pub fn new(txs: Vec<Bytes>, block_number: U64) -> Self {
+        Self {
+            txs,
+            block_number,
+            state_block_number: BlockNumber::Latest,
+            timestamp: None,
+        }
+    }
+
+    /// Starts the simulation from the state of another block.
+    pub fn with_state_block(mut self, state_block_number: BlockNumber) -> Self {
+        self.state_block_number = state_block_number;
+        self
+    }
+
+    /// Simulates the bundle at a given timestamp.
+    pub fn with_timestamp(mut self, timestamp: u64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+}
+
+/// Result of `eth_callBundle`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallBundleResponse {
+    /// Hash of the simulated bundle.
+    pub bundle_hash: H256,
+    /// Effective gas price of the bundle, the coinbase difference over the gas used.
+    #[serde(deserialize_with = "decimal_u256")]
+    pub bundle_gas_price: U256,
+    /// Balance change of the coinbase.
+    #[serde(deserialize_with = "decimal_u256")]
+    pub coinbase_diff: U256,
+    /// Ether transferred to the coinbase directly.
+    #[serde(deserialize_with = "decimal_u256")]
+    pub eth_sent_to_coinbase: U256,
+    /// Gas fees paid to the coinbase.
+    #[serde(deserialize_with = "decimal_u256")]
+    pub gas_fees: U256,
+    /// Result of each transaction.
+    pub results: Vec<CallBundleResult>,
+    /// Block whose state the simulation started from.
+    pub state_block_number: u64,
+    /// Gas used by all transactions.
+    pub total_gas_used: u64,
+}
+
+impl CallBundleResponse {
+    /// Returns the first transaction that failed, if any.
+    pub fn first_failure(&self) -> Option<&CallBundleResult> {
+        self.results.iter().find(|result| !result.is_success())
+    }
+}
+
+/// Result of one transaction in `eth_callBundle`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallBundleResult {
+    /// Hash of the transaction.
+    pub tx_hash: H256,
+    /// Sender of the transaction.
+    pub from_address: Address,
+    /// Recipient of the transaction, None for contract creations.
+    #[serde(default)]
+    pub to_address: Option<Address>,
+    /// Gas used by the transaction.
+    pub gas_used: u64,
+    /// Effective gas price of the transaction.
+    #[serde(deserialize_with = "decimal_u256")]
+    pub gas_price: U256,
+    /// Balance change of the coinbase.
+    #[serde(deserialize_with = "decimal_u256")]
+    pub coinbase_diff: U256,
+    /// Ether transferred to the coinbase directly.
+    #[serde(deserialize_with = "decimal_u256")]
+    pub eth_sent_to_coinbase: U256,
+    /// Gas fees paid to the coinbase.
+    #[serde(deserialize_with = "decimal_u256")]
+    pub gas_fees: U256,
+    /// Return data of the transaction.
+    #[serde(default)]
+    pub value: Option<Bytes>,
+    /// Error of a failed transaction.
+    #[serde(default)]
+    pub error: Option<String>,
+    /// Revert reason of a reverted transaction.
+    #[serde(default)]
+    pub revert: Option<String>,
+}
+
+impl CallBundleResult {
+    /// Returns true if the transaction neither failed nor reverted.
+    pub fn is_success(&self) -> bool {
+        self.error.is_none() && self.revert.is_none()
+    }
+}
+
+/// Result of `flashbots_getBundleStatsV2`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleStats {
+    /// Whether the bundle was prioritized by the relay.
+    #[serde(default)]
+    pub is_high_priority: bool,
+    /// Whether the relay simulated the bundle.
+    #[serde(default)]
+    pub is_simulated: bool,
+    /// When the relay simulated the bundle.
+    #[serde(default)]
+    pub simulated_at: Option<String>,
+    /// When the relay received the bundle.
+    #[serde(default)]
+    pub received_at: Option<String>,
+    /// Builders that considered the bundle.
+    #[serde(default)]
+    pub considered_by_builders_at: Vec<BuilderTimestamp>,
+    /// Builders that sealed a block with the bundle.
+    #[serde(default)]
+    pub sealed_by_builders_at: Vec<BuilderTimestamp>,
+}
+
+/// A builder and the time it handled a bundle.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct BuilderTimestamp {
+    /// Public key of the builder.
+    pub pubkey: String,
+    /// When the builder handled the bundle.
+    pub timestamp: String,
+}
+
+fn decimal_u256<'de, D>(deserializer: D) -> Result<U256, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    U256::from_dec_str(&value).map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_bundle_request_serialization() {
+        let bundle = BundleRequest::new(vec![Bytes::from(vec![0x02, 0xf8])], U64::from(0x10))
+            .with_timestamps(1, 2);
+
+        assert_eq!(
+            serde_json::to_value(&bundle).unwrap(),
+            json!({
+                "txs": ["0x02f8"],
+                "blockNumber": "0x10",
+                "minTimestamp": 1,
+                "maxTimestamp": 2,
+            })
+        );
+    }
+
+    #[test]
+    fn test_call_bundle_response_decimal_amounts() {
+        let response: CallBundleResponse = serde_json::from_value(json!({
+            "bundleGasPrice": "476190476193",
+            "bundleHash": format!("{:?}", H256::repeat_byte(1)),
+            "coinbaseDiff": "20000000000126000",
+            "ethSentToCoinbase": "20000000000000000",
+            "gasFees": "126000",
+            "results": [{
+                "coinbaseDiff": "10000000000063000",
+                "ethSentToCoinbase": "10000000000000000",
+                "fromAddress": format!("{:?}", Address::repeat_byte(2)),
+                "gasFees": "63000",
+                "gasPrice": "476190476193",
+                "gasUsed": 21000,
+                "toAddress": format!("{:?}", Address::repeat_byte(3)),
+                "txHash": format!("{:?}", H256::repeat_byte(4)),
+                "value": "0x"
+            }, {
+                "coinbaseDiff": "0",
+                "ethSentToCoinbase": "0",
+                "fromAddress": format!("{:?}", Address::repeat_byte(2)),
+                "gasFees": "0",
+                "gasPrice": "0",
+                "gasUsed": 30000,
+                "toAddress": format!("{:?}", Address::repeat_byte(3)),
+                "txHash": format!("{:?}", H256::repeat_byte(5)),
+                "error": "execution reverted",
+                "revert": "TooLittleReceived"
+            }],
+            "stateBlockNumber": 5221585,
+            "totalGasUsed": 51000
+        }))
+        .unwrap();
+
+        assert_eq!(
+            response.coinbase_diff,
+            U256::from(20_000_000_000_126_000_u64)
+        );
+        assert_eq!(response.results[0].gas_used, 21_000);
+        assert!(response.results[0].is_success());
+        assert_eq!(
+            response.first_failure().unwrap().tx_hash,
+            H256::repeat_byte(5)
+        );
+    }
+}
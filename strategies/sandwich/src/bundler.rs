@@ -3,17 +3,19 @@
 
 use ethers::{
     prelude::*,
-    types::{Transaction, U256, transaction::eip2718::TypedTransaction},
+    types::{transaction::eip2718::TypedTransaction, U256},
+};
+use mev_core::{middleware::FlashBotMiddleware, security::SecureVault};
+use mev_relayer::{
+    fanout::BuilderResult,
+    types::BundleRequest,
+    BuilderConfig, BundleFanout,
 };
-use mev_rs::relay::Relay;
-use mev_rs::{BlobBundle, BlobTransaction, SigningWallet};
 use parking_lot::Mutex;
-use std::{sync::Arc, time::Duration};
+use std::sync::Arc;
 
-// Constants for gas buffer percentage and maximum gas price deviation.
+// Constant for gas buffer percentage.
 const GAS_BUFFER_PERCENT: u64 = 10;
-const MAX_GAS_PRICE_DEVIATION: f64 = 0.25;
-const POLYGON_FLASHBOTS_RELAY: &str = "https://polygon-relay.flashbots.net";
 
 // Error types for bundling operations.
 #[derive(Debug, thiserror::Error)]
@@ -21,7 +23,7 @@ pub enum BundleError {
     #[error("Signing error: {0}")]
     SigningError(#[from] ethers::signers::WalletError),
     #[error("Relay error: {0}")]
-    RelayError(String),
+    RelayError(#[from] mev_relayer::RelayError),
 }
 
 // Structure representing gas parameters for transactions.
@@ -34,31 +36,41 @@ struct GasParameters {
 
 // The BundleConstructor struct is responsible for creating and managing transaction bundles.
 pub struct BundleConstructor {
-    relay: Relay,
+    fanout: BundleFanout,
     wallet: LocalWallet,
     provider: Provider<Http>,
     gas_cache: Arc<Mutex<GasParameters>>, // Caches gas parameters for efficiency.
 }
 
 impl BundleConstructor {
-    // Initializes a new BundleConstructor instance with the provided RPC URL.
-    pub async fn new(rpc_url: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        // Create a new relay and provider for transaction management.
-        let relay = Relay::new(rpc_url);
+    // Initializes a new BundleConstructor instance with the provided RPC URL and the configured builders.
+    // Transactions are signed with the vault's funded key, builder requests with its Flashbots signing key.
+    pub async fn new(
+        rpc_url: &str,
+        builders: Vec<BuilderConfig>,
+        vault: &SecureVault,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        // Create the builder fan-out and provider for transaction management.
         let provider = Provider::<Http>::try_from(rpc_url)?;
-        let wallet = LocalWallet::new(&mut rand::thread_rng());
+        let wallet = vault.get_signer()?;
+        let fanout = builder_fanout(builders, &vault.get_bundle_signer()?);
 
         // Fetch gas parameters for transaction optimization.
         let gas_params = Self::fetch_gas_parameters(&provider).await?;
 
         Ok(Self {
-            relay,
+            fanout,
             wallet,
             provider,
             gas_cache: Arc::new(Mutex::new(gas_params)),
         })
     }
 
+    // Function to get the builder fan-out bundles are submitted through.
+    pub fn fanout(&self) -> &BundleFanout {
+        &self.fanout
+    }
+
     // Function to fetch gas parameters from the provider.
     async fn fetch_gas_parameters(provider: &Provider<Http>) -> Result<GasParameters, ProviderError> {
         // Get the latest block from the provider.
@@ -67,7 +79,7 @@ impl BundleConstructor {
         let gas_limit = block.gas_limit;
 
         // Estimate EIP-1559 fees for priority fee calculation.
-        let priority_fee = provider.estimate_eip1559_fees(None).await?.max_priority_fee_per_gas;
+        let (_, priority_fee) = provider.estimate_eip1559_fees(None).await?;
 
         Ok(GasParameters {
             base_fee,
@@ -84,12 +96,15 @@ impl BundleConstructor {
     }
 
     // Function to build sandwich transactions.
-    // This function constructs the necessary transactions for executing a sandwich attack.
-    pub async fn build_sandwich_txs(&self, amount: U256) -> (TransactionRequest, TransactionRequest) {
+    // This function constructs the frontrun and backrun calls of `calldata`, such as the output of
+    // `SandwichMath::build_sandwich_data`, to the sandwich `executor`.
+    pub async fn build_sandwich_txs(
+        &self,
+        executor: Address,
+        calldata: (Bytes, Bytes),
+    ) -> Result<(TransactionRequest, TransactionRequest), ProviderError> {
         // Get the current nonce for the wallet.
-        let nonce = self.provider.get_transaction_count(self.wallet.address(), None)
-            .await
-            .unwrap();
+        let nonce = self.provider.get_transaction_count(self.wallet.address(), None).await?;
 
         // Calculate dynamic gas price and gas limit for transactions.
         let gas_price = self.calculate_dynamic_gas();
@@ -97,94 +112,83 @@ impl BundleConstructor {
 
         // Construct the frontrun and backrun transactions.
         let frontrun = TransactionRequest::new()
-            .to(self.pool_address)
-            .data(encode_swap(amount))
+            .to(executor)
+            .data(calldata.0)
             .nonce(nonce)
             .gas_price(gas_price)
             .gas(gas_limit);
 
         let backrun = TransactionRequest::new()
-            .to(self.pool_address)
-            .data(encode_swap(amount))
+            .to(executor)
+            .data(calldata.1)
             .nonce(nonce + 1)
             .gas_price(gas_price * 90 / 100)
             .gas(gas_limit);
 
-        (frontrun, backrun)
+        Ok((frontrun, backrun))
     }
 }
 
-// Structure representing a MEV bundle.
+// Function to create the fan-out over the configured builders.
+// Builders configured to sign with the bundle-signing key sign with `bundle_signer`.
+fn builder_fanout(builders: Vec<BuilderConfig>, bundle_signer: &LocalWallet) -> BundleFanout {
+    BundleFanout::new(
+        builders
+            .into_iter()
+            .map(|builder| builder.with_bundle_signer(bundle_signer))
+            .collect(),
+    )
+}
+
+// Structure representing a MEV bundle of signed raw transactions.
 pub struct MevBundle {
-    pub transactions: Vec<TypedTransaction>,
-    pub block_number: U256,
+    pub transactions: Vec<Bytes>,
+    pub block_number: U64,
     pub min_timestamp: u64,
     pub max_timestamp: u64,
 }
 
 impl MevBundle {
-    // Function to submit the transaction bundle to the relay.
-    // This function takes a wallet for signing the bundle.
-    pub async fn submit_to_relay(
-        self,
-        wallet: &LocalWallet,
-    ) -> Result<(), BundleError> {
-        // Create a new relay instance for submitting the bundle.
-        let relay = Relay::new(POLYGON_FLASHBOTS_RELAY);
-        let signer = SigningWallet::from(wallet.clone());
-
-        // Convert transactions to blob transactions for relay submission.
-        let blob_txs = self.transactions
-            .into_iter()
-            .map(|tx| BlobTransaction::from(tx.rlp()))
-            .collect();
-
-        // Create a blob bundle for submission.
-        let blob_bundle = BlobBundle {
-            transactions: blob_txs,
-            block_number: self.block_number,
-            min_timestamp: self.min_timestamp,
-            max_timestamp: self.max_timestamp,
-        };
-
-        // Submit the bundle to the relay.
-        relay.send_bundle(&signer, blob_bundle)
-            .await
-            .map_err(|e| BundleError::RelayError(e.to_string()))?;
-        Ok(())
+    // Function to submit the transaction bundle to every configured builder in parallel.
+    // The outcome of each builder is returned and recorded in the fan-out statistics.
+    pub async fn submit_to_builders(self, fanout: &BundleFanout) -> Vec<BuilderResult> {
+        let bundle = BundleRequest::new(self.transactions, self.block_number)
+            .with_timestamps(self.min_timestamp, self.max_timestamp);
+
+        fanout.send_bundle(&bundle).await
     }
 }
 
-use ethers::prelude::*;
-use ethers::core::abi::Abi;
-use mev_core::middleware::FlashBotMiddleware;
-use std::sync::Arc;
-
 pub struct SandwichBundler {
     provider: Arc<FlashBotMiddleware>,
     flash_loan_handler: Arc<Contract<FlashBotMiddleware>>,
-    gas_estimator: Arc<GasEstimator>,
 }
 
 impl SandwichBundler {
     pub fn new(
         provider: Arc<FlashBotMiddleware>,
         flash_loan_handler_address: Address,
-        gas_estimator: Arc<GasEstimator>,
     ) -> Result<Self, Box<dyn std::error::Error>> {
+        let flash_loan_abi = ethers::abi::parse_abi(&[
+            "function requestFlashLoan(address asset, uint256 amount, address target, bytes data)",
+        ])?;
         let flash_loan_handler = Contract::new(
             flash_loan_handler_address,
-            include_bytes!("../../../contracts/core/FlashLoanHandler.json").to_vec(),
+            flash_loan_abi,
             provider.clone(),
         );
 
         Ok(Self {
             provider,
             flash_loan_handler: Arc::new(flash_loan_handler),
-            gas_estimator,
         })
     }
 
+    // Function to estimate the gas of a transaction through the provider.
+    async fn estimate_gas(&self, tx: &TypedTransaction) -> Result<U256, Box<dyn std::error::Error>> {
+        Ok(self.provider.estimate_gas(tx, None).await?)
+    }
+
     pub async fn create_sandwich_bundle(
         &self,
         frontrun_tx: TypedTransaction,
@@ -194,9 +198,9 @@ impl SandwichBundler {
         token: Address,
     ) -> Result<Vec<TypedTransaction>, Box<dyn std::error::Error>> {
         // Estimate gas for each transaction
-        let frontrun_gas = self.gas_estimator.estimate_gas(&frontrun_tx).await?;
-        let victim_gas = self.gas_estimator.estimate_gas(&victim_tx).await?;
-        let backrun_gas = self.gas_estimator.estimate_gas(&backrun_tx).await?;
+        let frontrun_gas = self.estimate_gas(&frontrun_tx).await?;
+        let victim_gas = self.estimate_gas(&victim_tx).await?;
+        let backrun_gas = self.estimate_gas(&backrun_tx).await?;
 
         // Create flash loan transaction
         let flash_loan_data = self.flash_loan_handler
@@ -205,8 +209,8 @@ impl SandwichBundler {
                 (
                     token,
                     flash_loan_amount,
-                    frontrun_tx.to().unwrap(),
-                    frontrun_tx.data().unwrap(),
+                    *frontrun_tx.to_addr().ok_or("frontrun has no target")?,
+                    frontrun_tx.data().cloned().unwrap_or_default(),
                 ),
             )?;
 
@@ -217,13 +221,7 @@ impl SandwichBundler {
             .into();
 
         // Bundle transactions
-        let mut bundle = Vec::new();
-        bundle.push(flash_loan_tx);
-        bundle.push(frontrun_tx);
-        bundle.push(victim_tx);
-        bundle.push(backrun_tx);
-
-        Ok(bundle)
+        Ok(vec![flash_loan_tx, frontrun_tx, victim_tx, backrun_tx])
     }
 
     pub async fn simulate_bundle(
@@ -234,7 +232,7 @@ impl SandwichBundler {
         let mut total_gas_used = U256::zero();
         
         for tx in bundle {
-            let gas_used = self.gas_estimator.estimate_gas(tx).await?;
+            let gas_used = self.estimate_gas(tx).await?;
             total_gas_used = total_gas_used.checked_add(gas_used)
                 .ok_or("Gas calculation overflow")?;
         }
@@ -267,3 +265,95 @@ pub enum BundlerError {
     #[error("Bundle submission failed: {0}")]
     SubmissionError(String),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mev_relayer::{fanout::SubmissionOutcome, RelayAuth};
+    use serde_json::json;
+
+    fn bundle() -> MevBundle {
+        MevBundle {
+            transactions: vec![Bytes::from(vec![0x02, 0xf8])],
+            block_number: U64::from(0x10),
+            min_timestamp: 0,
+            max_timestamp: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_flashbots_builders_sign_with_the_bundle_signer() {
+        let bundle_signer: LocalWallet =
+            "0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318"
+                .parse()
+                .unwrap();
+        let mut server = mockito::Server::new_async().await;
+        let signed = server
+            .mock("POST", "/")
+            .match_header(
+                "x-flashbots-signature",
+                mockito::Matcher::Regex(format!("^(?i){:?}:0x", bundle_signer.address())),
+            )
+            .with_body(
+                json!({ "jsonrpc": "2.0", "id": 1, "result": { "bundleHash": H256::repeat_byte(1) } })
+                    .to_string(),
+            )
+            .create_async()
+            .await;
+
+        // Configured as `{ "type": "flashbots" }`, the builder has no key until the vault's is given.
+        let fanout = builder_fanout(
+            vec![BuilderConfig::new("flashbots", server.url(), RelayAuth::BundleSigner)],
+            &bundle_signer,
+        );
+        let results = bundle().submit_to_builders(&fanout).await;
+
+        assert!(matches!(results[0].outcome, SubmissionOutcome::Accepted { .. }));
+        signed.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_bundle_is_submitted_to_every_builder() {
+        let mut accepting = mockito::Server::new_async().await;
+        let accepted = accepting
+            .mock("POST", "/")
+            .match_body(mockito::Matcher::PartialJson(json!({
+                "method": "eth_sendBundle",
+                "params": [{ "txs": ["0x02f8"], "blockNumber": "0x10" }],
+            })))
+            .with_body(
+                json!({ "jsonrpc": "2.0", "id": 1, "result": { "bundleHash": H256::repeat_byte(1) } })
+                    .to_string(),
+            )
+            .create_async()
+            .await;
+        let mut rejecting = mockito::Server::new_async().await;
+        rejecting
+            .mock("POST", "/")
+            .with_status(400)
+            .with_body(r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32000,"message":"bundle underpriced"}}"#)
+            .create_async()
+            .await;
+
+        let fanout = BundleFanout::new(vec![
+            BuilderConfig::new("accepting", accepting.url(), RelayAuth::None),
+            BuilderConfig::new("rejecting", rejecting.url(), RelayAuth::None),
+        ]);
+        let results = bundle().submit_to_builders(&fanout).await;
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(
+            results[0].outcome,
+            SubmissionOutcome::Accepted {
+                bundle_hash: H256::repeat_byte(1)
+            }
+        );
+        assert!(matches!(
+            &results[1].outcome,
+            SubmissionOutcome::Rejected { reason } if reason.contains("bundle underpriced")
+        ));
+        assert_eq!(fanout.stats("accepting").unwrap().accepted, 1);
+        assert_eq!(fanout.stats("rejecting").unwrap().rejected, 1);
+        accepted.assert_async().await;
+    }
+}
@@ -1,19 +1,50 @@
 use ethers::{
-    types::{Address, H256, U256},
+    types::{Address, Transaction, H256, I256, U256, U512},
     providers::Provider,
     contract::Contract,
     core::abi::Abi,
 };
-use std::{sync::Arc, error::Error};
-use mev_risk::RiskEngine;
+use std::{sync::{Arc, RwLock}, error::Error};
+use mev_core::circuit_breaker::{CircuitBreaker, TradePermit};
+use mev_math::{
+    decoder::{DecodedSwap, SwapKind},
+    pool_state::PoolStateTracker,
+    sandwich::SandwichAmounts,
+};
+use mev_risk::TradeProposal;
+use serde::Deserialize;
 use serde_json::from_slice;
+use tokio::sync::mpsc;
+
+pub mod bundler;
+pub mod mempool;
+
+use mempool::{FilterConfig, FilterPipeline, MempoolWatcher};
+
+/// Gas used by the frontrun and the backrun of a sandwich.
+const SANDWICH_GAS: u64 = 350_000;
+/// Units of a token the price oracle quotes a price for, in units of the quote token.
+const PRICE_UNIT: u64 = 1_000_000_000_000_000_000;
+
+/// Bot configuration of the sandwich strategy, its shared settings with the filters of its mempool stream.
+#[derive(Debug, Deserialize)]
+pub struct SandwichConfig {
+    #[serde(flatten)]
+    pub bot: mev_utils::types::Config,
+    /// Filters a pending transaction has to pass to be sandwiched.
+    #[serde(default)]
+    pub filters: FilterConfig,
+    /// Wrapped native token, as which the gas of a sandwich is priced.
+    pub wrapped_native: Address,
+}
 
 #[derive(Debug)]
 #[allow(dead_code)]
 pub struct SandwichStrategy {
     #[allow(dead_code)]
     provider: Arc<Provider<ethers::providers::Http>>,
-    risk_engine: RiskEngine,
+    breaker: Arc<CircuitBreaker>,
+    pools: Arc<RwLock<PoolStateTracker>>,
     #[allow(dead_code)]
     active_positions: Vec<Position>,
     #[allow(dead_code)]
@@ -21,6 +52,18 @@ pub struct SandwichStrategy {
     sandwich_executor: Contract<Provider<ethers::providers::Http>>,
     price_oracle: Contract<Provider<ethers::providers::Http>>,
     math: mev_math::sandwich::SandwichMath,
+    mempool: MempoolWatcher,
+    wrapped_native: Address,
+}
+
+/// A sent sandwich, holding its position and circuit breaker permit until `settle_sandwich`.
+#[derive(Debug)]
+pub struct SentSandwich {
+    /// Hash of the sandwich transaction.
+    pub tx_hash: H256,
+    token0: Address,
+    amount0: U256,
+    permit: TradePermit,
 }
 
 #[derive(Debug)]
@@ -35,8 +78,12 @@ pub struct Position {
 impl SandwichStrategy {
     pub async fn new(
         provider: Arc<Provider<ethers::providers::Http>>,
-        _risk_params: RiskParams,
+        breaker: Arc<CircuitBreaker>,
+        pools: Arc<RwLock<PoolStateTracker>>,
+        config: &SandwichConfig,
     ) -> Result<Self, Box<dyn Error>> {
+        let mempool = MempoolWatcher::new(FilterPipeline::from_config(&config.filters)?);
+
         // Load contract ABIs
         let flash_loan_abi: Abi = from_slice(
             include_bytes!("../../../contracts/core/FlashLoanHandler.json")
@@ -70,30 +117,125 @@ impl SandwichStrategy {
 
         Ok(Self {
             provider,
-            risk_engine: RiskEngine::default(),
+            breaker,
+            pools,
             active_positions: Vec::new(),
             flash_loan_handler,
             sandwich_executor,
             price_oracle,
             math: mev_math::sandwich::SandwichMath::default(),
+            mempool,
+            wrapped_native: config.wrapped_native,
         })
     }
 
+    /// Sandwiches the swaps of the pending transactions from `pending`, such as
+    /// `mev_core::mempool::MempoolWatcher::tx_receiver`, that pass the configured filters, until it is closed.
+    pub async fn run(&mut self, pending: mpsc::Receiver<Transaction>) {
+        let mut candidates = self.mempool.start(pending);
+        while let Some(candidate) = candidates.recv().await {
+            // The frontrun outbids the victim, so its gas costs at least the victim's gas price.
+            let gas_price = candidate.tx.gas_price.unwrap_or_default();
+            for swap in candidate.swaps() {
+                if let Err(err) = self.sandwich_swap(swap, gas_price).await {
+                    log::debug!("Skipped sandwich of {:?}: {err}", candidate.tx.hash);
+                }
+            }
+        }
+    }
+
+    /// Sizes a sandwich around a single-hop exact-input swap on the pool state ahead of it, and sends it.
+    async fn sandwich_swap(
+        &mut self,
+        swap: &DecodedSwap,
+        gas_price: U256,
+    ) -> Result<SentSandwich, Box<dyn Error>> {
+        let (Some(token_in), Some(token_out)) = (swap.token_in(), swap.token_out()) else {
+            return Err("swap has no path".into());
+        };
+        if swap.kind != SwapKind::ExactInput || swap.path.len() != 2 {
+            return Err("only single-hop exact-input swaps are sandwiched".into());
+        }
+        let pool = {
+            let pools = self.pools.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+            pools
+                .route_hop(swap.protocol, token_in, token_out, swap.fees.first().copied())
+                .and_then(|address| pools.state_ahead_of(address, gas_price))
+                .ok_or("no tracked pool for the swap")?
+        };
+
+        let gas_cost = self.gas_cost_in(token_in, gas_price).await?;
+        let amounts = self.math.calculate_optimal_amounts(
+            pool.as_ref(),
+            token_in,
+            token_out,
+            swap.amount_in,
+            swap.amount_out,
+            gas_cost,
+        )?;
+        self.execute_sandwich(token_in, token_out, &amounts, gas_cost).await
+    }
+
+    /// Returns the filters pending transactions pass through, with their rejection counts.
+    pub fn filters(&self) -> &FilterPipeline {
+        self.mempool.pipeline()
+    }
+
+    /// Sends a sandwich sized by `SandwichMath::calculate_optimal_amounts` if the circuit breaker allows it.
+    ///
+    /// `amounts` and `gas_cost` are in units of `token0`, the victim's input token. Once the sandwich has
+    /// settled, pass it with its outcome to `settle_sandwich`.
     pub async fn execute_sandwich(
         &mut self,
         token0: Address,
         token1: Address,
-        amount0: U256,
-        amount1: U256,
-    ) -> Result<H256, Box<dyn Error>> {
-        // Check risk parameters
-        let potential_loss = self.calculate_potential_loss(token0).await?;
-        if let Err(err) = self.risk_engine.validate_risk(potential_loss) {
+        amounts: &SandwichAmounts,
+        gas_cost: U256,
+    ) -> Result<SentSandwich, Box<dyn Error>> {
+        // Check risk parameters, with every amount valued in the quote token
+        let price = self.price(token0).await?;
+        let value = |amount: U256| mul_div(amount, price, U256::from(PRICE_UNIT));
+        // The executor reverts unless the sandwich is profitable, so at worst it loses its gas.
+        let proposal = Self::proposal(token0, amounts.frontrun_amount_in)
+            .with_value(value(amounts.frontrun_amount_in))
+            .with_expected_profit(value(amounts.gross_profit))
+            .with_cost(value(gas_cost))
+            .with_max_loss(value(gas_cost));
+        let Some(permit) = self.breaker.check_trade(&proposal) else {
+            return Err(SandwichError::TradeRejected.into());
+        };
+        if let Err(err) = self.breaker.risk_engine().open_position(&proposal) {
+            self.breaker.release_trade(permit);
             return Err(err.into());
         }
 
+        match self.send_sandwich(token0, token1, amounts).await {
+            Ok(tx_hash) => Ok(SentSandwich {
+                tx_hash,
+                token0,
+                amount0: amounts.frontrun_amount_in,
+                permit,
+            }),
+            Err(err) => {
+                // Nothing was sent, so the position and the permit are released right away.
+                self.breaker.risk_engine().release_position(&proposal);
+                self.breaker.release_trade(permit);
+                Err(err)
+            }
+        }
+    }
+
+    /// Builds the sandwich transactions and sends them to the executor.
+    async fn send_sandwich(
+        &self,
+        token0: Address,
+        token1: Address,
+        amounts: &SandwichAmounts,
+    ) -> Result<H256, Box<dyn Error>> {
         // Build sandwich transactions
-        let sandwich_data = self.math.build_sandwich_data(amount0, amount1)?;
+        let sandwich_data = self
+            .math
+            .build_sandwich_data(amounts.frontrun_amount_in, amounts.frontrun_amount_out)?;
         let stored_data = (sandwich_data.0, sandwich_data.1);
         
         // Execute sandwich via flash loan
@@ -103,35 +245,60 @@ impl SandwichStrategy {
                 (
                     token0,
                     token1,
-                    amount0,
-                    amount1,
+                    amounts.frontrun_amount_in,
+                    amounts.frontrun_amount_out,
                     stored_data.0.clone(),
                     stored_data.1.clone(),
                 ),
             )?;
         
         let tx = method_call.send().await?;
-        
         Ok(tx.tx_hash())
     }
 
-    async fn calculate_potential_loss(
-        &self,
-        token0: Address,
-    ) -> Result<U256, Box<dyn Error>> {
+    /// Closes the position of a sent sandwich and records its realized PnL, or releases it if it did not land.
+    pub fn settle_sandwich(&self, sandwich: SentSandwich, profit_loss: Option<I256>) {
+        let proposal = Self::proposal(sandwich.token0, sandwich.amount0);
+        self.breaker.risk_engine().release_position(&proposal);
+        match profit_loss {
+            Some(profit_loss) => {
+                self.breaker
+                    .record_trade(sandwich.permit, proposal.strategy, profit_loss)
+            }
+            None => self.breaker.release_trade(sandwich.permit),
+        }
+    }
+
+    fn proposal(token0: Address, amount0: U256) -> TradeProposal {
+        TradeProposal::new(token0, amount0).with_strategy("sandwich")
+    }
+
+    /// Cost of the gas of a sandwich at `gas_price`, in units of `token`.
+    async fn gas_cost_in(&self, token: Address, gas_price: U256) -> Result<U256, Box<dyn Error>> {
+        let gas_cost = gas_price.saturating_mul(U256::from(SANDWICH_GAS));
+        if token == self.wrapped_native {
+            return Ok(gas_cost);
+        }
+        let (native_price, price) = (self.price(self.wrapped_native).await?, self.price(token).await?);
+        if price.is_zero() {
+            return Err(SandwichError::ContractError(format!("no price for token {token:?}")).into());
+        }
+        Ok(mul_div(gas_cost, native_price, price))
+    }
+
+    /// Price of `token` in units of the quote token per `PRICE_UNIT` units of it.
+    async fn price(&self, token: Address) -> Result<U256, Box<dyn Error>> {
         let price = self.price_oracle
-            .method::<_, U256>("getPrice", token0)?
+            .method::<_, U256>("getPrice", token)?
             .call()
             .await?;
-        
-        Ok(price.saturating_mul(U256::from(0))) // amount0 is not defined in this function
+        Ok(price)
     }
 }
 
-#[derive(Debug)]
-pub struct RiskParams {
-    max_loss: U256,
-    min_profit: U256,
+/// `amount * numerator / denominator`, saturating at `U256::MAX`.
+fn mul_div(amount: U256, numerator: U256, denominator: U256) -> U256 {
+    U256::try_from(amount.full_mul(numerator) / U512::from(denominator)).unwrap_or(U256::MAX)
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -144,4 +311,28 @@ pub enum SandwichError {
     RiskError(#[from] mev_risk::RiskError),
     #[error("Math error: {0}")]
     MathError(String),
+    #[error("Trade rejected by the circuit breaker")]
+    TradeRejected,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_filters_are_read_from_the_bot_config() {
+        let config: SandwichConfig = serde_json::from_value(json!({
+            "rpc_url": "http://localhost:8545",
+            "private_key": "",
+            "wrapped_native": Address::repeat_byte(2),
+            "filters": { "min_value": "0x64", "token_denylist": [Address::repeat_byte(1)] },
+        }))
+        .unwrap();
+        assert_eq!(config.bot.rpc_url, "http://localhost:8545");
+        assert_eq!(config.filters.min_value, Some(U256::from(100)));
+
+        let pipeline = FilterPipeline::from_config(&config.filters).unwrap();
+        assert_eq!(pipeline.rejections(), vec![("min_value", 0), ("token", 0)]);
+    }
 }
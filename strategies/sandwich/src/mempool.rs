@@ -1,34 +1,492 @@
-use ethers::{providers::Middleware, types::Transaction};
-use parking_lot::Mutex;
-use std::{sync::Arc, time::Duration};
+// This module filters the pending transaction stream before it reaches strategies.
+// Each filter looks at the transaction and its swaps, decoded on first use and shared by the whole pipeline.
 
-pub struct MempoolWatcher<M: Middleware> {
-    provider: Arc<M>,
-    pending_txs: Arc<Mutex<Vec<Transaction>>>,
+use ethers::types::{Address, Bytes, Transaction, U256};
+use mev_math::decoder::{decode_swaps, DecodedSwap};
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, OnceLock,
+    },
+};
+use tokio::sync::mpsc;
+
+#[derive(Debug, thiserror::Error)]
+pub enum FilterError {
+    #[error("Invalid selector {0}, expected 4 bytes")]
+    InvalidSelector(Bytes),
+    #[error("Swap size filter needs at least one token price")]
+    MissingPrices,
+}
+
+/// A pending transaction with the swaps decoded from its calldata.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingTx {
+    pub tx: Transaction,
+    swaps: OnceLock<Vec<DecodedSwap>>,
+}
+
+impl PendingTx {
+    pub fn new(tx: Transaction) -> Self {
+        Self {
+            tx,
+            swaps: OnceLock::new(),
+        }
+    }
+
+    /// Swaps of a supported router call, empty for any other transaction.
+    pub fn swaps(&self) -> &[DecodedSwap] {
+        self.swaps.get_or_init(|| match self.tx.to {
+            Some(_) => decode_swaps(&self.tx.input, self.tx.value).unwrap_or_default(),
+            None => Vec::new(),
+        })
+    }
+
+    pub fn selector(&self) -> Option<[u8; 4]> {
+        self.tx
+            .input
+            .get(..4)
+            .map(|selector| selector.try_into().unwrap())
+    }
+
+    /// Tokens traded by the decoded swaps.
+    pub fn tokens(&self) -> impl Iterator<Item = Address> + '_ {
+        self.swaps()
+            .iter()
+            .flat_map(|swap| swap.path.iter().copied())
+    }
+}
+
+/// A condition a pending transaction has to meet to reach the strategies.
+pub trait TxFilter: Debug + Send + Sync {
+    /// Name the rejections of the filter are counted under.
+    fn name(&self) -> &str;
+
+    fn accepts(&self, tx: &PendingTx) -> bool;
+}
+
+/// Accepts transactions sent to one of the addresses.
+#[derive(Debug, Clone)]
+pub struct TargetFilter(pub HashSet<Address>);
+
+impl TxFilter for TargetFilter {
+    fn name(&self) -> &str {
+        "target"
+    }
+
+    fn accepts(&self, tx: &PendingTx) -> bool {
+        tx.tx.to.is_some_and(|to| self.0.contains(&to))
+    }
+}
+
+/// Accepts transactions calling one of the function selectors.
+#[derive(Debug, Clone)]
+pub struct SelectorFilter(pub HashSet<[u8; 4]>);
+
+impl TxFilter for SelectorFilter {
+    fn name(&self) -> &str {
+        "selector"
+    }
+
+    fn accepts(&self, tx: &PendingTx) -> bool {
+        tx.selector()
+            .is_some_and(|selector| self.0.contains(&selector))
+    }
+}
+
+/// Accepts transactions sending at least this many wei.
+#[derive(Debug, Clone)]
+pub struct MinValueFilter(pub U256);
+
+impl TxFilter for MinValueFilter {
+    fn name(&self) -> &str {
+        "min_value"
+    }
+
+    fn accepts(&self, tx: &PendingTx) -> bool {
+        tx.tx.value >= self.0
+    }
+}
+
+/// Accepts transactions paying at least this gas price, the max fee of EIP-1559 transactions.
+#[derive(Debug, Clone)]
+pub struct GasPriceFilter(pub U256);
+
+impl TxFilter for GasPriceFilter {
+    fn name(&self) -> &str {
+        "gas_price"
+    }
+
+    fn accepts(&self, tx: &PendingTx) -> bool {
+        tx.tx
+            .max_fee_per_gas
+            .or(tx.tx.gas_price)
+            .is_some_and(|gas_price| gas_price >= self.0)
+    }
+}
+
+/// USD price of a token.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct TokenPrice {
+    pub token: Address,
+    /// Price of one whole token.
+    pub usd: f64,
+    pub decimals: u8,
+}
+
+/// Accepts transactions with a decoded swap worth at least `min_usd`.
+///
+/// A swap is valued at the larger of its input and output, among the legs whose token has a price.
+#[derive(Debug, Clone)]
+pub struct SwapSizeFilter {
+    pub min_usd: f64,
+    prices: HashMap<Address, TokenPrice>,
 }
 
-impl<M: Middleware> MempoolWatcher<M> {
-    pub fn new(provider: Arc<M>) -> Self {
+impl SwapSizeFilter {
+    pub fn new(min_usd: f64, prices: impl IntoIterator<Item = TokenPrice>) -> Self {
         Self {
-            provider,
-            pending_txs: Arc::new(Mutex::new(Vec::new())),
+            min_usd,
+            prices: prices
+                .into_iter()
+                .map(|price| (price.token, price))
+                .collect(),
         }
     }
 
-    pub async fn start(&self) {
-        let provider = self.provider.clone();
-        let pending_txs = self.pending_txs.clone();
-        
+    /// Returns the USD value of a swap, None if neither of its tokens has a price.
+    pub fn swap_usd(&self, swap: &DecodedSwap) -> Option<f64> {
+        let legs = [
+            (swap.token_in(), swap.amount_in),
+            (swap.token_out(), swap.amount_out),
+        ];
+        legs.into_iter()
+            .filter_map(|(token, amount)| self.usd_value(token?, amount))
+            .reduce(f64::max)
+    }
+
+    fn usd_value(&self, token: Address, amount: U256) -> Option<f64> {
+        let price = self.prices.get(&token)?;
+        // Amounts beyond f64 precision only need to compare against a threshold.
+        let amount = amount.to_string().parse::<f64>().ok()?;
+        Some(amount / 10f64.powi(price.decimals.into()) * price.usd)
+    }
+}
+
+impl TxFilter for SwapSizeFilter {
+    fn name(&self) -> &str {
+        "swap_usd"
+    }
+
+    fn accepts(&self, tx: &PendingTx) -> bool {
+        tx.swaps()
+            .iter()
+            .filter_map(|swap| self.swap_usd(swap))
+            .any(|usd| usd >= self.min_usd)
+    }
+}
+
+/// Rejects swaps through a denied token and, with an allowlist, through any token not on it.
+///
+/// Transactions without decoded swaps trade no token and pass.
+#[derive(Debug, Clone, Default)]
+pub struct TokenFilter {
+    pub allow: Option<HashSet<Address>>,
+    pub deny: HashSet<Address>,
+}
+
+impl TxFilter for TokenFilter {
+    fn name(&self) -> &str {
+        "token"
+    }
+
+    fn accepts(&self, tx: &PendingTx) -> bool {
+        tx.tokens().all(|token| {
+            !self.deny.contains(&token)
+                && self
+                    .allow
+                    .as_ref()
+                    .is_none_or(|allow| allow.contains(&token))
+        })
+    }
+}
+
+/// Filters of a `FilterPipeline`, as read from the bot configuration. Unset filters are not applied.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct FilterConfig {
+    pub targets: Vec<Address>,
+    pub selectors: Vec<Bytes>,
+    pub min_value: Option<U256>,
+    pub min_gas_price: Option<U256>,
+    pub min_swap_usd: Option<f64>,
+    pub token_prices: Vec<TokenPrice>,
+    pub token_allowlist: Option<Vec<Address>>,
+    pub token_denylist: Vec<Address>,
+}
+
+/// Filters applied in order, a transaction passing only if every filter accepts it.
+#[derive(Debug, Default)]
+pub struct FilterPipeline {
+    filters: Vec<(Box<dyn TxFilter>, AtomicU64)>,
+}
+
+impl FilterPipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds the filters set in `config`, cheapest first so that most transactions are never decoded.
+    pub fn from_config(config: &FilterConfig) -> Result<Self, FilterError> {
+        let mut pipeline = Self::new();
+        if !config.targets.is_empty() {
+            pipeline = pipeline.with_filter(TargetFilter(config.targets.iter().copied().collect()));
+        }
+        if !config.selectors.is_empty() {
+            let selectors = config
+                .selectors
+                .iter()
+                .map(|selector| {
+                    <[u8; 4]>::try_from(selector.as_ref())
+                        .map_err(|_| FilterError::InvalidSelector(selector.clone()))
+                })
+                .collect::<Result<_, _>>()?;
+            pipeline = pipeline.with_filter(SelectorFilter(selectors));
+        }
+        if let Some(min_value) = config.min_value {
+            pipeline = pipeline.with_filter(MinValueFilter(min_value));
+        }
+        if let Some(min_gas_price) = config.min_gas_price {
+            pipeline = pipeline.with_filter(GasPriceFilter(min_gas_price));
+        }
+        if config.token_allowlist.is_some() || !config.token_denylist.is_empty() {
+            pipeline = pipeline.with_filter(TokenFilter {
+                allow: config
+                    .token_allowlist
+                    .as_ref()
+                    .map(|allow| allow.iter().copied().collect()),
+                deny: config.token_denylist.iter().copied().collect(),
+            });
+        }
+        if let Some(min_swap_usd) = config.min_swap_usd {
+            if config.token_prices.is_empty() {
+                return Err(FilterError::MissingPrices);
+            }
+            pipeline = pipeline.with_filter(SwapSizeFilter::new(
+                min_swap_usd,
+                config.token_prices.iter().copied(),
+            ));
+        }
+        Ok(pipeline)
+    }
+
+    /// Appends a filter, applied after the ones already added.
+    pub fn with_filter(mut self, filter: impl TxFilter + 'static) -> Self {
+        self.filters.push((Box::new(filter), AtomicU64::new(0)));
+        self
+    }
+
+    /// Decodes a transaction and runs it through the filters, returning it if every filter accepts it.
+    pub fn apply(&self, tx: Transaction) -> Option<PendingTx> {
+        let tx = PendingTx::new(tx);
+        for (filter, rejected) in &self.filters {
+            if !filter.accepts(&tx) {
+                rejected.fetch_add(1, Ordering::Relaxed);
+                return None;
+            }
+        }
+        Some(tx)
+    }
+
+    /// Returns the number of transactions each filter rejected, in pipeline order.
+    pub fn rejections(&self) -> Vec<(&str, u64)> {
+        self.filters
+            .iter()
+            .map(|(filter, rejected)| (filter.name(), rejected.load(Ordering::Relaxed)))
+            .collect()
+    }
+}
+
+/// Feeds the pending transaction stream through a filter pipeline to the strategies.
+#[derive(Debug, Clone)]
+pub struct MempoolWatcher {
+    pipeline: Arc<FilterPipeline>,
+}
+
+impl MempoolWatcher {
+    pub fn new(pipeline: FilterPipeline) -> Self {
+        Self {
+            pipeline: Arc::new(pipeline),
+        }
+    }
+
+    pub fn pipeline(&self) -> &FilterPipeline {
+        &self.pipeline
+    }
+
+    /// Filters the transactions of `source`, such as `mev_core::mempool::MempoolWatcher::tx_receiver`, and
+    /// returns the receiver of the accepted ones.
+    pub fn start(&self, mut source: mpsc::Receiver<Transaction>) -> mpsc::Receiver<PendingTx> {
+        let (sender, receiver) = mpsc::channel(100);
+        let pipeline = self.pipeline.clone();
+
         tokio::spawn(async move {
-            loop {
-                if let Ok(txs) = provider.pending_transactions().await {
-                    let mut guard = pending_txs.lock();
-                    *guard = txs.into_iter()
-                        .filter(|tx| tx.to == Some("0x1b02dA8Cb0d097eB8D57A175b88c7D8b47997506".parse().unwrap()))
-                        .collect();
+            while let Some(tx) = source.recv().await {
+                if let Some(tx) = pipeline.apply(tx) {
+                    if sender.send(tx).await.is_err() {
+                        return;
+                    }
                 }
-                tokio::time::sleep(Duration::from_millis(50)).await;
             }
         });
+        receiver
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{
+        abi::{encode, Token},
+        utils::id,
+    };
+    use serde_json::json;
+
+    const ROUTER: &str = "0x1b02dA8Cb0d097eB8D57A175b88c7D8b47997506";
+    const WMATIC: &str = "0x0d500B1d8E8eF31E21C99d1Db9A6444d3ADf1270";
+    const USDC: &str = "0x2791Bca1f2de4661ED88A58C4A0e2A5C2C2aF2d0";
+
+    fn swap_tx(amount_in: U256, path: [&str; 2]) -> Transaction {
+        let mut input =
+            id("swapExactTokensForTokens(uint256,uint256,address[],address,uint256)").to_vec();
+        input.extend(encode(&[
+            Token::Uint(amount_in),
+            Token::Uint(U256::zero()),
+            Token::Array(
+                path.iter()
+                    .map(|token| Token::Address(token.parse().unwrap()))
+                    .collect(),
+            ),
+            Token::Address(Address::repeat_byte(0xaa)),
+            Token::Uint(U256::MAX),
+        ]));
+        Transaction {
+            to: Some(ROUTER.parse().unwrap()),
+            input: input.into(),
+            gas_price: Some(U256::from(50_000_000_000u64)),
+            ..Default::default()
+        }
+    }
+
+    fn config() -> FilterConfig {
+        serde_json::from_value(json!({
+            "targets": [ROUTER],
+            "selectors": ["0x38ed1739"],
+            "min_gas_price": "0x6fc23ac00",
+            "min_swap_usd": 1000.0,
+            "token_prices": [
+                { "token": WMATIC, "usd": 0.5, "decimals": 18 },
+                { "token": USDC, "usd": 1.0, "decimals": 6 }
+            ],
+            "token_denylist": ["0x0000000000000000000000000000000000000bad"]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_pipeline_from_config() {
+        let pipeline = FilterPipeline::from_config(&config()).unwrap();
+        let wmatic = U256::exp10(18);
+
+        // 4000 WMATIC is worth $2000.
+        let large = pipeline
+            .apply(swap_tx(wmatic * 4000, [WMATIC, USDC]))
+            .unwrap();
+        assert_eq!(large.swaps().len(), 1);
+        assert_eq!(large.selector(), Some([0x38, 0xed, 0x17, 0x39]));
+
+        assert!(pipeline
+            .apply(swap_tx(wmatic * 1000, [WMATIC, USDC]))
+            .is_none());
+        let denied = [WMATIC, "0x0000000000000000000000000000000000000bad"];
+        assert!(pipeline.apply(swap_tx(wmatic * 4000, denied)).is_none());
+        let cheap = Transaction {
+            gas_price: Some(U256::from(1_000_000_000u64)),
+            ..swap_tx(wmatic * 4000, [WMATIC, USDC])
+        };
+        assert!(pipeline.apply(cheap).is_none());
+        assert!(pipeline.apply(Transaction::default()).is_none());
+
+        assert_eq!(
+            pipeline.rejections(),
+            vec![
+                ("target", 1),
+                ("selector", 0),
+                ("gas_price", 1),
+                ("token", 1),
+                ("swap_usd", 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let selector = FilterConfig {
+            selectors: vec![Bytes::from(vec![0x38, 0xed])],
+            ..Default::default()
+        };
+        assert!(matches!(
+            FilterPipeline::from_config(&selector),
+            Err(FilterError::InvalidSelector(_))
+        ));
+
+        let prices = FilterConfig {
+            min_swap_usd: Some(1000.0),
+            ..Default::default()
+        };
+        assert!(matches!(
+            FilterPipeline::from_config(&prices),
+            Err(FilterError::MissingPrices)
+        ));
+    }
+
+    #[test]
+    fn test_token_allowlist() {
+        let filter = TokenFilter {
+            allow: Some(HashSet::from([WMATIC.parse().unwrap()])),
+            deny: HashSet::new(),
+        };
+        let swap = PendingTx::new(swap_tx(U256::one(), [WMATIC, USDC]));
+        assert!(!filter.accepts(&swap));
+        assert!(filter.accepts(&PendingTx::new(Transaction::default())));
+    }
+
+    #[tokio::test]
+    async fn test_watcher_forwards_accepted_transactions() {
+        let watcher =
+            MempoolWatcher::new(FilterPipeline::new().with_filter(MinValueFilter(U256::from(10))));
+        let (sender, source) = mpsc::channel(10);
+        let mut accepted = watcher.start(source);
+
+        for value in [5u64, 10, 20] {
+            sender
+                .send(Transaction {
+                    value: U256::from(value),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+        drop(sender);
+
+        let mut values = Vec::new();
+        while let Some(tx) = accepted.recv().await {
+            values.push(tx.tx.value.as_u64());
+        }
+        assert_eq!(values, vec![10, 20]);
+        assert_eq!(watcher.pipeline().rejections(), vec![("min_value", 1)]);
     }
 }